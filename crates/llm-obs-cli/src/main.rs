@@ -0,0 +1,17 @@
+use anyhow::Context;
+use llm_obs_core::telemetry;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let telemetry = telemetry::init("rust-llm-observability-guide")?;
+
+    tracing::info_span!("startup").in_scope(|| {
+        tracing::info!("Telemetry initialized; run the examples for instrumented agents");
+    });
+
+    telemetry
+        .tracer_provider()
+        .shutdown()
+        .context("Failed to shut down tracer provider")?;
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Full-fidelity artifacts stored outside the span pipeline.
+//!
+//! Spans should stay small, but debugging an agent often needs the complete
+//! prompt, plan or tool output. An `ArtifactSink` writes those as files keyed
+//! by trace and span ID, and the span only carries an `artifact.uri`
+//! reference plus one `artifact.stored` event per file.
+//!
+//! `LocalDirSink` covers local development; object stores such as S3 plug in
+//! by implementing `ArtifactSink` over their own client.
+
+use anyhow::Context;
+use opentelemetry::KeyValue;
+use opentelemetry::trace::TraceContextExt;
+use std::path::PathBuf;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub trait ArtifactSink: Send + Sync {
+    /// URI under which all artifacts of one span are grouped.
+    fn span_uri(&self, trace_id: &str, span_id: &str) -> String;
+
+    /// Stores one artifact and returns its URI.
+    fn store(
+        &self,
+        trace_id: &str,
+        span_id: &str,
+        name: &str,
+        content: &[u8],
+    ) -> anyhow::Result<String>;
+}
+
+/// Writes artifacts to `<root>/<trace_id>/<span_id>/<name>`.
+#[derive(Debug, Clone)]
+pub struct LocalDirSink {
+    root: PathBuf,
+}
+
+impl LocalDirSink {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn span_dir(&self, trace_id: &str, span_id: &str) -> PathBuf {
+        self.root.join(trace_id).join(span_id)
+    }
+}
+
+impl ArtifactSink for LocalDirSink {
+    fn span_uri(&self, trace_id: &str, span_id: &str) -> String {
+        format!("file://{}", self.span_dir(trace_id, span_id).display())
+    }
+
+    fn store(
+        &self,
+        trace_id: &str,
+        span_id: &str,
+        name: &str,
+        content: &[u8],
+    ) -> anyhow::Result<String> {
+        let dir = self.span_dir(trace_id, span_id);
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create artifact dir {}", dir.display()))?;
+        let path = dir.join(sanitize_name(name));
+        std::fs::write(&path, content)
+            .with_context(|| format!("Failed to write artifact {}", path.display()))?;
+        Ok(format!("file://{}", path.display()))
+    }
+}
+
+/// Stores `content` for `span` and links it; returns the artifact URI.
+///
+/// Failures are logged and swallowed: losing an artifact must never fail the
+/// LLM call it describes.
+pub fn record_artifact(
+    span: &tracing::Span,
+    sink: &dyn ArtifactSink,
+    name: &str,
+    content: impl AsRef<[u8]>,
+) -> Option<String> {
+    let context = span.context();
+    let span_context = context.span().span_context().clone();
+    if !span_context.is_valid() {
+        return None;
+    }
+    let trace_id = span_context.trace_id().to_string();
+    let span_id = span_context.span_id().to_string();
+    let content = content.as_ref();
+
+    match sink.store(&trace_id, &span_id, name, content) {
+        Ok(uri) => {
+            span.set_attribute("artifact.uri", sink.span_uri(&trace_id, &span_id));
+            span.add_event(
+                "artifact.stored",
+                vec![
+                    KeyValue::new("artifact.name", name.to_owned()),
+                    KeyValue::new("artifact.uri", uri.clone()),
+                    KeyValue::new("artifact.size", content.len() as i64),
+                ],
+            );
+            Some(uri)
+        }
+        Err(error) => {
+            tracing::warn!(error = %error, artifact.name = name, "Failed to store artifact");
+            None
+        }
+    }
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,633 @@
+//! Cost, wall-time and call budgets for agent workflows.
+//!
+//! An orchestration that quietly makes twice the calls it used to, or
+//! doubles its spend after a prompt change, still returns a correct answer;
+//! nothing fails until the invoice arrives. A [`WorkflowBudget`] declares
+//! what one run may use, and the run's verdict is recorded on the workflow
+//! span and returned, so CI can gate on it like on any other test:
+//!
+//! ```ignore
+//! let span = tracing::info_span!("agent_orchestrator");
+//! let run = WorkflowBudget::new()
+//!     .with_max_cost_usd(0.05)
+//!     .with_max_wall_time(Duration::from_secs(30))
+//!     .with_max_calls(8)
+//!     .track(&span);
+//! orchestrate().instrument(span).await?;
+//! let verdict = run.finish();
+//! assert!(verdict.passed(), "{verdict}");
+//! ```
+//!
+//! The workflow span gets `llm.budget.verdict` (`pass`, `fail` or
+//! `unmeasured`), the limits, the actual usage and the overage of each
+//! limit, and one `llm.budget.exceeded` event per exceeded limit (mirrored
+//! to logs by `processors::event_logs`). Calls and cost are summed over the
+//! spans below the workflow span that ended before [`BudgetedRun::finish`],
+//! counting each model call and its cost once however many wrapper spans
+//! repeat them (see `processors::calls`); cost needs
+//! `gen_ai.usage.cost_usd` on them (see `cost::record_cost`). The spans are
+//! counted by [`BudgetProcessor`], which `TelemetryBuilder` installs.
+//!
+//! Calls and cost cannot be counted when the workflow span is not exported:
+//! it was dropped by the sampler, the kill switch is off, or no
+//! [`BudgetProcessor`] is installed (a host app's own pipeline). A zero count
+//! would then pass any limit, so the cost and call limits are reported as
+//! unmeasured instead: the verdict does not pass, `llm.budget.verdict` is
+//! `unmeasured` and a warning names the reason.
+
+use crate::kill_switch;
+use crate::processors::calls::{CallCounter, Counted};
+use opentelemetry::trace::{Span as _, SpanId, TraceContextExt, TraceId};
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Traces tracked at once; runs started beyond this are not counted.
+const MAX_TRACKED_TRACES: usize = 10_000;
+
+/// Set once a [`BudgetProcessor`] is created.
+static PROCESSOR_INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Limits for one workflow run; unset limits always pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorkflowBudget {
+    pub max_cost_usd: Option<f64>,
+    pub max_wall_time: Option<Duration>,
+    pub max_calls: Option<u64>,
+}
+
+impl WorkflowBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_cost_usd(mut self, max_cost_usd: f64) -> Self {
+        self.max_cost_usd = Some(max_cost_usd);
+        self
+    }
+
+    pub fn with_max_wall_time(mut self, max_wall_time: Duration) -> Self {
+        self.max_wall_time = Some(max_wall_time);
+        self
+    }
+
+    /// Maximum LLM calls (tool calls are not counted).
+    pub fn with_max_calls(mut self, max_calls: u64) -> Self {
+        self.max_calls = Some(max_calls);
+        self
+    }
+
+    /// Starts measuring the run under `span` against this budget.
+    pub fn track(self, span: &tracing::Span) -> BudgetedRun {
+        let context = span.context();
+        let span_context = context.span().span_context().clone();
+        let mut unmeasured = if !kill_switch::is_enabled() {
+            Some("the kill switch is off")
+        } else if !span_context.is_valid() {
+            Some("the workflow span has no OpenTelemetry context")
+        } else if !span_context.is_sampled() {
+            Some("the workflow span is not sampled")
+        } else if !PROCESSOR_INSTALLED.load(Ordering::Relaxed) {
+            Some("no BudgetProcessor is installed")
+        } else {
+            None
+        };
+        let mut workflow = None;
+        if unmeasured.is_none() {
+            let (trace_id, span_id) = (span_context.trace_id(), span_context.span_id());
+            let mut traces = traces().lock().unwrap_or_else(PoisonError::into_inner);
+            if traces.len() < MAX_TRACKED_TRACES || traces.contains_key(&trace_id) {
+                *traces
+                    .entry(trace_id)
+                    .or_default()
+                    .workflows
+                    .entry(span_id)
+                    .or_default() += 1;
+                workflow = Some((trace_id, span_id));
+            } else {
+                unmeasured = Some("too many workflow runs are tracked");
+            }
+        }
+        if let Some(reason) = unmeasured {
+            if self.max_cost_usd.is_some() || self.max_calls.is_some() {
+                tracing::warn!(reason, "Workflow budget cannot count calls and cost");
+            }
+        }
+        BudgetedRun {
+            budget: self,
+            span: span.clone(),
+            workflow,
+            unmeasured,
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+}
+
+/// A budget limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetLimit {
+    Cost,
+    WallTime,
+    Calls,
+}
+
+impl BudgetLimit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetLimit::Cost => "cost",
+            BudgetLimit::WallTime => "wall_time",
+            BudgetLimit::Calls => "calls",
+        }
+    }
+}
+
+/// Usage of one run against its budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetVerdict {
+    pub budget: WorkflowBudget,
+    pub cost_usd: f64,
+    pub wall_time: Duration,
+    pub calls: u64,
+    /// Why calls and cost could not be counted, if they could not.
+    pub unmeasured: Option<&'static str>,
+}
+
+impl BudgetVerdict {
+    /// No limit exceeded and every set limit measured.
+    pub fn passed(&self) -> bool {
+        self.exceeded().is_empty() && self.unmeasured_limits().is_empty()
+    }
+
+    /// Set limits that could not be measured.
+    pub fn unmeasured_limits(&self) -> Vec<BudgetLimit> {
+        if self.unmeasured.is_none() {
+            return Vec::new();
+        }
+        [BudgetLimit::Cost, BudgetLimit::Calls]
+            .into_iter()
+            .filter(|limit| self.usage(*limit).1.is_some())
+            .collect()
+    }
+
+    /// Exceeded limits, in declaration order.
+    pub fn exceeded(&self) -> Vec<BudgetLimit> {
+        [BudgetLimit::Cost, BudgetLimit::WallTime, BudgetLimit::Calls]
+            .into_iter()
+            .filter(|limit| self.overage(*limit).is_some_and(|overage| overage > 0.0))
+            .collect()
+    }
+
+    /// Usage beyond `limit`, in USD, milliseconds or calls; 0 within it
+    /// and `None` when the limit is unset.
+    pub fn overage(&self, limit: BudgetLimit) -> Option<f64> {
+        let (actual, max) = self.usage(limit);
+        max.map(|max| (actual - max).max(0.0))
+    }
+
+    /// Actual usage and limit of `limit`, in the units of [`overage`](Self::overage).
+    fn usage(&self, limit: BudgetLimit) -> (f64, Option<f64>) {
+        match limit {
+            BudgetLimit::Cost => (self.cost_usd, self.budget.max_cost_usd),
+            BudgetLimit::WallTime => (
+                self.wall_time.as_secs_f64() * 1000.0,
+                self.budget
+                    .max_wall_time
+                    .map(|max| max.as_secs_f64() * 1000.0),
+            ),
+            BudgetLimit::Calls => (
+                self.calls as f64,
+                self.budget.max_calls.map(|max| max as f64),
+            ),
+        }
+    }
+
+    fn record(&self, span: &tracing::Span) {
+        let exceeded = self.exceeded();
+        let unmeasured = self.unmeasured_limits();
+        span.set_attribute(
+            "llm.budget.verdict",
+            if !exceeded.is_empty() {
+                "fail"
+            } else if !unmeasured.is_empty() {
+                "unmeasured"
+            } else {
+                "pass"
+            },
+        );
+        if let (Some(reason), false) = (self.unmeasured, unmeasured.is_empty()) {
+            span.set_attribute("llm.budget.unmeasured_reason", reason);
+        }
+        span.set_attribute("llm.budget.cost_usd", self.cost_usd);
+        span.set_attribute("llm.budget.wall_time_ms", self.wall_time.as_millis() as i64);
+        span.set_attribute("llm.budget.calls", self.calls as i64);
+        if let Some(max) = self.budget.max_cost_usd {
+            span.set_attribute("llm.budget.max_cost_usd", max);
+            span.set_attribute(
+                "llm.budget.cost_overage_usd",
+                self.overage(BudgetLimit::Cost).unwrap_or_default(),
+            );
+        }
+        if let Some(max) = self.budget.max_wall_time {
+            span.set_attribute("llm.budget.max_wall_time_ms", max.as_millis() as i64);
+            span.set_attribute(
+                "llm.budget.wall_time_overage_ms",
+                self.overage(BudgetLimit::WallTime).unwrap_or_default() as i64,
+            );
+        }
+        if let Some(max) = self.budget.max_calls {
+            span.set_attribute("llm.budget.max_calls", max as i64);
+            span.set_attribute(
+                "llm.budget.calls_overage",
+                self.overage(BudgetLimit::Calls).unwrap_or_default() as i64,
+            );
+        }
+        if exceeded.is_empty() {
+            return;
+        }
+        span.set_attribute(
+            "llm.budget.exceeded",
+            Value::Array(Array::String(
+                exceeded
+                    .iter()
+                    .map(|limit| StringValue::from(limit.as_str()))
+                    .collect(),
+            )),
+        );
+        for limit in exceeded {
+            let (actual, max) = self.usage(limit);
+            span.add_event(
+                "llm.budget.exceeded",
+                vec![
+                    KeyValue::new("llm.budget.limit", limit.as_str()),
+                    KeyValue::new("llm.budget.max", max.unwrap_or_default()),
+                    KeyValue::new("llm.budget.actual", actual),
+                    KeyValue::new(
+                        "llm.budget.overage",
+                        self.overage(limit).unwrap_or_default(),
+                    ),
+                ],
+            );
+        }
+    }
+}
+
+impl fmt::Display for BudgetVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unmeasured = !self.unmeasured_limits().is_empty();
+        write!(
+            f,
+            "budget {}: ${:.6}, {} ms, {} calls",
+            if !self.exceeded().is_empty() {
+                "failed"
+            } else if unmeasured {
+                "unmeasured"
+            } else {
+                "passed"
+            },
+            self.cost_usd,
+            self.wall_time.as_millis(),
+            self.calls
+        )?;
+        for limit in self.exceeded() {
+            let (actual, max) = self.usage(limit);
+            write!(
+                f,
+                "; {} {actual} over limit {}",
+                limit.as_str(),
+                max.unwrap_or_default()
+            )?;
+        }
+        if let (Some(reason), true) = (self.unmeasured, unmeasured) {
+            write!(f, "; calls and cost not counted: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A workflow run measured against its budget; see [`WorkflowBudget::track`].
+///
+/// Records the verdict on drop if [`finish`](Self::finish) was not called.
+#[derive(Debug)]
+pub struct BudgetedRun {
+    budget: WorkflowBudget,
+    span: tracing::Span,
+    workflow: Option<(TraceId, SpanId)>,
+    unmeasured: Option<&'static str>,
+    started: Instant,
+    finished: bool,
+}
+
+impl BudgetedRun {
+    /// Usage so far, from the spans that already ended.
+    pub fn verdict(&self) -> BudgetVerdict {
+        let (calls, cost_usd) = self
+            .workflow
+            .and_then(|(trace_id, span_id)| {
+                let traces = traces().lock().unwrap_or_else(PoisonError::into_inner);
+                traces
+                    .get(&trace_id)
+                    .map(|trace| trace.usage_below(span_id))
+            })
+            .unwrap_or_default();
+        BudgetVerdict {
+            budget: self.budget,
+            cost_usd,
+            wall_time: self.started.elapsed(),
+            calls,
+            // Spans ending while the switch is off are not counted either.
+            unmeasured: self
+                .unmeasured
+                .or_else(|| (!kill_switch::is_enabled()).then_some("the kill switch is off")),
+        }
+    }
+
+    /// Ends the run: records the verdict on the workflow span and returns it.
+    pub fn finish(mut self) -> BudgetVerdict {
+        self.complete()
+    }
+
+    fn complete(&mut self) -> BudgetVerdict {
+        self.finished = true;
+        let verdict = self.verdict();
+        verdict.record(&self.span);
+        if let Some((trace_id, span_id)) = self.workflow {
+            let mut traces = traces().lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(trace) = traces.get_mut(&trace_id) {
+                if let Some(count) = trace.workflows.get_mut(&span_id) {
+                    *count -= 1;
+                    if *count == 0 {
+                        trace.workflows.remove(&span_id);
+                    }
+                }
+                if trace.workflows.is_empty() {
+                    traces.remove(&trace_id);
+                }
+            }
+        }
+        verdict
+    }
+}
+
+impl Drop for BudgetedRun {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.complete();
+        }
+    }
+}
+
+/// Ended spans of a trace with a tracked workflow.
+#[derive(Debug, Default)]
+struct TrackedTrace {
+    /// Tracked workflow spans, with the number of runs tracking each.
+    workflows: HashMap<SpanId, usize>,
+    parents: HashMap<SpanId, SpanId>,
+    counter: CallCounter,
+    /// LLM calls and the cost counted on each span.
+    calls: Vec<(SpanId, Counted)>,
+}
+
+impl TrackedTrace {
+    fn add(&mut self, span: &SpanData) {
+        let span_id = span.span_context.span_id();
+        self.parents.insert(span_id, span.parent_span_id);
+        let counted = self.counter.on_end(span);
+        if counted.llm_call || counted.cost_usd != 0.0 {
+            self.calls.push((span_id, counted));
+        }
+    }
+
+    fn is_below(&self, span_id: SpanId, workflow: SpanId) -> bool {
+        let mut current = span_id;
+        // Bounded, in case of a cycle from a misbehaving id generator.
+        for _ in 0..=self.parents.len() {
+            match self.parents.get(&current) {
+                Some(parent) if *parent == workflow => return true,
+                Some(parent) => current = *parent,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    fn usage_below(&self, workflow: SpanId) -> (u64, f64) {
+        self.calls
+            .iter()
+            .filter(|(span_id, _)| self.is_below(*span_id, workflow))
+            .fold((0, 0.0), |(calls, cost), (_, counted)| {
+                (calls + u64::from(counted.llm_call), cost + counted.cost_usd)
+            })
+    }
+}
+
+fn traces() -> &'static Mutex<HashMap<TraceId, TrackedTrace>> {
+    static TRACES: OnceLock<Mutex<HashMap<TraceId, TrackedTrace>>> = OnceLock::new();
+    TRACES.get_or_init(Mutex::default)
+}
+
+/// Counts the ended spans of traces with a tracked workflow.
+#[derive(Debug)]
+pub struct BudgetProcessor<P> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> BudgetProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        PROCESSOR_INSTALLED.store(true, Ordering::Relaxed);
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for BudgetProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        {
+            let mut traces = traces().lock().unwrap_or_else(PoisonError::into_inner);
+            let span_context = span.span_context();
+            if let Some(trace) = traces.get_mut(&span_context.trace_id()) {
+                trace.counter.on_start(span_context);
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        {
+            let mut traces = traces().lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(trace) = traces.get_mut(&span.span_context.trace_id()) {
+                trace.add(&span);
+            }
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, agent_prompt, model_call};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Runs `body` under a tracked `agent_orchestrator` span and finishes
+    /// the run.
+    fn run_workflow(
+        budget: WorkflowBudget,
+        body: impl FnOnce(&SdkTracer, &Context),
+    ) -> BudgetVerdict {
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(BudgetProcessor::new(Collect::default()))
+            .build();
+        let tracer = provider.tracer("test");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("agent_orchestrator");
+            let run = budget.track(&span);
+            body(&tracer, &span.context());
+            run.finish()
+        })
+    }
+
+    fn verdict(
+        budget: WorkflowBudget,
+        cost_usd: f64,
+        wall_time_ms: u64,
+        calls: u64,
+    ) -> BudgetVerdict {
+        BudgetVerdict {
+            budget,
+            cost_usd,
+            wall_time: Duration::from_millis(wall_time_ms),
+            calls,
+            unmeasured: None,
+        }
+    }
+
+    #[test]
+    fn unset_limits_always_pass() {
+        let verdict = verdict(WorkflowBudget::new(), 10.0, 60_000, 100);
+        assert!(verdict.passed());
+        assert_eq!(verdict.overage(BudgetLimit::Cost), None);
+        assert_eq!(verdict.overage(BudgetLimit::WallTime), None);
+        assert_eq!(verdict.overage(BudgetLimit::Calls), None);
+    }
+
+    #[test]
+    fn usage_at_the_limit_passes() {
+        let budget = WorkflowBudget::new()
+            .with_max_cost_usd(0.05)
+            .with_max_wall_time(Duration::from_secs(1))
+            .with_max_calls(3);
+        let verdict = verdict(budget, 0.05, 1_000, 3);
+        assert!(verdict.passed());
+        assert_eq!(verdict.overage(BudgetLimit::Calls), Some(0.0));
+    }
+
+    #[test]
+    fn exceeded_limits_fail_with_their_overage_in_order() {
+        let budget = WorkflowBudget::new()
+            .with_max_cost_usd(0.05)
+            .with_max_wall_time(Duration::from_secs(1))
+            .with_max_calls(3);
+        let verdict = verdict(budget, 0.02, 1_500, 5);
+        assert!(!verdict.passed());
+        assert_eq!(
+            verdict.exceeded(),
+            vec![BudgetLimit::WallTime, BudgetLimit::Calls]
+        );
+        assert_eq!(verdict.overage(BudgetLimit::Cost), Some(0.0));
+        assert_eq!(verdict.overage(BudgetLimit::WallTime), Some(500.0));
+        assert_eq!(verdict.overage(BudgetLimit::Calls), Some(2.0));
+        assert!(verdict.to_string().starts_with("budget failed"));
+    }
+
+    #[test]
+    fn unmeasured_calls_and_cost_do_not_pass() {
+        let budget = WorkflowBudget::new()
+            .with_max_cost_usd(0.05)
+            .with_max_wall_time(Duration::from_secs(1))
+            .with_max_calls(3);
+        let verdict = BudgetVerdict {
+            unmeasured: Some("the workflow span is not sampled"),
+            ..verdict(budget, 0.0, 10, 0)
+        };
+        assert!(!verdict.passed());
+        assert!(verdict.exceeded().is_empty());
+        assert_eq!(
+            verdict.unmeasured_limits(),
+            vec![BudgetLimit::Cost, BudgetLimit::Calls]
+        );
+        assert!(verdict.to_string().contains("not sampled"));
+    }
+
+    #[test]
+    fn a_wall_time_budget_is_measured_without_spans() {
+        let budget = WorkflowBudget::new().with_max_wall_time(Duration::from_secs(1));
+        let verdict = BudgetVerdict {
+            unmeasured: Some("the kill switch is off"),
+            ..verdict(budget, 0.0, 10, 0)
+        };
+        assert!(verdict.passed());
+    }
+
+    #[test]
+    fn counts_the_model_calls_of_agent_prompts_once() {
+        let budget = WorkflowBudget::new()
+            .with_max_cost_usd(0.01)
+            .with_max_calls(4);
+        let verdict = run_workflow(budget, |tracer, workflow| {
+            agent_prompt(tracer, workflow);
+            agent_prompt(tracer, workflow);
+        });
+
+        assert_eq!(verdict.unmeasured, None);
+        assert_eq!(verdict.calls, 4);
+        assert!((verdict.cost_usd - 0.006).abs() < 1e-9);
+        assert!(verdict.passed(), "{verdict}");
+    }
+
+    #[test]
+    fn trips_the_call_limit_at_the_configured_count() {
+        let budget = WorkflowBudget::new().with_max_calls(3);
+        let verdict = run_workflow(budget, |tracer, workflow| {
+            agent_prompt(tracer, workflow);
+            agent_prompt(tracer, workflow);
+        });
+
+        assert_eq!(verdict.exceeded(), vec![BudgetLimit::Calls]);
+        assert_eq!(verdict.overage(BudgetLimit::Calls), Some(1.0));
+    }
+
+    #[test]
+    fn ignores_calls_outside_the_workflow() {
+        let budget = WorkflowBudget::new().with_max_calls(1);
+        let verdict = run_workflow(budget, |tracer, workflow| {
+            model_call(tracer, workflow);
+            model_call(tracer, &Context::new());
+        });
+
+        assert_eq!(verdict.calls, 1);
+        assert!(verdict.passed(), "{verdict}");
+    }
+}
@@ -0,0 +1,412 @@
+//! Portable per-conversation export bundles.
+//!
+//! `ConversationCollector` is a span processor that keeps recently finished
+//! traces in memory, indexed by the conversation they belong to. A bundle with
+//! every span, span event (log line) and recorded metric datapoint for one
+//! conversation can then be written to JSON and attached to a bug report.
+//! Metric datapoints are dropped with the last trace of their conversation,
+//! and kept for at most `max_traces` conversations.
+
+use crate::semconv;
+use anyhow::Context as _;
+use opentelemetry::trace::{SpanKind, Status, TraceId};
+use opentelemetry::{Array, Context, KeyValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use serde::Serialize;
+use serde_json::{Map, json};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_MAX_TRACES: usize = 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledEvent {
+    pub name: String,
+    pub time_unix_nano: u128,
+    pub attributes: Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledSpan {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: String,
+    pub start_time_unix_nano: u128,
+    pub end_time_unix_nano: u128,
+    pub status: String,
+    pub attributes: Map<String, serde_json::Value>,
+    pub events: Vec<BundledEvent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledDatapoint {
+    pub name: String,
+    pub value: f64,
+    pub time_unix_nano: u128,
+    pub attributes: Map<String, serde_json::Value>,
+}
+
+/// Everything known about one conversation, ready to serialize.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationBundle {
+    pub conversation_id: String,
+    pub exported_at_unix_nano: u128,
+    pub trace_ids: Vec<String>,
+    pub spans: Vec<BundledSpan>,
+    /// The events of `spans`, in time order; `tracing` events recorded in
+    /// a span end up there. Log records exported separately are not
+    /// collected.
+    pub logs: Vec<BundledEvent>,
+    pub metrics: Vec<BundledDatapoint>,
+}
+
+impl ConversationBundle {
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize conversation bundle")
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_json()?)
+            .with_context(|| format!("Failed to write bundle to {}", path.display()))
+    }
+}
+
+#[derive(Default)]
+struct CollectorState {
+    spans_by_trace: HashMap<TraceId, Vec<BundledSpan>>,
+    trace_order: VecDeque<TraceId>,
+    traces_by_conversation: HashMap<String, HashSet<TraceId>>,
+    metrics_by_conversation: HashMap<String, Vec<BundledDatapoint>>,
+    /// Conversations with metrics, in the order their first datapoint came.
+    metric_order: VecDeque<String>,
+}
+
+impl CollectorState {
+    /// Drops the metrics of `conversation_id`.
+    fn forget_metrics(&mut self, conversation_id: &str) {
+        if self
+            .metrics_by_conversation
+            .remove(conversation_id)
+            .is_some()
+        {
+            self.metric_order.retain(|known| known != conversation_id);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConversationCollector {
+    state: Arc<Mutex<CollectorState>>,
+    max_traces: usize,
+}
+
+impl std::fmt::Debug for CollectorState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CollectorState")
+            .field("traces", &self.trace_order.len())
+            .field("conversations", &self.traces_by_conversation.len())
+            .finish()
+    }
+}
+
+impl Default for ConversationCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConversationCollector {
+    pub fn new() -> Self {
+        Self::with_max_traces(DEFAULT_MAX_TRACES)
+    }
+
+    /// Bounds memory: the oldest traces are evicted beyond `max_traces`.
+    pub fn with_max_traces(max_traces: usize) -> Self {
+        Self {
+            state: Arc::default(),
+            max_traces: max_traces.max(1),
+        }
+    }
+
+    /// Records a metric datapoint that should travel with the conversation.
+    /// Beyond `max_traces` conversations with metrics, the metrics of the
+    /// conversation whose first datapoint is oldest are dropped.
+    pub fn record_metric(
+        &self,
+        conversation_id: &str,
+        name: &str,
+        value: f64,
+        attributes: &[KeyValue],
+    ) {
+        let mut state = self.lock();
+        if !state.metrics_by_conversation.contains_key(conversation_id) {
+            state.metric_order.push_back(conversation_id.to_owned());
+            while state.metric_order.len() > self.max_traces {
+                if let Some(evicted) = state.metric_order.pop_front() {
+                    state.metrics_by_conversation.remove(&evicted);
+                }
+            }
+        }
+        state
+            .metrics_by_conversation
+            .entry(conversation_id.to_owned())
+            .or_default()
+            .push(BundledDatapoint {
+                name: name.to_owned(),
+                value,
+                time_unix_nano: unix_nanos(SystemTime::now()),
+                attributes: attributes_to_json(attributes),
+            });
+    }
+
+    pub fn export(&self, conversation_id: &str) -> ConversationBundle {
+        let state = self.lock();
+        let mut trace_ids: Vec<TraceId> = state
+            .traces_by_conversation
+            .get(conversation_id)
+            .map(|traces| traces.iter().copied().collect())
+            .unwrap_or_default();
+        trace_ids.sort_by_key(|trace_id| {
+            state
+                .trace_order
+                .iter()
+                .position(|known| known == trace_id)
+                .unwrap_or(usize::MAX)
+        });
+
+        let mut spans: Vec<BundledSpan> = trace_ids
+            .iter()
+            .filter_map(|trace_id| state.spans_by_trace.get(trace_id))
+            .flatten()
+            .cloned()
+            .collect();
+        spans.sort_by_key(|span| span.start_time_unix_nano);
+
+        let mut logs: Vec<BundledEvent> = spans
+            .iter()
+            .flat_map(|span| span.events.iter().cloned())
+            .collect();
+        logs.sort_by_key(|event| event.time_unix_nano);
+
+        ConversationBundle {
+            conversation_id: conversation_id.to_owned(),
+            exported_at_unix_nano: unix_nanos(SystemTime::now()),
+            trace_ids: trace_ids.iter().map(ToString::to_string).collect(),
+            spans,
+            logs,
+            metrics: state
+                .metrics_by_conversation
+                .get(conversation_id)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CollectorState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl SpanProcessor for ConversationCollector {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        let trace_id = span.span_context.trace_id();
+        let conversation_id = span
+            .attributes
+            .iter()
+            .find(|kv| {
+                kv.key.as_str() == semconv::GEN_AI_CONVERSATION_ID
+                    || kv.key.as_str() == semconv::SESSION_ID
+            })
+            .map(|kv| kv.value.as_str().into_owned());
+        let bundled = bundle_span(&span);
+
+        let mut state = self.lock();
+        if !state.spans_by_trace.contains_key(&trace_id) {
+            state.trace_order.push_back(trace_id);
+            while state.trace_order.len() > self.max_traces {
+                if let Some(evicted) = state.trace_order.pop_front() {
+                    state.spans_by_trace.remove(&evicted);
+                    let mut emptied = Vec::new();
+                    state
+                        .traces_by_conversation
+                        .retain(|conversation_id, traces| {
+                            traces.remove(&evicted);
+                            if traces.is_empty() {
+                                emptied.push(conversation_id.clone());
+                            }
+                            !traces.is_empty()
+                        });
+                    for conversation_id in emptied {
+                        state.forget_metrics(&conversation_id);
+                    }
+                }
+            }
+        }
+        state
+            .spans_by_trace
+            .entry(trace_id)
+            .or_default()
+            .push(bundled);
+        if let Some(conversation_id) = conversation_id {
+            state
+                .traces_by_conversation
+                .entry(conversation_id)
+                .or_default()
+                .insert(trace_id);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+fn bundle_span(span: &SpanData) -> BundledSpan {
+    BundledSpan {
+        trace_id: span.span_context.trace_id().to_string(),
+        span_id: span.span_context.span_id().to_string(),
+        parent_span_id: (span.parent_span_id != opentelemetry::trace::SpanId::INVALID)
+            .then(|| span.parent_span_id.to_string()),
+        name: span.name.to_string(),
+        kind: span_kind_name(&span.span_kind).to_owned(),
+        start_time_unix_nano: unix_nanos(span.start_time),
+        end_time_unix_nano: unix_nanos(span.end_time),
+        status: match &span.status {
+            Status::Unset => "unset".to_owned(),
+            Status::Ok => "ok".to_owned(),
+            Status::Error { description } => format!("error: {description}"),
+        },
+        attributes: attributes_to_json(&span.attributes),
+        events: span
+            .events
+            .iter()
+            .map(|event| BundledEvent {
+                name: event.name.to_string(),
+                time_unix_nano: unix_nanos(event.timestamp),
+                attributes: attributes_to_json(&event.attributes),
+            })
+            .collect(),
+    }
+}
+
+pub(crate) fn span_kind_name(kind: &SpanKind) -> &'static str {
+    match kind {
+        SpanKind::Client => "client",
+        SpanKind::Server => "server",
+        SpanKind::Producer => "producer",
+        SpanKind::Consumer => "consumer",
+        SpanKind::Internal => "internal",
+    }
+}
+
+pub(crate) fn attributes_to_json(attributes: &[KeyValue]) -> Map<String, serde_json::Value> {
+    attributes
+        .iter()
+        .map(|kv| (kv.key.to_string(), value_to_json(&kv.value)))
+        .collect()
+}
+
+pub(crate) fn value_to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Bool(value) => json!(value),
+        Value::I64(value) => json!(value),
+        Value::F64(value) => json!(value),
+        Value::String(value) => json!(value.as_str()),
+        Value::Array(Array::Bool(values)) => json!(values),
+        Value::Array(Array::I64(values)) => json!(values),
+        Value::Array(Array::F64(values)) => json!(values),
+        Value::Array(Array::String(values)) => {
+            json!(
+                values
+                    .iter()
+                    .map(|value| value.as_str())
+                    .collect::<Vec<_>>()
+            )
+        }
+        other => json!(other.as_str()),
+    }
+}
+
+pub(crate) fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Span as _, Tracer, TracerProvider as _};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn end_conversation_span(provider: &SdkTracerProvider, conversation_id: &'static str) {
+        let tracer = provider.tracer("test");
+        tracer
+            .span_builder("chat")
+            .with_attributes([KeyValue::new(
+                semconv::GEN_AI_CONVERSATION_ID,
+                conversation_id,
+            )])
+            .start(&tracer)
+            .end();
+    }
+
+    #[test]
+    fn metrics_are_dropped_with_the_last_trace_of_their_conversation() {
+        let collector = ConversationCollector::with_max_traces(1);
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(collector.clone())
+            .build();
+        end_conversation_span(&provider, "first");
+        collector.record_metric("first", "llm.latency", 1.0, &[]);
+
+        end_conversation_span(&provider, "second");
+
+        let bundle = collector.export("first");
+        assert!(bundle.spans.is_empty());
+        assert!(bundle.metrics.is_empty());
+    }
+
+    #[test]
+    fn metrics_are_kept_for_at_most_max_traces_conversations() {
+        let collector = ConversationCollector::with_max_traces(2);
+        for conversation_id in ["first", "second", "third"] {
+            collector.record_metric(conversation_id, "llm.latency", 1.0, &[]);
+        }
+
+        assert!(collector.export("first").metrics.is_empty());
+        assert_eq!(collector.export("second").metrics.len(), 1);
+        assert_eq!(collector.export("third").metrics.len(), 1);
+    }
+
+    #[test]
+    fn logs_are_the_span_events() {
+        let collector = ConversationCollector::new();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(collector.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        let mut span = tracer
+            .span_builder("chat")
+            .with_attributes([KeyValue::new(semconv::SESSION_ID, "session")])
+            .start(&tracer);
+        span.add_event("retry", Vec::new());
+        span.end();
+
+        let bundle = collector.export("session");
+        assert_eq!(bundle.logs.len(), 1);
+        assert_eq!(bundle.logs[0].name, "retry");
+    }
+}
@@ -0,0 +1,109 @@
+//! Per-attribute cardinality guards for metric labels.
+//!
+//! Every distinct label value is a new time series. Model names are
+//! usually a handful, until a router passes user input through as the model
+//! or a fine-tune job mints a model per customer; one such attribute can
+//! multiply a backend's series count by thousands. The SDK's stream limit
+//! (`MetricView::with_cardinality_limit`) protects memory by folding
+//! whole attribute sets into `otel.metric.overflow`, which loses even the
+//! well-behaved labels. [`guarded`] instead limits one key at a time: the
+//! first `max` values of a limited key are kept as they are, later ones are
+//! recorded as [`OVERFLOW_VALUE`] and counted in
+//! `llm.metrics.cardinality.overflow`, by key.
+//!
+//! The built-in instruments guard `gen_ai.request.model` and
+//! `gen_ai.response.model` (see [`DEFAULT_LIMITS`]); raise, add or remove
+//! limits with [`set_limit`] and [`remove_limit`] before the first
+//! recording, and use [`guarded`] for labels of your own, such as
+//! `tenant.id`.
+
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// Value recorded in place of values past a key's limit.
+pub const OVERFLOW_VALUE: &str = "_OTHER";
+
+/// Limits in place until changed with [`set_limit`].
+pub const DEFAULT_LIMITS: [(&str, usize); 2] = [
+    ("gen_ai.request.model", 100),
+    ("gen_ai.response.model", 100),
+];
+
+#[derive(Debug)]
+struct KeyLimit {
+    max: usize,
+    seen: HashSet<String>,
+}
+
+fn limits() -> &'static Mutex<HashMap<String, KeyLimit>> {
+    static LIMITS: OnceLock<Mutex<HashMap<String, KeyLimit>>> = OnceLock::new();
+    LIMITS.get_or_init(|| {
+        Mutex::new(
+            DEFAULT_LIMITS
+                .iter()
+                .map(|&(key, max)| {
+                    let limit = KeyLimit {
+                        max,
+                        seen: HashSet::new(),
+                    };
+                    (key.to_owned(), limit)
+                })
+                .collect(),
+        )
+    })
+}
+
+/// Keeps at most `max` distinct values of `key`. Values already admitted
+/// stay admitted when the limit is lowered.
+pub fn set_limit(key: impl Into<String>, max: usize) {
+    limits()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .entry(key.into())
+        .and_modify(|limit| limit.max = max)
+        .or_insert_with(|| KeyLimit {
+            max,
+            seen: HashSet::new(),
+        });
+}
+
+/// Stops limiting `key`.
+pub fn remove_limit(key: &str) {
+    limits()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(key);
+}
+
+/// `key = value` for a metric label, with `value` replaced by
+/// [`OVERFLOW_VALUE`] once `key` has reached its limit.
+pub fn guarded(key: &'static str, value: &str) -> KeyValue {
+    let admitted = {
+        let mut limits = limits().lock().unwrap_or_else(PoisonError::into_inner);
+        match limits.get_mut(key) {
+            None => true,
+            Some(limit) if limit.seen.contains(value) => true,
+            Some(limit) if limit.seen.len() < limit.max => limit.seen.insert(value.to_owned()),
+            Some(_) => false,
+        }
+    };
+    if admitted {
+        return KeyValue::new(key, value.to_owned());
+    }
+    overflow_counter().add(1, &[KeyValue::new("llm.metrics.attribute", key)]);
+    KeyValue::new(key, OVERFLOW_VALUE)
+}
+
+fn overflow_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.metrics.cardinality.overflow")
+            .with_description("Metric label values replaced because their key hit its limit")
+            .build()
+    })
+}
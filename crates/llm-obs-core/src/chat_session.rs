@@ -0,0 +1,196 @@
+//! Conversations as a session of linked turns.
+//!
+//! A chat spans many requests over minutes or days. One span covering all
+//! of it would only be exported when the conversation ends, if ever, so
+//! [`ChatSession`] opens a short `chat_session` root span when the
+//! conversation starts and gives it a stable `gen_ai.conversation.id`. Each
+//! turn then runs in a `chat_turn` span, nested wherever the request's own
+//! spans are, which carries the conversation id, the turn index and the
+//! conversation's token totals so far, and links to the session span and
+//! the previous turn. Backends that follow links can walk the conversation
+//! from any turn; the others group by `gen_ai.conversation.id`.
+//!
+//! ```ignore
+//! let session = ChatSession::new();
+//! for question in questions {
+//!     let turn = session.turn();
+//!     let answer = ask(question).instrument(turn.span().clone()).await?;
+//!     turn.record_usage(&answer.usage);
+//! }
+//! ```
+//!
+//! A session is shared by reference (wrap it in an `Arc` to hand it to
+//! request handlers); turns may overlap, and each takes the next index when
+//! it starts.
+
+use crate::spans::{start_chat_session_span, start_chat_turn_span};
+use crate::tokens::TokenUsage;
+use opentelemetry::trace::{SpanContext, Status, TraceContextExt};
+use opentelemetry_sdk::trace::{IdGenerator, RandomIdGenerator};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug)]
+pub struct ChatSession {
+    conversation_id: String,
+    session_span: SpanContext,
+    next_turn: AtomicU64,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    totals: TokenUsage,
+    last_turn: Option<SpanContext>,
+}
+
+impl ChatSession {
+    /// Starts a conversation with a new random id.
+    pub fn new() -> Self {
+        let conversation_id = format!("{:032x}", RandomIdGenerator::default().new_trace_id());
+        Self::start(conversation_id, false)
+    }
+
+    /// Continues a conversation whose id was stored earlier, e.g. with the
+    /// chat history. Turn indexes and token totals restart at zero unless
+    /// set with [`with_turns`](Self::with_turns) and
+    /// [`with_totals`](Self::with_totals).
+    pub fn resume(conversation_id: impl Into<String>) -> Self {
+        Self::start(conversation_id.into(), true)
+    }
+
+    fn start(conversation_id: String, resumed: bool) -> Self {
+        let span = start_chat_session_span(&conversation_id);
+        if resumed {
+            span.record("llm.session.resumed", true);
+        }
+        let session_span = span.context().span().span_context().clone();
+        Self {
+            conversation_id,
+            session_span,
+            next_turn: AtomicU64::new(0),
+            state: Mutex::new(SessionState::default()),
+        }
+    }
+
+    /// Number of turns already taken in a resumed conversation.
+    pub fn with_turns(self, turns: u64) -> Self {
+        self.next_turn.store(turns, Ordering::Relaxed);
+        self
+    }
+
+    /// Tokens already used in a resumed conversation.
+    pub fn with_totals(self, totals: TokenUsage) -> Self {
+        self.lock().totals = totals;
+        self
+    }
+
+    pub fn conversation_id(&self) -> &str {
+        &self.conversation_id
+    }
+
+    /// Context of the `chat_session` span, for linking other spans (a
+    /// summary, an evaluation) to the conversation.
+    pub fn span_context(&self) -> &SpanContext {
+        &self.session_span
+    }
+
+    /// Turns started so far, including those of a resumed conversation.
+    pub fn turns(&self) -> u64 {
+        self.next_turn.load(Ordering::Relaxed)
+    }
+
+    /// Tokens used by the turns that recorded usage.
+    pub fn totals(&self) -> TokenUsage {
+        self.lock().totals
+    }
+
+    /// Starts the next turn under the current span.
+    pub fn turn(&self) -> ChatTurn<'_> {
+        let index = self.next_turn.fetch_add(1, Ordering::Relaxed);
+        let span = start_chat_turn_span(&self.conversation_id, index);
+        if self.session_span.is_valid() {
+            span.add_link(self.session_span.clone());
+        }
+        let context = span.context().span().span_context().clone();
+        let previous = self.lock().last_turn.replace(context);
+        if let Some(previous) = previous.filter(SpanContext::is_valid) {
+            span.add_link(previous);
+        }
+        ChatTurn {
+            session: self,
+            span,
+            index,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for ChatSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One turn of a [`ChatSession`]; the `chat_turn` span ends when the turn
+/// and every clone of its span are dropped.
+pub struct ChatTurn<'a> {
+    session: &'a ChatSession,
+    span: tracing::Span,
+    index: u64,
+}
+
+impl fmt::Debug for ChatTurn<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChatTurn")
+            .field("conversation_id", &self.session.conversation_id)
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl ChatTurn<'_> {
+    /// The turn's span; run the turn's work in it with `in_scope` or
+    /// `Instrument::instrument`.
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Zero-based position of the turn in the conversation.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Adds `usage` to the conversation's totals and records the turn's and
+    /// the cumulative counts on the turn span.
+    pub fn record_usage(&self, usage: &TokenUsage) {
+        let totals = {
+            let mut state = self.session.lock();
+            state.totals.input_tokens += usage.input_tokens;
+            state.totals.output_tokens += usage.output_tokens;
+            state.totals.reasoning_tokens += usage.reasoning_tokens;
+            state.totals
+        };
+        self.span
+            .record("gen_ai.usage.input_tokens", usage.input_tokens);
+        self.span
+            .record("gen_ai.usage.output_tokens", usage.output_tokens);
+        self.span
+            .record("llm.session.cumulative.input_tokens", totals.input_tokens);
+        self.span
+            .record("llm.session.cumulative.output_tokens", totals.output_tokens);
+        self.span
+            .record("llm.session.cumulative.total_tokens", totals.total_tokens());
+    }
+
+    /// Marks the turn failed with `error_type` (e.g. `prompt_error`).
+    pub fn record_error(&self, error_type: &str, description: impl Into<String>) {
+        self.span.record("error.type", error_type);
+        self.span.set_status(Status::error(description.into()));
+    }
+}
@@ -0,0 +1,148 @@
+//! Startup check for local clock skew.
+//!
+//! Span timestamps come from the emitting host's wall clock. A host that is
+//! a few seconds off makes children start before their parents and turns
+//! cross-service durations negative, and backends do not flag it. Measuring
+//! the offset once at init (over SNTP, or from a remote timestamp the app
+//! already has, such as a provider `Date` header) and recording it as the
+//! `host.clock_skew_ms` resource attribute makes such traces explainable.
+
+use anyhow::{Context, bail};
+use opentelemetry::KeyValue;
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970).
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Skew beyond which the check logs a warning.
+pub const DEFAULT_WARN_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// Offset of the local clock relative to a reference clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockSkew {
+    /// Positive when the local clock is ahead of the reference.
+    pub offset_ms: f64,
+    /// Round trip of the measurement; bounds its accuracy.
+    pub round_trip_ms: f64,
+    pub source: &'static str,
+}
+
+impl ClockSkew {
+    /// Skew from a timestamp reported by a remote service, assuming it was
+    /// taken halfway through the `round_trip`.
+    pub fn from_remote_time(
+        remote: SystemTime,
+        received_at: SystemTime,
+        round_trip: Duration,
+    ) -> Self {
+        let local_at_remote = unix_ms(received_at) - round_trip.as_secs_f64() * 500.0;
+        Self {
+            offset_ms: local_at_remote - unix_ms(remote),
+            round_trip_ms: round_trip.as_secs_f64() * 1000.0,
+            source: "remote_timestamp",
+        }
+    }
+
+    pub fn exceeds(&self, threshold: Duration) -> bool {
+        self.offset_ms.abs() > threshold.as_secs_f64() * 1000.0
+    }
+
+    /// Attributes to add to the telemetry `Resource`.
+    pub fn resource_attributes(&self) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new("host.clock_skew_ms", self.offset_ms.round() as i64),
+            KeyValue::new(
+                "host.clock_skew.round_trip_ms",
+                self.round_trip_ms.round() as i64,
+            ),
+            KeyValue::new("host.clock_skew.source", self.source),
+        ]
+    }
+
+    /// Logs a warning when the skew is large enough to distort traces.
+    pub fn warn_if_exceeds(&self, threshold: Duration) {
+        if self.exceeds(threshold) {
+            tracing::warn!(
+                host.clock_skew_ms = self.offset_ms,
+                "Local clock is skewed; span ordering and durations may be wrong"
+            );
+        }
+    }
+}
+
+/// Measures skew against an SNTP server such as `pool.ntp.org:123`.
+///
+/// Blocking: intended for the one-off check during telemetry init.
+pub fn measure_sntp(server: &str, timeout: Duration) -> anyhow::Result<ClockSkew> {
+    let socket = UdpSocket::bind("0.0.0.0:0").context("Failed to bind SNTP socket")?;
+    socket
+        .set_read_timeout(Some(timeout))
+        .context("Failed to set SNTP timeout")?;
+    socket
+        .connect(server)
+        .with_context(|| format!("Failed to resolve SNTP server {server}"))?;
+
+    // LI = 0, version = 4, mode = 3 (client).
+    let mut request = [0u8; 48];
+    request[0] = 0b00_100_011;
+    let sent_at = SystemTime::now();
+    request[40..48].copy_from_slice(&to_ntp_timestamp(sent_at).to_be_bytes());
+    socket
+        .send(&request)
+        .context("Failed to send SNTP request")?;
+
+    let mut response = [0u8; 48];
+    let received = socket
+        .recv(&mut response)
+        .context("No SNTP response before timeout")?;
+    let received_at = SystemTime::now();
+    if received < 48 {
+        bail!("Short SNTP response ({received} bytes)");
+    }
+
+    let server_received = from_ntp_timestamp(read_u64(&response[32..40]));
+    let server_sent = from_ntp_timestamp(read_u64(&response[40..48]));
+    if server_sent == 0.0 {
+        bail!("SNTP server returned an unset transmit timestamp");
+    }
+
+    // Standard NTP offset/delay with t1..t4 in Unix milliseconds.
+    let (t1, t4) = (unix_ms(sent_at), unix_ms(received_at));
+    let server_offset = ((server_received - t1) + (server_sent - t4)) / 2.0;
+    Ok(ClockSkew {
+        offset_ms: -server_offset,
+        round_trip_ms: (t4 - t1) - (server_sent - server_received),
+        source: "sntp",
+    })
+}
+
+fn unix_ms(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs_f64() * 1000.0,
+        Err(before) => -before.duration().as_secs_f64() * 1000.0,
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut buffer = [0u8; 8];
+    buffer.copy_from_slice(bytes);
+    u64::from_be_bytes(buffer)
+}
+
+fn to_ntp_timestamp(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since.as_secs() + NTP_UNIX_OFFSET_SECS;
+    let fraction = (u64::from(since.subsec_nanos()) << 32) / 1_000_000_000;
+    (seconds << 32) | fraction
+}
+
+/// NTP timestamp to Unix milliseconds; `0.0` for an unset timestamp.
+fn from_ntp_timestamp(timestamp: u64) -> f64 {
+    if timestamp == 0 {
+        return 0.0;
+    }
+    let seconds = (timestamp >> 32) as f64 - NTP_UNIX_OFFSET_SECS as f64;
+    let fraction = (timestamp & 0xFFFF_FFFF) as f64 / 4_294_967_296.0;
+    (seconds + fraction) * 1000.0
+}
@@ -0,0 +1,250 @@
+//! Cold-start phases of the first call to each provider.
+//!
+//! The first request a process sends to a provider pays for DNS resolution,
+//! the TCP and TLS handshakes and often an auth-token fetch before the model
+//! sees a byte. That is easily several hundred milliseconds, and in a trace
+//! it shows up as a slow first model call. [`instrument`] wraps a reqwest
+//! client so the first DNS lookup and the first connection to each provider
+//! after startup run in spans of their own, and [`auth`] does the same for
+//! token acquisition:
+//!
+//! ```text
+//! chat gemini-2.5-flash      llm.cold_start = true, llm.cold_start.dns_ms, .connect_ms
+//! └─ cold_start connect      the whole connection setup
+//!    └─ cold_start dns       server.address, llm.cold_start.addresses
+//! ```
+//!
+//! The phase spans are children of the span current when the connection
+//! starts, which for rig providers is the model call's span. That span also
+//! gets `llm.cold_start = true` and each phase's duration in milliseconds,
+//! where `connect_ms` excludes the DNS lookup. reqwest does not expose the
+//! boundary between the TCP and TLS handshakes, so they are one phase.
+//! Later lookups and connections are not recorded.
+//!
+//! ```ignore
+//! let client = gemini::Client::builder()
+//!     .api_key(api_key)
+//!     .http_client(cold_start::http_client("gcp.gemini")?)
+//!     .build()?;
+//! ```
+
+use opentelemetry::trace::Status;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::Instrument;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColdStartPhase {
+    Dns,
+    /// TCP and TLS handshakes.
+    Connect,
+    Auth,
+}
+
+impl ColdStartPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColdStartPhase::Dns => "dns",
+            ColdStartPhase::Connect => "connect",
+            ColdStartPhase::Auth => "auth",
+        }
+    }
+}
+
+impl fmt::Display for ColdStartPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Claims the first `phase` of `provider` since startup.
+fn first(provider: &str, phase: ColdStartPhase) -> bool {
+    static SEEN: OnceLock<Mutex<HashSet<(String, ColdStartPhase)>>> = OnceLock::new();
+    SEEN.get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert((provider.to_owned(), phase))
+}
+
+/// Span for one cold-start phase, named `cold_start {phase}`.
+fn phase_span(provider: &str, phase: ColdStartPhase) -> tracing::Span {
+    tracing::info_span!(
+        "cold_start",
+        otel.name = format!("cold_start {phase}"),
+        otel.status_code = Empty,
+        gen_ai.provider.name = provider,
+        llm.cold_start.phase = phase.as_str(),
+        server.address = Empty,
+        llm.cold_start.addresses = Empty,
+        error.type = Empty,
+    )
+}
+
+/// The connection being set up, for the DNS lookup inside it.
+struct Connecting {
+    caller: tracing::Span,
+    dns_ms: Cell<f64>,
+}
+
+tokio::task_local! {
+    static CONNECTING: Connecting;
+}
+
+/// Runs `future` in `span`, returning its result and duration in
+/// milliseconds; errors set the span's status.
+async fn run_phase<T, E: fmt::Display>(
+    span: &tracing::Span,
+    future: impl Future<Output = Result<T, E>>,
+) -> (Result<T, E>, f64) {
+    let started = Instant::now();
+    let result = future.instrument(span.clone()).await;
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+    if let Err(error) = &result {
+        span.record("error.type", "_OTHER");
+        span.set_status(Status::error(error.to_string()));
+    }
+    (result, elapsed_ms)
+}
+
+fn record_phase(caller: &tracing::Span, phase: ColdStartPhase, elapsed_ms: f64) {
+    // The DNS lookup runs inside the connection, which sets the flag.
+    if phase != ColdStartPhase::Dns {
+        caller.set_attribute("llm.cold_start", true);
+    }
+    caller.set_attribute(format!("llm.cold_start.{phase}_ms"), elapsed_ms);
+}
+
+/// Adds cold-start recording for `provider` to `builder`: a DNS resolver
+/// (the system resolver, timed) and a connector layer. A custom resolver
+/// set on `builder` is replaced.
+pub fn instrument(builder: reqwest::ClientBuilder, provider: &str) -> reqwest::ClientBuilder {
+    let provider: Arc<str> = Arc::from(provider);
+    builder
+        .dns_resolver(TimedResolver {
+            provider: Arc::clone(&provider),
+        })
+        .connector_layer(ConnectTimingLayer { provider })
+}
+
+/// A default reqwest client with [`instrument`] applied, for rig's
+/// `ClientBuilder::http_client`.
+pub fn http_client(provider: &str) -> reqwest::Result<reqwest::Client> {
+    instrument(reqwest::Client::builder(), provider).build()
+}
+
+/// Runs `acquire`, an auth-token fetch for `provider`, recording it as the
+/// `auth` phase the first time.
+pub async fn auth<T, E: fmt::Display>(
+    provider: &str,
+    acquire: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    if !first(provider, ColdStartPhase::Auth) {
+        return acquire.await;
+    }
+    let (result, elapsed_ms) =
+        run_phase(&phase_span(provider, ColdStartPhase::Auth), acquire).await;
+    record_phase(&tracing::Span::current(), ColdStartPhase::Auth, elapsed_ms);
+    result
+}
+
+struct TimedResolver {
+    provider: Arc<str>,
+}
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let provider = Arc::clone(&self.provider);
+        let host = name.as_str().to_owned();
+        Box::pin(async move {
+            let lookup = tokio::net::lookup_host((host.clone(), 0));
+            let addrs: Vec<SocketAddr> = if first(&provider, ColdStartPhase::Dns) {
+                let span = phase_span(&provider, ColdStartPhase::Dns);
+                span.record("server.address", host.as_str());
+                let (result, elapsed_ms) = run_phase(&span, lookup).await;
+                let addrs: Vec<SocketAddr> = result?.collect();
+                span.record("llm.cold_start.addresses", addrs.len() as u64);
+                let caller = CONNECTING
+                    .try_with(|connecting| {
+                        connecting.dns_ms.set(elapsed_ms);
+                        connecting.caller.clone()
+                    })
+                    .unwrap_or_else(|_| tracing::Span::current());
+                record_phase(&caller, ColdStartPhase::Dns, elapsed_ms);
+                addrs
+            } else {
+                lookup.await?.collect()
+            };
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ConnectTimingLayer {
+    provider: Arc<str>,
+}
+
+impl<S> Layer<S> for ConnectTimingLayer {
+    type Service = ConnectTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectTiming {
+            inner,
+            provider: Arc::clone(&self.provider),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ConnectTiming<S> {
+    inner: S,
+    provider: Arc<str>,
+}
+
+impl<S, R> Service<R> for ConnectTiming<S>
+where
+    S: Service<R>,
+    S::Error: fmt::Display,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let future = self.inner.call(request);
+        if !first(&self.provider, ColdStartPhase::Connect) {
+            return Box::pin(future);
+        }
+        // Created here, where the caller's span is current; the future may
+        // be polled elsewhere.
+        let caller = tracing::Span::current();
+        let span = phase_span(&self.provider, ColdStartPhase::Connect);
+        let connecting = Connecting {
+            caller: caller.clone(),
+            dns_ms: Cell::new(0.0),
+        };
+        Box::pin(CONNECTING.scope(connecting, async move {
+            let (result, elapsed_ms) = run_phase(&span, future).await;
+            let dns_ms = CONNECTING.with(|connecting| connecting.dns_ms.get());
+            record_phase(&caller, ColdStartPhase::Connect, elapsed_ms - dns_ms);
+            result
+        }))
+    }
+}
@@ -0,0 +1,82 @@
+//! Instrumentation for history trimming and context compression.
+//!
+//! Quality regressions are often caused by aggressive trimming rather than
+//! by the model. Wrapping the trimming step in a `compress_context` span that
+//! records what was dropped makes that link visible in the trace.
+
+use crate::tokens::estimate_tokens;
+use tracing::field::Empty;
+
+/// What a compression step did to the context before the provider call.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressionStats {
+    pub messages_before: usize,
+    pub messages_after: usize,
+    pub tokens_before: u64,
+    pub tokens_after: u64,
+    /// Tokens spent by a summarization model, when one was used.
+    pub summarization_tokens: Option<u64>,
+    /// Cost of the summarization call in USD, when known.
+    pub summarization_cost_usd: Option<f64>,
+}
+
+impl CompressionStats {
+    /// Builds stats from message texts, estimating tokens locally.
+    pub fn from_messages<S: AsRef<str>>(before: &[S], after: &[S]) -> Self {
+        let tokens = |messages: &[S]| {
+            messages
+                .iter()
+                .map(|message| estimate_tokens(message.as_ref()))
+                .sum()
+        };
+        Self {
+            messages_before: before.len(),
+            messages_after: after.len(),
+            tokens_before: tokens(before),
+            tokens_after: tokens(after),
+            ..Self::default()
+        }
+    }
+
+    pub fn messages_dropped(&self) -> usize {
+        self.messages_before.saturating_sub(self.messages_after)
+    }
+
+    pub fn tokens_dropped(&self) -> u64 {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+}
+
+/// Opens the `compress_context` span; enter it around the trimming work.
+pub fn compress_context_span(strategy: &str) -> tracing::Span {
+    tracing::info_span!(
+        "compress_context",
+        llm.context.strategy = strategy,
+        llm.context.messages_before = Empty,
+        llm.context.messages_after = Empty,
+        llm.context.messages_dropped = Empty,
+        llm.context.tokens_before = Empty,
+        llm.context.tokens_after = Empty,
+        llm.context.tokens_dropped = Empty,
+        llm.context.summarization_tokens = Empty,
+        llm.context.summarization_cost_usd = Empty,
+    )
+}
+
+pub fn record_compression(span: &tracing::Span, stats: &CompressionStats) {
+    span.record("llm.context.messages_before", stats.messages_before as u64);
+    span.record("llm.context.messages_after", stats.messages_after as u64);
+    span.record(
+        "llm.context.messages_dropped",
+        stats.messages_dropped() as u64,
+    );
+    span.record("llm.context.tokens_before", stats.tokens_before);
+    span.record("llm.context.tokens_after", stats.tokens_after);
+    span.record("llm.context.tokens_dropped", stats.tokens_dropped());
+    if let Some(tokens) = stats.summarization_tokens {
+        span.record("llm.context.summarization_tokens", tokens);
+    }
+    if let Some(cost) = stats.summarization_cost_usd {
+        span.record("llm.context.summarization_cost_usd", cost);
+    }
+}
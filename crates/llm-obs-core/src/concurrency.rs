@@ -0,0 +1,457 @@
+//! Concurrency limiting with priority classes.
+//!
+//! With one shared cap on in-flight LLM calls, a batch job that queues a few
+//! hundred requests makes every interactive request wait behind it, and the
+//! trace only shows a slow call. [`ConcurrencyLimiter`] admits queued callers
+//! by [`Priority`] (interactive, then background, then batch; first come,
+//! first served within a class) and records every scheduling decision:
+//!
+//! - the caller's span gets `llm.priority`;
+//! - time spent queued is a `concurrency.wait` child span with the queue
+//!   position on arrival, `concurrency.queue_jumped` (lower-priority callers
+//!   it overtook) and `concurrency.passed_over` (how often a higher-priority
+//!   caller was admitted ahead of it);
+//! - `llm.concurrency.queue_wait` records the wait per priority.
+//!
+//! Calls already in flight are never interrupted: being passed over in the
+//! queue is the only preemption.
+
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Histogram;
+use std::cmp::Reverse;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Instant;
+use tokio::sync::oneshot;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// A user is waiting on the answer.
+    Interactive,
+    /// Work triggered by a user but not awaited, e.g. summarizing a thread.
+    Background,
+    /// Offline jobs: evaluations, backfills, embeddings.
+    Batch,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Background => "background",
+            Priority::Batch => "batch",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Priority::Interactive => 2,
+            Priority::Background => 1,
+            Priority::Batch => 0,
+        }
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Sent to a queued caller when a slot is handed to it.
+struct Admission {
+    permit: ConcurrencyPermit,
+    jumped: u64,
+    passed_over: u64,
+}
+
+struct Waiter {
+    ticket: u64,
+    priority: Priority,
+    passed_over: u64,
+    admit: oneshot::Sender<Admission>,
+}
+
+#[derive(Default)]
+struct Queue {
+    in_flight: usize,
+    next_ticket: u64,
+    waiters: Vec<Waiter>,
+}
+
+struct Shared {
+    max_in_flight: usize,
+    queue: Mutex<Queue>,
+}
+
+impl Shared {
+    /// Hands the slot of a finished call to the next queued caller, or frees
+    /// it when nobody is waiting.
+    fn release(self: &Arc<Self>) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            // Callers that gave up waiting.
+            queue.waiters.retain(|waiter| !waiter.admit.is_closed());
+            let next = queue
+                .waiters
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, waiter)| (waiter.priority.rank(), Reverse(waiter.ticket)))
+                .map(|(index, _)| index);
+            let Some(index) = next else {
+                queue.in_flight -= 1;
+                return;
+            };
+            let waiter = queue.waiters.remove(index);
+            // Everyone still queued from before arrived earlier with a lower
+            // priority.
+            let jumped = queue
+                .waiters
+                .iter()
+                .filter(|other| other.ticket < waiter.ticket)
+                .count() as u64;
+            let admission = Admission {
+                permit: ConcurrencyPermit {
+                    shared: Some(Arc::clone(self)),
+                },
+                jumped,
+                passed_over: waiter.passed_over,
+            };
+            match waiter.admit.send(admission) {
+                Ok(()) => {
+                    for other in &mut queue.waiters {
+                        if other.ticket < waiter.ticket {
+                            other.passed_over += 1;
+                        }
+                    }
+                    return;
+                }
+                // Cancelled after the sweep; the slot goes to the next one.
+                Err(mut admission) => admission.permit.shared = None,
+            }
+        }
+    }
+}
+
+/// Caps concurrent calls, admitting queued callers by priority. Clones share
+/// the same slots.
+#[derive(Clone)]
+pub struct ConcurrencyLimiter {
+    shared: Arc<Shared>,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                max_in_flight: max_in_flight.max(1),
+                queue: Mutex::default(),
+            }),
+        }
+    }
+
+    /// Waits for a slot at `priority`. The slot is held until the permit
+    /// drops.
+    pub async fn acquire(&self, priority: Priority) -> ConcurrencyPermit {
+        tracing::Span::current().set_attribute("llm.priority", priority.as_str());
+
+        let (admitted, position) = {
+            let mut queue = self
+                .shared
+                .queue
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if queue.in_flight < self.shared.max_in_flight && queue.waiters.is_empty() {
+                queue.in_flight += 1;
+                return ConcurrencyPermit {
+                    shared: Some(Arc::clone(&self.shared)),
+                };
+            }
+            let position = queue
+                .waiters
+                .iter()
+                .filter(|waiter| waiter.priority.rank() >= priority.rank())
+                .count();
+            let (admit, admitted) = oneshot::channel();
+            let ticket = queue.next_ticket;
+            queue.next_ticket += 1;
+            queue.waiters.push(Waiter {
+                ticket,
+                priority,
+                passed_over: 0,
+                admit,
+            });
+            (admitted, position)
+        };
+
+        let started = Instant::now();
+        let wait_span = tracing::info_span!(
+            "concurrency.wait",
+            llm.priority = priority.as_str(),
+            concurrency.queue_position = position as u64,
+            concurrency.queue_jumped = tracing::field::Empty,
+            concurrency.passed_over = tracing::field::Empty,
+            concurrency.wait_ms = tracing::field::Empty,
+        );
+        let admission = admitted
+            .instrument(wait_span.clone())
+            .await
+            .expect("queued callers are only removed once admitted or cancelled");
+        let waited = started.elapsed();
+
+        wait_span.record("concurrency.queue_jumped", admission.jumped);
+        wait_span.record("concurrency.passed_over", admission.passed_over);
+        wait_span.record("concurrency.wait_ms", waited.as_millis() as u64);
+        if admission.jumped > 0 {
+            tracing::debug!(
+                parent: &wait_span,
+                concurrency.queue_jumped = admission.jumped,
+                "Admitted ahead of lower-priority callers"
+            );
+        }
+        if admission.passed_over > 0 {
+            tracing::debug!(
+                parent: &wait_span,
+                concurrency.passed_over = admission.passed_over,
+                "Passed over for higher-priority callers"
+            );
+        }
+        queue_wait_histogram().record(
+            waited.as_secs_f64(),
+            &[KeyValue::new("llm.priority", priority.as_str())],
+        );
+        admission.permit
+    }
+
+    /// Calls currently holding a slot.
+    pub fn in_flight(&self) -> usize {
+        self.shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .in_flight
+    }
+}
+
+impl fmt::Debug for ConcurrencyLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let queue = self
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("ConcurrencyLimiter")
+            .field("max_in_flight", &self.shared.max_in_flight)
+            .field("in_flight", &queue.in_flight)
+            .field("queued", &queue.waiters.len())
+            .finish()
+    }
+}
+
+/// A slot of a [`ConcurrencyLimiter`], released on drop.
+pub struct ConcurrencyPermit {
+    shared: Option<Arc<Shared>>,
+}
+
+impl fmt::Debug for ConcurrencyPermit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConcurrencyPermit").finish_non_exhaustive()
+    }
+}
+
+impl Drop for ConcurrencyPermit {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.take() {
+            shared.release();
+        }
+    }
+}
+
+fn queue_wait_histogram() -> &'static Histogram<f64> {
+    static HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+    HISTOGRAM.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .f64_histogram("llm.concurrency.queue_wait")
+            .with_unit("s")
+            .with_description("Time calls spent queued for a concurrency slot, by priority")
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::Collect;
+    use crate::processors::{attribute, attribute_f64};
+    use opentelemetry::Value;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData};
+    use tokio::task::JoinHandle;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    type Admitted = Arc<Mutex<Vec<Priority>>>;
+
+    /// Queues a caller that records its priority once admitted and then
+    /// releases its slot.
+    fn queue(
+        limiter: &ConcurrencyLimiter,
+        priority: Priority,
+        admitted: &Admitted,
+    ) -> JoinHandle<()> {
+        let (limiter, admitted) = (limiter.clone(), Arc::clone(admitted));
+        tokio::spawn(async move {
+            let _permit = limiter.acquire(priority).await;
+            admitted
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(priority);
+        })
+    }
+
+    /// Yields until `waiters` callers are queued.
+    async fn settle(limiter: &ConcurrencyLimiter, waiters: usize) {
+        while limiter
+            .shared
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .waiters
+            .len()
+            != waiters
+        {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_slots_in_flight_and_queues_beyond_the_cap() {
+        let limiter = ConcurrencyLimiter::new(2);
+        let admitted = Admitted::default();
+
+        let first = limiter.acquire(Priority::Interactive).await;
+        let second = limiter.acquire(Priority::Batch).await;
+        assert_eq!(limiter.in_flight(), 2);
+
+        let third = queue(&limiter, Priority::Interactive, &admitted);
+        settle(&limiter, 1).await;
+        assert!(admitted.lock().unwrap().is_empty());
+
+        drop(first);
+        third.await.unwrap();
+        assert_eq!(*admitted.lock().unwrap(), [Priority::Interactive]);
+        assert_eq!(limiter.in_flight(), 1);
+
+        drop(second);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn admits_by_priority_then_arrival() {
+        let limiter = ConcurrencyLimiter::new(1);
+        let admitted = Admitted::default();
+        let held = limiter.acquire(Priority::Batch).await;
+
+        let mut callers = Vec::new();
+        for (queued, priority) in [
+            Priority::Batch,
+            Priority::Background,
+            Priority::Interactive,
+            Priority::Batch,
+            Priority::Interactive,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            callers.push(queue(&limiter, priority, &admitted));
+            settle(&limiter, queued + 1).await;
+        }
+        drop(held);
+        for caller in callers {
+            caller.await.unwrap();
+        }
+
+        assert_eq!(
+            *admitted.lock().unwrap(),
+            [
+                Priority::Interactive,
+                Priority::Interactive,
+                Priority::Background,
+                Priority::Batch,
+                Priority::Batch,
+            ]
+        );
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn cancelled_callers_give_up_their_place() {
+        let limiter = ConcurrencyLimiter::new(1);
+        let admitted = Admitted::default();
+        let held = limiter.acquire(Priority::Interactive).await;
+
+        let cancelled = queue(&limiter, Priority::Interactive, &admitted);
+        settle(&limiter, 1).await;
+        cancelled.abort();
+        let _ = cancelled.await;
+        let waiting = queue(&limiter, Priority::Batch, &admitted);
+        settle(&limiter, 2).await;
+
+        drop(held);
+        waiting.await.unwrap();
+        assert_eq!(*admitted.lock().unwrap(), [Priority::Batch]);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn wait_spans_record_queue_jumps_and_pass_overs() {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(collect.clone())
+            .build();
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::registry()
+                .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test"))),
+        );
+        let limiter = ConcurrencyLimiter::new(1);
+        let admitted = Admitted::default();
+        let held = limiter.acquire(Priority::Interactive).await;
+
+        let batch = queue(&limiter, Priority::Batch, &admitted);
+        settle(&limiter, 1).await;
+        let interactive = queue(&limiter, Priority::Interactive, &admitted);
+        settle(&limiter, 2).await;
+        drop(held);
+        interactive.await.unwrap();
+        batch.await.unwrap();
+
+        let waits: Vec<SpanData> = collect
+            .spans()
+            .into_iter()
+            .filter(|span| span.name == "concurrency.wait")
+            .collect();
+        let wait = |priority: Priority| {
+            waits
+                .iter()
+                .find(|span| {
+                    attribute(span, "llm.priority") == Some(&Value::from(priority.as_str()))
+                })
+                .unwrap()
+        };
+        let interactive = wait(Priority::Interactive);
+        assert_eq!(
+            attribute_f64(interactive, "concurrency.queue_jumped"),
+            Some(1.0)
+        );
+        assert_eq!(
+            attribute_f64(interactive, "concurrency.passed_over"),
+            Some(0.0)
+        );
+        let batch = wait(Priority::Batch);
+        assert_eq!(attribute_f64(batch, "concurrency.queue_jumped"), Some(0.0));
+        assert_eq!(attribute_f64(batch, "concurrency.passed_over"), Some(1.0));
+    }
+}
@@ -0,0 +1,79 @@
+//! Human-readable span output for local debugging.
+//!
+//! The SDK already fans out to every processor on a provider, so console
+//! output next to OTLP export is just a second processor around
+//! [`ConsoleSpanExporter`]; `TelemetryBuilder::with_console_exporter` wires
+//! it up. Each finished span becomes one line on stderr:
+//!
+//! ```text
+//! agent.planner 812.4ms trace=4bf92f… span=00f067… gen_ai.request.model=gemini-2.5-flash
+//! ```
+
+use opentelemetry::trace::Status;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// Attribute values longer than this are cut, to keep one span per line.
+const MAX_VALUE_CHARS: usize = 120;
+
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleSpanExporter {
+    with_attributes: bool,
+}
+
+impl ConsoleSpanExporter {
+    /// Prints names, durations, ids and attributes.
+    pub fn new() -> Self {
+        Self {
+            with_attributes: true,
+        }
+    }
+
+    /// Prints only names, durations, ids and status.
+    pub fn compact() -> Self {
+        Self {
+            with_attributes: false,
+        }
+    }
+
+    fn format(&self, span: &SpanData) -> String {
+        let duration_ms = span
+            .end_time
+            .duration_since(span.start_time)
+            .unwrap_or_default()
+            .as_secs_f64()
+            * 1000.0;
+        let mut line = format!(
+            "{} {duration_ms:.1}ms trace={} span={}",
+            span.name,
+            span.span_context.trace_id(),
+            span.span_context.span_id()
+        );
+        if let Status::Error { description } = &span.status {
+            let _ = write!(line, " error={description:?}");
+        }
+        if self.with_attributes {
+            for attribute in &span.attributes {
+                let value = attribute.value.as_str();
+                let shown: String = value.chars().take(MAX_VALUE_CHARS).collect();
+                let ellipsis = if shown.len() < value.len() { "…" } else { "" };
+                let _ = write!(line, " {}={shown}{ellipsis}", attribute.key);
+            }
+        }
+        line
+    }
+}
+
+impl SpanExporter for ConsoleSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let mut stderr = std::io::stderr().lock();
+        for span in &batch {
+            // Console output is best effort; a closed stderr must not fail
+            // the pipeline.
+            let _ = writeln!(stderr, "{}", self.format(span));
+        }
+        Ok(())
+    }
+}
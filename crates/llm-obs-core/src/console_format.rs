@@ -0,0 +1,98 @@
+//! Console log lines that name their trace.
+//!
+//! A warning in the terminal says what went wrong but not where to look:
+//! finding the trace means matching timestamps in the backend. With
+//! [`TraceIdFormat`] every line logged inside a span ends with the ids of
+//! that span, ready to paste into the backend's trace search:
+//!
+//! ```text
+//! 2026-03-02T10:15:03.123Z  WARN agent_orchestrator: Planner failed branch="planner_pro" trace_id=4bf92f3577b34da6a3ce929d0e0e4736 span_id=00f067aa0ba902b7
+//! ```
+//!
+//! `TelemetryBuilder::with_console_trace_ids` turns it on for the console
+//! layer it installs; host apps pass it to `fmt::layer().event_format(..)`.
+
+use opentelemetry::trace::{SpanId, TraceContextExt, TraceId};
+use std::fmt;
+use tracing::{Event, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::fmt::format::{Format, Full, Writer};
+use tracing_subscriber::fmt::time::SystemTime;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Trace and span id of a span known to the OpenTelemetry layer. Root spans
+/// get their trace id up front; children share the parent's.
+pub(crate) fn otel_ids(data: &OtelData) -> Option<(TraceId, SpanId)> {
+    let trace_id = data
+        .builder
+        .trace_id
+        .unwrap_or_else(|| data.parent_cx.span().span_context().trace_id());
+    let span_id = data.builder.span_id?;
+    (trace_id != TraceId::INVALID && span_id != SpanId::INVALID).then_some((trace_id, span_id))
+}
+
+/// `fmt` event format appending `trace_id` and `span_id` to lines logged
+/// inside a span, after the default format.
+#[derive(Debug, Clone)]
+pub struct TraceIdFormat {
+    plain: Format<Full, SystemTime>,
+    ansi: Format<Full, SystemTime>,
+    with_ids: bool,
+}
+
+impl Default for TraceIdFormat {
+    fn default() -> Self {
+        Self::new(Format::default())
+    }
+}
+
+impl TraceIdFormat {
+    /// Appends ids to lines formatted by `inner`, e.g.
+    /// `fmt::format().with_target(false)`.
+    pub fn new(inner: Format<Full, SystemTime>) -> Self {
+        Self {
+            plain: inner.clone().with_ansi(false),
+            ansi: inner.with_ansi(true),
+            with_ids: true,
+        }
+    }
+
+    /// Whether ids are appended; without them lines are `inner`'s alone.
+    pub fn with_ids(mut self, enabled: bool) -> Self {
+        self.with_ids = enabled;
+        self
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for TraceIdFormat
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    N: for<'writer> FormatFields<'writer> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let inner = if writer.has_ansi_escapes() {
+            &self.ansi
+        } else {
+            &self.plain
+        };
+        let ids = ctx
+            .parent_span()
+            .filter(|_| self.with_ids)
+            .and_then(|span| span.extensions().get::<OtelData>().and_then(otel_ids));
+        let Some((trace_id, span_id)) = ids else {
+            return inner.format_event(ctx, writer, event);
+        };
+        // The inner format ends the line itself, so it is written to a
+        // buffer and the ids are added before the newline.
+        let mut line = String::new();
+        inner.format_event(ctx, Writer::new(&mut line), event)?;
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        writeln!(writer, "{line} trace_id={trace_id} span_id={span_id}")
+    }
+}
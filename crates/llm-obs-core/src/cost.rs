@@ -0,0 +1,321 @@
+//! Per-call cost estimation from token usage.
+//!
+//! Prices live in a [`PricingTable`] loaded from JSON or TOML and adjustable
+//! at runtime, so new models can be priced without a release. [`record_cost`]
+//! prices one call, sets `gen_ai.usage.cost_usd` on the span and adds it to
+//! the `llm.cost.usd` counter.
+
+use crate::cardinality::guarded;
+use crate::scopes::Subsystem;
+use crate::tokens::TokenUsage;
+use anyhow::Context;
+use opentelemetry::metrics::Counter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{OnceLock, PoisonError, RwLock};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// USD prices per 1K tokens for one model.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+    /// Reasoning/thinking tokens; billed at the output price when unset.
+    #[serde(default)]
+    pub reasoning_per_1k: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct CostBreakdown {
+    pub input_usd: f64,
+    pub output_usd: f64,
+    pub reasoning_usd: f64,
+}
+
+impl CostBreakdown {
+    pub fn total_usd(&self) -> f64 {
+        self.input_usd + self.output_usd + self.reasoning_usd
+    }
+}
+
+impl ModelPricing {
+    pub fn cost(&self, usage: &TokenUsage) -> CostBreakdown {
+        let per_token = |tokens: u64, per_1k: f64| tokens as f64 * per_1k / 1000.0;
+        CostBreakdown {
+            input_usd: per_token(usage.input_tokens, self.input_per_1k),
+            output_usd: per_token(usage.output_tokens, self.output_per_1k),
+            reasoning_usd: per_token(
+                usage.reasoning_tokens,
+                self.reasoning_per_1k.unwrap_or(self.output_per_1k),
+            ),
+        }
+    }
+}
+
+/// Per-model prices, keyed by model name or model-name prefix.
+///
+/// The JSON format maps model names to [`ModelPricing`]:
+/// `{"gemini-2.5-flash": {"input_per_1k": 0.0003, "output_per_1k": 0.0025}}`.
+/// In TOML each model is a table:
+///
+/// ```toml
+/// ["gemini-2.5-flash"]
+/// input_per_1k = 0.0003
+/// output_per_1k = 0.0025
+/// ```
+#[derive(Debug, Default)]
+pub struct PricingTable {
+    models: RwLock<HashMap<String, ModelPricing>>,
+}
+
+impl PricingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide table, empty until loaded or populated.
+    pub fn global() -> &'static PricingTable {
+        static GLOBAL: OnceLock<PricingTable> = OnceLock::new();
+        GLOBAL.get_or_init(PricingTable::new)
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let models: HashMap<String, ModelPricing> =
+            serde_json::from_str(json).context("Failed to parse pricing table")?;
+        Ok(Self::from_models(models))
+    }
+
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        let models: HashMap<String, ModelPricing> =
+            toml::from_str(toml).context("Failed to parse pricing table")?;
+        Ok(Self::from_models(models))
+    }
+
+    /// Reads a pricing table from `path`: TOML for a `.toml` file, JSON
+    /// otherwise.
+    pub fn from_path(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read pricing table {}", path.display()))?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            Self::from_toml(&contents)
+        } else {
+            Self::from_json(&contents)
+        }
+    }
+
+    /// Merges the entries of a JSON table into this one, replacing prices of
+    /// models present in both.
+    pub fn merge_json(&self, json: &str) -> anyhow::Result<()> {
+        self.merge(Self::from_json(json)?);
+        Ok(())
+    }
+
+    /// [`PricingTable::merge_json`] for a TOML table.
+    pub fn merge_toml(&self, toml: &str) -> anyhow::Result<()> {
+        self.merge(Self::from_toml(toml)?);
+        Ok(())
+    }
+
+    fn from_models(models: HashMap<String, ModelPricing>) -> Self {
+        Self {
+            models: RwLock::new(models),
+        }
+    }
+
+    fn merge(&self, loaded: Self) {
+        let loaded = loaded
+            .models
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.write().extend(loaded);
+    }
+
+    /// Adds or overrides the price of `model`, e.g. for a newly released
+    /// model.
+    pub fn set(&self, model: impl Into<String>, pricing: ModelPricing) {
+        self.write().insert(model.into(), pricing);
+    }
+
+    pub fn remove(&self, model: &str) -> Option<ModelPricing> {
+        self.write().remove(model)
+    }
+
+    /// Exact match first, then the longest key that `model` is a dated,
+    /// numbered or `latest` version of, so `gemini-2.5-flash` also prices
+    /// `gemini-2.5-flash-001`. Other models are unpriced rather than priced
+    /// like a sibling: `gemini-2.5-flash` does not price
+    /// `gemini-2.5-flash-lite`, nor `gpt-4` `gpt-4o` or `gpt-4-turbo`.
+    pub fn get(&self, model: &str) -> Option<ModelPricing> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models.get(model).copied().or_else(|| {
+            models
+                .iter()
+                .filter(|(key, _)| is_version_of(model, key))
+                .max_by_key(|(key, _)| key.len())
+                .map(|(_, pricing)| *pricing)
+        })
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, ModelPricing>> {
+        self.models.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Whether `model` is `prefix` followed by a version suffix: `@` and
+/// anything (`@20240620`, `@001`), or `-` and numeric or `latest` parts
+/// (`-001`, `-2024-08-06`, `-20241022`, `-latest`).
+fn is_version_of(model: &str, prefix: &str) -> bool {
+    let Some(rest) = model.strip_prefix(prefix) else {
+        return false;
+    };
+    if rest.is_empty() || rest.starts_with('@') {
+        return true;
+    }
+    rest.strip_prefix('-').is_some_and(|version| {
+        version.split('-').all(|part| {
+            part == "latest" || (!part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit()))
+        })
+    })
+}
+
+/// Prices one call from `table`, records `gen_ai.usage.cost_usd` on `span`
+/// and adds the cost to `llm.cost.usd`. Returns `None` for unpriced models.
+pub fn record_cost(
+    span: &tracing::Span,
+    table: &PricingTable,
+    model: &str,
+    usage: &TokenUsage,
+) -> Option<CostBreakdown> {
+    let cost = table.get(model)?.cost(usage);
+    span.set_attribute("gen_ai.usage.cost_usd", cost.total_usd());
+    cost_counter().add(cost.total_usd(), &[guarded("gen_ai.request.model", model)]);
+    Some(cost)
+}
+
+fn cost_counter() -> &'static Counter<f64> {
+    static COUNTER: OnceLock<Counter<f64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .f64_counter("llm.cost.usd")
+            .with_unit("USD")
+            .with_description("Estimated spend on model calls")
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pricing(input_per_1k: f64) -> ModelPricing {
+        ModelPricing {
+            input_per_1k,
+            output_per_1k: 0.0,
+            reasoning_per_1k: None,
+        }
+    }
+
+    #[test]
+    fn prefix_match_stops_at_version_separators() {
+        let table = PricingTable::new();
+        table.set("gpt-4", pricing(0.03));
+        table.set("gemini-2.5-flash", pricing(0.0003));
+
+        assert_eq!(table.get("gpt-4-0613"), Some(pricing(0.03)));
+        assert_eq!(table.get("gpt-4o"), None);
+        assert_eq!(table.get("gemini-2.5-flash@001"), Some(pricing(0.0003)));
+        assert_eq!(table.get("gemini-2.5-flashy"), None);
+    }
+
+    #[test]
+    fn prefix_match_prefers_the_longest_key() {
+        let table = PricingTable::new();
+        table.set("gemini-2.5-flash", pricing(0.0003));
+        table.set("gemini-2.5-flash-lite", pricing(0.0001));
+
+        assert_eq!(
+            table.get("gemini-2.5-flash-lite-001"),
+            Some(pricing(0.0001))
+        );
+        assert_eq!(table.get("gemini-2.5-flash-001"), Some(pricing(0.0003)));
+    }
+
+    #[test]
+    fn accepts_date_numeric_and_latest_suffixes() {
+        let table = PricingTable::new();
+        table.set("gpt-4o", pricing(0.0025));
+        table.set("claude-3-5-sonnet", pricing(0.003));
+
+        assert_eq!(table.get("gpt-4o-2024-08-06"), Some(pricing(0.0025)));
+        assert_eq!(table.get("gpt-4o-latest"), Some(pricing(0.0025)));
+        assert_eq!(
+            table.get("claude-3-5-sonnet-20241022"),
+            Some(pricing(0.003))
+        );
+        assert_eq!(
+            table.get("claude-3-5-sonnet@20241022"),
+            Some(pricing(0.003))
+        );
+        assert_eq!(table.get("gpt-4o-"), None);
+    }
+
+    #[test]
+    fn unlisted_siblings_are_unpriced() {
+        let table = PricingTable::new();
+        table.set("gpt-4", pricing(0.03));
+        table.set("gemini-2.5-flash", pricing(0.0003));
+
+        assert_eq!(table.get("gemini-2.5-flash-lite"), None);
+        assert_eq!(table.get("gemini-2.5-flash-lite-001"), None);
+        assert_eq!(table.get("gpt-4-turbo"), None);
+        assert_eq!(table.get("gpt-4-turbo-2024-04-09"), None);
+    }
+
+    #[test]
+    fn loads_toml_tables() {
+        let table = PricingTable::from_toml(
+            r#"
+            ["gemini-2.5-flash"]
+            input_per_1k = 0.0003
+            output_per_1k = 0.0025
+
+            ["o3"]
+            input_per_1k = 0.002
+            output_per_1k = 0.008
+            reasoning_per_1k = 0.008
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            table.get("gemini-2.5-flash"),
+            Some(ModelPricing {
+                input_per_1k: 0.0003,
+                output_per_1k: 0.0025,
+                reasoning_per_1k: None,
+            })
+        );
+        assert_eq!(table.get("o3").unwrap().reasoning_per_1k, Some(0.008));
+
+        table
+            .merge_toml("[o3]\ninput_per_1k = 0.001\noutput_per_1k = 0.004\n")
+            .unwrap();
+        assert_eq!(table.get("o3").unwrap().input_per_1k, 0.001);
+        assert!(PricingTable::from_toml("[o3]\ninput_per_1k = \"cheap\"\n").is_err());
+    }
+
+    #[test]
+    fn exact_match_wins() {
+        let table = PricingTable::new();
+        table.set("gpt-4", pricing(0.03));
+        table.set("gpt-4-turbo", pricing(0.01));
+
+        assert_eq!(table.get("gpt-4-turbo"), Some(pricing(0.01)));
+    }
+}
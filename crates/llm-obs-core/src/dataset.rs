@@ -0,0 +1,276 @@
+//! Prompt regression runs that report through the normal tracing pipeline.
+//!
+//! Evaluation harnesses usually live outside the observability stack, so a
+//! failing case cannot be compared with production traces of the same
+//! prompt. [`DatasetRunner`] executes JSONL cases against one or more model
+//! configs, emitting one root `eval_case` trace per case and model with
+//! expected-vs-actual attributes, and returns an aggregate [`EvalReport`].
+
+use crate::fingerprint::fingerprint;
+use anyhow::Context as _;
+use opentelemetry::trace::TraceContextExt;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// One line of the dataset file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct EvalCase {
+    pub id: String,
+    pub prompt: String,
+    pub expected: String,
+    #[serde(default)]
+    pub check: CheckMode,
+}
+
+/// How the actual output is compared with `expected`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckMode {
+    /// Equal after trimming and case folding.
+    #[default]
+    Exact,
+    /// `expected` appears anywhere in the output, case-insensitively.
+    Contains,
+}
+
+impl CheckMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckMode::Exact => "exact",
+            CheckMode::Contains => "contains",
+        }
+    }
+
+    pub fn passes(&self, expected: &str, actual: &str) -> bool {
+        let expected = expected.trim().to_lowercase();
+        let actual = actual.trim().to_lowercase();
+        match self {
+            CheckMode::Exact => actual == expected,
+            CheckMode::Contains => actual.contains(&expected),
+        }
+    }
+}
+
+/// A model configuration the dataset is run against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelConfig {
+    /// Label used in the report, e.g. `flash-temp0`.
+    pub name: String,
+    pub model: String,
+}
+
+impl ModelConfig {
+    pub fn new(name: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            model: model.into(),
+        }
+    }
+}
+
+/// Result of one case against one model config.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub case_id: String,
+    pub config: String,
+    pub passed: bool,
+    pub error: Option<String>,
+    pub latency_ms: u128,
+    pub trace_id: Option<String>,
+}
+
+/// Pass/fail counts for one model config.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigSummary {
+    pub cases: usize,
+    pub passed: usize,
+    pub failed: usize,
+    pub errors: usize,
+    pub mean_latency_ms: f64,
+}
+
+impl ConfigSummary {
+    pub fn pass_rate(&self) -> f64 {
+        if self.cases == 0 {
+            return 0.0;
+        }
+        self.passed as f64 / self.cases as f64
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvalReport {
+    pub dataset: String,
+    pub results: Vec<CaseResult>,
+    pub summaries: BTreeMap<String, ConfigSummary>,
+}
+
+impl EvalReport {
+    fn summarize(&mut self) {
+        let mut summaries: BTreeMap<String, (ConfigSummary, u128)> = BTreeMap::new();
+        for result in &self.results {
+            let (summary, total_latency) = summaries.entry(result.config.clone()).or_default();
+            summary.cases += 1;
+            *total_latency += result.latency_ms;
+            match (&result.error, result.passed) {
+                (Some(_), _) => summary.errors += 1,
+                (None, true) => summary.passed += 1,
+                (None, false) => summary.failed += 1,
+            }
+        }
+        self.summaries = summaries
+            .into_iter()
+            .map(|(config, (mut summary, total_latency))| {
+                summary.mean_latency_ms = total_latency as f64 / summary.cases.max(1) as f64;
+                (config, summary)
+            })
+            .collect();
+    }
+}
+
+pub struct DatasetRunner {
+    name: String,
+    cases: Vec<EvalCase>,
+    configs: Vec<ModelConfig>,
+}
+
+impl DatasetRunner {
+    pub fn new(name: impl Into<String>, cases: Vec<EvalCase>) -> Self {
+        Self {
+            name: name.into(),
+            cases,
+            configs: Vec::new(),
+        }
+    }
+
+    /// Loads cases from a JSONL file; blank lines are skipped.
+    pub fn from_jsonl(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read dataset {}", path.display()))?;
+        let cases = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "Invalid eval case on line {} of {}",
+                        index + 1,
+                        path.display()
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<EvalCase>>>()?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Self::new(name, cases))
+    }
+
+    pub fn with_config(mut self, config: ModelConfig) -> Self {
+        self.configs.push(config);
+        self
+    }
+
+    pub fn cases(&self) -> &[EvalCase] {
+        &self.cases
+    }
+
+    /// Runs every case against every config, sequentially.
+    ///
+    /// `generate` performs the actual model call; instrument it as usual and
+    /// its spans nest under the case's `eval_case` root span.
+    pub async fn run<F, Fut>(&self, generate: F) -> EvalReport
+    where
+        F: Fn(ModelConfig, String) -> Fut,
+        Fut: Future<Output = anyhow::Result<String>>,
+    {
+        let mut report = EvalReport {
+            dataset: self.name.clone(),
+            ..EvalReport::default()
+        };
+        for case in &self.cases {
+            for config in &self.configs {
+                let result = self.run_case(case, config, &generate).await;
+                report.results.push(result);
+            }
+        }
+        report.summarize();
+        report
+    }
+
+    async fn run_case<F, Fut>(
+        &self,
+        case: &EvalCase,
+        config: &ModelConfig,
+        generate: &F,
+    ) -> CaseResult
+    where
+        F: Fn(ModelConfig, String) -> Fut,
+        Fut: Future<Output = anyhow::Result<String>>,
+    {
+        // Each case is its own trace so it can be compared in isolation.
+        let span = tracing::info_span!(
+            parent: None,
+            "eval_case",
+            llm.eval.dataset = %self.name,
+            llm.eval.case_id = %case.id,
+            llm.eval.config = %config.name,
+            llm.eval.check = case.check.as_str(),
+            gen_ai.request.model = %config.model,
+        );
+        span.set_attribute(
+            "llm.eval.expected.chars",
+            case.expected.chars().count() as i64,
+        );
+        span.set_attribute("llm.eval.expected.fingerprint", fingerprint(&case.expected));
+
+        let started = Instant::now();
+        let output = generate(config.clone(), case.prompt.clone())
+            .instrument(span.clone())
+            .await;
+        let latency = started.elapsed();
+
+        let trace_id = {
+            let context = span.context();
+            let span_context = context.span().span_context().clone();
+            span_context
+                .is_valid()
+                .then(|| span_context.trace_id().to_string())
+        };
+        let (passed, error) = match output {
+            Ok(actual) => {
+                let passed = case.check.passes(&case.expected, &actual);
+                span.set_attribute("llm.eval.actual.chars", actual.chars().count() as i64);
+                span.set_attribute("llm.eval.actual.fingerprint", fingerprint(&actual));
+                (passed, None)
+            }
+            Err(error) => {
+                span.set_attribute("error.type", "generation_failed");
+                (false, Some(format!("{error:#}")))
+            }
+        };
+        span.set_attribute("llm.eval.passed", passed);
+        span.set_attribute("llm.eval.latency_ms", duration_ms(latency));
+
+        CaseResult {
+            case_id: case.id.clone(),
+            config: config.name.clone(),
+            passed,
+            error,
+            latency_ms: latency.as_millis(),
+            trace_id,
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,191 @@
+//! Injectable time and trace/span ids, so tests can produce the same trace
+//! on every run.
+//!
+//! The SDK draws ids at random and the `tracing` bridge stamps spans with the
+//! system clock, so two runs of one test never export the same trace and a
+//! snapshot comparison fails on noise. `TelemetryBuilder::with_id_generator`
+//! takes a [`SequentialIdGenerator`] (or any SDK `IdGenerator`), and
+//! `TelemetryBuilder::with_clock` a [`SimulatedClock`] (or any [`Clock`]).
+//! Neither the SDK nor the bridge can take a clock, so the clock is applied
+//! by [`ClockLayer`](crate::processors::clock::ClockLayer), which stamps span
+//! starts and events as they happen, and
+//! [`ClockProcessor`](crate::processors::clock::ClockProcessor), which stamps
+//! span ends.
+
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry_sdk::trace::IdGenerator;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// Source of span timestamps.
+pub trait Clock: Send + Sync + fmt::Debug {
+    fn now(&self) -> SystemTime;
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+/// The system clock, as used without an injected one.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[derive(Debug)]
+struct SimulatedTime {
+    now: SystemTime,
+    step: Duration,
+}
+
+/// Clock that moves only when told to, or by a fixed step on every reading.
+///
+/// Clones share one time, so a test can keep a handle and advance the clock
+/// it passed to the builder.
+#[derive(Debug, Clone)]
+pub struct SimulatedClock {
+    time: Arc<Mutex<SimulatedTime>>,
+}
+
+impl SimulatedClock {
+    /// Starts at `start`, standing still.
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            time: Arc::new(Mutex::new(SimulatedTime {
+                now: start,
+                step: Duration::ZERO,
+            })),
+        }
+    }
+
+    /// Advances by `step` after every reading, so successive spans get
+    /// distinct, increasing times without the test driving the clock.
+    pub fn with_step(self, step: Duration) -> Self {
+        self.lock().step = step;
+        self
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.lock().now += duration;
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        self.lock().now = now;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedTime> {
+        self.time.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> SystemTime {
+        let mut time = self.lock();
+        let now = time.now;
+        let step = time.step;
+        time.now += step;
+        now
+    }
+}
+
+/// Trace and span ids counting up from 1.
+///
+/// Clones share the counters. The tracer provider is installed once per
+/// process, so tests sharing it call [`SequentialIdGenerator::reset`] to
+/// restart numbering.
+#[derive(Debug, Clone, Default)]
+pub struct SequentialIdGenerator {
+    trace_ids: Arc<AtomicU64>,
+    span_ids: Arc<AtomicU64>,
+}
+
+impl SequentialIdGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&self) {
+        self.trace_ids.store(0, Ordering::SeqCst);
+        self.span_ids.store(0, Ordering::SeqCst);
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        TraceId::from(u128::from(
+            self.trace_ids.fetch_add(1, Ordering::SeqCst) + 1,
+        ))
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        SpanId::from(self.span_ids.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000)
+    }
+
+    #[test]
+    fn simulated_clock_stands_still_without_a_step() {
+        let clock = SimulatedClock::new(start());
+        assert_eq!(clock.now(), start());
+        assert_eq!(clock.now(), start());
+    }
+
+    #[test]
+    fn simulated_clock_steps_after_every_reading() {
+        let clock = SimulatedClock::new(start()).with_step(Duration::from_millis(5));
+        assert_eq!(clock.now(), start());
+        assert_eq!(clock.now(), start() + Duration::from_millis(5));
+        assert_eq!(clock.now(), start() + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn simulated_clock_clones_share_the_time() {
+        let clock = SimulatedClock::new(start());
+        let handle = clock.clone();
+        handle.advance(Duration::from_secs(3));
+        assert_eq!(clock.now(), start() + Duration::from_secs(3));
+        handle.set(start());
+        assert_eq!(clock.now(), start());
+    }
+
+    #[test]
+    fn sequential_ids_count_up_from_one() {
+        let ids = SequentialIdGenerator::new();
+        assert_eq!(ids.new_trace_id(), TraceId::from(1_u128));
+        assert_eq!(ids.new_trace_id(), TraceId::from(2_u128));
+        assert_eq!(ids.new_span_id(), SpanId::from(1_u64));
+        assert_eq!(ids.new_span_id(), SpanId::from(2_u64));
+    }
+
+    #[test]
+    fn sequential_ids_restart_after_reset_on_every_clone() {
+        let ids = SequentialIdGenerator::new();
+        let handle = ids.clone();
+        ids.new_trace_id();
+        ids.new_span_id();
+        handle.reset();
+        assert_eq!(ids.new_trace_id(), TraceId::from(1_u128));
+        assert_eq!(ids.new_span_id(), SpanId::from(1_u64));
+    }
+}
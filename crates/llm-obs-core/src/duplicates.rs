@@ -0,0 +1,59 @@
+//! Duplicate-request detection within a time window.
+//!
+//! Accidental double-submits and retry storms pay for the same generation
+//! twice. Prompts are normalized and hashed per session; a repeat inside the
+//! window is flagged with `llm.duplicate_request = true` on the span.
+
+use crate::fingerprint::fingerprint;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug)]
+pub struct DuplicateDetector {
+    window: Duration,
+    seen: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl DuplicateDetector {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Flags `span` and returns `true` when the same normalized prompt was
+    /// sent in `session_id` within the window.
+    pub fn check(&self, span: &tracing::Span, session_id: &str, prompt: &str) -> bool {
+        let prompt_hash = fingerprint(normalize(prompt));
+        let now = Instant::now();
+
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.retain(|_, last_seen| now.duration_since(*last_seen) <= self.window);
+        let previous = seen.insert((session_id.to_owned(), prompt_hash.clone()), now);
+        drop(seen);
+
+        span.set_attribute("llm.prompt.hash", prompt_hash);
+        span.set_attribute("llm.duplicate_request", previous.is_some());
+        if let Some(previous) = previous {
+            span.set_attribute(
+                "llm.duplicate_request.age_ms",
+                now.duration_since(previous).as_millis() as i64,
+            );
+        }
+
+        previous.is_some()
+    }
+}
+
+/// Lower-cases and collapses whitespace so trivially different submissions
+/// of the same prompt hash identically.
+pub(crate) fn normalize(prompt: &str) -> String {
+    prompt
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,157 @@
+//! Application attributes on LLM and tool spans.
+//!
+//! The wrappers know the model and the tokens, not the domain: which
+//! workspace a request belongs to, which plan the customer is on, which
+//! experiment arm served them. Callbacks registered with
+//! `TelemetryBuilder::with_span_enricher` run whenever a span with
+//! `gen_ai.operation.name` (an LLM, embedding or tool span, ours or rig's)
+//! is created. They run synchronously in the task creating the span, so
+//! task-locals and (behind `server::ServerSpanLayer`)
+//! `server::request_headers` still describe the request:
+//!
+//! ```ignore
+//! let telemetry = TelemetryBuilder::new("support-bot")
+//!     .with_span_enricher(|start: &SpanStart<'_>| {
+//!         let mut attributes = vec![KeyValue::new("app.workspace", WORKSPACE.get())];
+//!         let plan = server::request_headers(|headers| plan_of(headers));
+//!         if let (Some(plan), "chat") = (plan, start.operation) {
+//!             attributes.push(KeyValue::new("app.plan", plan));
+//!         }
+//!         attributes
+//!     })
+//!     .init()?;
+//! ```
+//!
+//! Enrichers run on the hot path of every model and tool call: keep them to
+//! lookups, and keep the values low-cardinality where they are used for
+//! grouping. Host apps with their own subscriber add [`EnrichmentLayer`]
+//! after the OpenTelemetry layer.
+
+use opentelemetry::KeyValue;
+use std::fmt;
+use std::sync::Arc;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Metadata, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The span being started, as seen by an enricher.
+#[derive(Debug, Clone, Copy)]
+pub struct SpanStart<'a> {
+    /// `gen_ai.operation.name`, e.g. `chat` or `execute_tool`.
+    pub operation: &'a str,
+    /// The span's exported name when set with `otel.name`, e.g.
+    /// `chat gemini-2.5-flash`.
+    pub name: Option<&'a str>,
+    pub metadata: &'static Metadata<'static>,
+}
+
+/// Callback returning the attributes to add to a starting span.
+pub trait SpanEnricher: Send + Sync + 'static {
+    fn enrich(&self, start: &SpanStart<'_>) -> Vec<KeyValue>;
+}
+
+impl<F> SpanEnricher for F
+where
+    F: Fn(&SpanStart<'_>) -> Vec<KeyValue> + Send + Sync + 'static,
+{
+    fn enrich(&self, start: &SpanStart<'_>) -> Vec<KeyValue> {
+        self(start)
+    }
+}
+
+/// `tracing` layer running the registered enrichers; must be added after
+/// (outside) the OpenTelemetry layer, whose span data it extends.
+#[derive(Clone, Default)]
+pub struct EnrichmentLayer {
+    enrichers: Vec<Arc<dyn SpanEnricher>>,
+}
+
+impl fmt::Debug for EnrichmentLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnrichmentLayer")
+            .field("enrichers", &self.enrichers.len())
+            .finish()
+    }
+}
+
+impl EnrichmentLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `enricher`; enrichers run in registration order.
+    pub fn with_enricher(mut self, enricher: impl SpanEnricher) -> Self {
+        self.enrichers.push(Arc::new(enricher));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enrichers.is_empty()
+    }
+}
+
+#[derive(Default)]
+struct StartFields {
+    operation: Option<String>,
+    name: Option<String>,
+}
+
+impl Visit for StartFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "gen_ai.operation.name" => self.operation = Some(value.to_owned()),
+            "otel.name" => self.name = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // Names given as `format_args!` or `%display` values.
+        if field.name() == "otel.name" {
+            self.name = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl<S> Layer<S> for EnrichmentLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if self.enrichers.is_empty() {
+            return;
+        }
+        let mut fields = StartFields::default();
+        attrs.record(&mut fields);
+        let Some(operation) = fields.operation.as_deref() else {
+            return;
+        };
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        // Missing when the OpenTelemetry layer filtered the span out.
+        if span.extensions().get::<OtelData>().is_none() {
+            return;
+        }
+        let start = SpanStart {
+            operation,
+            name: fields.name.as_deref(),
+            metadata: attrs.metadata(),
+        };
+        // Collected before locking the span, so enrichers may use `tracing`.
+        let added: Vec<KeyValue> = self
+            .enrichers
+            .iter()
+            .flat_map(|enricher| enricher.enrich(&start))
+            .collect();
+        if let Some(data) = span.extensions_mut().get_mut::<OtelData>() {
+            data.builder
+                .attributes
+                .get_or_insert_with(Vec::new)
+                .extend(added);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Typed errors callers may want to match on.
+
+use crate::otlp_config::{OtlpProtocol, Signal};
+use crate::telemetry::Telemetry;
+use opentelemetry::trace::TraceId;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TelemetryError {
+    /// The host application already installed a global `tracing`
+    /// subscriber. Add the OpenTelemetry layer to that subscriber instead.
+    SubscriberAlreadySet,
+    /// An exporter header name or value is not valid in gRPC metadata. Only
+    /// the name is kept, since values usually carry credentials.
+    InvalidHeader { name: String },
+    /// The configured OTLP protocol is not built in, e.g. `http/protobuf`
+    /// without the `otlp-http` feature or `grpc` without `otlp-grpc`.
+    UnsupportedProtocol {
+        signal: Signal,
+        protocol: OtlpProtocol,
+    },
+    /// A tenant pipeline was built while `variable` is set. The OTLP
+    /// exporters always add those headers, which would send the platform's
+    /// credentials to the tenant's endpoint.
+    TenantEnvHeaders {
+        tenant: String,
+        variable: &'static str,
+    },
+}
+
+impl fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TelemetryError::SubscriberAlreadySet => write!(
+                f,
+                "a global tracing subscriber is already installed; compose the OpenTelemetry layer into it instead"
+            ),
+            TelemetryError::InvalidHeader { name } => {
+                write!(f, "OTLP header `{name}` is not valid gRPC metadata")
+            }
+            TelemetryError::UnsupportedProtocol { signal, protocol } => write!(
+                f,
+                "OTLP {protocol} export of {} is not available in this build; enable the `{}` feature or change OTEL_EXPORTER_OTLP_{}_PROTOCOL",
+                signal.as_str(),
+                if protocol.is_http() {
+                    "otlp-http"
+                } else {
+                    "otlp-grpc"
+                },
+                signal.as_str().to_ascii_uppercase()
+            ),
+            TelemetryError::TenantEnvHeaders { tenant, variable } => write!(
+                f,
+                "{variable} is set and would be sent to the endpoint of tenant `{tenant}`; pass the platform's headers with TelemetryBuilder::with_header instead"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TelemetryError {}
+
+/// Failure of an LLM call that remembers which trace it happened in.
+///
+/// The trace id is captured when the error is created, so it must be built
+/// inside the failing call's span; its `Display` output ends with
+/// `(trace_id=...)` for user-facing messages and support tickets.
+#[derive(Debug)]
+pub struct LlmError {
+    error: anyhow::Error,
+    trace_id: Option<TraceId>,
+}
+
+impl LlmError {
+    /// Wraps `error` with the trace id of the current span.
+    pub fn new(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            trace_id: Telemetry::current_trace_id(),
+        }
+    }
+
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.trace_id
+    }
+
+    pub fn into_inner(self) -> anyhow::Error {
+        self.error
+    }
+}
+
+impl From<anyhow::Error> for LlmError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::new(error)
+    }
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(trace_id) = self.trace_id {
+            write!(f, " (trace_id={trace_id})")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LlmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
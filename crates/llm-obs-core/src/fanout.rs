@@ -0,0 +1,173 @@
+//! Concurrent agent branches under one parent span.
+//!
+//! Asking several agents the same question (or splitting a task across
+//! specialists) and merging the answers is easy with a `JoinSet`, but the
+//! trace then shows a tangle of overlapping model calls with nothing saying
+//! which branch they belong to, which branch held the orchestrator up or
+//! which one failed. [`fanout`] runs each branch on its own task in a
+//! `fanout_branch {name}` span under a `fanout` span (see
+//! [`start_fanout_span`]) and, once all branches finished, records on the
+//! parent:
+//!
+//! - `llm.fanout.branch.names`, `llm.fanout.branch.durations_ms` and
+//!   `llm.fanout.branch.failed`, one entry per branch in input order;
+//! - `llm.fanout.failed`, the number of failed branches, and error status
+//!   when all of them failed;
+//! - `llm.fanout.slowest_branch`, the branch on the critical path.
+//!
+//! ```ignore
+//! // One closure, so every branch has the same future type.
+//! let ask = |agent: Agent<_>| {
+//!     let question = question.clone();
+//!     async move { agent.prompt(question).await }
+//! };
+//! let answers = fanout([("researcher", ask(researcher)), ("critic", ask(critic))]).await;
+//! for (name, answer) in answers.successes() { /* merge */ }
+//! ```
+//!
+//! A panicking branch is resumed in the caller, after the other branches
+//! are aborted.
+
+use crate::spans::{start_fanout_branch_span, start_fanout_span};
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, StringValue, Value};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// One finished branch.
+#[derive(Debug)]
+pub struct Branch<T, E> {
+    pub name: String,
+    /// Position in the input.
+    pub index: usize,
+    pub duration: Duration,
+    pub result: Result<T, E>,
+}
+
+/// The branches of a [`fanout`], in input order.
+#[derive(Debug)]
+pub struct FanOut<T, E> {
+    pub branches: Vec<Branch<T, E>>,
+}
+
+impl<T, E> FanOut<T, E> {
+    pub fn successes(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.branches
+            .iter()
+            .filter_map(|branch| Some((branch.name.as_str(), branch.result.as_ref().ok()?)))
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &E)> {
+        self.branches
+            .iter()
+            .filter_map(|branch| Some((branch.name.as_str(), branch.result.as_ref().err()?)))
+    }
+
+    /// Results in input order.
+    pub fn into_results(self) -> Vec<Result<T, E>> {
+        self.branches
+            .into_iter()
+            .map(|branch| branch.result)
+            .collect()
+    }
+}
+
+/// Runs `branches`, each a name and a future, concurrently under a `fanout`
+/// span opened under the current span, and waits for all of them.
+pub async fn fanout<I, N, F, T, E>(branches: I) -> FanOut<T, E>
+where
+    I: IntoIterator<Item = (N, F)>,
+    N: Into<String>,
+    F: Future<Output = Result<T, E>> + Send + 'static,
+    T: Send + 'static,
+    E: fmt::Display + Send + 'static,
+{
+    let branches: Vec<(String, F)> = branches
+        .into_iter()
+        .map(|(name, future)| (name.into(), future))
+        .collect();
+    let span = start_fanout_span(branches.len());
+    let size = branches.len();
+
+    let mut tasks = JoinSet::new();
+    for (index, (name, future)) in branches.into_iter().enumerate() {
+        let branch_span = span.in_scope(|| start_fanout_branch_span(&name, index));
+        tasks.spawn(async move {
+            let started = Instant::now();
+            let result = future.instrument(branch_span.clone()).await;
+            if let Err(error) = &result {
+                branch_span.record("error.type", "_OTHER");
+                branch_span.set_status(Status::error(error.to_string()));
+            }
+            Branch {
+                name,
+                index,
+                duration: started.elapsed(),
+                result,
+            }
+        });
+    }
+
+    let mut finished = Vec::with_capacity(size);
+    while let Some(joined) = tasks.join_next().instrument(span.clone()).await {
+        match joined {
+            Ok(branch) => finished.push(branch),
+            Err(error) if error.is_panic() => {
+                span.record("error.type", "panic");
+                span.set_status(Status::error("fan-out branch panicked"));
+                std::panic::resume_unwind(error.into_panic());
+            }
+            // Cancelled: only when the runtime shuts down.
+            Err(_) => {}
+        }
+    }
+    finished.sort_by_key(|branch| branch.index);
+    record_branches(&span, &finished);
+    FanOut { branches: finished }
+}
+
+fn record_branches<T, E>(span: &tracing::Span, branches: &[Branch<T, E>]) {
+    let failed = branches
+        .iter()
+        .filter(|branch| branch.result.is_err())
+        .count();
+    span.set_attribute(
+        "llm.fanout.branch.names",
+        Value::Array(Array::String(
+            branches
+                .iter()
+                .map(|branch| StringValue::from(branch.name.clone()))
+                .collect(),
+        )),
+    );
+    span.set_attribute(
+        "llm.fanout.branch.durations_ms",
+        Value::Array(Array::F64(
+            branches
+                .iter()
+                .map(|branch| branch.duration.as_secs_f64() * 1000.0)
+                .collect(),
+        )),
+    );
+    span.set_attribute(
+        "llm.fanout.branch.failed",
+        Value::Array(Array::Bool(
+            branches
+                .iter()
+                .map(|branch| branch.result.is_err())
+                .collect(),
+        )),
+    );
+    span.record("llm.fanout.failed", failed as u64);
+    if let Some(slowest) = branches.iter().max_by_key(|branch| branch.duration) {
+        span.record("llm.fanout.slowest_branch", slowest.name.as_str());
+    }
+    if failed > 0 && failed == branches.len() {
+        span.record("error.type", "all_branches_failed");
+        span.set_status(Status::error("every fan-out branch failed"));
+    }
+}
@@ -0,0 +1,155 @@
+//! Interim user feedback carried in baggage for the rest of a conversation.
+//!
+//! Explicit ratings are rare, but implicit signals are not: the user edits
+//! the answer, retries, or copies it. Putting a running score in baggage
+//! lets every later span in the session (and downstream services) record
+//! `feedback.recent_score`, so adaptive routing decisions can be analysed
+//! against how the conversation was going when they were made.
+//!
+//! Only a bounded number and a fixed signal name travel in baggage; free-text
+//! comments do not, since baggage is sent to every downstream hop.
+
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::{Context, KeyValue};
+use std::fmt;
+use std::str::FromStr;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SCORE_KEY: &str = "feedback.recent_score";
+const SIGNAL_KEY: &str = "feedback.last_signal";
+
+/// Weight of the newest signal in the running score.
+pub const DEFAULT_RECENCY_WEIGHT: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedbackSignal {
+    Accepted,
+    Copied,
+    Edited,
+    Retried,
+    Rejected,
+}
+
+impl FeedbackSignal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedbackSignal::Accepted => "accepted",
+            FeedbackSignal::Copied => "copied",
+            FeedbackSignal::Edited => "edited",
+            FeedbackSignal::Retried => "retried",
+            FeedbackSignal::Rejected => "rejected",
+        }
+    }
+
+    /// How strongly the signal suggests the answer was good.
+    pub fn score(&self) -> FeedbackScore {
+        FeedbackScore::new(match self {
+            FeedbackSignal::Accepted => 1.0,
+            FeedbackSignal::Copied => 0.5,
+            FeedbackSignal::Edited => -0.3,
+            FeedbackSignal::Retried => -0.7,
+            FeedbackSignal::Rejected => -1.0,
+        })
+    }
+}
+
+impl FromStr for FeedbackSignal {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "accepted" => Ok(FeedbackSignal::Accepted),
+            "copied" => Ok(FeedbackSignal::Copied),
+            "edited" => Ok(FeedbackSignal::Edited),
+            "retried" => Ok(FeedbackSignal::Retried),
+            "rejected" => Ok(FeedbackSignal::Rejected),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Score in `[-1.0, 1.0]`, serialized with two decimals so it stays short
+/// and cannot smuggle arbitrary text into baggage.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct FeedbackScore(f64);
+
+impl FeedbackScore {
+    /// Clamps `score` into range; NaN becomes neutral.
+    pub fn new(score: f64) -> Self {
+        if score.is_nan() {
+            return Self(0.0);
+        }
+        Self(score.clamp(-1.0, 1.0))
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+
+    /// Moves the score towards `latest` by `weight`.
+    pub fn blend(&self, latest: FeedbackScore, weight: f64) -> Self {
+        let weight = weight.clamp(0.0, 1.0);
+        Self::new(self.0 * (1.0 - weight) + latest.0 * weight)
+    }
+}
+
+impl fmt::Display for FeedbackScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0)
+    }
+}
+
+/// The feedback state found in baggage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecentFeedback {
+    pub score: FeedbackScore,
+    pub last_signal: Option<FeedbackSignal>,
+}
+
+impl RecentFeedback {
+    /// Reads the state from the baggage of `cx`; `None` before any feedback.
+    pub fn from_context(cx: &Context) -> Option<Self> {
+        let baggage = cx.baggage();
+        let score = baggage.get(SCORE_KEY)?.as_str().parse().ok()?;
+        Some(Self {
+            score: FeedbackScore::new(score),
+            last_signal: baggage
+                .get(SIGNAL_KEY)
+                .and_then(|signal| signal.as_str().parse().ok()),
+        })
+    }
+
+    pub fn record(&self, span: &tracing::Span) {
+        span.set_attribute(SCORE_KEY, self.score.value());
+        if let Some(signal) = self.last_signal {
+            span.set_attribute(SIGNAL_KEY, signal.as_str());
+        }
+    }
+}
+
+/// Returns `cx` with `signal` folded into the running score in its baggage,
+/// using [`DEFAULT_RECENCY_WEIGHT`]. The first signal sets the score.
+pub fn with_feedback_in_baggage(cx: &Context, signal: FeedbackSignal) -> Context {
+    let score = match RecentFeedback::from_context(cx) {
+        Some(recent) => recent.score.blend(signal.score(), DEFAULT_RECENCY_WEIGHT),
+        None => signal.score(),
+    };
+    let baggage: Baggage = cx
+        .baggage()
+        .iter()
+        .filter(|(key, _)| key.as_str() != SCORE_KEY && key.as_str() != SIGNAL_KEY)
+        .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+        .chain([
+            KeyValue::new(SCORE_KEY, score.to_string()),
+            KeyValue::new(SIGNAL_KEY, signal.as_str()),
+        ])
+        .collect();
+    cx.with_baggage(baggage)
+}
+
+/// Records the feedback found in the baggage of `cx` on `span`, if any.
+pub fn record_baggage_feedback(span: &tracing::Span, cx: &Context) -> Option<RecentFeedback> {
+    let recent = RecentFeedback::from_context(cx)?;
+    recent.record(span);
+    Some(recent)
+}
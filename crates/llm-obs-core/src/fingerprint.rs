@@ -0,0 +1,18 @@
+//! Stable content hashes for fingerprints and reference-only capture.
+
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: impl AsRef<[u8]>) -> String {
+    Sha256::digest(bytes.as_ref())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Short (16 hex chars) fingerprint, compact enough for span attributes.
+pub fn fingerprint(bytes: impl AsRef<[u8]>) -> String {
+    let mut digest = sha256_hex(bytes);
+    digest.truncate(16);
+    digest
+}
@@ -0,0 +1,100 @@
+//! Cross-provider finish reasons and max-token truncation detection.
+//!
+//! Gemini reports `MAX_TOKENS`, OpenAI `length`, Anthropic `max_tokens`; all
+//! three mean the answer was cut off. Truncated answers otherwise look like
+//! successful short responses, so they get a dedicated
+//! `gen_ai.response.truncated` event and the `llm.response.truncations`
+//! counter.
+
+use crate::cardinality::guarded;
+use crate::scopes::Subsystem;
+use opentelemetry::metrics::Counter;
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FinishReason {
+    Stop,
+    MaxTokens,
+    ContentFilter,
+    ToolCalls,
+    Other(String),
+}
+
+impl FinishReason {
+    /// Normalizes a raw provider finish reason.
+    pub fn parse(raw: &str) -> Self {
+        match raw.to_ascii_lowercase().as_str() {
+            "stop" | "end_turn" | "stop_sequence" | "completed" => FinishReason::Stop,
+            "max_tokens" | "length" | "max_output_tokens" => FinishReason::MaxTokens,
+            "safety" | "content_filter" | "blocklist" | "prohibited_content" | "spii"
+            | "recitation" | "refusal" => FinishReason::ContentFilter,
+            "tool_calls" | "tool_use" | "function_call" => FinishReason::ToolCalls,
+            other => FinishReason::Other(other.to_owned()),
+        }
+    }
+
+    /// Value used for `gen_ai.response.finish_reasons`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::MaxTokens => "length",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::Other(raw) => raw,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, FinishReason::MaxTokens)
+    }
+}
+
+/// Records the finish reason; on truncation also emits the event and
+/// increments the counter. Returns whether the response was truncated.
+pub fn record_finish_reason(
+    span: &tracing::Span,
+    model: &str,
+    reason: &FinishReason,
+    max_tokens: Option<u64>,
+) -> bool {
+    span.set_attribute(
+        "gen_ai.response.finish_reasons",
+        Value::Array(Array::String(vec![StringValue::from(
+            reason.as_str().to_owned(),
+        )])),
+    );
+
+    if !reason.is_truncated() {
+        return false;
+    }
+
+    let mut attributes = vec![KeyValue::new("gen_ai.request.model", model.to_owned())];
+    if let Some(max_tokens) = max_tokens {
+        attributes.push(KeyValue::new(
+            "gen_ai.request.max_tokens",
+            max_tokens as i64,
+        ));
+    }
+    span.set_attribute("gen_ai.response.truncated", true);
+    span.add_event("gen_ai.response.truncated", attributes);
+    tracing::warn!(
+        gen_ai.request.model = model,
+        "Response truncated at max tokens"
+    );
+    truncation_counter().add(1, &[guarded("gen_ai.request.model", model)]);
+
+    true
+}
+
+fn truncation_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.response.truncations")
+            .with_description("Responses cut off by the max-token limit")
+            .build()
+    })
+}
@@ -0,0 +1,415 @@
+//! Feature flags: exposure on spans and in baggage, and runtime control of
+//! the instrumentation itself.
+//!
+//! Evaluated flags are recorded as `feature_flag.evaluation` events following
+//! the OpenTelemetry feature-flag conventions, so traces can be split by
+//! cohort. Flags can also travel in baggage (`feature_flag.<key>`) so
+//! downstream services record the same cohort without re-evaluating.
+//!
+//! A process-wide provider installed with [`set_provider`] also drives the
+//! telemetry: [`bool_flag`], [`f64_flag`] and [`string_flag`] evaluate like an
+//! OpenFeature client (typed value, default on error, resolution reason) and
+//! record each evaluation on the current span. The crate reads these keys:
+//!
+//! - [`CONTENT_CAPTURE_FLAG`]: a `ContentCapturePolicy` for
+//!   `ContentCaptureProcessor::with_flag`;
+//! - [`SAMPLING_RATIO_FLAG`]: the root sampling ratio of [`FlagRatioSampler`];
+//! - [`TOOL_BATCH_FLAG`]: whether rig tool calls are grouped under
+//!   `execute_tools` spans.
+//!
+//! OpenFeature, LaunchDarkly or Unleash clients plug in by implementing
+//! [`FlagProvider`]; evaluation is synchronous, so providers with async
+//! clients should serve from their local cache.
+
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::trace::{
+    Link, SamplingDecision, SamplingResult, SpanKind, TraceContextExt, TraceId,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::trace::{Sampler, ShouldSample};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, PoisonError, RwLock};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const BAGGAGE_PREFIX: &str = "feature_flag.";
+
+/// Flag holding the content capture policy (`full`, `truncated:<n>`, `hash`
+/// or `off`).
+pub const CONTENT_CAPTURE_FLAG: &str = "llm.content_capture";
+
+/// Flag holding the head sampling ratio for root spans, `0.0..=1.0`.
+pub const SAMPLING_RATIO_FLAG: &str = "llm.sampling.ratio";
+
+/// Flag switching `execute_tools` batch spans on and off; on by default.
+pub const TOOL_BATCH_FLAG: &str = "llm.instrumentation.tool_batch";
+
+static PROVIDER: RwLock<Option<Arc<dyn FlagProvider>>> = RwLock::new(None);
+
+/// Source of flag values (LaunchDarkly, Unleash, a config file, ...).
+pub trait FlagProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Returns the evaluated variant, or `None` when the flag is unknown.
+    fn evaluate(&self, key: &str) -> Option<String>;
+
+    /// Evaluates `key` with the reason for the result. The default reports
+    /// [`FlagReason::Unknown`], or a `flag_not_found` error when
+    /// [`evaluate`](Self::evaluate) returns `None`.
+    fn resolve(&self, key: &str) -> FlagEvaluation {
+        match self.evaluate(key) {
+            Some(variant) => FlagEvaluation::resolved(variant, FlagReason::Unknown),
+            None => FlagEvaluation::error("flag_not_found"),
+        }
+    }
+}
+
+/// Why a provider returned its value, after OpenFeature's resolution
+/// reasons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagReason {
+    Static,
+    Default,
+    TargetingMatch,
+    Split,
+    Cached,
+    Disabled,
+    Unknown,
+    Stale,
+    Error,
+}
+
+impl FlagReason {
+    /// The `feature_flag.result.reason` value.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlagReason::Static => "static",
+            FlagReason::Default => "default",
+            FlagReason::TargetingMatch => "targeting_match",
+            FlagReason::Split => "split",
+            FlagReason::Cached => "cached",
+            FlagReason::Disabled => "disabled",
+            FlagReason::Unknown => "unknown",
+            FlagReason::Stale => "stale",
+            FlagReason::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for FlagReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The outcome of one flag evaluation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlagEvaluation {
+    pub variant: Option<String>,
+    pub reason: FlagReason,
+    /// OpenFeature error code in lower case, e.g. `flag_not_found` or
+    /// `type_mismatch`; the caller's default applies.
+    pub error_type: Option<String>,
+}
+
+impl FlagEvaluation {
+    pub fn resolved(variant: impl Into<String>, reason: FlagReason) -> Self {
+        Self {
+            variant: Some(variant.into()),
+            reason,
+            error_type: None,
+        }
+    }
+
+    pub fn error(error_type: impl Into<String>) -> Self {
+        Self {
+            variant: None,
+            reason: FlagReason::Error,
+            error_type: Some(error_type.into()),
+        }
+    }
+}
+
+/// Fixed flag values, e.g. loaded from config or set in tests.
+#[derive(Debug, Clone, Default)]
+pub struct StaticFlags {
+    values: HashMap<String, String>,
+}
+
+impl StaticFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_flag(mut self, key: impl Into<String>, variant: impl Into<String>) -> Self {
+        self.values.insert(key.into(), variant.into());
+        self
+    }
+
+    /// Parses `key=variant` pairs separated by commas, e.g. from an env var.
+    pub fn parse(spec: &str) -> Self {
+        let values = spec
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, variant)| (key.trim().to_owned(), variant.trim().to_owned()))
+            .filter(|(key, _)| !key.is_empty())
+            .collect();
+        Self { values }
+    }
+}
+
+impl FlagProvider for StaticFlags {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn evaluate(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn resolve(&self, key: &str) -> FlagEvaluation {
+        match self.evaluate(key) {
+            Some(variant) => FlagEvaluation::resolved(variant, FlagReason::Static),
+            None => FlagEvaluation::error("flag_not_found"),
+        }
+    }
+}
+
+/// Installs the process-wide provider read by [`bool_flag`], [`f64_flag`],
+/// [`string_flag`] and the flag-driven telemetry settings, replacing any
+/// previous one.
+pub fn set_provider(provider: impl FlagProvider + 'static) {
+    *PROVIDER.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(provider));
+}
+
+/// Removes the process-wide provider; every flag then takes its default.
+pub fn clear_provider() {
+    PROVIDER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+}
+
+pub(crate) fn provider() -> Option<Arc<dyn FlagProvider>> {
+    PROVIDER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Evaluates a boolean flag (`true` / `false`) with the process-wide provider
+/// and records the evaluation on the current span. Returns `default` when no
+/// provider is installed (recording nothing), the flag is unknown or its
+/// variant is not a boolean.
+pub fn bool_flag(key: &str, default: bool) -> bool {
+    typed_flag(key, default, |variant| variant.trim().parse().ok())
+}
+
+/// [`bool_flag`] for numeric flags.
+pub fn f64_flag(key: &str, default: f64) -> f64 {
+    typed_flag(key, default, |variant| variant.trim().parse().ok())
+}
+
+/// [`bool_flag`] for string flags.
+pub fn string_flag(key: &str, default: &str) -> String {
+    typed_flag(key, default.to_owned(), |variant| Some(variant.to_owned()))
+}
+
+fn typed_flag<T: fmt::Display>(key: &str, default: T, parse: impl FnOnce(&str) -> Option<T>) -> T {
+    let Some(provider) = provider() else {
+        return default;
+    };
+    let (evaluation, value) = resolve_typed(provider.as_ref(), key, default, parse);
+    tracing::Span::current().add_event(
+        "feature_flag.evaluation",
+        evaluation_attributes(provider.name(), key, &evaluation, &value.to_string()),
+    );
+    value
+}
+
+/// Evaluates `key` and parses its variant, falling back to `default` with a
+/// `type_mismatch` error when the variant does not parse.
+pub(crate) fn resolve_typed<T>(
+    provider: &dyn FlagProvider,
+    key: &str,
+    default: T,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> (FlagEvaluation, T) {
+    let mut evaluation = provider.resolve(key);
+    let value = match evaluation.variant.as_deref().map(parse) {
+        Some(Some(value)) => value,
+        Some(None) => {
+            evaluation.reason = FlagReason::Error;
+            evaluation.error_type = Some("type_mismatch".to_owned());
+            default
+        }
+        None => default,
+    };
+    (evaluation, value)
+}
+
+/// Attributes of a `feature_flag.evaluation` event.
+pub(crate) fn evaluation_attributes(
+    provider_name: &str,
+    key: &str,
+    evaluation: &FlagEvaluation,
+    value: &str,
+) -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new("feature_flag.key", key.to_owned()),
+        KeyValue::new("feature_flag.provider.name", provider_name.to_owned()),
+        KeyValue::new("feature_flag.result.value", value.to_owned()),
+        KeyValue::new("feature_flag.result.reason", evaluation.reason.as_str()),
+    ];
+    if let Some(variant) = &evaluation.variant {
+        attributes.push(KeyValue::new(
+            "feature_flag.result.variant",
+            variant.clone(),
+        ));
+    }
+    if let Some(error_type) = &evaluation.error_type {
+        attributes.push(KeyValue::new("error.type", error_type.clone()));
+    }
+    attributes
+}
+
+/// Evaluates `key` and records the evaluation on `span`.
+pub fn evaluate_flag(
+    span: &tracing::Span,
+    provider: &dyn FlagProvider,
+    key: &str,
+) -> Option<String> {
+    let variant = provider.evaluate(key);
+    record_flag(span, provider.name(), key, variant.as_deref());
+    variant
+}
+
+pub fn record_flag(span: &tracing::Span, provider_name: &str, key: &str, variant: Option<&str>) {
+    let mut attributes = vec![
+        KeyValue::new("feature_flag.key", key.to_owned()),
+        KeyValue::new("feature_flag.provider.name", provider_name.to_owned()),
+    ];
+    if let Some(variant) = variant {
+        attributes.push(KeyValue::new(
+            "feature_flag.result.variant",
+            variant.to_owned(),
+        ));
+    }
+    span.add_event("feature_flag.evaluation", attributes);
+}
+
+/// Returns `cx` with the given flag variants added to its baggage.
+pub fn with_flags_in_baggage(cx: &Context, flags: &[(&str, &str)]) -> Context {
+    let baggage: Baggage = cx
+        .baggage()
+        .iter()
+        .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+        .chain(flags.iter().map(|(key, variant)| {
+            KeyValue::new(format!("{BAGGAGE_PREFIX}{key}"), (*variant).to_owned())
+        }))
+        .collect();
+    cx.with_baggage(baggage)
+}
+
+/// Records every flag found in the baggage of `cx` on `span`.
+pub fn record_baggage_flags(span: &tracing::Span, cx: &Context) {
+    for (key, (value, _)) in cx.baggage().iter() {
+        if let Some(flag) = key.as_str().strip_prefix(BAGGAGE_PREFIX) {
+            record_flag(span, "baggage", flag, Some(value.as_str()));
+        }
+    }
+}
+
+/// Head sampler whose root-span ratio is the [`SAMPLING_RATIO_FLAG`] value,
+/// re-read on every root span so a ramp takes effect without a restart.
+/// Child spans follow their parent. Sampled roots carry the ratio in effect
+/// as `llm.sampling.ratio`; the evaluation itself is not recorded, since the
+/// span does not exist yet when the sampler runs.
+#[derive(Debug, Clone)]
+pub struct FlagRatioSampler {
+    key: String,
+    default_ratio: f64,
+}
+
+impl FlagRatioSampler {
+    /// Samples `default_ratio` of root spans while the flag is unset or
+    /// invalid, or no provider is installed.
+    pub fn new(default_ratio: f64) -> Self {
+        Self {
+            key: SAMPLING_RATIO_FLAG.to_owned(),
+            default_ratio,
+        }
+    }
+
+    /// Reads the ratio from `key` instead of [`SAMPLING_RATIO_FLAG`].
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = key.into();
+        self
+    }
+
+    fn ratio(&self) -> f64 {
+        let ratio = match provider() {
+            Some(provider) => {
+                resolve_typed(
+                    provider.as_ref(),
+                    &self.key,
+                    self.default_ratio,
+                    |variant| {
+                        variant
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|ratio| (0.0..=1.0).contains(ratio))
+                    },
+                )
+                .1
+            }
+            None => self.default_ratio,
+        };
+        ratio.clamp(0.0, 1.0)
+    }
+}
+
+impl ShouldSample for FlagRatioSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let parent = parent_context
+            .map(|cx| cx.span().span_context().clone())
+            .filter(|span_context| span_context.is_valid());
+        if let Some(parent) = parent {
+            let decision = if parent.is_sampled() {
+                SamplingDecision::RecordAndSample
+            } else {
+                SamplingDecision::Drop
+            };
+            return SamplingResult {
+                decision,
+                attributes: Vec::new(),
+                trace_state: parent.trace_state().clone(),
+            };
+        }
+
+        let ratio = self.ratio();
+        let mut result = Sampler::TraceIdRatioBased(ratio).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        );
+        if result.decision == SamplingDecision::RecordAndSample {
+            result
+                .attributes
+                .push(KeyValue::new("llm.sampling.ratio", ratio));
+        }
+        result
+    }
+}
@@ -0,0 +1,127 @@
+//! GenAI client metrics following the OpenTelemetry semantic conventions.
+//!
+//! `gen_ai.client.token.usage` and `gen_ai.client.operation.duration` use the
+//! names, units, attributes and advisory bucket boundaries from the GenAI
+//! semconv, so generic GenAI dashboards work without custom queries.
+
+use crate::cardinality::guarded;
+use crate::scopes::Subsystem;
+use crate::tokens::TokenUsage;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Histogram, Meter};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const TOKEN_USAGE_BOUNDARIES: [f64; 14] = [
+    1.0, 4.0, 16.0, 64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0,
+    16777216.0, 67108864.0,
+];
+
+const DURATION_BOUNDARIES: [f64; 14] = [
+    0.01, 0.02, 0.04, 0.08, 0.16, 0.32, 0.64, 1.28, 2.56, 5.12, 10.24, 20.48, 40.96, 81.92,
+];
+
+/// Identifies one GenAI operation for metric attributes.
+#[derive(Debug, Clone, Copy)]
+pub struct GenAiCall<'a> {
+    /// `chat`, `text_completion`, `embeddings`, ...
+    pub operation: &'a str,
+    /// `gcp.gemini`, `openai`, `anthropic`, ...
+    pub provider: &'a str,
+    pub request_model: &'a str,
+    pub response_model: Option<&'a str>,
+    pub server_address: Option<&'a str>,
+    pub server_port: Option<u16>,
+}
+
+impl GenAiCall<'_> {
+    pub(crate) fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.operation.name", self.operation.to_owned()),
+            KeyValue::new("gen_ai.provider.name", self.provider.to_owned()),
+            guarded("gen_ai.request.model", self.request_model),
+        ];
+        if let Some(response_model) = self.response_model {
+            attributes.push(guarded("gen_ai.response.model", response_model));
+        }
+        if let Some(server_address) = self.server_address {
+            attributes.push(KeyValue::new("server.address", server_address.to_owned()));
+        }
+        if let Some(server_port) = self.server_port {
+            attributes.push(KeyValue::new("server.port", i64::from(server_port)));
+        }
+        attributes
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GenAiMetrics {
+    token_usage: Histogram<u64>,
+    operation_duration: Histogram<f64>,
+}
+
+impl GenAiMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            token_usage: meter
+                .u64_histogram("gen_ai.client.token.usage")
+                .with_unit("{token}")
+                .with_description("Measures number of input and output tokens used")
+                .with_boundaries(TOKEN_USAGE_BOUNDARIES.to_vec())
+                .build(),
+            operation_duration: meter
+                .f64_histogram("gen_ai.client.operation.duration")
+                .with_unit("s")
+                .with_description("GenAI operation duration")
+                .with_boundaries(DURATION_BOUNDARIES.to_vec())
+                .build(),
+        }
+    }
+
+    /// Instruments on the global meter provider. Install the provider
+    /// before the first call; instruments created earlier stay no-ops.
+    pub fn global() -> &'static GenAiMetrics {
+        static GLOBAL: OnceLock<GenAiMetrics> = OnceLock::new();
+        GLOBAL.get_or_init(|| GenAiMetrics::new(&Subsystem::Agent.meter()))
+    }
+
+    /// Records duration and, when known, token usage for one operation.
+    /// `error_type` is set for failed operations (e.g. `timeout`, `429`).
+    pub fn record(
+        &self,
+        call: &GenAiCall<'_>,
+        duration: Duration,
+        usage: Option<&TokenUsage>,
+        error_type: Option<&str>,
+    ) {
+        self.record_attributes(call.attributes(), duration, usage, error_type);
+    }
+
+    /// [`GenAiMetrics::record`] with the call's attributes already built.
+    pub(crate) fn record_attributes(
+        &self,
+        attributes: Vec<KeyValue>,
+        duration: Duration,
+        usage: Option<&TokenUsage>,
+        error_type: Option<&str>,
+    ) {
+        let mut duration_attributes = attributes.clone();
+        if let Some(error_type) = error_type {
+            duration_attributes.push(KeyValue::new("error.type", error_type.to_owned()));
+        }
+        self.operation_duration
+            .record(duration.as_secs_f64(), &duration_attributes);
+
+        let Some(usage) = usage else {
+            return;
+        };
+        for (token_type, tokens) in [
+            ("input", usage.input_tokens),
+            ("output", usage.output_tokens + usage.reasoning_tokens),
+        ] {
+            let mut token_attributes = attributes.clone();
+            token_attributes.push(KeyValue::new("gen_ai.token.type", token_type));
+            self.token_usage.record(tokens, &token_attributes);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! User, session and tenant identity carried in baggage.
+//!
+//! Cost and latency per user only add up if every LLM span of the user's
+//! request says whose it is, including spans opened deep inside agent code
+//! and in downstream services that never see the user. Baggage travels with
+//! the context to child spans and, through the propagator, across HTTP
+//! calls; [`BaggageProcessor`](crate::processors::baggage::BaggageProcessor)
+//! then copies `user.id`, `session.id` and `tenant.id` onto the spans
+//! themselves, where backends can group by them.
+//!
+//! ```ignore
+//! let identity = Identity::new().with_user_id(&user).with_session_id(&session);
+//! let span = tracing::info_span!("handle_chat");
+//! span.set_parent(identity.current_context());
+//! agent.prompt(question).instrument(span).await?;
+//! ```
+//!
+//! Servers behind `server::ServerSpanLayer` get the caller's identity from
+//! the incoming `baggage` header without any code.
+
+use crate::semconv::{SESSION_ID, TENANT_ID, USER_ID};
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::{Context, KeyValue};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Who a request is for. Unset fields leave the baggage entry as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Identity {
+    pub user_id: Option<String>,
+    pub session_id: Option<String>,
+    pub tenant_id: Option<String>,
+}
+
+impl Identity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An opaque user id; avoid emails and names, since baggage is sent to
+    /// every downstream service.
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    pub fn with_session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    pub fn with_tenant_id(mut self, tenant_id: impl Into<String>) -> Self {
+        self.tenant_id = Some(tenant_id.into());
+        self
+    }
+
+    /// The identity in the baggage of `cx`.
+    pub fn from_context(cx: &Context) -> Self {
+        let baggage = cx.baggage();
+        let entry = |key: &str| baggage.get(key).map(|value| value.to_string());
+        Self {
+            user_id: entry(USER_ID),
+            session_id: entry(SESSION_ID),
+            tenant_id: entry(TENANT_ID),
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            (USER_ID, &self.user_id),
+            (SESSION_ID, &self.session_id),
+            (TENANT_ID, &self.tenant_id),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_deref().map(|value| (key, value)))
+    }
+
+    /// Returns `cx` with this identity in its baggage, replacing the entries
+    /// it sets and keeping the rest.
+    pub fn apply(&self, cx: &Context) -> Context {
+        let baggage: Baggage = cx
+            .baggage()
+            .iter()
+            .filter(|(key, _)| self.entries().all(|(set, _)| set != key.as_str()))
+            .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+            .chain(
+                self.entries()
+                    .map(|(key, value)| KeyValue::new(key, value.to_owned())),
+            )
+            .collect();
+        cx.with_baggage(baggage)
+    }
+
+    /// The current span's context with this identity added: the parent for
+    /// the spans of a request, so they nest where they would have and carry
+    /// the identity.
+    pub fn current_context(&self) -> Context {
+        self.apply(&tracing::Span::current().context())
+    }
+}
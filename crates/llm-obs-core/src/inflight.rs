@@ -0,0 +1,268 @@
+//! Registry of LLM calls that are still running.
+//!
+//! Spans are only exported once they end, so a stuck agent is invisible in the
+//! backend until it finally times out. The registry keeps a live view of every
+//! tracked call (model, elapsed time, tokens so far) that can be queried in
+//! process or over a tiny HTTP admin endpoint. `llm-obs-rig`'s
+//! `InstrumentedAgent` tracks its calls in [`InflightRegistry::global`],
+//! adding an estimate of the streamed tokens as chunks arrive.
+
+use crate::spans::start_server_span;
+use crate::telemetry::Telemetry;
+use anyhow::Context;
+use opentelemetry::trace::TraceContextExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct InflightEntry {
+    model: String,
+    operation: String,
+    trace_id: Option<String>,
+    started: Instant,
+    started_at_unix_ms: u128,
+    tokens: u64,
+}
+
+/// Point-in-time view of one in-flight call.
+#[derive(Debug, Clone, Serialize)]
+pub struct InflightSnapshot {
+    pub id: u64,
+    pub model: String,
+    pub operation: String,
+    pub trace_id: Option<String>,
+    pub started_at_unix_ms: u128,
+    pub elapsed_ms: u128,
+    pub tokens: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct InflightRegistry {
+    entries: Arc<Mutex<HashMap<u64, InflightEntry>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl InflightRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide registry used when callers do not manage their own.
+    pub fn global() -> &'static InflightRegistry {
+        static GLOBAL: OnceLock<InflightRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(InflightRegistry::new)
+    }
+
+    /// Registers a call; it stays visible until the returned guard is dropped.
+    pub fn track(&self, model: &str, operation: &str) -> InflightGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let span_context = tracing::Span::current().context();
+        let span_context = span_context.span().span_context().clone();
+        let trace_id = span_context
+            .is_valid()
+            .then(|| span_context.trace_id().to_string());
+
+        let entry = InflightEntry {
+            model: model.to_owned(),
+            operation: operation.to_owned(),
+            trace_id,
+            started: Instant::now(),
+            started_at_unix_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis())
+                .unwrap_or_default(),
+            tokens: 0,
+        };
+        self.lock().insert(id, entry);
+
+        InflightGuard {
+            registry: self.clone(),
+            id,
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<InflightSnapshot> {
+        let mut snapshot: Vec<InflightSnapshot> = self
+            .lock()
+            .iter()
+            .map(|(id, entry)| InflightSnapshot {
+                id: *id,
+                model: entry.model.clone(),
+                operation: entry.operation.clone(),
+                trace_id: entry.trace_id.clone(),
+                started_at_unix_ms: entry.started_at_unix_ms,
+                elapsed_ms: entry.started.elapsed().as_millis(),
+                tokens: entry.tokens,
+            })
+            .collect();
+        snapshot.sort_by_key(|entry| std::cmp::Reverse(entry.elapsed_ms));
+        snapshot
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, InflightEntry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keeps a call registered while alive.
+pub struct InflightGuard {
+    registry: InflightRegistry,
+    id: u64,
+}
+
+impl InflightGuard {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Adds streamed or partial token counts as they arrive.
+    pub fn add_tokens(&self, tokens: u64) {
+        if let Some(entry) = self.registry.lock().get_mut(&self.id) {
+            entry.tokens += tokens;
+        }
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
+}
+
+/// Serves `GET /inflight` as JSON on `addr` until the task is dropped.
+///
+/// This is a debugging aid: bind it to localhost or an internal interface only.
+pub async fn serve_admin(
+    registry: InflightRegistry,
+    addr: impl ToSocketAddrs,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .context("Failed to bind in-flight admin endpoint")?;
+    serve_admin_on(registry, listener).await
+}
+
+/// [`serve_admin`] on an already bound `listener`, e.g. one on port 0.
+pub async fn serve_admin_on(
+    registry: InflightRegistry,
+    listener: TcpListener,
+) -> anyhow::Result<()> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .context("Failed to accept admin connection")?;
+        let registry = registry.clone();
+
+        tokio::spawn(async move {
+            let mut buffer = [0_u8; 1024];
+            let read = match stream.read(&mut buffer).await {
+                Ok(read) => read,
+                Err(error) => {
+                    tracing::debug!(%error, "Failed to read admin request");
+                    return;
+                }
+            };
+            let request = String::from_utf8_lossy(&buffer[..read]);
+            let mut request_line = request.split_whitespace();
+            let method = request_line.next().unwrap_or("GET");
+            let path = request_line.next().unwrap_or("/");
+
+            let known_route = method == "GET" && path == "/inflight";
+            let span = start_server_span(method, if known_route { path } else { "unknown" });
+            let (status, status_code, body) = if known_route {
+                let body =
+                    serde_json::to_string(&registry.snapshot()).unwrap_or_else(|_| "[]".to_owned());
+                ("200 OK", 200_u16, body)
+            } else {
+                ("404 Not Found", 404, r#"{"error":"not found"}"#.to_owned())
+            };
+            span.record("http.response.status_code", status_code);
+            let trace_header = span
+                .in_scope(Telemetry::trace_id_header)
+                .map(|(name, value)| format!("{name}: {value}\r\n"))
+                .unwrap_or_default();
+
+            let response = format!(
+                "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n{trace_header}connection: close\r\n\r\n{body}",
+                body.len()
+            );
+            if let Err(error) = stream.write_all(response.as_bytes()).instrument(span).await {
+                tracing::debug!(%error, "Failed to write admin response");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpStream;
+
+    #[test]
+    fn tracks_calls_until_their_guard_drops() {
+        let registry = InflightRegistry::new();
+        let first = registry.track("gemini-2.5-flash", "chat");
+        let second = registry.track("gpt-4o", "chat_streaming");
+        second.add_tokens(3);
+        second.add_tokens(4);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        let streaming = snapshot.iter().find(|call| call.id == second.id()).unwrap();
+        assert_eq!(streaming.model, "gpt-4o");
+        assert_eq!(streaming.operation, "chat_streaming");
+        assert_eq!(streaming.tokens, 7);
+        assert_eq!(streaming.trace_id, None);
+
+        drop(first);
+        assert_eq!(registry.len(), 1);
+        drop(second);
+        assert!(registry.is_empty());
+    }
+
+    async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {path} HTTP/1.1\r\nhost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn the_admin_endpoint_lists_inflight_calls() {
+        let registry = InflightRegistry::new();
+        let _call = registry.track("gemini-2.5-flash", "chat");
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_admin_on(registry, listener));
+
+        let response = get(addr, "/inflight").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let calls: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(calls[0]["model"], "gemini-2.5-flash");
+        assert_eq!(calls[0]["tokens"], 0);
+
+        let response = get(addr, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"), "{response}");
+        server.abort();
+    }
+}
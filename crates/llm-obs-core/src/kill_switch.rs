@@ -0,0 +1,212 @@
+//! Runtime kill switch for telemetry.
+//!
+//! During an incident the telemetry pipeline can be the problem: an exporter
+//! retrying against a dead collector, span processing dominating CPU, or
+//! prompts leaking into a backend they should not reach. [`disable`] stops
+//! capture and export process-wide without a restart; [`enable`] resumes it.
+//!
+//! The switch starts off when `LLM_TELEMETRY_DISABLED` or the standard
+//! `OTEL_SDK_DISABLED` is `true` (read by [`init_from_env`], which
+//! `TelemetryBuilder` calls). While it is on:
+//!
+//! - the OpenTelemetry layer installed by `TelemetryBuilder::init` skips new
+//!   spans entirely, so they are no-ops for the exporter;
+//! - [`KillSwitchSampler`] drops spans started through any other path;
+//! - the OTLP log layer (`logs::layer`, with `logs`) emits no records;
+//! - [`KillSwitchExporter`] (with `metrics`) discards metric exports, for
+//!   every exporter `MetricsBuilder` installs.
+//!
+//! Console logs from the `fmt` layer are unaffected.
+
+use opentelemetry::KeyValue;
+use opentelemetry::trace::{Link, SamplingDecision, SamplingResult, SpanKind, TraceId};
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::error::OTelSdkResult;
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::metrics::Temporality;
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::trace::ShouldSample;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "metrics")]
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing::subscriber::Interest;
+use tracing_subscriber::filter::DynFilterFn;
+use tracing_subscriber::layer::Filter;
+
+const DISABLE_ENV_VARS: [&str; 2] = ["LLM_TELEMETRY_DISABLED", "OTEL_SDK_DISABLED"];
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Stops span capture and metric export until [`enable`] is called.
+pub fn disable() {
+    if !DISABLED.swap(true, Ordering::Relaxed) {
+        tracing::warn!("Telemetry disabled by kill switch");
+    }
+}
+
+pub fn enable() {
+    if DISABLED.swap(false, Ordering::Relaxed) {
+        tracing::warn!("Telemetry re-enabled");
+    }
+}
+
+pub fn is_enabled() -> bool {
+    !DISABLED.load(Ordering::Relaxed)
+}
+
+/// Disables telemetry when one of the disable variables is `true`; leaves
+/// the switch alone otherwise, so a runtime [`disable`] is not undone.
+pub fn init_from_env() {
+    let disabled = DISABLE_ENV_VARS.iter().any(|name| {
+        std::env::var(name).is_ok_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    });
+    if disabled {
+        disable();
+    }
+}
+
+/// Per-layer filter that hides every span and event from the layer it is
+/// attached to while the switch is off. Every callsite reports `sometimes`
+/// interest, so tracing asks again on each hit instead of caching the
+/// answer, and flipping the switch takes effect immediately.
+pub fn layer_filter<S: Subscriber>() -> impl Filter<S> {
+    switch_filter(is_enabled)
+}
+
+fn switch_filter<S: Subscriber>(enabled: fn() -> bool) -> impl Filter<S> {
+    DynFilterFn::new(move |_, _| enabled()).with_callsite_filter(|_| Interest::sometimes())
+}
+
+/// Drops every span while the switch is off and defers to `inner` otherwise.
+#[derive(Debug, Clone)]
+pub struct KillSwitchSampler<S> {
+    inner: S,
+}
+
+impl<S: ShouldSample> KillSwitchSampler<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: ShouldSample + Clone + 'static> ShouldSample for KillSwitchSampler<S> {
+    fn should_sample(
+        &self,
+        parent_context: Option<&opentelemetry::Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        if !is_enabled() {
+            return SamplingResult {
+                decision: SamplingDecision::Drop,
+                attributes: Vec::new(),
+                trace_state: Default::default(),
+            };
+        }
+        self.inner
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Metric exporter that discards exports while the switch is off.
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+pub struct KillSwitchExporter<E> {
+    inner: E,
+}
+
+#[cfg(feature = "metrics")]
+impl<E: PushMetricExporter> KillSwitchExporter<E> {
+    pub fn new(inner: E) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl<E: PushMetricExporter> PushMetricExporter for KillSwitchExporter<E> {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        if !is_enabled() {
+            return Ok(());
+        }
+        self.inner.export(metrics).await
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.inner.temporality()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    /// Counts the spans and events that reach it.
+    #[derive(Clone, Default)]
+    struct Count(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for Count {
+        fn on_new_span(
+            &self,
+            _attrs: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _ctx: Context<'_, S>,
+        ) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_event(&self, _event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // A switch of its own, so flipping it does not disable telemetry for
+    // tests running in parallel.
+    static TEST_ENABLED: AtomicBool = AtomicBool::new(true);
+
+    fn test_enabled() -> bool {
+        TEST_ENABLED.load(Ordering::Relaxed)
+    }
+
+    fn hit() {
+        tracing::info_span!("kill_switch_probe").in_scope(|| tracing::info!("probe"));
+    }
+
+    #[test]
+    fn flipping_the_switch_filters_callsites_that_already_fired() {
+        let count = Count::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(count.clone().with_filter(switch_filter(test_enabled)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            hit();
+            assert_eq!(count.0.load(Ordering::Relaxed), 2);
+
+            TEST_ENABLED.store(false, Ordering::Relaxed);
+            hit();
+            assert_eq!(count.0.load(Ordering::Relaxed), 2);
+
+            TEST_ENABLED.store(true, Ordering::Relaxed);
+            hit();
+            assert_eq!(count.0.load(Ordering::Relaxed), 4);
+        });
+    }
+}
@@ -0,0 +1,260 @@
+//! Lightweight language detection for prompts and responses.
+//!
+//! Multilingual products regress per language: a model update can keep
+//! English quality flat while Japanese answers get slower or worse. Tagging
+//! spans with `llm.input.language` / `llm.output.language` lets quality and
+//! latency dashboards be sliced by language without capturing content.
+//!
+//! The built-in [`HeuristicDetector`] uses Unicode scripts plus stopword
+//! counts for common Latin-script languages. It is cheap enough to run on
+//! every call; plug a statistical detector in through [`LanguageDetector`]
+//! when more precision is needed.
+
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Recorded when the detector cannot decide (ISO 639-2 "undetermined").
+pub const UNDETERMINED: &str = "und";
+
+/// Characters scanned per text; enough to decide, bounded for long outputs.
+const SCAN_CHARS: usize = 2_000;
+
+/// Minimum share of letters in one non-Latin script to pick that script.
+const SCRIPT_SHARE: f64 = 0.3;
+
+/// Minimum stopword hits before a Latin-script language is reported.
+const MIN_STOPWORD_HITS: usize = 2;
+
+const STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "in", "that", "it", "with", "for", "this",
+            "what", "you",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "las", "de", "que", "y", "es", "en", "por", "para", "una", "con",
+            "qué",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "des", "est", "et", "une", "que", "pour", "dans", "avec", "pas",
+            "vous", "je",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ein", "eine", "mit", "für", "ich", "sie",
+            "auf", "wie",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "che", "di", "e", "è", "un", "una", "per", "non", "con", "sono", "gli", "della",
+            "come",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "que", "de", "e", "é", "um", "uma", "para", "com", "não", "por", "você",
+            "como",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "is", "van", "niet", "dat", "ik", "je", "met", "voor", "wat",
+            "zijn",
+        ],
+    ),
+];
+
+/// Detects the dominant language of a text as an ISO 639-1 code.
+pub trait LanguageDetector: Send + Sync {
+    fn detect(&self, text: &str) -> Option<String>;
+}
+
+/// Script and stopword based detector with no external dependencies.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicDetector;
+
+impl LanguageDetector for HeuristicDetector {
+    fn detect(&self, text: &str) -> Option<String> {
+        detect_script(text)
+            .or_else(|| detect_latin(text))
+            .map(str::to_owned)
+    }
+}
+
+fn detect_script(text: &str) -> Option<&'static str> {
+    let mut letters = 0usize;
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+    for ch in text
+        .chars()
+        .take(SCAN_CHARS)
+        .filter(|ch| ch.is_alphabetic())
+    {
+        letters += 1;
+        if let Some(language) = script_language(ch) {
+            match counts.iter_mut().find(|(code, _)| *code == language) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((language, 1)),
+            }
+        }
+    }
+    if letters == 0 {
+        return None;
+    }
+
+    // Japanese mixes kana with kanji; any meaningful kana share wins over
+    // the Han count that would otherwise read as Chinese.
+    let count_of = |code: &str| {
+        counts
+            .iter()
+            .find(|(language, _)| *language == code)
+            .map_or(0, |(_, count)| *count)
+    };
+    if count_of("ja") as f64 / letters as f64 >= SCRIPT_SHARE / 3.0 {
+        return Some("ja");
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|(_, count)| *count as f64 / letters as f64 >= SCRIPT_SHARE)
+        .map(|(language, _)| language)
+}
+
+fn script_language(ch: char) -> Option<&'static str> {
+    match ch as u32 {
+        0x3040..=0x30FF => Some("ja"),
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF => Some("zh"),
+        0xAC00..=0xD7AF | 0x1100..=0x11FF => Some("ko"),
+        0x0400..=0x04FF => Some("ru"),
+        0x0600..=0x06FF => Some("ar"),
+        0x0590..=0x05FF => Some("he"),
+        0x0900..=0x097F => Some("hi"),
+        0x0370..=0x03FF => Some("el"),
+        0x0E00..=0x0E7F => Some("th"),
+        _ => None,
+    }
+}
+
+fn detect_latin(text: &str) -> Option<&'static str> {
+    let head: String = text
+        .chars()
+        .take(SCAN_CHARS)
+        .collect::<String>()
+        .to_lowercase();
+    let words: Vec<&str> = head
+        .split(|ch: char| !ch.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words.iter().filter(|word| stopwords.contains(word)).count();
+            (*language, hits)
+        })
+        .filter(|(_, hits)| *hits >= MIN_STOPWORD_HITS)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(language, _)| language)
+}
+
+/// Records `llm.input.language` for the prompt.
+pub fn record_input_language(span: &tracing::Span, detector: &dyn LanguageDetector, prompt: &str) {
+    let language = detector
+        .detect(prompt)
+        .unwrap_or_else(|| UNDETERMINED.to_owned());
+    span.set_attribute("llm.input.language", language);
+}
+
+/// Records `llm.output.language` for the response.
+pub fn record_output_language(
+    span: &tracing::Span,
+    detector: &dyn LanguageDetector,
+    response: &str,
+) {
+    let language = detector
+        .detect(response)
+        .unwrap_or_else(|| UNDETERMINED.to_owned());
+    span.set_attribute("llm.output.language", language);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(text: &str) -> Option<String> {
+        HeuristicDetector.detect(text)
+    }
+
+    #[test]
+    fn detects_latin_languages_by_stopwords() {
+        let samples = [
+            ("What is the capital of France and why is it famous?", "en"),
+            ("¿Cuál es la capital de Francia y por qué es famosa?", "es"),
+            (
+                "Je ne sais pas pourquoi le train est en retard dans la gare",
+                "fr",
+            ),
+            (
+                "Ich weiß nicht, wie das Wetter morgen ist und ob die Sonne scheint",
+                "de",
+            ),
+        ];
+        for (text, language) in samples {
+            assert_eq!(detect(text).as_deref(), Some(language), "{text}");
+        }
+    }
+
+    #[test]
+    fn detects_non_latin_scripts() {
+        let samples = [
+            ("私は猫が好きです", "ja"),
+            ("我喜欢猫，今天天气很好", "zh"),
+            ("오늘 날씨가 좋네요", "ko"),
+            ("Привет, как дела? Всё OK", "ru"),
+            ("مرحبا، كيف حالك؟", "ar"),
+            ("Καλημέρα σε όλους", "el"),
+        ];
+        for (text, language) in samples {
+            assert_eq!(detect(text).as_deref(), Some(language), "{text}");
+        }
+    }
+
+    #[test]
+    fn a_few_foreign_characters_do_not_outweigh_the_main_language() {
+        assert_eq!(
+            detect("The word 猫 means cat and it is what you see in the picture").as_deref(),
+            Some("en")
+        );
+    }
+
+    #[test]
+    fn undecided_texts_yield_none() {
+        assert_eq!(detect(""), None);
+        assert_eq!(detect("12345 + 678 = ?"), None);
+        assert_eq!(detect("Hello"), None);
+        assert_eq!(detect("Kubernetes Terraform Grafana"), None);
+    }
+
+    #[test]
+    fn only_the_head_of_long_texts_is_scanned() {
+        let text = format!(
+            "{}{}",
+            "the cat is in the garden and it is happy ".repeat(60),
+            "el gato y el perro de la casa que es para una familia ".repeat(60)
+        );
+
+        assert_eq!(detect(&text).as_deref(), Some("en"));
+    }
+}
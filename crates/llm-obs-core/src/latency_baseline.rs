@@ -0,0 +1,147 @@
+//! Per-model latency baselines with anomaly events.
+//!
+//! Provider-side slowdowns usually show up as single calls that take far
+//! longer than that model normally does, well before an hourly p99 panel
+//! moves. [`LatencyBaseline`] keeps an exponentially weighted mean and
+//! variance of latency per model and, when a call exceeds the mean by more
+//! than `threshold_std_devs` standard deviations, adds an
+//! `llm.latency.anomaly` event to the call's span annotated with the baseline
+//! it was compared against.
+
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LatencyBaselineConfig {
+    /// Weight of each new sample in the moving mean and variance.
+    pub alpha: f64,
+    /// Excess over the mean, in standard deviations, that counts as an
+    /// anomaly.
+    pub threshold_std_devs: f64,
+    /// Samples per model before anomalies are reported.
+    pub warmup_samples: u64,
+}
+
+impl Default for LatencyBaselineConfig {
+    fn default() -> Self {
+        Self {
+            alpha: 0.1,
+            threshold_std_devs: 3.0,
+            warmup_samples: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyAnomaly {
+    pub model: String,
+    pub latency_ms: f64,
+    pub baseline_mean_ms: f64,
+    pub baseline_std_dev_ms: f64,
+    /// Standard deviations above the baseline mean.
+    pub z_score: f64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Ewma {
+    mean: f64,
+    variance: f64,
+    samples: u64,
+}
+
+impl Ewma {
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = alpha * diff;
+            self.mean += increment;
+            self.variance = (1.0 - alpha) * (self.variance + diff * increment);
+        }
+        self.samples += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LatencyBaseline {
+    config: LatencyBaselineConfig,
+    models: Mutex<HashMap<String, Ewma>>,
+}
+
+impl LatencyBaseline {
+    pub fn new(config: LatencyBaselineConfig) -> Self {
+        Self {
+            config,
+            models: Mutex::default(),
+        }
+    }
+
+    pub fn global() -> &'static LatencyBaseline {
+        static GLOBAL: OnceLock<LatencyBaseline> = OnceLock::new();
+        GLOBAL.get_or_init(LatencyBaseline::default)
+    }
+
+    /// Compares `latency` with the model's baseline, records an anomaly
+    /// event on `span` when it is too slow, then folds the sample into the
+    /// baseline, so a lasting slowdown becomes the new normal.
+    pub fn observe(
+        &self,
+        span: &tracing::Span,
+        model: &str,
+        latency: Duration,
+    ) -> Option<LatencyAnomaly> {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let mut models = self.models.lock().unwrap_or_else(PoisonError::into_inner);
+        let baseline = models.entry(model.to_owned()).or_default();
+        let previous = *baseline;
+        baseline.update(latency_ms, self.config.alpha);
+        drop(models);
+
+        if previous.samples < self.config.warmup_samples {
+            return None;
+        }
+        // Floor the deviation so a perfectly steady model does not flag
+        // every millisecond of jitter.
+        let std_dev = previous.variance.sqrt().max(previous.mean * 0.01);
+        if std_dev <= 0.0 {
+            return None;
+        }
+        let z_score = (latency_ms - previous.mean) / std_dev;
+        if z_score <= self.config.threshold_std_devs {
+            return None;
+        }
+
+        let anomaly = LatencyAnomaly {
+            model: model.to_owned(),
+            latency_ms,
+            baseline_mean_ms: previous.mean,
+            baseline_std_dev_ms: std_dev,
+            z_score,
+        };
+        span.add_event(
+            "llm.latency.anomaly",
+            vec![
+                KeyValue::new("gen_ai.request.model", anomaly.model.clone()),
+                KeyValue::new("llm.latency.ms", anomaly.latency_ms),
+                KeyValue::new("llm.latency.baseline_mean_ms", anomaly.baseline_mean_ms),
+                KeyValue::new(
+                    "llm.latency.baseline_std_dev_ms",
+                    anomaly.baseline_std_dev_ms,
+                ),
+                KeyValue::new("llm.latency.z_score", anomaly.z_score),
+            ],
+        );
+        tracing::warn!(
+            gen_ai.request.model = %anomaly.model,
+            latency_ms = anomaly.latency_ms,
+            baseline_mean_ms = anomaly.baseline_mean_ms,
+            z_score = anomaly.z_score,
+            "Model latency anomaly"
+        );
+        Some(anomaly)
+    }
+}
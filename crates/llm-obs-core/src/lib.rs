@@ -0,0 +1,89 @@
+//! Provider-agnostic building blocks for observing LLM applications:
+//! telemetry initialization, GenAI semantic conventions, span processors and
+//! metrics. Provider and agent framework integrations live in adapter crates
+//! such as `llm-obs-rig`.
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+#[path = "macro_support.rs"]
+pub mod __private;
+pub mod artifacts;
+pub mod budget;
+pub mod bundle;
+pub mod cardinality;
+pub mod chat_session;
+pub mod clock_skew;
+#[cfg(feature = "http-client")]
+pub mod cold_start;
+pub mod compression;
+pub mod concurrency;
+pub mod console_exporter;
+pub mod console_format;
+#[cfg(feature = "cost")]
+pub mod cost;
+pub mod dataset;
+pub mod deterministic;
+pub mod duplicates;
+pub mod enrichment;
+pub mod error;
+pub mod fanout;
+pub mod feedback;
+pub mod fingerprint;
+pub mod finish_reason;
+pub mod flags;
+pub mod genai_metrics;
+pub mod identity;
+pub mod inflight;
+pub mod kill_switch;
+pub mod language;
+pub mod latency_baseline;
+pub mod logprobs;
+#[cfg(feature = "logs")]
+pub mod logs;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "metrics-facade")]
+pub mod metrics_bridge;
+#[cfg(feature = "openfeature")]
+pub mod openfeature;
+pub mod otlp_config;
+pub mod outcome;
+pub mod output_limit;
+pub mod payload_size;
+pub mod processors;
+pub mod prompt_fingerprint;
+pub mod prompt_template;
+pub mod propagation;
+pub mod quality;
+#[cfg(feature = "cost")]
+pub mod query_rewrite;
+pub mod rate_limit;
+#[cfg(feature = "cost")]
+pub mod reasoning;
+pub mod replay;
+pub mod request_ids;
+pub mod response_diff;
+pub mod run_report;
+pub mod sampling;
+pub mod scopes;
+pub mod semconv;
+#[cfg(feature = "axum")]
+pub mod server;
+pub mod serverless;
+pub mod spans;
+pub mod streaming;
+pub mod sub_agent;
+pub mod summarizer;
+pub mod telemetry;
+#[cfg(feature = "otlp")]
+pub mod tenancy;
+pub mod timeouts;
+pub mod tokens;
+pub mod tool_batch;
+pub mod tracestate;
+#[cfg(feature = "webhook")]
+pub mod usage_report;
+pub mod watchdog;
+
+#[cfg(feature = "macros")]
+pub use llm_obs_macros::llm_span;
@@ -0,0 +1,77 @@
+//! Confidence statistics from token log-probabilities.
+//!
+//! Providers that return logprobs (Gemini with `responseLogprobs`, OpenAI
+//! with `logprobs: true`) give a cheap signal that correlates with
+//! hallucination risk: answers with many low-probability tokens are worth a
+//! second look. Only aggregates are recorded, never the tokens themselves,
+//! so the attributes stay small and content-free.
+
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Tokens below this logprob (about 10% probability) count as low confidence.
+pub const DEFAULT_LOW_CONFIDENCE_LOGPROB: f64 = -2.3;
+
+/// Aggregate confidence of one generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogprobStats {
+    pub mean: f64,
+    /// Unset when the provider only reports an average.
+    pub min: Option<f64>,
+    pub token_count: Option<u64>,
+    pub low_confidence_tokens: Option<u64>,
+}
+
+impl LogprobStats {
+    /// Aggregates per-token logprobs; `None` when there are none.
+    pub fn from_logprobs(
+        logprobs: impl IntoIterator<Item = f64>,
+        low_confidence_threshold: f64,
+    ) -> Option<Self> {
+        let mut count = 0_u64;
+        let mut sum = 0.0;
+        let mut min = f64::INFINITY;
+        let mut low_confidence = 0_u64;
+        for logprob in logprobs.into_iter().filter(|logprob| logprob.is_finite()) {
+            count += 1;
+            sum += logprob;
+            min = min.min(logprob);
+            if logprob < low_confidence_threshold {
+                low_confidence += 1;
+            }
+        }
+        (count > 0).then(|| Self {
+            mean: sum / count as f64,
+            min: Some(min),
+            token_count: Some(count),
+            low_confidence_tokens: Some(low_confidence),
+        })
+    }
+
+    /// Share of tokens below the threshold, when per-token data was given.
+    pub fn low_confidence_ratio(&self) -> Option<f64> {
+        match (self.low_confidence_tokens, self.token_count) {
+            (Some(low), Some(count)) if count > 0 => Some(low as f64 / count as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Records the statistics on the generation span.
+pub fn record_logprob_stats(span: &tracing::Span, stats: &LogprobStats) {
+    span.set_attribute("llm.logprobs.mean", stats.mean);
+    if let Some(min) = stats.min {
+        span.set_attribute("llm.logprobs.min", min);
+    }
+    if let Some(token_count) = stats.token_count {
+        span.set_attribute("llm.logprobs.token_count", token_count as i64);
+    }
+    if let Some(low_confidence_tokens) = stats.low_confidence_tokens {
+        span.set_attribute(
+            "llm.logprobs.low_confidence_tokens",
+            low_confidence_tokens as i64,
+        );
+    }
+    if let Some(ratio) = stats.low_confidence_ratio() {
+        span.set_attribute("llm.logprobs.low_confidence_ratio", ratio);
+    }
+}
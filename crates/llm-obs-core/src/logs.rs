@@ -0,0 +1,56 @@
+//! `tracing` events as OpenTelemetry log records.
+//!
+//! The OpenTelemetry layer turns `tracing::info!` and friends into span
+//! events, which backends only show inside the trace view: they cannot be
+//! searched, filtered by severity or alerted on like logs, and events
+//! outside any span are lost. [`layer`] also emits every event as a log
+//! record through `opentelemetry-appender-tracing`'s
+//! [`OpenTelemetryTracingBridge`]:
+//!
+//! - severity from the level, body from the message, other fields as
+//!   attributes;
+//! - the `tracing` target as the record's target;
+//! - the trace and span ids of the span the event belongs to, so SigNoz
+//!   (or any OTLP logs backend) links each log line to its trace.
+//!
+//! `TelemetryBuilder::with_otlp_logs` installs the layer with a logger
+//! provider exporting over OTLP (see `event_logs::otlp_logger_provider` for
+//! the endpoint and protocol). Host apps with their own subscriber add
+//! [`layer`] after the OpenTelemetry layer. Events of the exporter's own
+//! stack (`opentelemetry`, `tonic`, `h2`, `hyper`, `reqwest`, `tower`) are
+//! skipped, so an export failure does not produce more records to export,
+//! and nothing is emitted while the kill switch is off.
+
+use crate::kill_switch;
+use opentelemetry::logs::{Logger, LoggerProvider};
+pub use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::filter::{FilterExt, filter_fn};
+use tracing_subscriber::layer::Filter;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Target prefixes whose events are not exported as logs.
+pub const EXCLUDED_TARGETS: [&str; 6] =
+    ["opentelemetry", "tonic", "h2", "hyper", "reqwest", "tower"];
+
+/// The log bridge for `logger_provider`, behind [`exporter_filter`] and the
+/// kill switch.
+pub fn layer<S, P, L>(logger_provider: &P) -> impl Layer<S> + use<S, P, L>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    P: LoggerProvider<Logger = L> + Send + Sync + 'static,
+    L: Logger + Send + Sync + 'static,
+{
+    OpenTelemetryTracingBridge::new(logger_provider)
+        .with_filter(exporter_filter().and(kill_switch::layer_filter()))
+}
+
+/// Per-layer filter hiding events from [`EXCLUDED_TARGETS`].
+pub fn exporter_filter<S>() -> impl Filter<S> {
+    filter_fn(|metadata| {
+        !EXCLUDED_TARGETS
+            .iter()
+            .any(|excluded| metadata.target().starts_with(excluded))
+    })
+}
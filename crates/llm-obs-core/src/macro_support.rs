@@ -0,0 +1,102 @@
+//! Runtime support for `#[llm_span]`. Not a stable API: only the code the
+//! macro generates should use it.
+
+use crate::processors::capture::{CAPTURE_POLICY_KEY, ContentCapturePolicy};
+use crate::processors::scrub::{ScrubPattern, builtin_patterns, scrub_with};
+use crate::spans::{start_llm_span, start_tool_span};
+use opentelemetry::trace::Status;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub use tracing::Instrument;
+
+pub fn llm_span(operation: &str, model: &str, provider: Option<&str>) -> tracing::Span {
+    let span = start_llm_span(operation, model);
+    if let Some(provider) = provider {
+        span.record("gen_ai.provider.name", provider);
+    }
+    span
+}
+
+pub fn tool_span(name: &str) -> tracing::Span {
+    let span = start_tool_span(name);
+    span.record("gen_ai.tool.type", "function");
+    span
+}
+
+/// A value to capture: by `Display` through [`CaptureDisplay`] when it has
+/// one, otherwise by `Debug` through [`CaptureDebug`]. The macro calls
+/// `(&Captured(&value)).capture()`, and method resolution tries the
+/// `Display` impl on `Captured` before autoref reaches the `Debug` impl on
+/// `&Captured`.
+pub struct Captured<'a, T: ?Sized>(pub &'a T);
+
+pub trait CaptureDisplay {
+    fn capture(&self) -> String;
+}
+
+impl<T: fmt::Display + ?Sized> CaptureDisplay for Captured<'_, T> {
+    fn capture(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+pub trait CaptureDebug {
+    fn capture(&self) -> String;
+}
+
+impl<T: fmt::Debug + ?Sized> CaptureDebug for &Captured<'_, T> {
+    fn capture(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+fn scrub(text: &str) -> String {
+    static PATTERNS: OnceLock<Vec<ScrubPattern>> = OnceLock::new();
+    scrub_with(PATTERNS.get_or_init(builtin_patterns), text, &mut 0).into_owned()
+}
+
+/// Scrubs `content` and applies the content capture policy, noting the
+/// policy on `span` so the export pipeline does not apply it again.
+fn sanitize(span: &tracing::Span, content: &str) -> Option<String> {
+    static POLICY: OnceLock<ContentCapturePolicy> = OnceLock::new();
+    let policy = POLICY.get_or_init(ContentCapturePolicy::from_env_or_off);
+    if *policy != ContentCapturePolicy::Full {
+        span.set_attribute(CAPTURE_POLICY_KEY, policy.to_string());
+    }
+    if *policy == ContentCapturePolicy::Off {
+        return None;
+    }
+    policy.apply(&scrub(content))
+}
+
+/// Records the arguments as a JSON object keyed by parameter name.
+pub fn record_input(span: &tracing::Span, key: &'static str, arguments: &[(&str, String)]) {
+    let arguments: serde_json::Map<String, serde_json::Value> = arguments
+        .iter()
+        .map(|(name, value)| ((*name).to_owned(), value.as_str().into()))
+        .collect();
+    let arguments = serde_json::Value::Object(arguments).to_string();
+    if let Some(arguments) = sanitize(span, &arguments) {
+        span.set_attribute(key, arguments);
+    }
+}
+
+pub fn record_output(span: &tracing::Span, key: &'static str, output: String) {
+    if let Some(output) = sanitize(span, &output) {
+        span.set_attribute(key, output);
+    }
+}
+
+pub fn record_error<E: fmt::Display>(span: &tracing::Span, error: &E) {
+    span.record("error.type", error_type::<E>());
+    span.set_status(Status::error(scrub(&error.to_string())));
+}
+
+/// Last path segment of the error type, e.g. `ToolError`.
+fn error_type<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
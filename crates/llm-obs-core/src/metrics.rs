@@ -0,0 +1,429 @@
+//! OTLP metrics pipeline and pre-built LLM instruments.
+//!
+//! Spans answer "what happened in this request"; request rates, latency
+//! percentiles and token burn over time are cheaper to answer from metrics.
+//! [`init`] / [`MetricsBuilder`] install an OTLP `SdkMeterProvider`
+//! with a periodic reader as the global meter provider, and [`LlmMetrics`]
+//! records every model call into a request counter, a token counter and the
+//! semconv latency and token histograms from [`GenAiMetrics`].
+//!
+//! Every instrument of the crate is created on first use and cached, so
+//! install the provider at startup, before any instrumented call: an
+//! instrument first used earlier stays a no-op for the life of the process.
+//!
+//! [`MetricView`]s reshape instruments before export: rename them, keep only
+//! some attributes, change histogram buckets or cap their series. Label
+//! values of single keys are capped by [`crate::cardinality`].
+
+use crate::genai_metrics::{GenAiCall, GenAiMetrics};
+#[cfg(feature = "otlp")]
+use crate::kill_switch::{self, KillSwitchExporter};
+#[cfg(feature = "otlp")]
+use crate::otlp_config::{OtlpEndpoints, OtlpProtocol, Signal, SignalEndpoint, metric_exporter};
+use crate::scopes::Subsystem;
+use crate::tokens::TokenUsage;
+#[cfg(feature = "otlp")]
+use opentelemetry::global;
+use opentelemetry::metrics::{Counter, Meter};
+use opentelemetry::{Key, KeyValue};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::Resource;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use opentelemetry_sdk::metrics::{Aggregation, Instrument, InstrumentKind, Stream, StreamBuilder};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::metrics::{MeterProviderBuilder, PeriodicReader, SdkMeterProvider};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+#[cfg(feature = "otlp")]
+type AddReader = Box<dyn FnOnce(MeterProviderBuilder, Duration) -> MeterProviderBuilder>;
+
+/// Default export cadence; the SDK default of 60s hides short incidents.
+pub const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Installs an OTLP meter provider with the default configuration.
+#[cfg(feature = "otlp")]
+pub fn init(service_name: &str) -> anyhow::Result<SdkMeterProvider> {
+    MetricsBuilder::new(service_name).init()
+}
+
+/// Configuration of the meter provider installed by [`MetricsBuilder::init`].
+///
+/// The endpoint defaults to `OTEL_EXPORTER_OTLP_METRICS_ENDPOINT` /
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` and the transport to
+/// `OTEL_EXPORTER_OTLP_METRICS_PROTOCOL` / `OTEL_EXPORTER_OTLP_PROTOCOL`; headers from `OTEL_EXPORTER_OTLP_*HEADERS`
+/// are read by the exporter itself and win over [`MetricsBuilder::with_header`].
+#[cfg(feature = "otlp")]
+pub struct MetricsBuilder {
+    service_name: String,
+    endpoint: Option<String>,
+    protocol: Option<OtlpProtocol>,
+    headers: Vec<(String, String)>,
+    export_interval: Duration,
+    resource_attributes: Vec<KeyValue>,
+    extra_readers: Vec<AddReader>,
+    views: Vec<MetricView>,
+    attribute_limits: Vec<(String, usize)>,
+}
+
+#[cfg(feature = "otlp")]
+impl MetricsBuilder {
+    pub fn new(service_name: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            endpoint: None,
+            protocol: None,
+            headers: Vec::new(),
+            export_interval: DEFAULT_EXPORT_INTERVAL,
+            resource_attributes: Vec::new(),
+            extra_readers: Vec::new(),
+            views: Vec::new(),
+            attribute_limits: Vec::new(),
+        }
+    }
+
+    /// OTLP endpoint, overriding the environment. Over HTTP this is the
+    /// full URL, including `/v1/metrics`.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// OTLP transport, overriding the environment.
+    pub fn with_protocol(mut self, protocol: OtlpProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Exporter header, e.g. an ingestion key loaded from a secret store.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_export_interval(mut self, export_interval: Duration) -> Self {
+        self.export_interval = export_interval;
+        self
+    }
+
+    pub fn with_resource_attribute(mut self, attribute: KeyValue) -> Self {
+        self.resource_attributes.push(attribute);
+        self
+    }
+
+    /// Also exports to `exporter` on the same interval, e.g. a
+    /// `UsageAggregator` feeding the usage webhook. Like the OTLP exporter,
+    /// it is skipped while the kill switch is off.
+    pub fn with_exporter(mut self, exporter: impl PushMetricExporter) -> Self {
+        self.extra_readers.push(Box::new(move |builder, interval| {
+            builder.with_reader(
+                PeriodicReader::builder(KillSwitchExporter::new(exporter))
+                    .with_interval(interval)
+                    .build(),
+            )
+        }));
+        self
+    }
+
+    /// Reshapes the instruments `view` matches; the first matching view
+    /// applies.
+    pub fn with_view(mut self, view: MetricView) -> Self {
+        self.views.push(view);
+        self
+    }
+
+    /// Keeps at most `max` distinct values of the label `key`, see
+    /// [`crate::cardinality::set_limit`]. Applied when the provider is built.
+    pub fn with_attribute_limit(mut self, key: impl Into<String>, max: usize) -> Self {
+        self.attribute_limits.push((key.into(), max));
+        self
+    }
+
+    /// Builds the provider without installing it globally. Fails on an
+    /// invalid [`MetricView`].
+    pub fn build(self) -> anyhow::Result<SdkMeterProvider> {
+        for view in &self.views {
+            view.validate()?;
+        }
+        for (key, max) in self.attribute_limits {
+            crate::cardinality::set_limit(key, max);
+        }
+        let metrics = OtlpEndpoints::signal_from_env(Signal::Metrics, self.protocol);
+        let exporter = metric_exporter(&SignalEndpoint {
+            protocol: metrics.protocol,
+            endpoint: self.endpoint.unwrap_or(metrics.endpoint),
+            headers: self.headers,
+        })?;
+        kill_switch::init_from_env();
+        let reader = PeriodicReader::builder(KillSwitchExporter::new(exporter))
+            .with_interval(self.export_interval)
+            .build();
+
+        let resource = Resource::builder()
+            .with_service_name(self.service_name)
+            .with_attribute(KeyValue::new("telemetry.sdk.language", "rust"))
+            .with_attributes(self.resource_attributes)
+            .build();
+
+        let mut builder = SdkMeterProvider::builder()
+            .with_resource(resource)
+            .with_reader(reader);
+        for add_reader in self.extra_readers {
+            builder = add_reader(builder, self.export_interval);
+        }
+        if !self.views.is_empty() {
+            let views = self.views;
+            builder = builder.with_view(move |instrument: &Instrument| {
+                views.iter().find_map(|view| view.stream(instrument))
+            });
+        }
+        Ok(builder.build())
+    }
+
+    /// Builds the provider and installs it as the global meter provider, so
+    /// [`LlmMetrics::global`] and [`GenAiMetrics::global`] export through it.
+    /// Call it before any instrumented call, since instruments are cached on
+    /// first use. Keep the returned provider and call `shutdown` before exit.
+    pub fn init(self) -> anyhow::Result<SdkMeterProvider> {
+        let meter_provider = self.build()?;
+        global::set_meter_provider(meter_provider.clone());
+        Ok(meter_provider)
+    }
+}
+
+/// The provider and model attributes of the counters.
+fn counter_attributes(call_attributes: &[KeyValue]) -> Vec<KeyValue> {
+    call_attributes
+        .iter()
+        .filter(|attribute| {
+            matches!(
+                attribute.key.as_str(),
+                "gen_ai.provider.name" | "gen_ai.request.model"
+            )
+        })
+        .cloned()
+        .collect()
+}
+
+/// Changes to the instruments named `instrument`, or to all instruments
+/// starting with a prefix when it ends in `*` (e.g. `gen_ai.*`).
+///
+/// ```ignore
+/// MetricsBuilder::new("support-bot")
+///     .with_view(MetricView::new("gen_ai.client.operation.duration")
+///         .with_histogram_buckets(vec![0.5, 1.0, 2.0, 5.0, 10.0, 30.0]))
+///     .with_view(MetricView::new("llm.tokens")
+///         .with_allowed_attributes(["gen_ai.provider.name", "gen_ai.token.type"]))
+///     .init()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct MetricView {
+    instrument: String,
+    name: Option<String>,
+    description: Option<String>,
+    allowed_attributes: Option<Vec<Key>>,
+    histogram_buckets: Option<Vec<f64>>,
+    cardinality_limit: Option<usize>,
+}
+
+impl MetricView {
+    pub fn new(instrument: impl Into<String>) -> Self {
+        Self {
+            instrument: instrument.into(),
+            name: None,
+            description: None,
+            allowed_attributes: None,
+            histogram_buckets: None,
+            cardinality_limit: None,
+        }
+    }
+
+    /// Exports the instrument under `name`, e.g. to match an existing
+    /// dashboard. Only for views matching a single instrument.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Keeps only these attributes and drops the rest; measurements that
+    /// differed only in a dropped attribute are aggregated together. The SDK
+    /// filters by allow-list, so list what stays.
+    pub fn with_allowed_attributes<K: Into<Key>>(
+        mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Self {
+        self.allowed_attributes = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Histogram bucket upper bounds, in increasing order. Ignored for
+    /// instruments that are not histograms.
+    pub fn with_histogram_buckets(mut self, boundaries: Vec<f64>) -> Self {
+        self.histogram_buckets = Some(boundaries);
+        self
+    }
+
+    /// Attribute sets kept per collection before the rest are folded into
+    /// one `otel.metric.overflow = true` series; the SDK default is 2000.
+    pub fn with_cardinality_limit(mut self, limit: usize) -> Self {
+        self.cardinality_limit = Some(limit);
+        self
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self.instrument.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == self.instrument,
+        }
+    }
+
+    /// The stream for `instrument`, or `None` when the view does not match
+    /// it. [`MetricsBuilder::build`] rejects invalid views up front.
+    pub fn stream(&self, instrument: &Instrument) -> Option<Stream> {
+        if !self.matches(instrument.name()) {
+            return None;
+        }
+        let histogram = matches!(instrument.kind(), InstrumentKind::Histogram);
+        match self.stream_builder(histogram).build() {
+            Ok(stream) => Some(stream),
+            Err(error) => {
+                tracing::warn!(
+                    metric.view = %self.instrument,
+                    "Metric view skipped: {error}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Fails when the view cannot apply to any instrument, e.g. unsorted
+    /// buckets or an invalid name.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.stream_builder(true)
+            .build()
+            .map(drop)
+            .map_err(|error| {
+                anyhow::anyhow!("Invalid metric view for {}: {error}", self.instrument)
+            })
+    }
+
+    fn stream_builder(&self, histogram: bool) -> StreamBuilder {
+        let mut stream = Stream::builder();
+        if let Some(name) = &self.name {
+            stream = stream.with_name(name.clone());
+        }
+        if let Some(description) = &self.description {
+            stream = stream.with_description(description.clone());
+        }
+        if let Some(keys) = &self.allowed_attributes {
+            stream = stream.with_allowed_attribute_keys(keys.iter().cloned());
+        }
+        if let Some(boundaries) = self.histogram_buckets.as_ref().filter(|_| histogram) {
+            stream = stream.with_aggregation(Aggregation::ExplicitBucketHistogram {
+                boundaries: boundaries.clone(),
+                record_min_max: true,
+            });
+        }
+        if let Some(limit) = self.cardinality_limit {
+            stream = stream.with_cardinality_limit(limit);
+        }
+        stream
+    }
+}
+
+/// Request and token counters plus the semconv histograms.
+#[derive(Debug, Clone)]
+pub struct LlmMetrics {
+    requests: Counter<u64>,
+    tokens: Counter<u64>,
+    genai: GenAiMetrics,
+}
+
+impl LlmMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            requests: meter
+                .u64_counter("llm.requests")
+                .with_unit("{request}")
+                .with_description("Model calls, by model, provider and error type")
+                .build(),
+            tokens: meter
+                .u64_counter("llm.tokens")
+                .with_unit("{token}")
+                .with_description("Tokens consumed, by model, provider and token type")
+                .build(),
+            genai: GenAiMetrics::new(meter),
+        }
+    }
+
+    /// Instruments on the global meter provider. Install the provider
+    /// before the first call; instruments created earlier stay no-ops.
+    pub fn global() -> &'static LlmMetrics {
+        static GLOBAL: OnceLock<LlmMetrics> = OnceLock::new();
+        GLOBAL.get_or_init(|| LlmMetrics::new(&Subsystem::Agent.meter()))
+    }
+
+    /// Records one model call. `error_type` is set for failed calls and
+    /// becomes the `error.type` attribute.
+    pub fn record(
+        &self,
+        call: &GenAiCall<'_>,
+        duration: Duration,
+        usage: Option<&TokenUsage>,
+        error_type: Option<&str>,
+    ) {
+        // Built once, so each guarded label is admitted or counted once.
+        let call_attributes = call.attributes();
+        let attributes = counter_attributes(&call_attributes);
+        self.genai
+            .record_attributes(call_attributes, duration, usage, error_type);
+        self.record_counters(&attributes, usage, error_type);
+    }
+
+    /// Records only the `llm.requests` and `llm.tokens` counters, for calls
+    /// whose semconv histograms an adapter already recorded, such as
+    /// `InstrumentedAgent` calls.
+    pub fn record_counts(
+        &self,
+        call: &GenAiCall<'_>,
+        usage: Option<&TokenUsage>,
+        error_type: Option<&str>,
+    ) {
+        self.record_counters(&counter_attributes(&call.attributes()), usage, error_type);
+    }
+
+    fn record_counters(
+        &self,
+        attributes: &[KeyValue],
+        usage: Option<&TokenUsage>,
+        error_type: Option<&str>,
+    ) {
+        let mut request_attributes = attributes.to_vec();
+        if let Some(error_type) = error_type {
+            request_attributes.push(KeyValue::new("error.type", error_type.to_owned()));
+        }
+        self.requests.add(1, &request_attributes);
+
+        let Some(usage) = usage else {
+            return;
+        };
+        for (token_type, tokens) in [
+            ("input", usage.input_tokens),
+            ("output", usage.output_tokens),
+            ("reasoning", usage.reasoning_tokens),
+        ] {
+            if tokens == 0 {
+                continue;
+            }
+            let mut token_attributes = attributes.to_vec();
+            token_attributes.push(KeyValue::new("gen_ai.token.type", token_type));
+            self.tokens.add(tokens, &token_attributes);
+        }
+    }
+}
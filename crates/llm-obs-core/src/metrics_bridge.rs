@@ -0,0 +1,324 @@
+//! Bridge from the `metrics` crate facade into OpenTelemetry.
+//!
+//! Installing `OtelMetricsRecorder` as the global `metrics` recorder makes
+//! `metrics::counter!`, `gauge!` and `histogram!` calls flow into the same
+//! OpenTelemetry meter provider as the LLM metrics, so existing
+//! `metrics`-based instrumentation and this crate share one pipeline.
+//!
+//! The `metrics` macros register their key on every call, so the recorder
+//! hands out one shared handle per key; otherwise gauge increments and
+//! absolute counter values would start from zero on each call.
+
+use anyhow::anyhow;
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug, Clone)]
+struct Description {
+    unit: Option<Unit>,
+    text: SharedString,
+}
+
+pub struct OtelMetricsRecorder {
+    meter: Meter,
+    descriptions: Mutex<HashMap<String, Description>>,
+    counters: Mutex<HashMap<Key, Arc<OtelCounter>>>,
+    gauges: Mutex<HashMap<Key, Arc<OtelGauge>>>,
+    histograms: Mutex<HashMap<Key, Arc<OtelHistogram>>>,
+}
+
+impl OtelMetricsRecorder {
+    pub fn new(meter: Meter) -> Self {
+        Self {
+            meter,
+            descriptions: Mutex::default(),
+            counters: Mutex::default(),
+            gauges: Mutex::default(),
+            histograms: Mutex::default(),
+        }
+    }
+
+    /// Installs the recorder as the process-wide `metrics` recorder.
+    pub fn install(self) -> anyhow::Result<()> {
+        metrics::set_global_recorder(self)
+            .map_err(|_| anyhow!("a global metrics recorder is already installed"))
+    }
+
+    fn describe(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.descriptions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                key.as_str().to_owned(),
+                Description {
+                    unit,
+                    text: description,
+                },
+            );
+    }
+
+    fn description(&self, name: &str) -> Option<Description> {
+        self.descriptions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+    }
+}
+
+impl Recorder for OtelMetricsRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(key, unit, description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let mut counters = self.counters.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(counter) = counters.get(key) {
+            return Counter::from_arc(Arc::clone(counter));
+        }
+        let mut builder = self.meter.u64_counter(key.name().to_owned());
+        if let Some(description) = self.description(key.name()) {
+            builder = builder.with_description(description.text.to_string());
+            if let Some(unit) = description.unit {
+                builder = builder.with_unit(unit.as_canonical_label());
+            }
+        }
+        let counter = Arc::new(OtelCounter {
+            counter: builder.build(),
+            attributes: attributes(key),
+            last_absolute: AtomicU64::new(0),
+        });
+        counters.insert(key.clone(), Arc::clone(&counter));
+        Counter::from_arc(counter)
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let mut gauges = self.gauges.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(gauge) = gauges.get(key) {
+            return Gauge::from_arc(Arc::clone(gauge));
+        }
+        let mut builder = self.meter.f64_gauge(key.name().to_owned());
+        if let Some(description) = self.description(key.name()) {
+            builder = builder.with_description(description.text.to_string());
+            if let Some(unit) = description.unit {
+                builder = builder.with_unit(unit.as_canonical_label());
+            }
+        }
+        let gauge = Arc::new(OtelGauge {
+            gauge: builder.build(),
+            attributes: attributes(key),
+            value_bits: AtomicU64::new(0f64.to_bits()),
+        });
+        gauges.insert(key.clone(), Arc::clone(&gauge));
+        Gauge::from_arc(gauge)
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let mut histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(histogram) = histograms.get(key) {
+            return Histogram::from_arc(Arc::clone(histogram));
+        }
+        let mut builder = self.meter.f64_histogram(key.name().to_owned());
+        if let Some(description) = self.description(key.name()) {
+            builder = builder.with_description(description.text.to_string());
+            if let Some(unit) = description.unit {
+                builder = builder.with_unit(unit.as_canonical_label());
+            }
+        }
+        let histogram = Arc::new(OtelHistogram {
+            histogram: builder.build(),
+            attributes: attributes(key),
+        });
+        histograms.insert(key.clone(), Arc::clone(&histogram));
+        Histogram::from_arc(histogram)
+    }
+}
+
+fn attributes(key: &Key) -> Vec<KeyValue> {
+    key.labels()
+        .map(|label| KeyValue::new(label.key().to_owned(), label.value().to_owned()))
+        .collect()
+}
+
+struct OtelCounter {
+    counter: opentelemetry::metrics::Counter<u64>,
+    attributes: Vec<KeyValue>,
+    /// OTel counters only accept deltas, so `absolute` is turned into the
+    /// increase since the largest value seen.
+    last_absolute: AtomicU64,
+}
+
+impl CounterFn for OtelCounter {
+    fn increment(&self, value: u64) {
+        self.counter.add(value, &self.attributes);
+    }
+
+    fn absolute(&self, value: u64) {
+        let previous = self.last_absolute.fetch_max(value, Ordering::AcqRel);
+        if value > previous {
+            self.counter.add(value - previous, &self.attributes);
+        }
+    }
+}
+
+struct OtelGauge {
+    gauge: opentelemetry::metrics::Gauge<f64>,
+    attributes: Vec<KeyValue>,
+    /// Current value as `f64` bits, needed for increment/decrement.
+    value_bits: AtomicU64,
+}
+
+impl OtelGauge {
+    fn update(&self, apply: impl Fn(f64) -> f64) {
+        let mut current = self.value_bits.load(Ordering::Acquire);
+        loop {
+            let next = apply(f64::from_bits(current));
+            match self.value_bits.compare_exchange_weak(
+                current,
+                next.to_bits(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    self.gauge.record(next, &self.attributes);
+                    return;
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl GaugeFn for OtelGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.update(|_| value);
+    }
+}
+
+struct OtelHistogram {
+    histogram: opentelemetry::metrics::Histogram<f64>,
+    attributes: Vec<KeyValue>,
+}
+
+impl HistogramFn for OtelHistogram {
+    fn record(&self, value: f64) {
+        self.histogram.record(value, &self.attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+    use opentelemetry_sdk::metrics::reader::MetricReader;
+    use opentelemetry_sdk::metrics::{
+        InstrumentKind, ManualReader, Pipeline, SdkMeterProvider, Temporality,
+    };
+    use std::sync::Weak;
+    use std::time::Duration;
+
+    /// A manual reader the test keeps a handle to after the provider owns it.
+    #[derive(Debug, Clone, Default)]
+    struct SharedReader(Arc<ManualReader>);
+
+    impl MetricReader for SharedReader {
+        fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+            self.0.register_pipeline(pipeline);
+        }
+
+        fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+            self.0.collect(rm)
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            self.0.force_flush()
+        }
+
+        fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+            self.0.shutdown_with_timeout(timeout)
+        }
+
+        fn temporality(&self, kind: InstrumentKind) -> Temporality {
+            self.0.temporality(kind)
+        }
+    }
+
+    /// The last exported value of each counter and gauge, by name.
+    fn values(reader: &SharedReader) -> HashMap<String, f64> {
+        let mut metrics = ResourceMetrics::default();
+        reader.collect(&mut metrics).unwrap();
+        let mut values = HashMap::new();
+        for metric in metrics.scope_metrics().flat_map(|scope| scope.metrics()) {
+            let value = match metric.data() {
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => {
+                    sum.data_points().map(|point| point.value() as f64).sum()
+                }
+                AggregatedMetrics::F64(MetricData::Gauge(gauge)) => {
+                    gauge.data_points().map(|point| point.value()).sum()
+                }
+                AggregatedMetrics::F64(MetricData::Histogram(histogram)) => histogram
+                    .data_points()
+                    .map(|point| point.count() as f64)
+                    .sum(),
+                _ => continue,
+            };
+            values.insert(metric.name().to_owned(), value);
+        }
+        values
+    }
+
+    #[test]
+    fn repeated_macro_calls_share_one_instrument() {
+        let reader = SharedReader::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader.clone())
+            .build();
+        let recorder = OtelMetricsRecorder::new(provider.meter("test"));
+
+        metrics::with_local_recorder(&recorder, || {
+            for total in [5, 8, 8, 12] {
+                metrics::counter!("jobs.processed").absolute(total);
+            }
+            for _ in 0..3 {
+                metrics::counter!("jobs.retried").increment(1);
+                metrics::gauge!("jobs.running").increment(2.0);
+                metrics::histogram!("jobs.duration").record(0.5);
+            }
+            metrics::gauge!("jobs.running").decrement(1.0);
+        });
+
+        let values = values(&reader);
+        assert_eq!(values["jobs.processed"], 12.0);
+        assert_eq!(values["jobs.retried"], 3.0);
+        assert_eq!(values["jobs.running"], 5.0);
+        assert_eq!(values["jobs.duration"], 3.0);
+    }
+}
@@ -0,0 +1,125 @@
+//! OpenFeature flag evaluations on spans.
+//!
+//! Apps that evaluate flags through an OpenFeature client (`open-feature`
+//! crate) register [`FlagEvaluationHook`] on the API or the client, and
+//! every evaluation is recorded on the current span as the same
+//! `feature_flag.evaluation` event [`flags::bool_flag`](crate::flags::bool_flag)
+//! records: key, provider name, value, variant, reason and, on error,
+//! `error.type`.
+//!
+//! ```ignore
+//! let mut api = open_feature::OpenFeature::singleton_mut().await;
+//! api.set_provider(my_provider).await;
+//! api.add_hook(FlagEvaluationHook).await;
+//! ```
+//!
+//! The client is async, while the crate's own flag-driven settings
+//! (content capture, sampling ratio, tool batching) evaluate synchronously
+//! through a [`FlagProvider`](crate::flags::FlagProvider); serve those from
+//! the OpenFeature provider's local cache.
+
+use crate::flags::{FlagEvaluation, FlagReason, evaluation_attributes};
+use open_feature::{
+    EvaluationContext, EvaluationDetails, EvaluationError, EvaluationErrorCode, EvaluationReason,
+    Hook, HookContext, HookHints, Value, async_trait,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// OpenFeature hook recording each flag evaluation on the current span.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlagEvaluationHook;
+
+#[async_trait]
+impl Hook for FlagEvaluationHook {
+    async fn before<'a>(
+        &self,
+        _context: &HookContext<'a>,
+        _hints: Option<&'a HookHints>,
+    ) -> Result<Option<EvaluationContext>, EvaluationError> {
+        Ok(None)
+    }
+
+    async fn after<'a>(
+        &self,
+        context: &HookContext<'a>,
+        details: &EvaluationDetails<Value>,
+        _hints: Option<&'a HookHints>,
+    ) -> Result<(), EvaluationError> {
+        let evaluation = FlagEvaluation {
+            variant: details.variant.clone(),
+            reason: details.reason.as_ref().map_or(FlagReason::Unknown, reason),
+            error_type: None,
+        };
+        record(context, &evaluation, &details.value);
+        Ok(())
+    }
+
+    async fn error<'a>(
+        &self,
+        context: &HookContext<'a>,
+        error: &EvaluationError,
+        _hints: Option<&'a HookHints>,
+    ) {
+        // The caller gets its default value.
+        let default = context
+            .default_value
+            .clone()
+            .unwrap_or_else(|| Value::String(String::new()));
+        record(
+            context,
+            &FlagEvaluation::error(error_type(&error.code)),
+            &default,
+        );
+    }
+
+    async fn finally<'a>(
+        &self,
+        _context: &HookContext<'a>,
+        _details: &EvaluationDetails<Value>,
+        _hints: Option<&'a HookHints>,
+    ) {
+    }
+}
+
+fn record(context: &HookContext<'_>, evaluation: &FlagEvaluation, value: &Value) {
+    tracing::Span::current().add_event(
+        "feature_flag.evaluation",
+        evaluation_attributes(
+            &context.provider_metadata.name,
+            context.flag_key,
+            evaluation,
+            &value_string(value),
+        ),
+    );
+}
+
+fn reason(reason: &EvaluationReason) -> FlagReason {
+    match reason {
+        EvaluationReason::Static => FlagReason::Static,
+        EvaluationReason::Default => FlagReason::Default,
+        EvaluationReason::TargetingMatch => FlagReason::TargetingMatch,
+        EvaluationReason::Split => FlagReason::Split,
+        EvaluationReason::Cached => FlagReason::Cached,
+        EvaluationReason::Disabled => FlagReason::Disabled,
+        EvaluationReason::Error => FlagReason::Error,
+        EvaluationReason::Unknown | EvaluationReason::Other(_) => FlagReason::Unknown,
+    }
+}
+
+/// The OpenFeature error code in lower case, as `error.type`.
+fn error_type(code: &EvaluationErrorCode) -> String {
+    match code {
+        EvaluationErrorCode::General(_) => "general".to_owned(),
+        code => code.to_string().to_ascii_lowercase(),
+    }
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::Float(value) => value.to_string(),
+        Value::String(value) => value.clone(),
+        Value::Array(_) | Value::Struct(_) => format!("{value:?}"),
+    }
+}
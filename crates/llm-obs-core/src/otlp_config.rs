@@ -0,0 +1,561 @@
+//! Per-signal OTLP endpoints and headers.
+//!
+//! Traces, metrics and logs often go to different places: traces to SigNoz,
+//! metrics to a Prometheus remote-write compatible OTLP receiver, logs to a
+//! separate collector. The standard `OTEL_EXPORTER_OTLP_{TRACES,METRICS,LOGS}_*`
+//! variables override the generic `OTEL_EXPORTER_OTLP_*` ones per signal;
+//! this module resolves them in one place so every exporter the app builds
+//! agrees on where its signal goes.
+//!
+//! Backends authenticate with headers rather than a fixed metadata key
+//! (`signoz-ingestion-key` for SigNoz Cloud, `x-honeycomb-team` for
+//! Honeycomb, `authorization=Basic ...` for Grafana Cloud), so headers are
+//! taken as generic `key=value` pairs; [`metadata_map`] converts them to the
+//! gRPC metadata the tonic exporters send.
+//!
+//! `OTEL_EXPORTER_OTLP_PROTOCOL` (and its per-signal variants) selects the
+//! transport. gRPC on 4317 is often blocked by corporate proxies, so with the
+//! `otlp-http` feature every signal can also go over HTTP (`http/protobuf` or
+//! `http/json`, port 4318); as in the SDKs, the generic endpoint then gets
+//! `/v1/<signal>` appended while a per-signal endpoint is used as given.
+
+#[cfg(feature = "otlp")]
+use crate::error::TelemetryError;
+#[cfg(feature = "otlp")]
+use anyhow::Context as _;
+#[cfg(feature = "otlp")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "otlp-grpc")]
+use opentelemetry_otlp::WithTonicConfig;
+#[cfg(feature = "otlp-grpc")]
+use opentelemetry_otlp::tonic_types::metadata::MetadataMap;
+#[cfg(feature = "otlp-http")]
+use opentelemetry_otlp::{Protocol, WithHttpConfig};
+use std::fmt;
+use std::str::FromStr;
+#[cfg(feature = "otlp")]
+use std::time::Duration;
+
+/// Default OTLP/gRPC endpoint when nothing is configured.
+pub const DEFAULT_GRPC_ENDPOINT: &str = "http://localhost:4317";
+
+/// Default OTLP/HTTP base URL when nothing is configured.
+pub const DEFAULT_HTTP_ENDPOINT: &str = "http://localhost:4318";
+
+/// OTLP transport, as named by `OTEL_EXPORTER_OTLP_PROTOCOL`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OtlpProtocol {
+    #[default]
+    Grpc,
+    HttpProtobuf,
+    HttpJson,
+}
+
+impl OtlpProtocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OtlpProtocol::Grpc => "grpc",
+            OtlpProtocol::HttpProtobuf => "http/protobuf",
+            OtlpProtocol::HttpJson => "http/json",
+        }
+    }
+
+    pub fn is_http(&self) -> bool {
+        !matches!(self, OtlpProtocol::Grpc)
+    }
+}
+
+impl fmt::Display for OtlpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidOtlpProtocol(pub String);
+
+impl fmt::Display for InvalidOtlpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown OTLP protocol `{}`; expected grpc, http/protobuf or http/json",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidOtlpProtocol {}
+
+impl FromStr for OtlpProtocol {
+    type Err = InvalidOtlpProtocol;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "grpc" => Ok(OtlpProtocol::Grpc),
+            "http/protobuf" => Ok(OtlpProtocol::HttpProtobuf),
+            "http/json" => Ok(OtlpProtocol::HttpJson),
+            _ => Err(InvalidOtlpProtocol(value.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Signal {
+    Traces,
+    Metrics,
+    Logs,
+}
+
+impl Signal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Signal::Traces => "traces",
+            Signal::Metrics => "metrics",
+            Signal::Logs => "logs",
+        }
+    }
+
+    fn env_infix(&self) -> &'static str {
+        match self {
+            Signal::Traces => "TRACES",
+            Signal::Metrics => "METRICS",
+            Signal::Logs => "LOGS",
+        }
+    }
+}
+
+/// Where one signal is exported.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SignalEndpoint {
+    pub protocol: OtlpProtocol,
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Header values usually carry ingestion keys, so only names are printed.
+impl fmt::Debug for SignalEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header_names: Vec<&str> = self.headers.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("SignalEndpoint")
+            .field("protocol", &self.protocol)
+            .field("endpoint", &self.endpoint)
+            .field("headers", &header_names)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtlpEndpoints {
+    pub traces: SignalEndpoint,
+    pub metrics: SignalEndpoint,
+    pub logs: SignalEndpoint,
+}
+
+impl OtlpEndpoints {
+    /// Resolves every signal from the process environment.
+    pub fn from_env() -> Self {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Resolves every signal through `lookup`, for configuration sources
+    /// other than the process environment.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            traces: resolve(Signal::Traces, None, &lookup),
+            metrics: resolve(Signal::Metrics, None, &lookup),
+            logs: resolve(Signal::Logs, None, &lookup),
+        }
+    }
+
+    /// Resolves one signal from the process environment, with `protocol`
+    /// (when set) taking precedence over the `*_PROTOCOL` variables.
+    pub fn signal_from_env(signal: Signal, protocol: Option<OtlpProtocol>) -> SignalEndpoint {
+        resolve(signal, protocol, &|name| std::env::var(name).ok())
+    }
+
+    pub fn signal(&self, signal: Signal) -> &SignalEndpoint {
+        match signal {
+            Signal::Traces => &self.traces,
+            Signal::Metrics => &self.metrics,
+            Signal::Logs => &self.logs,
+        }
+    }
+}
+
+/// Signal-specific variables win; as in the OpenTelemetry SDK, a
+/// signal-specific header list replaces the generic one entirely. An
+/// unknown protocol falls back to gRPC, the SDK default.
+fn resolve(
+    signal: Signal,
+    protocol: Option<OtlpProtocol>,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> SignalEndpoint {
+    let specific = |suffix: &str| {
+        lookup(&format!(
+            "OTEL_EXPORTER_OTLP_{}_{suffix}",
+            signal.env_infix()
+        ))
+        .filter(|value| !value.trim().is_empty())
+    };
+    let generic = |suffix: &str| {
+        lookup(&format!("OTEL_EXPORTER_OTLP_{suffix}")).filter(|value| !value.trim().is_empty())
+    };
+
+    let protocol = protocol.unwrap_or_else(|| {
+        specific("PROTOCOL")
+            .or_else(|| generic("PROTOCOL"))
+            .and_then(|raw| raw.parse().ok())
+            .unwrap_or_default()
+    });
+    let http_path = |base: &str| format!("{}/v1/{}", base.trim_end_matches('/'), signal.as_str());
+    let endpoint = match (specific("ENDPOINT"), protocol.is_http()) {
+        (Some(endpoint), _) => endpoint,
+        (None, false) => generic("ENDPOINT").unwrap_or_else(|| DEFAULT_GRPC_ENDPOINT.to_owned()),
+        (None, true) => http_path(
+            generic("ENDPOINT")
+                .as_deref()
+                .unwrap_or(DEFAULT_HTTP_ENDPOINT)
+                .trim(),
+        ),
+    };
+    let headers = specific("HEADERS")
+        .or_else(|| generic("HEADERS"))
+        .map(|raw| parse_headers(&raw))
+        .unwrap_or_default();
+
+    SignalEndpoint {
+        protocol,
+        endpoint: endpoint.trim().to_owned(),
+        headers,
+    }
+}
+
+/// Span exporter sending to `target` over its protocol. Only
+/// `target.headers` are set here: both transports add the
+/// `OTEL_EXPORTER_OTLP_*HEADERS` variables themselves, and those win on a
+/// clash.
+#[cfg(feature = "otlp")]
+pub(crate) fn span_exporter(
+    target: &SignalEndpoint,
+    timeout: Option<Duration>,
+) -> anyhow::Result<opentelemetry_otlp::SpanExporter> {
+    let builder = opentelemetry_otlp::SpanExporter::builder();
+    let exporter = match target.protocol {
+        #[cfg(feature = "otlp-grpc")]
+        OtlpProtocol::Grpc => tonic_config(builder.with_tonic(), target, timeout)?.build(),
+        #[cfg(feature = "otlp-http")]
+        OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
+            http_config(builder.with_http(), target, timeout).build()
+        }
+        #[cfg(not(all(feature = "otlp-grpc", feature = "otlp-http")))]
+        protocol => return Err(unsupported(Signal::Traces, protocol)),
+    };
+    exporter.context("Failed to create OTLP span exporter")
+}
+
+/// [`span_exporter`] for metrics.
+#[cfg(all(feature = "otlp", feature = "metrics"))]
+pub(crate) fn metric_exporter(
+    target: &SignalEndpoint,
+) -> anyhow::Result<opentelemetry_otlp::MetricExporter> {
+    let builder = opentelemetry_otlp::MetricExporter::builder();
+    let exporter = match target.protocol {
+        #[cfg(feature = "otlp-grpc")]
+        OtlpProtocol::Grpc => tonic_config(builder.with_tonic(), target, None)?.build(),
+        #[cfg(feature = "otlp-http")]
+        OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
+            http_config(builder.with_http(), target, None).build()
+        }
+        #[cfg(not(all(feature = "otlp-grpc", feature = "otlp-http")))]
+        protocol => return Err(unsupported(Signal::Metrics, protocol)),
+    };
+    exporter.context("Failed to create OTLP metric exporter")
+}
+
+/// [`span_exporter`] for logs.
+#[cfg(all(feature = "otlp", feature = "logs"))]
+pub(crate) fn log_exporter(
+    target: &SignalEndpoint,
+) -> anyhow::Result<opentelemetry_otlp::LogExporter> {
+    let builder = opentelemetry_otlp::LogExporter::builder();
+    let exporter = match target.protocol {
+        #[cfg(feature = "otlp-grpc")]
+        OtlpProtocol::Grpc => tonic_config(builder.with_tonic(), target, None)?.build(),
+        #[cfg(feature = "otlp-http")]
+        OtlpProtocol::HttpProtobuf | OtlpProtocol::HttpJson => {
+            http_config(builder.with_http(), target, None).build()
+        }
+        #[cfg(not(all(feature = "otlp-grpc", feature = "otlp-http")))]
+        protocol => return Err(unsupported(Signal::Logs, protocol)),
+    };
+    exporter.context("Failed to create OTLP log exporter")
+}
+
+#[cfg(feature = "otlp-grpc")]
+fn tonic_config<B: WithExportConfig + WithTonicConfig>(
+    builder: B,
+    target: &SignalEndpoint,
+    timeout: Option<Duration>,
+) -> Result<B, TelemetryError> {
+    let builder = builder
+        .with_endpoint(target.endpoint.clone())
+        .with_metadata(metadata_map(&target.headers)?);
+    Ok(match timeout {
+        Some(timeout) => builder.with_timeout(timeout),
+        None => builder,
+    })
+}
+
+#[cfg(feature = "otlp-http")]
+fn http_config<B: WithExportConfig + WithHttpConfig>(
+    builder: B,
+    target: &SignalEndpoint,
+    timeout: Option<Duration>,
+) -> B {
+    let protocol = match target.protocol {
+        OtlpProtocol::HttpJson => Protocol::HttpJson,
+        _ => Protocol::HttpBinary,
+    };
+    let builder = builder
+        .with_endpoint(target.endpoint.clone())
+        .with_protocol(protocol)
+        .with_headers(target.headers.iter().cloned().collect());
+    match timeout {
+        Some(timeout) => builder.with_timeout(timeout),
+        None => builder,
+    }
+}
+
+#[cfg(all(
+    feature = "otlp",
+    not(all(feature = "otlp-grpc", feature = "otlp-http"))
+))]
+fn unsupported(signal: Signal, protocol: OtlpProtocol) -> anyhow::Error {
+    TelemetryError::UnsupportedProtocol { signal, protocol }.into()
+}
+
+/// Parses the `key1=value1,key2=value2` format, percent-decoding values.
+pub fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.trim().to_owned(), percent_decode(value.trim())))
+        .filter(|(name, value)| !name.is_empty() && !value.is_empty())
+        .collect()
+}
+
+/// Converts headers to gRPC metadata for `WithTonicConfig::with_metadata`.
+/// Names are lowercased, as gRPC requires.
+#[cfg(feature = "otlp-grpc")]
+pub fn metadata_map(headers: &[(String, String)]) -> Result<MetadataMap, TelemetryError> {
+    let mut map = http::HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        let invalid = || TelemetryError::InvalidHeader { name: name.clone() };
+        let header_name = http::HeaderName::from_bytes(name.to_ascii_lowercase().as_bytes())
+            .map_err(|_| invalid())?;
+        let header_value = http::HeaderValue::from_str(value).map_err(|_| invalid())?;
+        map.append(header_name, header_value);
+    }
+    Ok(MetadataMap::from_headers(map))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn endpoints(vars: &[(&str, &str)]) -> OtlpEndpoints {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        OtlpEndpoints::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn defaults_to_local_grpc() {
+        let endpoints = endpoints(&[]);
+
+        for signal in [Signal::Traces, Signal::Metrics, Signal::Logs] {
+            let target = endpoints.signal(signal);
+            assert_eq!(target.protocol, OtlpProtocol::Grpc);
+            assert_eq!(target.endpoint, DEFAULT_GRPC_ENDPOINT);
+            assert!(target.headers.is_empty());
+        }
+    }
+
+    #[test]
+    fn signal_specific_variables_win_over_generic_ones() {
+        let endpoints = endpoints(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_EXPORTER_OTLP_METRICS_ENDPOINT", "http://metrics:4317"),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "grpc"),
+            ("OTEL_EXPORTER_OTLP_LOGS_PROTOCOL", "http/json"),
+            (
+                "OTEL_EXPORTER_OTLP_LOGS_ENDPOINT",
+                "http://logs:4318/custom",
+            ),
+        ]);
+
+        assert_eq!(endpoints.traces.endpoint, "http://collector:4317");
+        assert_eq!(endpoints.metrics.endpoint, "http://metrics:4317");
+        assert_eq!(endpoints.metrics.protocol, OtlpProtocol::Grpc);
+        assert_eq!(endpoints.logs.protocol, OtlpProtocol::HttpJson);
+        assert_eq!(endpoints.logs.endpoint, "http://logs:4318/custom");
+    }
+
+    #[test]
+    fn http_appends_the_signal_path_to_the_generic_endpoint_only() {
+        let endpoints = endpoints(&[
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "https://otlp.example.com/"),
+            (
+                "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+                "https://metrics.example.com/otlp",
+            ),
+        ]);
+
+        assert_eq!(
+            endpoints.traces.endpoint,
+            "https://otlp.example.com/v1/traces"
+        );
+        assert_eq!(
+            endpoints.metrics.endpoint,
+            "https://metrics.example.com/otlp"
+        );
+        assert_eq!(
+            self::endpoints(&[("OTEL_EXPORTER_OTLP_PROTOCOL", "http/json")])
+                .logs
+                .endpoint,
+            "http://localhost:4318/v1/logs"
+        );
+    }
+
+    #[test]
+    fn blank_signal_variables_fall_back_to_generic_ones() {
+        let endpoints = endpoints(&[
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT", "  "),
+            ("OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf"),
+            ("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL", ""),
+        ]);
+
+        assert_eq!(endpoints.traces.protocol, OtlpProtocol::HttpProtobuf);
+        assert_eq!(endpoints.traces.endpoint, "http://collector:4317/v1/traces");
+    }
+
+    #[test]
+    fn an_explicit_protocol_wins_over_the_environment() {
+        let vars = [("OTEL_EXPORTER_OTLP_TRACES_PROTOCOL", "http/json")];
+        let lookup = |name: &str| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        };
+
+        let target = resolve(Signal::Traces, Some(OtlpProtocol::Grpc), &lookup);
+
+        assert_eq!(target.protocol, OtlpProtocol::Grpc);
+        assert_eq!(target.endpoint, DEFAULT_GRPC_ENDPOINT);
+    }
+
+    #[test]
+    fn an_unknown_protocol_falls_back_to_grpc() {
+        let endpoints = endpoints(&[("OTEL_EXPORTER_OTLP_PROTOCOL", "carrier-pigeon")]);
+
+        assert_eq!(endpoints.traces.protocol, OtlpProtocol::Grpc);
+        assert!("carrier-pigeon".parse::<OtlpProtocol>().is_err());
+        assert_eq!(
+            " HTTP/Protobuf ".parse::<OtlpProtocol>(),
+            Ok(OtlpProtocol::HttpProtobuf)
+        );
+    }
+
+    #[test]
+    fn signal_headers_replace_generic_headers() {
+        let endpoints = endpoints(&[
+            ("OTEL_EXPORTER_OTLP_HEADERS", "x-team=core,x-env=prod"),
+            ("OTEL_EXPORTER_OTLP_LOGS_HEADERS", "x-logs-key=secret"),
+        ]);
+
+        assert_eq!(
+            endpoints.traces.headers,
+            [
+                ("x-team".to_owned(), "core".to_owned()),
+                ("x-env".to_owned(), "prod".to_owned())
+            ]
+        );
+        assert_eq!(
+            endpoints.logs.headers,
+            [("x-logs-key".to_owned(), "secret".to_owned())]
+        );
+    }
+
+    #[test]
+    fn parses_headers_skipping_empty_entries() {
+        assert_eq!(
+            parse_headers(" api-key = secret ,, =orphan,no-value=,missing-equals,x-team=core,"),
+            [
+                ("api-key".to_owned(), "secret".to_owned()),
+                ("x-team".to_owned(), "core".to_owned())
+            ]
+        );
+        assert!(parse_headers("").is_empty());
+    }
+
+    #[test]
+    fn keeps_equals_signs_inside_header_values() {
+        assert_eq!(
+            parse_headers("Authorization=Basic dXNlcjpwYXNz==,filter=a=b"),
+            [
+                (
+                    "Authorization".to_owned(),
+                    "Basic dXNlcjpwYXNz==".to_owned()
+                ),
+                ("filter".to_owned(), "a=b".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_decodes_header_values() {
+        assert_eq!(
+            parse_headers("Authorization=Bearer%20abc%2Cdef"),
+            [("Authorization".to_owned(), "Bearer abc,def".to_owned())]
+        );
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+    }
+
+    #[test]
+    fn leaves_invalid_percent_escapes_literal() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%41"), "%zzA");
+        assert_eq!(percent_decode("%+1"), "%+1");
+        assert_eq!(percent_decode("%FF"), "\u{FFFD}");
+    }
+}
@@ -0,0 +1,112 @@
+//! Classification of responses into completed / refused / blocked / empty.
+//!
+//! A refusal or a safety block still comes back as a "successful" call, so
+//! without classification refusal-rate trends are invisible. The outcome is
+//! recorded as `llm.response.outcome` on the span and counted in
+//! `llm.response.outcomes` so it can be charted per model.
+
+use crate::cardinality::guarded;
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Phrases that mark a model declining the request. Matched case-insensitively
+/// against the start of the response only, so quoted refusals deeper in a
+/// normal answer are not misclassified.
+const REFUSAL_MARKERS: &[&str] = &[
+    "i can't help with",
+    "i cannot help with",
+    "i can't assist with",
+    "i cannot assist with",
+    "i'm not able to help",
+    "i am not able to help",
+    "i'm unable to",
+    "i am unable to",
+    "i won't be able to",
+    "i'm sorry, but i can't",
+    "i'm sorry, but i cannot",
+    "sorry, i can't",
+];
+const REFUSAL_SCAN_CHARS: usize = 200;
+
+/// Finish reasons (lower-cased) that mean the provider filtered the output.
+const SAFETY_FINISH_REASONS: &[&str] = &[
+    "safety",
+    "blocklist",
+    "prohibited_content",
+    "spii",
+    "recitation",
+    "content_filter",
+    "refusal",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseOutcome {
+    Completed,
+    Refused,
+    SafetyBlocked,
+    Empty,
+}
+
+impl ResponseOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResponseOutcome::Completed => "completed",
+            ResponseOutcome::Refused => "refused",
+            ResponseOutcome::SafetyBlocked => "safety_blocked",
+            ResponseOutcome::Empty => "empty",
+        }
+    }
+}
+
+/// Classifies a response from its text and provider finish reason.
+pub fn classify_response(text: &str, finish_reason: Option<&str>) -> ResponseOutcome {
+    if let Some(reason) = finish_reason {
+        let reason = reason.to_ascii_lowercase();
+        if SAFETY_FINISH_REASONS.contains(&reason.as_str()) {
+            return ResponseOutcome::SafetyBlocked;
+        }
+    }
+
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return ResponseOutcome::Empty;
+    }
+
+    let head: String = trimmed
+        .chars()
+        .take(REFUSAL_SCAN_CHARS)
+        .collect::<String>()
+        .to_lowercase()
+        .replace('\u{2019}', "'");
+    if REFUSAL_MARKERS.iter().any(|marker| head.contains(marker)) {
+        return ResponseOutcome::Refused;
+    }
+
+    ResponseOutcome::Completed
+}
+
+/// Records the outcome on `span` and increments the outcome counter.
+pub fn record_outcome(span: &tracing::Span, model: &str, outcome: ResponseOutcome) {
+    span.set_attribute("llm.response.outcome", outcome.as_str());
+    outcome_counter().add(
+        1,
+        &[
+            guarded("gen_ai.request.model", model),
+            KeyValue::new("llm.response.outcome", outcome.as_str()),
+        ],
+    );
+}
+
+fn outcome_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.response.outcomes")
+            .with_description("LLM responses by outcome classification")
+            .build()
+    })
+}
@@ -0,0 +1,204 @@
+//! Post-generation enforcement of a maximum output length.
+//!
+//! Downstream consumers (SMS gateways, UI cards, fixed-width columns) often
+//! have hard size limits that `max_tokens` alone does not guarantee. The
+//! enforcement step truncates or rejects oversized outputs and records the
+//! action and the original length on the span, so a clipped answer can be
+//! told apart from a short one. Enforcements are counted in
+//! `llm.guardrail.output_limit.enforcements`.
+
+use crate::cardinality::guarded;
+use crate::scopes::Subsystem;
+use crate::tokens::estimate_tokens;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::fmt;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Token ceilings are enforced on the `estimate_tokens` heuristic, so a
+/// truncation keeps this many characters per allowed token.
+const CHARS_PER_TOKEN: usize = 4;
+
+/// What happens to an output that exceeds the ceiling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitAction {
+    Truncate,
+    Reject,
+}
+
+/// Action actually taken for one output; recorded as
+/// `llm.guardrail.output_limit.action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Enforcement {
+    WithinLimit,
+    Truncated,
+    Rejected,
+}
+
+impl Enforcement {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Enforcement::WithinLimit => "none",
+            Enforcement::Truncated => "truncated",
+            Enforcement::Rejected => "rejected",
+        }
+    }
+}
+
+/// Returned when an oversized output is rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTooLong {
+    pub chars: usize,
+    pub estimated_tokens: u64,
+    pub max_chars: Option<usize>,
+    pub max_tokens: Option<u64>,
+}
+
+impl fmt::Display for OutputTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "output of {} chars (~{} tokens) exceeds the configured limit",
+            self.chars, self.estimated_tokens
+        )
+    }
+}
+
+impl std::error::Error for OutputTooLong {}
+
+/// Character and/or token ceiling for generated output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    max_chars: Option<usize>,
+    max_tokens: Option<u64>,
+    action: LimitAction,
+}
+
+impl OutputLimit {
+    pub fn new(action: LimitAction) -> Self {
+        Self {
+            max_chars: None,
+            max_tokens: None,
+            action,
+        }
+    }
+
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = Some(max_chars);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Effective character budget when both ceilings are set.
+    fn char_budget(&self) -> Option<usize> {
+        let from_tokens = self
+            .max_tokens
+            .map(|tokens| (tokens as usize).saturating_mul(CHARS_PER_TOKEN));
+        match (self.max_chars, from_tokens) {
+            (Some(chars), Some(tokens)) => Some(chars.min(tokens)),
+            (chars, tokens) => chars.or(tokens),
+        }
+    }
+
+    /// Applies the limit to `text`, recording the outcome on `span`.
+    ///
+    /// Returns the output to hand downstream: unchanged when within the
+    /// limit, cut on a character boundary when truncating, or an error when
+    /// rejecting.
+    pub fn enforce(
+        &self,
+        span: &tracing::Span,
+        model: &str,
+        text: String,
+    ) -> Result<String, OutputTooLong> {
+        let chars = text.chars().count();
+        let estimated_tokens = estimate_tokens(&text);
+        let exceeded = self.max_chars.is_some_and(|max| chars > max)
+            || self.max_tokens.is_some_and(|max| estimated_tokens > max);
+
+        let enforcement = match (exceeded, self.action) {
+            (false, _) => Enforcement::WithinLimit,
+            (true, LimitAction::Truncate) => Enforcement::Truncated,
+            (true, LimitAction::Reject) => Enforcement::Rejected,
+        };
+        record_enforcement(span, model, self, enforcement, chars, estimated_tokens);
+
+        match enforcement {
+            Enforcement::WithinLimit => Ok(text),
+            Enforcement::Truncated => {
+                let budget = self.char_budget().unwrap_or(chars);
+                Ok(text.chars().take(budget).collect())
+            }
+            Enforcement::Rejected => Err(OutputTooLong {
+                chars,
+                estimated_tokens,
+                max_chars: self.max_chars,
+                max_tokens: self.max_tokens,
+            }),
+        }
+    }
+}
+
+fn record_enforcement(
+    span: &tracing::Span,
+    model: &str,
+    limit: &OutputLimit,
+    enforcement: Enforcement,
+    chars: usize,
+    estimated_tokens: u64,
+) {
+    span.set_attribute("llm.guardrail.output_limit.action", enforcement.as_str());
+    if enforcement == Enforcement::WithinLimit {
+        return;
+    }
+
+    let mut attributes = vec![
+        KeyValue::new("llm.output.original_chars", chars as i64),
+        KeyValue::new("llm.output.original_tokens", estimated_tokens as i64),
+    ];
+    if let Some(max_chars) = limit.max_chars {
+        attributes.push(KeyValue::new(
+            "llm.guardrail.output_limit.max_chars",
+            max_chars as i64,
+        ));
+    }
+    if let Some(max_tokens) = limit.max_tokens {
+        attributes.push(KeyValue::new(
+            "llm.guardrail.output_limit.max_tokens",
+            max_tokens as i64,
+        ));
+    }
+    for attribute in &attributes {
+        span.set_attribute(attribute.key.clone(), attribute.value.clone());
+    }
+    span.add_event("llm.guardrail.output_limit", attributes);
+    tracing::warn!(
+        gen_ai.request.model = model,
+        llm.guardrail.output_limit.action = enforcement.as_str(),
+        original_chars = chars,
+        "Output exceeded the configured length limit"
+    );
+    enforcement_counter().add(
+        1,
+        &[
+            guarded("gen_ai.request.model", model),
+            KeyValue::new("llm.guardrail.output_limit.action", enforcement.as_str()),
+        ],
+    );
+}
+
+fn enforcement_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.guardrail.output_limit.enforcements")
+            .with_description("Outputs truncated or rejected by the length guardrail")
+            .build()
+    })
+}
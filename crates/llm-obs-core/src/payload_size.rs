@@ -0,0 +1,120 @@
+//! Serialized request and response sizes of provider calls.
+//!
+//! Token counts say little about what goes over the wire: chat history,
+//! tool definitions, inline images and JSON framing all add bytes, and
+//! payload bloat from long histories drives both latency and egress cost.
+//! [`PayloadSizeMetrics::record`] sets the HTTP semconv body size
+//! attributes on the call's span and records the sizes in the
+//! `llm.client.request.size` / `llm.client.response.size` histograms, with
+//! the same attributes as the GenAI metrics.
+//!
+//! Pass the size of the body actually sent and received where it is
+//! available; otherwise [`serialized_size`] measures the JSON encoding of
+//! the request and response types without allocating it.
+
+use crate::genai_metrics::GenAiCall;
+use crate::scopes::Subsystem;
+use opentelemetry::metrics::{Histogram, Meter};
+use serde::Serialize;
+use std::io;
+use std::sync::OnceLock;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Powers of four from 256 B to 64 MiB.
+const SIZE_BOUNDARIES: [f64; 10] = [
+    256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0, 67108864.0,
+];
+
+/// Length in bytes of the JSON encoding of `value`, or 0 if it cannot be
+/// serialized.
+pub fn serialized_size<T: Serialize + ?Sized>(value: &T) -> u64 {
+    let mut counter = ByteCounter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+struct ByteCounter(u64);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PayloadSizeMetrics {
+    request_size: Histogram<u64>,
+    response_size: Histogram<u64>,
+}
+
+impl PayloadSizeMetrics {
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            request_size: meter
+                .u64_histogram("llm.client.request.size")
+                .with_unit("By")
+                .with_description("Serialized size of LLM provider request bodies")
+                .with_boundaries(SIZE_BOUNDARIES.to_vec())
+                .build(),
+            response_size: meter
+                .u64_histogram("llm.client.response.size")
+                .with_unit("By")
+                .with_description("Serialized size of LLM provider response bodies")
+                .with_boundaries(SIZE_BOUNDARIES.to_vec())
+                .build(),
+        }
+    }
+
+    /// Instruments on the global meter provider. Install the provider
+    /// before the first call; instruments created earlier stay no-ops.
+    pub fn global() -> &'static PayloadSizeMetrics {
+        static GLOBAL: OnceLock<PayloadSizeMetrics> = OnceLock::new();
+        GLOBAL.get_or_init(|| PayloadSizeMetrics::new(&Subsystem::Agent.meter()))
+    }
+
+    /// Records body sizes on `span` as `http.request.body.size` /
+    /// `http.response.body.size` and in the histograms. `response_bytes` is
+    /// `None` when the call failed before a body arrived.
+    pub fn record(
+        &self,
+        span: &tracing::Span,
+        call: &GenAiCall<'_>,
+        request_bytes: u64,
+        response_bytes: Option<u64>,
+    ) {
+        let attributes = call.attributes();
+        span.set_attribute("http.request.body.size", request_bytes as i64);
+        self.request_size.record(request_bytes, &attributes);
+        if let Some(response_bytes) = response_bytes {
+            span.set_attribute("http.response.body.size", response_bytes as i64);
+            self.response_size.record(response_bytes, &attributes);
+        }
+    }
+
+    /// [`PayloadSizeMetrics::record`] with the sizes measured by
+    /// [`serialized_size`].
+    pub fn record_serialized<Req, Resp>(
+        &self,
+        span: &tracing::Span,
+        call: &GenAiCall<'_>,
+        request: &Req,
+        response: Option<&Resp>,
+    ) where
+        Req: Serialize + ?Sized,
+        Resp: Serialize + ?Sized,
+    {
+        self.record(
+            span,
+            call,
+            serialized_size(request),
+            response.map(serialized_size),
+        );
+    }
+}
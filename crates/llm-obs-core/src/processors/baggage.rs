@@ -0,0 +1,113 @@
+//! Copies baggage entries onto spans.
+//!
+//! Baggage reaches every span's context but is not exported with the span.
+//! [`BaggageProcessor`] reads the configured keys from the parent context
+//! when a span starts and adds them as attributes when it ends, so per-user
+//! or per-session cost and latency can be sliced in the backend. By default
+//! only LLM spans (those with `gen_ai.operation.name`) get them, which is
+//! where cost and latency are aggregated; attributes the span already has
+//! are kept. Keys may end in `*` to copy every entry with that prefix, such
+//! as the `feedback.*` scores set by `feedback::FeedbackState`.
+//! `TelemetryBuilder` installs the processor with the default keys.
+
+use super::{attribute, glob_match};
+use crate::semconv::{SESSION_ID, TENANT_ID, USER_ID};
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::trace::{Span as _, SpanId, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Baggage keys copied by default, see `identity::Identity` and
+/// `feedback::FeedbackState`.
+pub const DEFAULT_BAGGAGE_KEYS: [&str; 4] = [USER_ID, SESSION_ID, TENANT_ID, "feedback.*"];
+
+#[derive(Debug)]
+pub struct BaggageProcessor<P> {
+    inner: P,
+    keys: Vec<String>,
+    all_spans: bool,
+    pending: Mutex<HashMap<(TraceId, SpanId), Vec<KeyValue>>>,
+}
+
+impl<P: SpanProcessor> BaggageProcessor<P> {
+    /// Copies [`DEFAULT_BAGGAGE_KEYS`] onto LLM spans.
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            keys: DEFAULT_BAGGAGE_KEYS.map(str::to_owned).to_vec(),
+            all_spans: false,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Also copies `key`, e.g. `feature_flag.<name>`, `experiment.*` or an
+    /// experiment id.
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.keys.push(key.into());
+        self
+    }
+
+    /// Copies onto every span, not only LLM spans.
+    pub fn with_all_spans(mut self) -> Self {
+        self.all_spans = true;
+        self
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for BaggageProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let baggage = cx.baggage();
+        let entries: Vec<KeyValue> = baggage
+            .iter()
+            .filter(|(key, _)| {
+                self.keys
+                    .iter()
+                    .any(|pattern| glob_match(pattern, key.as_str()))
+            })
+            .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+            .collect();
+        if !entries.is_empty() {
+            let context = span.span_context();
+            self.pending
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert((context.trace_id(), context.span_id()), entries);
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let key = (span.span_context.trace_id(), span.span_context.span_id());
+        let entries = self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&key);
+        let copy = self.all_spans || attribute(&span, "gen_ai.operation.name").is_some();
+        if let (Some(entries), true) = (entries, copy) {
+            for entry in entries {
+                if attribute(&span, entry.key.as_str()).is_none() {
+                    span.attributes.push(entry);
+                }
+            }
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
@@ -0,0 +1,105 @@
+//! Which finished spans count as LLM and tool calls, and which carry the
+//! usage and cost of a call, shared by the rollup, the run report and
+//! workflow budgets.
+//!
+//! One instrumented prompt leaves the same numbers on several spans: rig
+//! records each turn's usage on its own `chat` span (and the sum on its
+//! `invoke_agent` span), `InstrumentedAgent` sets the sum on the caller's
+//! span, and a cost deriver prices every span with usage. [`CallCounter`]
+//! counts a model call only on a span whose `gen_ai.operation.name` is a
+//! model operation and that has no model call below it, and takes tokens
+//! and cost from the deepest spans carrying them, so each is counted once
+//! whichever of those spans a pipeline sees.
+
+use super::{attribute, attribute_f64};
+use opentelemetry::trace::{SpanContext, SpanId, TraceId};
+use opentelemetry_sdk::trace::SpanData;
+use std::collections::HashMap;
+
+/// `gen_ai.operation.name` of spans that are one model call.
+const MODEL_OPERATIONS: [&str; 4] = [
+    "chat",
+    "chat_streaming",
+    "generate_content",
+    "text_completion",
+];
+
+/// Open spans tracked at once; spans started beyond this count as leaves.
+const MAX_OPEN_SPANS: usize = 10_000;
+
+/// What ended below an open span.
+#[derive(Debug, Clone, Copy, Default)]
+struct Below {
+    call: bool,
+    usage: bool,
+    cost: bool,
+}
+
+/// What one finished span adds to the totals.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Counted {
+    pub(crate) llm_call: bool,
+    pub(crate) tool_call: bool,
+    pub(crate) input_tokens: u64,
+    pub(crate) output_tokens: u64,
+    pub(crate) cost_usd: f64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CallCounter {
+    open: HashMap<(TraceId, SpanId), Below>,
+}
+
+impl CallCounter {
+    /// Starts tracking `span`, so its children can report to it.
+    pub(crate) fn on_start(&mut self, span: &SpanContext) {
+        if self.open.len() < MAX_OPEN_SPANS {
+            self.open
+                .insert((span.trace_id(), span.span_id()), Below::default());
+        }
+    }
+
+    /// Classifies `span` and reports what it carries to its parent.
+    pub(crate) fn on_end(&mut self, span: &SpanData) -> Counted {
+        let trace_id = span.span_context.trace_id();
+        let below = self
+            .open
+            .remove(&(trace_id, span.span_context.span_id()))
+            .unwrap_or_default();
+        let tool_call = is_tool(span);
+        let model_call = !tool_call
+            && attribute(span, "gen_ai.operation.name")
+                .is_some_and(|operation| MODEL_OPERATIONS.contains(&&*operation.as_str()));
+        let input_tokens = attribute_f64(span, "gen_ai.usage.input_tokens");
+        let output_tokens = attribute_f64(span, "gen_ai.usage.output_tokens");
+        let has_usage = input_tokens.is_some() || output_tokens.is_some();
+        let cost_usd = attribute_f64(span, "gen_ai.usage.cost_usd");
+
+        let mut counted = Counted {
+            llm_call: model_call && !below.call,
+            tool_call,
+            ..Counted::default()
+        };
+        if !below.usage {
+            counted.input_tokens = input_tokens.unwrap_or_default() as u64;
+            counted.output_tokens = output_tokens.unwrap_or_default() as u64;
+        }
+        if !below.cost {
+            counted.cost_usd = cost_usd.unwrap_or_default();
+        }
+
+        if let Some(parent) = self.open.get_mut(&(trace_id, span.parent_span_id)) {
+            parent.call |= below.call || model_call;
+            parent.usage |= below.usage || has_usage;
+            parent.cost |= below.cost || cost_usd.is_some();
+        }
+        counted
+    }
+}
+
+fn is_tool(span: &SpanData) -> bool {
+    attribute(span, "gen_ai.tool.name").is_some()
+        || attribute(span, "gen_ai.operation.name")
+            .is_some_and(|operation| operation.as_str() == "execute_tool")
+        || span.name.starts_with("tool.")
+}
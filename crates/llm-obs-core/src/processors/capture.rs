@@ -0,0 +1,289 @@
+//! Prompt and response content capture policy.
+//!
+//! `record_model_input` / `record_model_output` (and rig's own
+//! instrumentation) write full message JSON to `gen_ai.input.messages` and
+//! `gen_ai.output.messages`, and tool spans carry
+//! `gen_ai.tool.call.arguments` and `gen_ai.tool.call.result`. Whether that is acceptable depends on the
+//! deployment, not the call site, so the policy is applied once by
+//! [`ContentCaptureProcessor`] to every finished span:
+//!
+//! - `full`: content is exported unchanged;
+//! - `truncated:<n>`: only the first `n` characters are kept;
+//! - `hash`: content is replaced by its fingerprint, so identical prompts
+//!   still correlate;
+//! - `off`: content attributes are removed.
+//!
+//! The policy comes from `LLM_CONTENT_CAPTURE` unless set in code, and
+//! `TelemetryBuilder` installs the processor in its export pipeline. Spans
+//! whose content was changed carry `llm.content_capture.policy`, and a span
+//! that already names the same policy (content recorded by `#[llm_span]` or
+//! `InstrumentedTool`, which apply the policy themselves) is not changed
+//! twice. With [`ContentCaptureProcessor::with_flag`] the policy is a
+//! feature flag instead (see `flags::CONTENT_CAPTURE_FLAG`), re-evaluated
+//! for every span with content, so capture can be ramped up or cut off at
+//! runtime.
+
+use super::attribute;
+use crate::fingerprint::fingerprint;
+use crate::flags::{self, evaluation_attributes, resolve_typed};
+use opentelemetry::trace::Event;
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+pub const CONTENT_CAPTURE_ENV: &str = "LLM_CONTENT_CAPTURE";
+
+/// Span attribute naming the policy applied to the span's content.
+pub const CAPTURE_POLICY_KEY: &str = "llm.content_capture.policy";
+
+/// Attributes that carry prompt, response or tool call content, including
+/// the legacy names rig's agent spans (and `compat::SemconvCompat::Dual`)
+/// write.
+pub const DEFAULT_CONTENT_KEYS: [&str; 7] = [
+    "gen_ai.input.messages",
+    "gen_ai.output.messages",
+    "gen_ai.system_instructions",
+    "gen_ai.prompt",
+    "gen_ai.completion",
+    "gen_ai.tool.call.arguments",
+    "gen_ai.tool.call.result",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentCapturePolicy {
+    #[default]
+    Full,
+    /// Keep at most this many characters.
+    Truncated(usize),
+    HashOnly,
+    Off,
+}
+
+impl ContentCapturePolicy {
+    /// Reads [`CONTENT_CAPTURE_ENV`]; unset means [`ContentCapturePolicy::Full`].
+    pub fn from_env() -> Result<Self, InvalidContentCapturePolicy> {
+        match std::env::var(CONTENT_CAPTURE_ENV) {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(ContentCapturePolicy::Full),
+        }
+    }
+
+    /// Like [`ContentCapturePolicy::from_env`], but an unparsable value is
+    /// logged and falls back to `Off`, since a typo should not start
+    /// exporting prompts.
+    pub fn from_env_or_off() -> Self {
+        Self::from_env().unwrap_or_else(|error| {
+            tracing::warn!(%error, "Not capturing content");
+            ContentCapturePolicy::Off
+        })
+    }
+
+    /// Content to export under this policy, or `None` to drop it.
+    pub fn apply(&self, content: &str) -> Option<String> {
+        match self {
+            ContentCapturePolicy::Full => Some(content.to_owned()),
+            ContentCapturePolicy::Truncated(max_chars) => {
+                Some(content.chars().take(*max_chars).collect())
+            }
+            ContentCapturePolicy::HashOnly => Some(fingerprint(content)),
+            ContentCapturePolicy::Off => None,
+        }
+    }
+}
+
+impl fmt::Display for ContentCapturePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentCapturePolicy::Full => write!(f, "full"),
+            ContentCapturePolicy::Truncated(max_chars) => write!(f, "truncated:{max_chars}"),
+            ContentCapturePolicy::HashOnly => write!(f, "hash"),
+            ContentCapturePolicy::Off => write!(f, "off"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidContentCapturePolicy(String);
+
+impl fmt::Display for InvalidContentCapturePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid content capture policy `{}` (expected full, truncated:<n>, hash or off)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidContentCapturePolicy {}
+
+impl FromStr for ContentCapturePolicy {
+    type Err = InvalidContentCapturePolicy;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let normalized = value.trim().to_ascii_lowercase();
+        match normalized.as_str() {
+            "full" => Ok(ContentCapturePolicy::Full),
+            "hash" | "hash_only" => Ok(ContentCapturePolicy::HashOnly),
+            "off" | "none" => Ok(ContentCapturePolicy::Off),
+            other => other
+                .strip_prefix("truncated:")
+                .and_then(|max_chars| max_chars.trim().parse().ok())
+                .map(ContentCapturePolicy::Truncated)
+                .ok_or_else(|| InvalidContentCapturePolicy(value.to_owned())),
+        }
+    }
+}
+
+/// Applies a [`ContentCapturePolicy`] to content attributes before export.
+#[derive(Debug)]
+pub struct ContentCaptureProcessor<P> {
+    inner: P,
+    policy: ContentCapturePolicy,
+    flag: Option<String>,
+    keys: Vec<String>,
+}
+
+impl<P: SpanProcessor> ContentCaptureProcessor<P> {
+    /// Uses the policy from the environment and [`DEFAULT_CONTENT_KEYS`].
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            policy: ContentCapturePolicy::from_env_or_off(),
+            flag: None,
+            keys: DEFAULT_CONTENT_KEYS.map(str::to_owned).to_vec(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: ContentCapturePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Takes the policy from the flag `key` (e.g.
+    /// `flags::CONTENT_CAPTURE_FLAG`) of the process-wide flag provider.
+    /// The configured policy applies while no provider is installed or the
+    /// flag is unknown or invalid. Spans with content get the
+    /// `feature_flag.evaluation` event.
+    pub fn with_flag(mut self, key: impl Into<String>) -> Self {
+        self.flag = Some(key.into());
+        self
+    }
+
+    /// Also applies the policy to `key`, e.g. an application's own
+    /// `llm.response.preview`.
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.keys.push(key.into());
+        self
+    }
+
+    pub fn policy(&self) -> ContentCapturePolicy {
+        self.policy
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ContentCaptureProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let has_content = self.keys.iter().any(|key| attribute(&span, key).is_some());
+        let mut policy = self.policy;
+        if has_content {
+            if let (Some(key), Some(provider)) = (&self.flag, flags::provider()) {
+                let (evaluation, flag_policy) =
+                    resolve_typed(provider.as_ref(), key, self.policy, |variant| {
+                        variant.parse().ok()
+                    });
+                policy = flag_policy;
+                span.events.events.push(Event::new(
+                    "feature_flag.evaluation",
+                    SystemTime::now(),
+                    evaluation_attributes(provider.name(), key, &evaluation, &policy.to_string()),
+                    0,
+                ));
+            }
+        }
+        let policy_name = policy.to_string();
+        let applied = attribute(&span, CAPTURE_POLICY_KEY)
+            .is_some_and(|applied| applied.as_str() == policy_name.as_str());
+        if policy != ContentCapturePolicy::Full && has_content && !applied {
+            span.attributes.retain_mut(|attribute| {
+                if attribute.key.as_str() == CAPTURE_POLICY_KEY {
+                    return false;
+                }
+                if !self.keys.iter().any(|key| key == attribute.key.as_str()) {
+                    return true;
+                }
+                match policy.apply(attribute.value.as_str().as_ref()) {
+                    Some(content) => {
+                        attribute.value = Value::from(content);
+                        true
+                    }
+                    None => false,
+                }
+            });
+            span.attributes
+                .push(KeyValue::new(CAPTURE_POLICY_KEY, policy_name));
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    #[test]
+    fn policy_applies_to_tool_call_arguments_and_results() {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(
+                ContentCaptureProcessor::new(collect.clone())
+                    .with_policy(ContentCapturePolicy::Off),
+            )
+            .build();
+        let cx = start(
+            &provider.tracer("test"),
+            &Context::new(),
+            "execute_tool lookup_customer",
+            vec![
+                KeyValue::new("gen_ai.tool.name", "lookup_customer"),
+                KeyValue::new("gen_ai.tool.call.arguments", r#"{"email":"a@b.c"}"#),
+                KeyValue::new("gen_ai.tool.call.result", r#"{"plan":"pro"}"#),
+            ],
+        );
+        end(&cx);
+
+        let span = collect.span("execute_tool lookup_customer");
+        assert!(attribute(&span, "gen_ai.tool.call.arguments").is_none());
+        assert!(attribute(&span, "gen_ai.tool.call.result").is_none());
+        assert_eq!(
+            attribute(&span, "gen_ai.tool.name").map(|name| name.as_str().into_owned()),
+            Some("lookup_customer".to_owned())
+        );
+        assert_eq!(
+            attribute(&span, CAPTURE_POLICY_KEY).map(|policy| policy.as_str().into_owned()),
+            Some("off".to_owned())
+        );
+    }
+}
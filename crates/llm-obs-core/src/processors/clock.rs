@@ -0,0 +1,185 @@
+//! Stamps spans with an injected [`Clock`].
+//!
+//! `tracing-opentelemetry` starts the OpenTelemetry span only when the
+//! `tracing` span closes, so a span processor sees every span start and end
+//! at the same moment. The times are therefore taken where they happen:
+//! [`ClockLayer`] stamps the start when the `tracing` span is created and
+//! each event when it is recorded, and [`ClockProcessor`] stamps the end when
+//! the span closes. Both must share one clock; `TelemetryBuilder::with_clock`
+//! installs both. Host apps using `build_layer` add [`ClockLayer`] after the
+//! OpenTelemetry layer themselves.
+
+use crate::deterministic::Clock;
+use opentelemetry::Context;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::time::Duration;
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Stamps span ends with the clock.
+#[derive(Debug)]
+pub struct ClockProcessor<P> {
+    inner: P,
+    clock: Box<dyn Clock>,
+}
+
+impl<P: SpanProcessor> ClockProcessor<P> {
+    pub fn new(inner: P, clock: impl Clock + 'static) -> Self {
+        Self {
+            inner,
+            clock: Box::new(clock),
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ClockProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        span.end_time = self.clock.now().max(span.start_time);
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// `tracing` layer stamping span starts and events with the clock. Must be
+/// added after the OpenTelemetry layer, whose callbacks run first.
+#[derive(Debug)]
+pub struct ClockLayer {
+    clock: Box<dyn Clock>,
+}
+
+impl ClockLayer {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+        }
+    }
+}
+
+/// Number of the span's events already stamped.
+struct StampedEvents(usize);
+
+impl<S> Layer<S> for ClockLayer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(otel_data) = extensions.get_mut::<OtelData>() {
+            otel_data.builder.start_time = Some(self.clock.now());
+            extensions.insert(StampedEvents(0));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        let stamped = extensions
+            .get_mut::<StampedEvents>()
+            .map_or(0, |stamped| stamped.0);
+        let Some(events) = extensions
+            .get_mut::<OtelData>()
+            .and_then(|otel_data| otel_data.builder.events.as_mut())
+        else {
+            return;
+        };
+        // Only the events the OpenTelemetry layer just recorded; one event
+        // may add two (the event and an exception), or none when filtered.
+        let now = self.clock.now();
+        for event in events.iter_mut().skip(stamped) {
+            event.timestamp = now;
+        }
+        let total = events.len();
+        extensions.replace(StampedEvents(total));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deterministic::{SequentialIdGenerator, SimulatedClock};
+    use crate::processors::testing::Collect;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::time::SystemTime;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn collect_with_clock(clock: &SimulatedClock, run: impl FnOnce()) -> Vec<SpanData> {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_id_generator(SequentialIdGenerator::new())
+            .with_span_processor(ClockProcessor::new(collect.clone(), clock.clone()))
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+            .with(ClockLayer::new(clock.clone()));
+        tracing::subscriber::with_default(subscriber, run);
+        collect.spans()
+    }
+
+    #[test]
+    fn parents_enclose_children_and_events_keep_their_times() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = SimulatedClock::new(start).with_step(Duration::from_millis(1));
+        let spans = collect_with_clock(&clock, || {
+            let parent = tracing::info_span!("parent");
+            parent.in_scope(|| {
+                tracing::info!("first");
+                tracing::info_span!("child").in_scope(|| tracing::info!("inside"));
+                tracing::info!("second");
+            });
+        });
+
+        let child = spans.iter().find(|span| span.name == "child").unwrap();
+        let parent = spans.iter().find(|span| span.name == "parent").unwrap();
+        assert_eq!(parent.start_time, start);
+        assert!(parent.start_time < child.start_time);
+        assert!(child.start_time < child.end_time);
+        assert!(child.end_time < parent.end_time);
+
+        let times: Vec<_> = parent.events.iter().map(|event| event.timestamp).collect();
+        assert_eq!(times.len(), 2);
+        assert!(parent.start_time < times[0]);
+        assert!(times[0] < child.start_time);
+        assert!(child.end_time < times[1]);
+        assert!(times[1] < parent.end_time);
+    }
+
+    #[test]
+    fn a_standing_clock_gives_every_span_the_same_time() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let clock = SimulatedClock::new(start);
+        let spans = collect_with_clock(&clock, || {
+            tracing::info_span!("one").in_scope(|| tracing::info!("event"));
+        });
+
+        assert_eq!(spans[0].start_time, start);
+        assert_eq!(spans[0].end_time, start);
+        assert_eq!(spans[0].events.iter().next().unwrap().timestamp, start);
+    }
+}
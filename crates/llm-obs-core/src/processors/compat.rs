@@ -0,0 +1,305 @@
+//! Legacy GenAI attribute names alongside the current ones.
+//!
+//! The GenAI semantic conventions renamed several attributes
+//! (`gen_ai.prompt` became `gen_ai.input.messages`, `gen_ai.system` became
+//! `gen_ai.provider.name`, ...), as did the feature-flag conventions
+//! (`feature_flag.provider_name` became `feature_flag.provider.name`) on
+//! `feature_flag.evaluation` events. Dashboards and alerts built on the old
+//! names go blank the day the instrumentation moves on. During a migration window
+//! [`SemconvCompatProcessor`] rewrites each finished span per
+//! [`SemconvCompat`], span attributes and event attributes alike:
+//!
+//! - `off`: attributes are exported as the instrumentation wrote them;
+//! - `dual`: each current attribute is also exported under its legacy name,
+//!   so old and new dashboards work side by side;
+//! - `legacy`: only the old names, for backends not yet migrated.
+//!
+//! Values are only copied from the current names to the legacy ones: rig
+//! still writes a plain prompt string to `gen_ai.prompt` on its agent spans,
+//! which is not the message JSON `gen_ai.input.messages` holds.
+//!
+//! `TelemetryBuilder` takes the mode from `LLM_SEMCONV_COMPAT` unless set
+//! with `TelemetryBuilder::with_semconv_compat`.
+
+use opentelemetry::{Context, Key, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const SEMCONV_COMPAT_ENV: &str = "LLM_SEMCONV_COMPAT";
+
+/// `(current, legacy)` names of the renamed GenAI and feature-flag
+/// attributes.
+pub const RENAMED_ATTRIBUTES: [(&str, &str); 7] = [
+    ("gen_ai.input.messages", "gen_ai.prompt"),
+    ("gen_ai.output.messages", "gen_ai.completion"),
+    ("gen_ai.usage.input_tokens", "gen_ai.usage.prompt_tokens"),
+    (
+        "gen_ai.usage.output_tokens",
+        "gen_ai.usage.completion_tokens",
+    ),
+    ("gen_ai.provider.name", "gen_ai.system"),
+    ("feature_flag.provider.name", "feature_flag.provider_name"),
+    ("feature_flag.result.variant", "feature_flag.variant"),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SemconvCompat {
+    #[default]
+    Off,
+    Dual,
+    Legacy,
+}
+
+impl SemconvCompat {
+    /// Reads [`SEMCONV_COMPAT_ENV`]; unset means [`SemconvCompat::Off`].
+    pub fn from_env() -> Result<Self, InvalidSemconvCompat> {
+        match std::env::var(SEMCONV_COMPAT_ENV) {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(SemconvCompat::Off),
+        }
+    }
+
+    /// Rewrites `attributes` in place under this mode.
+    pub fn apply(&self, attributes: &mut Vec<KeyValue>) {
+        match self {
+            SemconvCompat::Off => {}
+            SemconvCompat::Dual => {
+                for (current, legacy) in RENAMED_ATTRIBUTES {
+                    copy_missing(attributes, current, legacy);
+                }
+            }
+            SemconvCompat::Legacy => {
+                for (current, legacy) in RENAMED_ATTRIBUTES {
+                    copy_missing(attributes, current, legacy);
+                    attributes.retain(|kv| kv.key.as_str() != current);
+                }
+            }
+        }
+    }
+}
+
+/// Adds `to` with the value of `from` unless the span already has `to`.
+fn copy_missing(attributes: &mut Vec<KeyValue>, from: &'static str, to: &'static str) {
+    if attributes.iter().any(|kv| kv.key.as_str() == to) {
+        return;
+    }
+    let value = attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == from)
+        .map(|kv| kv.value.clone());
+    if let Some(value) = value {
+        attributes.push(KeyValue::new(Key::from_static_str(to), value));
+    }
+}
+
+impl fmt::Display for SemconvCompat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemconvCompat::Off => write!(f, "off"),
+            SemconvCompat::Dual => write!(f, "dual"),
+            SemconvCompat::Legacy => write!(f, "legacy"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSemconvCompat(String);
+
+impl fmt::Display for InvalidSemconvCompat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid semconv compat mode `{}` (expected off, dual or legacy)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidSemconvCompat {}
+
+impl FromStr for SemconvCompat {
+    type Err = InvalidSemconvCompat;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "current" | "none" => Ok(SemconvCompat::Off),
+            "dual" | "both" => Ok(SemconvCompat::Dual),
+            "legacy" => Ok(SemconvCompat::Legacy),
+            _ => Err(InvalidSemconvCompat(value.to_owned())),
+        }
+    }
+}
+
+/// Applies a [`SemconvCompat`] mode to finished spans before export.
+#[derive(Debug)]
+pub struct SemconvCompatProcessor<P> {
+    inner: P,
+    mode: SemconvCompat,
+}
+
+impl<P: SpanProcessor> SemconvCompatProcessor<P> {
+    /// Starts with [`SemconvCompat::Off`]; see [`SemconvCompat::from_env`].
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            mode: SemconvCompat::Off,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: SemconvCompat) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn mode(&self) -> SemconvCompat {
+        self.mode
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for SemconvCompatProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        self.mode.apply(&mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            self.mode.apply(&mut event.attributes);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(attributes: &[KeyValue]) -> Vec<&str> {
+        attributes.iter().map(|kv| kv.key.as_str()).collect()
+    }
+
+    fn value<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a opentelemetry::Value> {
+        attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| &kv.value)
+    }
+
+    #[test]
+    fn dual_adds_legacy_names_next_to_current_ones() {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.input.messages", "[]"),
+            KeyValue::new("gen_ai.usage.input_tokens", 12),
+            KeyValue::new("gen_ai.provider.name", "gemini"),
+        ];
+        SemconvCompat::Dual.apply(&mut attributes);
+
+        assert_eq!(
+            keys(&attributes),
+            [
+                "gen_ai.input.messages",
+                "gen_ai.usage.input_tokens",
+                "gen_ai.provider.name",
+                "gen_ai.prompt",
+                "gen_ai.usage.prompt_tokens",
+                "gen_ai.system",
+            ]
+        );
+        assert_eq!(
+            value(&attributes, "gen_ai.usage.prompt_tokens"),
+            Some(&opentelemetry::Value::I64(12))
+        );
+    }
+
+    #[test]
+    fn dual_does_not_copy_legacy_strings_into_message_attributes() {
+        let mut attributes = vec![KeyValue::new("gen_ai.prompt", "What is 2 + 2?")];
+        SemconvCompat::Dual.apply(&mut attributes);
+
+        assert_eq!(keys(&attributes), ["gen_ai.prompt"]);
+    }
+
+    #[test]
+    fn dual_keeps_an_existing_legacy_value() {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.system", "vertex_ai"),
+            KeyValue::new("gen_ai.provider.name", "gcp.vertex_ai"),
+        ];
+        SemconvCompat::Dual.apply(&mut attributes);
+
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(
+            value(&attributes, "gen_ai.system").map(|value| value.as_str()),
+            Some("vertex_ai".into())
+        );
+    }
+
+    #[test]
+    fn legacy_renames_current_attributes() {
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.output.messages", "[]"),
+            KeyValue::new("gen_ai.usage.output_tokens", 3),
+            KeyValue::new("gen_ai.request.model", "gemini-2.5-flash"),
+        ];
+        SemconvCompat::Legacy.apply(&mut attributes);
+
+        assert_eq!(
+            keys(&attributes),
+            [
+                "gen_ai.request.model",
+                "gen_ai.completion",
+                "gen_ai.usage.completion_tokens",
+            ]
+        );
+    }
+
+    #[test]
+    fn dual_keeps_the_old_feature_flag_keys() {
+        let mut attributes = vec![
+            KeyValue::new("feature_flag.key", "llm.sampling.ratio"),
+            KeyValue::new("feature_flag.provider.name", "static"),
+            KeyValue::new("feature_flag.result.variant", "0.5"),
+        ];
+        SemconvCompat::Dual.apply(&mut attributes);
+
+        assert_eq!(
+            value(&attributes, "feature_flag.provider_name"),
+            Some(&"static".into())
+        );
+        assert_eq!(
+            value(&attributes, "feature_flag.variant"),
+            Some(&"0.5".into())
+        );
+    }
+
+    #[test]
+    fn off_leaves_attributes_alone() {
+        let mut attributes = vec![KeyValue::new("gen_ai.input.messages", "[]")];
+        SemconvCompat::Off.apply(&mut attributes);
+
+        assert_eq!(keys(&attributes), ["gen_ai.input.messages"]);
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(" Dual ".parse(), Ok(SemconvCompat::Dual));
+        assert_eq!("legacy".parse(), Ok(SemconvCompat::Legacy));
+        assert_eq!("current".parse(), Ok(SemconvCompat::Off));
+        assert!("both-ish".parse::<SemconvCompat>().is_err());
+    }
+}
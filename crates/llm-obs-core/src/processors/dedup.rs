@@ -0,0 +1,334 @@
+//! Protection against double-emitted CLIENT spans.
+//!
+//! When the host app already instruments its HTTP client (reqwest-tracing,
+//! hyper, tower-http), every provider call shows up twice: the GenAI CLIENT
+//! span and, nested directly below it, an HTTP CLIENT span for the same
+//! request. Backends then count the dependency twice in service maps.
+//! `ClientSpanDedupProcessor` keeps one of the two, chosen with
+//! [`ClientSpanWinner`]:
+//!
+//! - `GenAi` drops the nested HTTP spans and copies the `http.*`, `url.*`
+//!   and `server.*` attributes of the last one, the attempt that produced
+//!   the response when the client retried, onto the GenAI span. HTTP spans
+//!   with children of their own are kept, so those children stay attached.
+//! - `Http` keeps the HTTP span as the CLIENT span and demotes the GenAI
+//!   span to INTERNAL, leaving its attributes untouched.
+//!
+//! The OpenTelemetry span of a `tracing` span starts when it closes, so
+//! children reach the processor before their parent; HTTP spans are held
+//! until their parent ends and the decision can be made. Spans under a
+//! remote parent are never held, since that parent ends in another process.
+//! Held spans are exported unchanged on `force_flush` and shutdown, and when
+//! too many parents are pending, those pending the longest.
+//!
+//! [`crate::telemetry::TelemetryBuilder::with_client_span_dedup`] adds the
+//! processor to the export pipeline.
+
+use super::attribute;
+use opentelemetry::trace::{Span as _, SpanId, SpanKind, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Upper bound on parents with held HTTP spans, so spans whose parent
+/// never ends cannot grow memory without limit; beyond it the oldest are
+/// exported undeduplicated.
+const MAX_PENDING_PARENTS: usize = 10_000;
+
+/// Attribute prefixes copied from a dropped HTTP span onto the GenAI span.
+const MERGED_PREFIXES: &[&str] = &["http.", "url.", "server.", "network."];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClientSpanWinner {
+    /// The GenAI span stays the CLIENT span.
+    #[default]
+    GenAi,
+    /// The host's HTTP span stays the CLIENT span.
+    Http,
+}
+
+#[derive(Debug, Default)]
+struct DedupState {
+    held_http: HashMap<SpanId, Vec<SpanData>>,
+    http_parents: HashSet<SpanId>,
+    /// Spans started under a remote parent, until they end.
+    remote_children: HashSet<SpanId>,
+    /// Spans with ended children, until they end themselves.
+    with_children: HashSet<SpanId>,
+}
+
+impl DedupState {
+    /// Removes the parent whose held spans ended first, returning its spans.
+    fn evict_oldest(&mut self) -> Vec<SpanData> {
+        let oldest = self
+            .held_http
+            .iter()
+            .filter_map(|(parent, spans)| spans.first().map(|span| (span.end_time, *parent)))
+            .min_by_key(|(end, _)| *end)
+            .map(|(_, parent)| parent);
+        oldest
+            .and_then(|parent| self.held_http.remove(&parent))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientSpanDedupProcessor<P> {
+    inner: P,
+    winner: ClientSpanWinner,
+    state: Mutex<DedupState>,
+}
+
+impl<P: SpanProcessor> ClientSpanDedupProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            winner: ClientSpanWinner::default(),
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn with_winner(mut self, winner: ClientSpanWinner) -> Self {
+        self.winner = winner;
+        self
+    }
+
+    /// Exports every held HTTP span unchanged, e.g. before the exporter
+    /// flushes; their parents are then exported undeduplicated.
+    fn export_held(&self) {
+        let held = std::mem::take(
+            &mut self
+                .state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .held_http,
+        );
+        for span in held.into_values().flatten() {
+            self.inner.on_end(span);
+        }
+    }
+}
+
+fn is_genai_client(span: &SpanData) -> bool {
+    span.span_kind == SpanKind::Client && attribute(span, "gen_ai.operation.name").is_some()
+}
+
+fn is_http_client(span: &SpanData) -> bool {
+    span.span_kind == SpanKind::Client
+        && attribute(span, "gen_ai.operation.name").is_none()
+        && (attribute(span, "http.request.method").is_some()
+            || attribute(span, "http.method").is_some())
+}
+
+impl<P: SpanProcessor> SpanProcessor for ClientSpanDedupProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        if cx.span().span_context().is_remote() {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.remote_children.len() < MAX_PENDING_PARENTS {
+                state.remote_children.insert(span.span_context().span_id());
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let span_id = span.span_context.span_id();
+        let parent_id = span.parent_span_id;
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let local_parent = !state.remote_children.remove(&span_id) && parent_id != SpanId::INVALID;
+        let has_children = state.with_children.remove(&span_id);
+        if self.winner == ClientSpanWinner::GenAi
+            && local_parent
+            && state.with_children.len() < MAX_PENDING_PARENTS
+        {
+            state.with_children.insert(parent_id);
+        }
+
+        if is_http_client(&span) && local_parent {
+            let mut evicted = Vec::new();
+            match self.winner {
+                ClientSpanWinner::GenAi if !has_children => {
+                    if state.held_http.len() >= MAX_PENDING_PARENTS
+                        && !state.held_http.contains_key(&parent_id)
+                    {
+                        evicted = state.evict_oldest();
+                    }
+                    state.held_http.entry(parent_id).or_default().push(span);
+                    drop(state);
+                    for evicted in evicted {
+                        self.inner.on_end(evicted);
+                    }
+                    return;
+                }
+                ClientSpanWinner::Http if state.http_parents.len() < MAX_PENDING_PARENTS => {
+                    state.http_parents.insert(parent_id);
+                }
+                ClientSpanWinner::GenAi | ClientSpanWinner::Http => {}
+            }
+            drop(state);
+            self.inner.on_end(span);
+            return;
+        }
+
+        let held = state.held_http.remove(&span_id).unwrap_or_default();
+        let had_http_child = state.http_parents.remove(&span_id);
+        drop(state);
+
+        if is_genai_client(&span) {
+            match self.winner {
+                ClientSpanWinner::GenAi if !held.is_empty() => {
+                    let merged: Vec<KeyValue> = held
+                        .iter()
+                        .max_by_key(|http_span| http_span.end_time)
+                        .into_iter()
+                        .flat_map(|http_span| http_span.attributes.iter())
+                        .filter(|kv| {
+                            let key = kv.key.as_str();
+                            MERGED_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+                                && attribute(&span, key).is_none()
+                        })
+                        .cloned()
+                        .collect();
+                    span.attributes.extend(merged);
+                    span.attributes.push(KeyValue::new(
+                        "llm.dedup.merged_http_spans",
+                        held.len() as i64,
+                    ));
+                    self.inner.on_end(span);
+                    return;
+                }
+                ClientSpanWinner::Http if had_http_child => {
+                    span.span_kind = SpanKind::Internal;
+                    span.attributes
+                        .push(KeyValue::new("llm.dedup.demoted_from", "client"));
+                }
+                _ => {}
+            }
+        }
+
+        for http_span in held {
+            self.inner.on_end(http_span);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.export_held();
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.export_held();
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::{Tracer, TracerProvider as _};
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+
+    fn client(
+        tracer: &SdkTracer,
+        parent: &Context,
+        name: &'static str,
+        attributes: Vec<KeyValue>,
+    ) -> Context {
+        let span = tracer
+            .span_builder(name)
+            .with_kind(SpanKind::Client)
+            .with_attributes(attributes)
+            .start_with_context(tracer, parent);
+        parent.with_span(span)
+    }
+
+    fn http(status: i64) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new("http.request.method", "POST"),
+            KeyValue::new("http.response.status_code", status),
+        ]
+    }
+
+    fn export(winner: ClientSpanWinner, run: impl FnOnce(&SdkTracer, &Context)) -> Collect {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(ClientSpanDedupProcessor::new(collect.clone()).with_winner(winner))
+            .build();
+        let tracer = provider.tracer("test");
+        let request = start(&tracer, &Context::new(), "request", Vec::new());
+        run(&tracer, &request);
+        end(&request);
+        collect
+    }
+
+    fn genai() -> Vec<KeyValue> {
+        vec![KeyValue::new("gen_ai.operation.name", "chat")]
+    }
+
+    #[test]
+    fn merges_only_the_last_attempt_of_retried_requests() {
+        let collect = export(ClientSpanWinner::GenAi, |tracer, parent| {
+            let chat = client(tracer, parent, "chat", genai());
+            end(&client(tracer, &chat, "POST", http(503)));
+            end(&client(tracer, &chat, "POST", http(200)));
+            end(&chat);
+        });
+
+        let names: Vec<_> = collect.spans().into_iter().map(|span| span.name).collect();
+        assert_eq!(names, ["chat", "request"]);
+        let chat = collect.span("chat");
+        let statuses: Vec<_> = chat
+            .attributes
+            .iter()
+            .filter(|kv| kv.key.as_str() == "http.response.status_code")
+            .map(|kv| kv.value.clone())
+            .collect();
+        assert_eq!(statuses, [200_i64.into()]);
+        assert_eq!(
+            attribute(&chat, "llm.dedup.merged_http_spans"),
+            Some(&2_i64.into())
+        );
+    }
+
+    #[test]
+    fn keeps_http_spans_with_children() {
+        let collect = export(ClientSpanWinner::GenAi, |tracer, parent| {
+            let chat = client(tracer, parent, "chat", genai());
+            let post = client(tracer, &chat, "POST", http(200));
+            end(&start(tracer, &post, "connect", Vec::new()));
+            end(&post);
+            end(&chat);
+        });
+
+        let post = collect.span("POST");
+        assert_eq!(
+            collect.span("connect").parent_span_id,
+            post.span_context.span_id()
+        );
+        assert_eq!(
+            attribute(&collect.span("chat"), "llm.dedup.merged_http_spans"),
+            None
+        );
+    }
+
+    #[test]
+    fn the_http_winner_demotes_the_genai_span() {
+        let collect = export(ClientSpanWinner::Http, |tracer, parent| {
+            let chat = client(tracer, parent, "chat", genai());
+            end(&client(tracer, &chat, "POST", http(200)));
+            end(&chat);
+        });
+
+        assert_eq!(collect.span("POST").span_kind, SpanKind::Client);
+        assert_eq!(collect.span("chat").span_kind, SpanKind::Internal);
+    }
+}
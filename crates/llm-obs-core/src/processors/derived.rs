@@ -0,0 +1,191 @@
+//! Derived attributes computed at export time.
+//!
+//! `DerivedAttributesProcessor` runs user-supplied closures on every finished
+//! span and appends the attributes they return before handing the span to
+//! the inner processor. Enrichment such as cost from usage or tokens/sec from
+//! duration can then live in the pipeline instead of in application code.
+
+#[cfg(feature = "cost")]
+use super::attribute;
+use super::attribute_f64;
+#[cfg(feature = "cost")]
+use crate::cost::ModelPricing;
+#[cfg(feature = "cost")]
+use crate::tokens::TokenUsage;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::time::Duration;
+
+pub type DeriveFn = Box<dyn Fn(&SpanData) -> Vec<KeyValue> + Send + Sync>;
+
+pub struct DerivedAttributesProcessor<P> {
+    inner: P,
+    derivers: Vec<DeriveFn>,
+}
+
+impl<P: SpanProcessor> DerivedAttributesProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            derivers: Vec::new(),
+        }
+    }
+
+    pub fn with_deriver<F>(mut self, deriver: F) -> Self
+    where
+        F: Fn(&SpanData) -> Vec<KeyValue> + Send + Sync + 'static,
+    {
+        self.derivers.push(Box::new(deriver));
+        self
+    }
+}
+
+impl<P> fmt::Debug for DerivedAttributesProcessor<P>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DerivedAttributesProcessor")
+            .field("inner", &self.inner)
+            .field("derivers", &self.derivers.len())
+            .finish()
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for DerivedAttributesProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let derived: Vec<KeyValue> = self
+            .derivers
+            .iter()
+            .flat_map(|deriver| deriver(&span))
+            .collect();
+        span.attributes.extend(derived);
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// `llm.output_tokens_per_second` from output tokens and span duration.
+pub fn tokens_per_second(span: &SpanData) -> Vec<KeyValue> {
+    let Some(output_tokens) = attribute_f64(span, "gen_ai.usage.output_tokens") else {
+        return Vec::new();
+    };
+    let Ok(duration) = span.end_time.duration_since(span.start_time) else {
+        return Vec::new();
+    };
+    if duration.is_zero() {
+        return Vec::new();
+    }
+    vec![KeyValue::new(
+        "llm.output_tokens_per_second",
+        output_tokens / duration.as_secs_f64(),
+    )]
+}
+
+/// `gen_ai.provider.name` of providers whose reported output tokens
+/// include the reasoning tokens.
+#[cfg(feature = "cost")]
+const REASONING_IN_OUTPUT: &[&str] = &["openai", "azure.ai.openai"];
+
+/// Deriver adding `gen_ai.usage.cost_usd` from usage attributes, using
+/// `pricing` to look up the price of `gen_ai.request.model`. Reasoning
+/// tokens are priced once, taken out of the output tokens for providers
+/// that count them there.
+#[cfg(feature = "cost")]
+pub fn cost_from_usage<F>(pricing: F) -> impl Fn(&SpanData) -> Vec<KeyValue> + Send + Sync
+where
+    F: Fn(&str) -> Option<ModelPricing> + Send + Sync,
+{
+    move |span| {
+        if attribute(span, "gen_ai.usage.cost_usd").is_some() {
+            return Vec::new();
+        }
+        let Some(model) = attribute(span, "gen_ai.request.model") else {
+            return Vec::new();
+        };
+        let Some(pricing) = pricing(&model.as_str()) else {
+            return Vec::new();
+        };
+        let tokens = |key| attribute_f64(span, key).unwrap_or_default() as u64;
+        let reasoning_tokens = tokens("gen_ai.usage.reasoning_tokens");
+        let mut output_tokens = tokens("gen_ai.usage.output_tokens");
+        if attribute(span, "gen_ai.provider.name")
+            .is_some_and(|provider| REASONING_IN_OUTPUT.contains(&&*provider.as_str()))
+        {
+            output_tokens = output_tokens.saturating_sub(reasoning_tokens);
+        }
+        let usage = TokenUsage {
+            input_tokens: tokens("gen_ai.usage.input_tokens"),
+            output_tokens,
+            reasoning_tokens,
+        };
+        vec![KeyValue::new(
+            "gen_ai.usage.cost_usd",
+            pricing.cost(&usage).total_usd(),
+        )]
+    }
+}
+
+#[cfg(all(test, feature = "cost"))]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn priced(provider_name: &'static str) -> f64 {
+        let collect = Collect::default();
+        let pricing = ModelPricing {
+            input_per_1k: 1.0,
+            output_per_1k: 2.0,
+            reasoning_per_1k: Some(3.0),
+        };
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(
+                DerivedAttributesProcessor::new(collect.clone())
+                    .with_deriver(cost_from_usage(move |_| Some(pricing))),
+            )
+            .build();
+        let tracer = provider.tracer("test");
+        end(&start(
+            &tracer,
+            &Context::new(),
+            "chat",
+            vec![
+                KeyValue::new("gen_ai.provider.name", provider_name),
+                KeyValue::new("gen_ai.request.model", "model"),
+                KeyValue::new("gen_ai.usage.input_tokens", 1000),
+                KeyValue::new("gen_ai.usage.output_tokens", 3000),
+                KeyValue::new("gen_ai.usage.reasoning_tokens", 2000),
+            ],
+        ));
+        attribute_f64(&collect.span("chat"), "gen_ai.usage.cost_usd").expect("no cost")
+    }
+
+    #[test]
+    fn prices_reasoning_counted_in_openai_output_once() {
+        assert!((priced("openai") - (1.0 + 2.0 + 6.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn prices_reasoning_reported_apart_from_gemini_output() {
+        assert!((priced("gcp.gemini") - (1.0 + 6.0 + 6.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,205 @@
+//! Mirroring selected span events into the logs signal.
+//!
+//! Guardrail blocks, truncations and timeouts are recorded as span events,
+//! but several backends can only alert on log records. `EventLogProcessor`
+//! emits a log record for every event whose name matches one of its patterns,
+//! carrying the event's attributes, its timestamp and the span's trace and
+//! span ids, so the alert links straight back to the trace. The span itself
+//! is passed on unchanged.
+
+use super::glob_match;
+#[cfg(feature = "otlp")]
+use crate::otlp_config::{OtlpEndpoints, Signal, SignalEndpoint, log_exporter};
+use crate::scopes::Subsystem;
+#[cfg(feature = "otlp")]
+use opentelemetry::KeyValue;
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, LoggerProvider, Severity};
+use opentelemetry::{Array, Context, Value};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::logs::SdkLogger;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::time::Duration;
+
+/// Events mirrored by [`EventLogProcessor::new`], with their severity.
+pub const DEFAULT_MIRRORED_EVENTS: [(&str, Severity); 4] = [
+    ("llm.guardrail.*", Severity::Warn),
+    ("llm.budget.*", Severity::Warn),
+    ("gen_ai.response.truncated", Severity::Warn),
+    ("timeout", Severity::Error),
+];
+
+/// Logger provider exporting over OTLP to the logs endpoint from
+/// `OTEL_EXPORTER_OTLP_LOGS_ENDPOINT` / `OTEL_EXPORTER_OTLP_ENDPOINT`, over
+/// the transport from `OTEL_EXPORTER_OTLP_LOGS_PROTOCOL` /
+/// `OTEL_EXPORTER_OTLP_PROTOCOL`. Call `shutdown` on it before exit.
+#[cfg(feature = "otlp")]
+pub fn otlp_logger_provider(service_name: &str) -> anyhow::Result<SdkLoggerProvider> {
+    otlp_logger_provider_with_attributes(service_name, Vec::new())
+}
+
+/// [`otlp_logger_provider`] with extra resource attributes, matching those
+/// of the tracer provider.
+#[cfg(feature = "otlp")]
+pub(crate) fn otlp_logger_provider_with_attributes(
+    service_name: &str,
+    resource_attributes: Vec<KeyValue>,
+) -> anyhow::Result<SdkLoggerProvider> {
+    // The exporter reads the header variables itself.
+    let logs = OtlpEndpoints::signal_from_env(Signal::Logs, None);
+    let exporter = log_exporter(&SignalEndpoint {
+        headers: Vec::new(),
+        ..logs
+    })?;
+    let resource = Resource::builder()
+        .with_service_name(service_name.to_owned())
+        .with_attribute(KeyValue::new("telemetry.sdk.language", "rust"))
+        .with_attributes(resource_attributes)
+        .build();
+    Ok(SdkLoggerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(resource)
+        .build())
+}
+
+pub struct EventLogProcessor<P, L = SdkLogger> {
+    inner: P,
+    logger: L,
+    events: Vec<(String, Severity)>,
+}
+
+impl<P: SpanProcessor> EventLogProcessor<P> {
+    /// Mirrors [`DEFAULT_MIRRORED_EVENTS`] through the agent-scope logger of
+    /// `logger_provider`.
+    pub fn new(inner: P, logger_provider: &impl LoggerProvider<Logger = SdkLogger>) -> Self {
+        Self::with_logger(
+            inner,
+            logger_provider.logger_with_scope(Subsystem::Agent.scope()),
+        )
+        .with_events(DEFAULT_MIRRORED_EVENTS)
+    }
+}
+
+impl<P: SpanProcessor, L: Logger> EventLogProcessor<P, L> {
+    /// Mirrors nothing until events are added.
+    pub fn with_logger(inner: P, logger: L) -> Self {
+        Self {
+            inner,
+            logger,
+            events: Vec::new(),
+        }
+    }
+
+    /// Mirrors events whose name matches `pattern` (`*` matches any run of
+    /// characters) at `severity`. The first matching pattern wins.
+    pub fn with_event(mut self, pattern: impl Into<String>, severity: Severity) -> Self {
+        self.events.push((pattern.into(), severity));
+        self
+    }
+
+    fn with_events<'a>(mut self, events: impl IntoIterator<Item = (&'a str, Severity)>) -> Self {
+        self.events.extend(
+            events
+                .into_iter()
+                .map(|(pattern, severity)| (pattern.to_owned(), severity)),
+        );
+        self
+    }
+
+    fn severity(&self, event_name: &str) -> Option<Severity> {
+        self.events
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, event_name))
+            .map(|(_, severity)| *severity)
+    }
+
+    fn mirror(&self, span: &SpanData) {
+        for event in span.events.iter() {
+            let Some(severity) = self.severity(&event.name) else {
+                continue;
+            };
+            let mut record = self.logger.create_log_record();
+            record.set_timestamp(event.timestamp);
+            record.set_severity_number(severity);
+            record.set_severity_text(severity.name());
+            record.set_body(AnyValue::from(event.name.to_string()));
+            record.set_trace_context(
+                span.span_context.trace_id(),
+                span.span_context.span_id(),
+                Some(span.span_context.trace_flags()),
+            );
+            record.add_attribute("event.name", event.name.to_string());
+            record.add_attribute("llm.span.name", span.name.to_string());
+            record.add_attributes(
+                event
+                    .attributes
+                    .iter()
+                    .map(|attribute| (attribute.key.clone(), any_value(&attribute.value))),
+            );
+            self.logger.emit(record);
+        }
+    }
+}
+
+fn any_value(value: &Value) -> AnyValue {
+    match value {
+        Value::Bool(value) => AnyValue::Boolean(*value),
+        Value::I64(value) => AnyValue::Int(*value),
+        Value::F64(value) => AnyValue::Double(*value),
+        Value::String(value) => AnyValue::String(value.clone()),
+        Value::Array(Array::Bool(values)) => {
+            values.iter().copied().map(AnyValue::Boolean).collect()
+        }
+        Value::Array(Array::I64(values)) => values.iter().copied().map(AnyValue::Int).collect(),
+        Value::Array(Array::F64(values)) => values.iter().copied().map(AnyValue::Double).collect(),
+        Value::Array(Array::String(values)) => {
+            values.iter().cloned().map(AnyValue::String).collect()
+        }
+        other => AnyValue::String(other.to_string().into()),
+    }
+}
+
+impl<P, L> fmt::Debug for EventLogProcessor<P, L>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLogProcessor")
+            .field("inner", &self.inner)
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P, L> SpanProcessor for EventLogProcessor<P, L>
+where
+    P: SpanProcessor,
+    L: Logger + Send + Sync + 'static,
+{
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if !span.events.is_empty() {
+            self.mirror(&span);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
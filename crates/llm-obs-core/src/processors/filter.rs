@@ -0,0 +1,182 @@
+//! Noise reduction for dense agent traces.
+//!
+//! `NoiseFilterProcessor` drops spans that are shorter than a threshold or
+//! whose name matches a glob (e.g. hyper's per-connection spans), without
+//! losing what happened inside them: events of a dropped span are re-attached
+//! to its nearest exported ancestor with a `dropped_span.name` attribute.
+//! Only leaf spans are dropped: a span with children is kept, or the
+//! children would point at a parent the backend never receives. Root spans
+//! and spans with an error status are always kept too.
+
+use super::glob_match;
+use opentelemetry::trace::{Event, SpanId, Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Upper bound on parents waiting for re-attached events, and on open spans
+/// known to have children, so spans whose parent never ends cannot grow
+/// memory without limit.
+const MAX_PENDING_PARENTS: usize = 10_000;
+
+#[derive(Debug)]
+pub struct NoiseFilterProcessor<P> {
+    inner: P,
+    min_duration: Option<Duration>,
+    drop_names: Vec<String>,
+    pending_events: Mutex<HashMap<SpanId, Vec<Event>>>,
+    /// Local spans a child started under, until they end.
+    parents: Mutex<HashSet<SpanId>>,
+}
+
+impl<P: SpanProcessor> NoiseFilterProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            min_duration: None,
+            drop_names: Vec::new(),
+            pending_events: Mutex::default(),
+            parents: Mutex::default(),
+        }
+    }
+
+    /// Drops spans that finished faster than `min_duration`.
+    pub fn with_min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = Some(min_duration);
+        self
+    }
+
+    /// Drops spans whose name matches `pattern` (`*` matches any run of
+    /// characters), e.g. `"hyper::*"` or `"*.connect"`.
+    pub fn with_dropped_name(mut self, pattern: impl Into<String>) -> Self {
+        self.drop_names.push(pattern.into());
+        self
+    }
+
+    fn is_noise(&self, span: &SpanData) -> bool {
+        if span.parent_span_id == SpanId::INVALID || matches!(span.status, Status::Error { .. }) {
+            return false;
+        }
+        if self
+            .drop_names
+            .iter()
+            .any(|pattern| glob_match(pattern, &span.name))
+        {
+            return true;
+        }
+        self.min_duration.is_some_and(|min_duration| {
+            span.end_time
+                .duration_since(span.start_time)
+                .is_ok_and(|duration| duration < min_duration)
+        })
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for NoiseFilterProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let parent = cx.span().span_context().clone();
+        if parent.is_valid() && !parent.is_remote() {
+            let mut parents = self.parents.lock().unwrap_or_else(PoisonError::into_inner);
+            if parents.len() < MAX_PENDING_PARENTS {
+                parents.insert(parent.span_id());
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let span_id = span.span_context.span_id();
+        let has_children = self
+            .parents
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&span_id);
+        let mut pending = self
+            .pending_events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(inherited) = pending.remove(&span_id) {
+            span.events.events.extend(inherited);
+        }
+
+        if has_children || !self.is_noise(&span) {
+            drop(pending);
+            self.inner.on_end(span);
+            return;
+        }
+
+        if span.events.is_empty() || pending.len() >= MAX_PENDING_PARENTS {
+            return;
+        }
+        let dropped_name = span.name.to_string();
+        let events = span.events.events.into_iter().map(|mut event| {
+            event
+                .attributes
+                .push(KeyValue::new("dropped_span.name", dropped_name.clone()));
+            event
+        });
+        pending
+            .entry(span.parent_span_id)
+            .or_default()
+            .extend(events);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn names(collect: &Collect) -> Vec<String> {
+        collect
+            .spans()
+            .into_iter()
+            .map(|span| span.name.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_noisy_spans_with_children_and_drops_noisy_leaves() {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(
+                NoiseFilterProcessor::new(collect.clone()).with_dropped_name("hyper::*"),
+            )
+            .build();
+        let tracer = provider.tracer("test");
+        let root = start(&tracer, &Context::new(), "request", Vec::new());
+        let connection = start(&tracer, &root, "hyper::connect", Vec::new());
+        end(&start(&tracer, &connection, "tls", Vec::new()));
+        end(&connection);
+        let pool = start(&tracer, &root, "hyper::pool", Vec::new());
+        pool.span().add_event("checkout", Vec::new());
+        end(&pool);
+        end(&root);
+
+        assert_eq!(names(&collect), ["tls", "hyper::connect", "request"]);
+        let request = collect.span("request");
+        let event = request.events.iter().next().expect("no re-attached event");
+        assert_eq!(event.name, "checkout");
+        assert_eq!(
+            event.attributes,
+            [KeyValue::new("dropped_span.name", "hyper::pool")]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Span processors that wrap an inner processor (usually the batch
+//! processor) and adjust finished spans before they are exported.
+
+pub mod baggage;
+pub(crate) mod calls;
+pub mod capture;
+pub mod clock;
+pub mod compat;
+pub mod dedup;
+pub mod derived;
+#[cfg(feature = "logs")]
+pub mod event_logs;
+pub mod filter;
+#[cfg(feature = "redaction")]
+pub mod redact;
+pub mod rollup;
+pub mod scope;
+#[cfg(feature = "redaction")]
+pub mod scrub;
+pub mod tail;
+#[cfg(test)]
+pub(crate) mod testing;
+
+use opentelemetry::Value;
+use opentelemetry_sdk::trace::SpanData;
+
+pub(crate) fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| &kv.value)
+}
+
+pub(crate) fn attribute_f64(span: &SpanData, key: &str) -> Option<f64> {
+    match attribute(span, key)? {
+        Value::I64(value) => Some(*value as f64),
+        Value::F64(value) => Some(*value),
+        Value::String(value) => value.as_str().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` matches any run of characters.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return text.is_empty();
+    };
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
@@ -0,0 +1,255 @@
+//! Per-exporter redaction for lower-trust backends.
+//!
+//! With several span processors on one provider, each one receives its own
+//! copy of every finished span. Wrapping only the SaaS exporter's processor
+//! in `RedactionProcessor` lets the self-hosted collector keep full content
+//! while the SaaS backend gets the same spans with matching attributes
+//! dropped, hashed or masked. Rules apply to span and event attributes;
+//! arbitrary transforms can be chained for anything else.
+
+use super::glob_match;
+use crate::fingerprint::fingerprint;
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::time::Duration;
+
+/// Replacement written by [`RedactionAction::Mask`].
+pub const MASK: &str = "[REDACTED]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionAction {
+    /// Removes the attribute.
+    Drop,
+    /// Replaces the value with its fingerprint, so equal values still
+    /// correlate across spans.
+    Hash,
+    /// Replaces the value with [`MASK`].
+    Mask,
+}
+
+pub type TransformFn = Box<dyn Fn(&mut SpanData) + Send + Sync>;
+
+pub struct RedactionProcessor<P> {
+    inner: P,
+    rules: Vec<(String, RedactionAction)>,
+    transforms: Vec<TransformFn>,
+}
+
+impl<P: SpanProcessor> RedactionProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            rules: Vec::new(),
+            transforms: Vec::new(),
+        }
+    }
+
+    /// Applies `action` to attributes whose key matches `pattern` (`*`
+    /// matches any run of characters), e.g. `"gen_ai.input.*"`. The first
+    /// matching rule wins.
+    pub fn with_rule(mut self, pattern: impl Into<String>, action: RedactionAction) -> Self {
+        self.rules.push((pattern.into(), action));
+        self
+    }
+
+    /// Runs `transform` on every span after the rules.
+    pub fn with_transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(&mut SpanData) + Send + Sync + 'static,
+    {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    fn redact(&self, attributes: &mut Vec<KeyValue>) {
+        attributes.retain_mut(|attribute| {
+            let action = self
+                .rules
+                .iter()
+                .find(|(pattern, _)| glob_match(pattern, attribute.key.as_str()))
+                .map(|(_, action)| *action);
+            match action {
+                None => true,
+                Some(RedactionAction::Drop) => false,
+                Some(RedactionAction::Hash) => {
+                    attribute.value = Value::from(fingerprint(attribute.value.as_str().as_bytes()));
+                    true
+                }
+                Some(RedactionAction::Mask) => {
+                    attribute.value = Value::from(MASK);
+                    true
+                }
+            }
+        });
+    }
+}
+
+impl<P> fmt::Debug for RedactionProcessor<P>
+where
+    P: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedactionProcessor")
+            .field("inner", &self.inner)
+            .field("rules", &self.rules)
+            .field("transforms", &self.transforms.len())
+            .finish()
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for RedactionProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        if !self.rules.is_empty() {
+            self.redact(&mut span.attributes);
+            for event in span.events.events.iter_mut() {
+                self.redact(&mut event.attributes);
+            }
+        }
+        for transform in &self.transforms {
+            transform(&mut span);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::attribute;
+    use crate::processors::testing::{Collect, start};
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    /// Emits one span with a prompt, a completion, a model, a session id and
+    /// an event carrying the prompt, and returns the full and the redacted
+    /// copies.
+    fn emit(
+        redaction: impl FnOnce(Collect) -> RedactionProcessor<Collect>,
+    ) -> (SpanData, SpanData) {
+        let (full, redacted) = (Collect::default(), Collect::default());
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(full.clone())
+            .with_span_processor(redaction(redacted.clone()))
+            .build();
+        let tracer = provider.tracer("test");
+        let cx = start(
+            &tracer,
+            &Context::new(),
+            "chat",
+            vec![
+                KeyValue::new("gen_ai.input.messages", "my card is 4111"),
+                KeyValue::new("gen_ai.output.messages", "noted"),
+                KeyValue::new("gen_ai.request.model", "gemini-2.5-flash"),
+                KeyValue::new("session.id", "session-1"),
+            ],
+        );
+        cx.span().add_event(
+            "gen_ai.user.message",
+            vec![KeyValue::new("gen_ai.input.messages", "my card is 4111")],
+        );
+        cx.span().end();
+        (full.span("chat"), redacted.span("chat"))
+    }
+
+    #[test]
+    fn drops_hashes_and_masks_matching_attributes() {
+        let (full, redacted) = emit(|inner| {
+            RedactionProcessor::new(inner)
+                .with_rule("gen_ai.input.*", RedactionAction::Drop)
+                .with_rule("gen_ai.*.messages", RedactionAction::Mask)
+                .with_rule("session.id", RedactionAction::Hash)
+        });
+
+        assert_eq!(attribute(&redacted, "gen_ai.input.messages"), None);
+        assert_eq!(
+            attribute(&redacted, "gen_ai.output.messages"),
+            Some(&Value::from(MASK))
+        );
+        assert_eq!(
+            attribute(&redacted, "session.id"),
+            Some(&Value::from(fingerprint("session-1")))
+        );
+        assert_eq!(
+            attribute(&redacted, "gen_ai.request.model"),
+            Some(&Value::from("gemini-2.5-flash"))
+        );
+        assert!(redacted.events.events[0].attributes.is_empty());
+
+        assert_eq!(
+            attribute(&full, "gen_ai.input.messages"),
+            Some(&Value::from("my card is 4111"))
+        );
+        assert_eq!(full.events.events[0].attributes.len(), 1);
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let (_, redacted) = emit(|inner| {
+            RedactionProcessor::new(inner)
+                .with_rule("gen_ai.output.messages", RedactionAction::Hash)
+                .with_rule("gen_ai.*", RedactionAction::Drop)
+        });
+
+        assert_eq!(
+            attribute(&redacted, "gen_ai.output.messages"),
+            Some(&Value::from(fingerprint("noted")))
+        );
+        assert_eq!(attribute(&redacted, "gen_ai.input.messages"), None);
+        assert_eq!(attribute(&redacted, "gen_ai.request.model"), None);
+        assert!(attribute(&redacted, "session.id").is_some());
+    }
+
+    #[test]
+    fn patterns_match_whole_keys() {
+        let (_, redacted) = emit(|inner| {
+            RedactionProcessor::new(inner)
+                .with_rule("session", RedactionAction::Drop)
+                .with_rule("*.model.*", RedactionAction::Drop)
+                .with_rule("*messages", RedactionAction::Mask)
+        });
+
+        assert!(attribute(&redacted, "session.id").is_some());
+        assert!(attribute(&redacted, "gen_ai.request.model").is_some());
+        assert_eq!(
+            attribute(&redacted, "gen_ai.input.messages"),
+            Some(&Value::from(MASK))
+        );
+    }
+
+    #[test]
+    fn transforms_run_after_the_rules() {
+        let (_, redacted) = emit(|inner| {
+            RedactionProcessor::new(inner)
+                .with_rule("gen_ai.input.messages", RedactionAction::Mask)
+                .with_transform(|span| {
+                    let masked = span
+                        .attributes
+                        .iter()
+                        .filter(|attribute| attribute.value.as_str() == MASK)
+                        .count();
+                    span.attributes
+                        .push(KeyValue::new("redacted.count", masked as i64));
+                })
+        });
+
+        assert_eq!(attribute(&redacted, "redacted.count"), Some(&Value::I64(1)));
+    }
+}
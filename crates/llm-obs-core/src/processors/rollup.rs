@@ -0,0 +1,209 @@
+//! Trace-level rollup attributes on the local root span.
+//!
+//! Questions like "how many LLM calls did this request make" or "what did it
+//! cost" otherwise need an aggregation over child spans in the backend.
+//! `RollupProcessor` accumulates those numbers while children finish and
+//! attaches them as `llm.rollup.*` attributes when the root span ends.
+//! Calls, tokens and cost are counted once per model call, as described in
+//! `processors::calls`: wrapper spans repeating a call's usage do not add
+//! to them, and tool spans count as tool calls only.
+
+use super::calls::CallCounter;
+use opentelemetry::trace::{Span as _, SpanId, Status, TraceContextExt, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Traces whose root never ends are discarded beyond this many.
+const MAX_OPEN_TRACES: usize = 10_000;
+
+#[derive(Debug, Default)]
+struct TraceRollup {
+    parents: HashMap<SpanId, SpanId>,
+    calls: CallCounter,
+    span_count: u64,
+    error_count: u64,
+    llm_calls: u64,
+    tool_calls: u64,
+    total_tokens: u64,
+    cost_usd: f64,
+}
+
+impl TraceRollup {
+    fn add(&mut self, span: &SpanData) {
+        self.parents
+            .insert(span.span_context.span_id(), span.parent_span_id);
+        self.span_count += 1;
+        if matches!(span.status, Status::Error { .. }) {
+            self.error_count += 1;
+        }
+        let counted = self.calls.on_end(span);
+        self.llm_calls += u64::from(counted.llm_call);
+        self.tool_calls += u64::from(counted.tool_call);
+        self.total_tokens += counted.input_tokens + counted.output_tokens;
+        self.cost_usd += counted.cost_usd;
+    }
+
+    /// Depth of the deepest span below `root` (the root itself is depth 0).
+    fn max_depth(&self, root: SpanId) -> u64 {
+        self.parents
+            .keys()
+            .map(|span_id| {
+                let mut depth = 0;
+                let mut current = *span_id;
+                while current != root {
+                    match self.parents.get(&current) {
+                        Some(parent) => {
+                            current = *parent;
+                            depth += 1;
+                        }
+                        None => break,
+                    }
+                }
+                depth
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    fn attributes(&self, root: SpanId) -> Vec<KeyValue> {
+        vec![
+            KeyValue::new("llm.rollup.span_count", self.span_count as i64),
+            KeyValue::new("llm.rollup.error_count", self.error_count as i64),
+            KeyValue::new("llm.rollup.llm_calls", self.llm_calls as i64),
+            KeyValue::new("llm.rollup.tool_calls", self.tool_calls as i64),
+            KeyValue::new("llm.rollup.total_tokens", self.total_tokens as i64),
+            KeyValue::new("llm.rollup.cost_usd", self.cost_usd),
+            KeyValue::new("llm.rollup.max_depth", self.max_depth(root) as i64),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+struct RollupState {
+    local_roots: HashSet<SpanId>,
+    traces: HashMap<TraceId, TraceRollup>,
+}
+
+#[derive(Debug)]
+pub struct RollupProcessor<P> {
+    inner: P,
+    state: Mutex<RollupState>,
+}
+
+impl<P: SpanProcessor> RollupProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            state: Mutex::default(),
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for RollupProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let parent = cx.span().span_context().clone();
+        {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let span_context = span.span_context();
+            if (!parent.is_valid() || parent.is_remote())
+                && state.local_roots.len() < MAX_OPEN_TRACES
+            {
+                state.local_roots.insert(span_context.span_id());
+            }
+            let trace_id = span_context.trace_id();
+            if state.traces.len() < MAX_OPEN_TRACES || state.traces.contains_key(&trace_id) {
+                state
+                    .traces
+                    .entry(trace_id)
+                    .or_default()
+                    .calls
+                    .on_start(span_context);
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let span_id = span.span_context.span_id();
+        let trace_id = span.span_context.trace_id();
+        {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.local_roots.remove(&span_id) {
+                let mut rollup = state.traces.remove(&trace_id).unwrap_or_default();
+                rollup.add(&span);
+                span.attributes.extend(rollup.attributes(span_id));
+            } else if state.traces.len() < MAX_OPEN_TRACES || state.traces.contains_key(&trace_id) {
+                state.traces.entry(trace_id).or_default().add(&span);
+            }
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, agent_prompt, end, model_call, start};
+    use opentelemetry::Value;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+
+    fn rollup(span: &SpanData, key: &str) -> Value {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+            .unwrap_or_else(|| panic!("no {key}"))
+    }
+
+    fn collect_request(run: impl FnOnce(&SdkTracer, &Context)) -> SpanData {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(RollupProcessor::new(collect.clone()))
+            .build();
+        let tracer = provider.tracer("test");
+        let request = start(&tracer, &Context::new(), "request", Vec::new());
+        run(&tracer, &request);
+        end(&request);
+        collect.span("request")
+    }
+
+    #[test]
+    fn counts_each_model_call_once_below_wrapper_spans() {
+        let root = collect_request(agent_prompt);
+
+        assert_eq!(rollup(&root, "llm.rollup.span_count"), Value::I64(7));
+        assert_eq!(rollup(&root, "llm.rollup.llm_calls"), Value::I64(2));
+        assert_eq!(rollup(&root, "llm.rollup.tool_calls"), Value::I64(1));
+        assert_eq!(rollup(&root, "llm.rollup.total_tokens"), Value::I64(42));
+        assert_eq!(rollup(&root, "llm.rollup.max_depth"), Value::I64(3));
+        let Value::F64(cost_usd) = rollup(&root, "llm.rollup.cost_usd") else {
+            panic!("cost is not a float");
+        };
+        assert!((cost_usd - 0.003).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_model_span_without_model_spans_below_is_the_call() {
+        let root = collect_request(model_call);
+
+        assert_eq!(rollup(&root, "llm.rollup.llm_calls"), Value::I64(1));
+        assert_eq!(rollup(&root, "llm.rollup.total_tokens"), Value::I64(15));
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-subsystem instrumentation scopes for `tracing` spans.
+//!
+//! The `tracing` bridge exports every span under the scope of the one tracer
+//! it was built with, `llm_obs::agent`. `ScopeProcessor` moves spans of that
+//! scope to the subsystem they belong to: spans recorded with
+//! `otel.scope = "llm_obs::rag"` (or another [`Subsystem::scope_name`]) and
+//! `execute_tool` spans, which go to `llm_obs::tool`. The `otel.scope`
+//! attribute itself is not exported. Spans from other tracers keep their
+//! scope.
+
+use super::attribute;
+use crate::scopes::{SCOPE_FIELD, Subsystem};
+use opentelemetry::Context;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub struct ScopeProcessor<P> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> ScopeProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+/// The subsystem `span` was recorded for, if it names or implies one.
+fn subsystem(span: &SpanData) -> Option<Subsystem> {
+    if let Some(scope) = attribute(span, SCOPE_FIELD) {
+        return Subsystem::from_scope_name(&scope.as_str());
+    }
+    match attribute(span, "gen_ai.operation.name")?.as_str().as_ref() {
+        "execute_tool" => Some(Subsystem::Tool),
+        _ => None,
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ScopeProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        if span.instrumentation_scope.name() == Subsystem::Agent.scope_name() {
+            if let Some(subsystem) = subsystem(&span) {
+                span.instrumentation_scope = subsystem.scope();
+            }
+        }
+        span.attributes
+            .retain(|attribute| attribute.key.as_str() != SCOPE_FIELD);
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::KeyValue;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    #[test]
+    fn moves_tool_and_rag_spans_to_their_subsystem_scope() {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(ScopeProcessor::new(collect.clone()))
+            .build();
+        let tracer = provider.tracer_with_scope(Subsystem::Agent.scope());
+        let root = start(
+            &tracer,
+            &Context::new(),
+            "invoke_agent",
+            vec![KeyValue::new("gen_ai.operation.name", "invoke_agent")],
+        );
+        end(&start(
+            &tracer,
+            &root,
+            "execute_tool add",
+            vec![KeyValue::new("gen_ai.operation.name", "execute_tool")],
+        ));
+        end(&start(
+            &tracer,
+            &root,
+            "rewrite_query",
+            vec![KeyValue::new(SCOPE_FIELD, Subsystem::Rag.scope_name())],
+        ));
+        end(&root);
+        let other = provider.tracer("app");
+        end(&start(
+            &other,
+            &Context::new(),
+            "execute_tool lookup",
+            vec![KeyValue::new("gen_ai.operation.name", "execute_tool")],
+        ));
+
+        let scope = |name: &str| collect.span(name).instrumentation_scope.name().to_owned();
+        assert_eq!(scope("invoke_agent"), "llm_obs::agent");
+        assert_eq!(scope("execute_tool add"), "llm_obs::tool");
+        assert_eq!(scope("rewrite_query"), "llm_obs::rag");
+        assert_eq!(scope("execute_tool lookup"), "app");
+        assert!(attribute(&collect.span("rewrite_query"), SCOPE_FIELD).is_none());
+    }
+}
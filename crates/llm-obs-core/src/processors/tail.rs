@@ -0,0 +1,618 @@
+//! Tail-based sampling of complete traces.
+//!
+//! A head sampler decides before anything interesting has happened, so a
+//! low ratio drops exactly the failing and expensive traces worth reading,
+//! while LLM traces are too large to export in full. `TailSamplingProcessor`
+//! holds the spans of each trace until its local root ends, then passes the
+//! whole trace on if any span failed, the slowest span exceeded the latency
+//! threshold, or the trace's tokens or cost exceeded theirs; other traces
+//! are kept at `baseline_ratio`. The baseline decision is taken from the
+//! trace id, so every service running the processor keeps the same traces.
+//! Decisions are remembered, so spans ending after their trace was decided
+//! (a detached task, or a root that outlived `max_wait`) follow it instead
+//! of arriving as fragments.
+//!
+//! Kept spans carry `llm.tail_sampling.reason`, and decisions are counted in
+//! `llm.tail_sampling.traces`. The processor only sees spans the head
+//! sampler recorded and sampled, so use it with the default sampler (or
+//! another that keeps everything) and let it do the reduction.
+//! `TelemetryBuilder::with_tail_sampling` installs it around the exporter.
+
+use super::attribute_f64;
+use crate::scopes::Subsystem;
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::{Span as _, SpanId, Status, TraceContextExt, TraceId};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Expired traces are looked for at most this often.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Decided traces remembered for their late spans; the oldest are forgotten
+/// first.
+const MAX_DECIDED_TRACES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TailSamplingConfig {
+    /// Traces with a span at least this long are kept.
+    pub latency_threshold: Duration,
+    /// Traces using at least this many tokens in total are kept.
+    pub token_threshold: u64,
+    /// Traces costing at least this much (`gen_ai.usage.cost_usd`) are kept.
+    pub cost_threshold_usd: f64,
+    /// Share of the remaining traces that is kept.
+    pub baseline_ratio: f64,
+    /// Traces whose root has not ended after this long are decided on the
+    /// spans that arrived.
+    pub max_wait: Duration,
+    /// Upper bound on buffered spans; a trace arriving beyond it is decided
+    /// immediately.
+    pub max_buffered_spans: usize,
+}
+
+impl Default for TailSamplingConfig {
+    fn default() -> Self {
+        Self {
+            latency_threshold: Duration::from_secs(10),
+            token_threshold: 20_000,
+            cost_threshold_usd: 0.10,
+            baseline_ratio: 0.05,
+            max_wait: Duration::from_secs(60),
+            max_buffered_spans: 100_000,
+        }
+    }
+}
+
+/// Why a trace was kept; recorded as `llm.tail_sampling.reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeepReason {
+    Error,
+    Latency,
+    Tokens,
+    Cost,
+    Baseline,
+}
+
+impl KeepReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeepReason::Error => "error",
+            KeepReason::Latency => "latency",
+            KeepReason::Tokens => "tokens",
+            KeepReason::Cost => "cost",
+            KeepReason::Baseline => "baseline",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BufferedTrace {
+    trace_id: TraceId,
+    spans: Vec<SpanData>,
+    first_seen: Instant,
+    has_error: bool,
+    max_duration: Duration,
+    tokens: u64,
+    cost_usd: f64,
+}
+
+impl BufferedTrace {
+    fn new(trace_id: TraceId) -> Self {
+        Self {
+            trace_id,
+            spans: Vec::new(),
+            first_seen: Instant::now(),
+            has_error: false,
+            max_duration: Duration::ZERO,
+            tokens: 0,
+            cost_usd: 0.0,
+        }
+    }
+
+    fn add(&mut self, span: SpanData) {
+        self.has_error |= matches!(span.status, Status::Error { .. });
+        let duration = span
+            .end_time
+            .duration_since(span.start_time)
+            .unwrap_or_default();
+        self.max_duration = self.max_duration.max(duration);
+        self.tokens += [
+            "gen_ai.usage.input_tokens",
+            "gen_ai.usage.output_tokens",
+            "gen_ai.usage.reasoning_tokens",
+        ]
+        .into_iter()
+        .filter_map(|key| attribute_f64(&span, key))
+        .sum::<f64>() as u64;
+        self.cost_usd += attribute_f64(&span, "gen_ai.usage.cost_usd").unwrap_or_default();
+        self.spans.push(span);
+    }
+}
+
+#[derive(Debug)]
+struct TailState {
+    local_roots: HashSet<SpanId>,
+    traces: HashMap<TraceId, BufferedTrace>,
+    buffered_spans: usize,
+    last_sweep: Instant,
+    /// Decision per trace, `None` when dropped.
+    decided: HashMap<TraceId, Option<KeepReason>>,
+    decided_order: VecDeque<TraceId>,
+}
+
+impl TailState {
+    fn remember(&mut self, trace_id: TraceId, reason: Option<KeepReason>) {
+        if self.decided.insert(trace_id, reason).is_some() {
+            return;
+        }
+        self.decided_order.push_back(trace_id);
+        if self.decided_order.len() > MAX_DECIDED_TRACES {
+            if let Some(oldest) = self.decided_order.pop_front() {
+                self.decided.remove(&oldest);
+            }
+        }
+    }
+
+    fn take(&mut self, trace_id: TraceId) -> Option<BufferedTrace> {
+        let trace = self.traces.remove(&trace_id)?;
+        self.buffered_spans -= trace.spans.len();
+        Some(trace)
+    }
+
+    fn take_expired(&mut self, max_wait: Duration) -> Vec<BufferedTrace> {
+        let now = Instant::now();
+        if now.duration_since(self.last_sweep) < SWEEP_INTERVAL {
+            return Vec::new();
+        }
+        self.last_sweep = now;
+        let expired: Vec<TraceId> = self
+            .traces
+            .iter()
+            .filter(|(_, trace)| now.duration_since(trace.first_seen) >= max_wait)
+            .map(|(trace_id, _)| *trace_id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|trace_id| self.take(trace_id))
+            .collect()
+    }
+
+    fn take_all(&mut self) -> Vec<BufferedTrace> {
+        self.local_roots.clear();
+        self.buffered_spans = 0;
+        self.traces.drain().map(|(_, trace)| trace).collect()
+    }
+}
+
+fn decisions_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.tail_sampling.traces")
+            .with_description("Traces decided by the tail sampler, by decision and reason")
+            .build()
+    })
+}
+
+#[derive(Debug)]
+pub struct TailSamplingProcessor<P> {
+    inner: P,
+    config: TailSamplingConfig,
+    state: Mutex<TailState>,
+}
+
+impl<P: SpanProcessor> TailSamplingProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            config: TailSamplingConfig::default(),
+            state: Mutex::new(TailState {
+                local_roots: HashSet::new(),
+                traces: HashMap::new(),
+                buffered_spans: 0,
+                last_sweep: Instant::now(),
+                decided: HashMap::new(),
+                decided_order: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn with_config(mut self, config: TailSamplingConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn keep_reason(&self, trace: &BufferedTrace) -> Option<KeepReason> {
+        if trace.has_error {
+            return Some(KeepReason::Error);
+        }
+        if trace.max_duration >= self.config.latency_threshold {
+            return Some(KeepReason::Latency);
+        }
+        if trace.tokens >= self.config.token_threshold {
+            return Some(KeepReason::Tokens);
+        }
+        if trace.cost_usd >= self.config.cost_threshold_usd {
+            return Some(KeepReason::Cost);
+        }
+        baseline_keep(trace.trace_id, self.config.baseline_ratio).then_some(KeepReason::Baseline)
+    }
+
+    /// Decides `traces` and remembers the decisions for their late spans.
+    fn decide_all(
+        &self,
+        state: &mut TailState,
+        traces: Vec<BufferedTrace>,
+    ) -> Vec<(BufferedTrace, Option<KeepReason>)> {
+        traces
+            .into_iter()
+            .map(|trace| {
+                let reason = self.keep_reason(&trace);
+                state.remember(trace.trace_id, reason);
+                (trace, reason)
+            })
+            .collect()
+    }
+
+    fn export(&self, trace: BufferedTrace, reason: Option<KeepReason>) {
+        let decision = if reason.is_some() { "kept" } else { "dropped" };
+        decisions_counter().add(
+            1,
+            &[
+                KeyValue::new("llm.tail_sampling.decision", decision),
+                KeyValue::new(
+                    "llm.tail_sampling.reason",
+                    reason.map_or("none", |reason| reason.as_str()),
+                ),
+            ],
+        );
+        let Some(reason) = reason else {
+            return;
+        };
+        for mut span in trace.spans {
+            span.attributes
+                .push(KeyValue::new("llm.tail_sampling.reason", reason.as_str()));
+            self.inner.on_end(span);
+        }
+    }
+}
+
+/// Same split as the SDK's `TraceIdRatioBased`, on the low 64 bits.
+fn baseline_keep(trace_id: TraceId, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    let bytes = trace_id.to_bytes();
+    let low = u64::from_be_bytes(bytes[8..].try_into().unwrap_or_default()) >> 1;
+    low < (ratio.max(0.0) * (1u64 << 63) as f64) as u64
+}
+
+impl<P: SpanProcessor> SpanProcessor for TailSamplingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let parent = cx.span().span_context().clone();
+        if !parent.is_valid() || parent.is_remote() {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.local_roots.len() < self.config.max_buffered_spans {
+                state.local_roots.insert(span.span_context().span_id());
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let span_id = span.span_context.span_id();
+        let trace_id = span.span_context.trace_id();
+        let mut late = None;
+        let ready = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let is_root = state.local_roots.remove(&span_id);
+            let expired = state.take_expired(self.config.max_wait);
+            let mut ready = self.decide_all(&mut state, expired);
+            if let Some(reason) = state.decided.get(&trace_id) {
+                late = Some((span, *reason));
+            } else if is_root {
+                let mut trace = state
+                    .take(trace_id)
+                    .unwrap_or_else(|| BufferedTrace::new(trace_id));
+                trace.add(span);
+                ready.extend(self.decide_all(&mut state, vec![trace]));
+            } else if state.buffered_spans >= self.config.max_buffered_spans
+                && !state.traces.contains_key(&trace_id)
+            {
+                // Decided alone and not remembered: the rest of the trace
+                // may still arrive with a reason to keep it.
+                let mut trace = BufferedTrace::new(trace_id);
+                trace.add(span);
+                let reason = self.keep_reason(&trace);
+                ready.push((trace, reason));
+            } else {
+                state.buffered_spans += 1;
+                state
+                    .traces
+                    .entry(trace_id)
+                    .or_insert_with(|| BufferedTrace::new(trace_id))
+                    .add(span);
+            }
+            ready
+        };
+        for (trace, reason) in ready {
+            self.export(trace, reason);
+        }
+        if let Some((mut span, Some(reason))) = late {
+            span.attributes
+                .push(KeyValue::new("llm.tail_sampling.reason", reason.as_str()));
+            self.inner.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        let expired = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let expired = state.take_expired(self.config.max_wait);
+            self.decide_all(&mut state, expired)
+        };
+        for (trace, reason) in expired {
+            self.export(trace, reason);
+        }
+        self.inner.force_flush()
+    }
+
+    /// Decides every buffered trace on the spans that arrived, so nothing
+    /// is lost at exit.
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        let buffered = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            let buffered = state.take_all();
+            self.decide_all(&mut state, buffered)
+        };
+        for (trace, reason) in buffered {
+            self.export(trace, reason);
+        }
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &opentelemetry_sdk::Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+
+    /// The spans `build` ends, in the order they end. A span still open
+    /// when `build` returns ends then.
+    fn spans(build: impl FnOnce(&SdkTracer, &Context)) -> Vec<SpanData> {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(collect.clone())
+            .build();
+        build(&provider.tracer("test"), &Context::new());
+        collect.spans()
+    }
+
+    fn sampler(baseline_ratio: f64) -> (TailSamplingProcessor<Collect>, Collect) {
+        let collect = Collect::default();
+        let processor =
+            TailSamplingProcessor::new(collect.clone()).with_config(TailSamplingConfig {
+                baseline_ratio,
+                ..TailSamplingConfig::default()
+            });
+        (processor, collect)
+    }
+
+    /// Ends `spans` in order, with the spans without a parent as local roots.
+    fn end_all(processor: &TailSamplingProcessor<Collect>, spans: Vec<SpanData>) {
+        for span in spans {
+            if span.parent_span_id == SpanId::INVALID {
+                processor
+                    .state
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .local_roots
+                    .insert(span.span_context.span_id());
+            }
+            processor.on_end(span);
+        }
+    }
+
+    fn reasons(collect: &Collect) -> Vec<(String, String)> {
+        collect
+            .spans()
+            .into_iter()
+            .map(|span| {
+                let reason = span
+                    .attributes
+                    .iter()
+                    .find(|kv| kv.key.as_str() == "llm.tail_sampling.reason")
+                    .map(|kv| kv.value.to_string())
+                    .unwrap_or_default();
+                (span.name.into_owned(), reason)
+            })
+            .collect()
+    }
+
+    fn failing_child(tracer: &SdkTracer, root: &Context) {
+        let child = start(tracer, root, "child", Vec::new());
+        child.span().set_status(Status::error("boom"));
+        end(&child);
+    }
+
+    #[test]
+    fn keeps_failing_traces_whole() {
+        let (processor, collect) = sampler(0.0);
+        end_all(
+            &processor,
+            spans(|tracer, cx| {
+                let root = start(tracer, cx, "root", Vec::new());
+                failing_child(tracer, &root);
+                end(&root);
+            }),
+        );
+
+        assert_eq!(
+            reasons(&collect),
+            [
+                ("child".to_owned(), "error".to_owned()),
+                ("root".to_owned(), "error".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_traces_over_the_token_threshold() {
+        let (processor, collect) = sampler(0.0);
+        end_all(
+            &processor,
+            spans(|tracer, cx| {
+                let root = start(tracer, cx, "root", Vec::new());
+                end(&start(
+                    tracer,
+                    &root,
+                    "chat",
+                    vec![
+                        KeyValue::new("gen_ai.usage.input_tokens", 15_000),
+                        KeyValue::new("gen_ai.usage.output_tokens", 5_000),
+                    ],
+                ));
+                end(&root);
+            }),
+        );
+
+        assert_eq!(reasons(&collect)[0].1, "tokens");
+    }
+
+    #[test]
+    fn drops_ordinary_traces_outside_the_baseline() {
+        let (processor, collect) = sampler(0.0);
+        end_all(
+            &processor,
+            spans(|tracer, cx| {
+                let root = start(tracer, cx, "root", Vec::new());
+                end(&start(tracer, &root, "child", Vec::new()));
+                end(&root);
+            }),
+        );
+
+        assert!(collect.spans().is_empty());
+        let state = processor
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(state.buffered_spans, 0);
+        assert_eq!(state.decided.values().collect::<Vec<_>>(), [&None]);
+    }
+
+    #[test]
+    fn spans_ending_after_the_decision_follow_it() {
+        let (processor, collect) = sampler(0.0);
+        let mut kept = spans(|tracer, cx| {
+            let root = start(tracer, cx, "root", Vec::new());
+            failing_child(tracer, &root);
+            end(&start(tracer, &root, "late", Vec::new()));
+            end(&root);
+        });
+        let late = kept.remove(1);
+        kept.push(late);
+        let mut dropped = spans(|tracer, cx| {
+            let root = start(tracer, cx, "dropped root", Vec::new());
+            end(&start(tracer, &root, "dropped late", Vec::new()));
+            end(&root);
+        });
+        dropped.reverse();
+        end_all(&processor, kept);
+        end_all(&processor, dropped);
+
+        assert_eq!(
+            reasons(&collect),
+            [
+                ("child".to_owned(), "error".to_owned()),
+                ("root".to_owned(), "error".to_owned()),
+                ("late".to_owned(), "error".to_owned())
+            ]
+        );
+        let state = processor
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert!(state.traces.is_empty());
+    }
+
+    #[test]
+    fn a_full_buffer_decides_new_traces_span_by_span() {
+        let collect = Collect::default();
+        let processor =
+            TailSamplingProcessor::new(collect.clone()).with_config(TailSamplingConfig {
+                baseline_ratio: 0.0,
+                max_buffered_spans: 1,
+                ..TailSamplingConfig::default()
+            });
+        let buffered = spans(|tracer, cx| {
+            let root = start(tracer, cx, "root", Vec::new());
+            end(&start(tracer, &root, "buffered", Vec::new()));
+        });
+        let overflow = spans(|tracer, cx| {
+            let root = start(tracer, cx, "root", Vec::new());
+            failing_child(tracer, &root);
+        });
+        // Neither root has ended yet.
+        end_all(&processor, buffered[..1].to_vec());
+        end_all(&processor, overflow[..1].to_vec());
+
+        assert_eq!(
+            reasons(&collect),
+            [("child".to_owned(), "error".to_owned())]
+        );
+        let state = processor
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(state.buffered_spans, 1);
+        assert!(state.decided.is_empty());
+    }
+
+    #[test]
+    fn forgets_the_oldest_decisions_beyond_the_limit() {
+        let (processor, _) = sampler(0.0);
+        let mut state = processor
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for trace_id in 1..=MAX_DECIDED_TRACES as u128 + 1 {
+            state.remember(TraceId::from(trace_id), None);
+        }
+
+        assert_eq!(state.decided.len(), MAX_DECIDED_TRACES);
+        assert!(!state.decided.contains_key(&TraceId::from(1)));
+        assert!(state.decided.contains_key(&TraceId::from(2)));
+    }
+
+    #[test]
+    fn shutdown_decides_buffered_traces() {
+        let (processor, collect) = sampler(0.0);
+        let mut open_root = spans(|tracer, cx| {
+            let root = start(tracer, cx, "root", Vec::new());
+            failing_child(tracer, &root);
+        });
+        open_root.pop();
+        end_all(&processor, open_root);
+        assert!(collect.spans().is_empty());
+
+        processor
+            .shutdown_with_timeout(Duration::from_secs(1))
+            .expect("shutdown");
+
+        assert_eq!(
+            reasons(&collect),
+            [("child".to_owned(), "error".to_owned())]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Helpers for processor tests.
+
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SdkTracer, Span, SpanData, SpanProcessor};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Collects the spans that reach it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Collect(Arc<Mutex<Vec<SpanData>>>);
+
+impl Collect {
+    pub(crate) fn spans(&self) -> Vec<SpanData> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn span(&self, name: &str) -> SpanData {
+        self.spans()
+            .into_iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no span named {name}"))
+    }
+}
+
+impl SpanProcessor for Collect {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+/// Starts a span named `name` under `parent`, returning its context.
+pub(crate) fn start(
+    tracer: &SdkTracer,
+    parent: &Context,
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+) -> Context {
+    let span = tracer
+        .span_builder(name)
+        .with_attributes(attributes)
+        .start_with_context(tracer, parent);
+    parent.with_span(span)
+}
+
+/// Ends the span of `cx`.
+pub(crate) fn end(cx: &Context) {
+    cx.span().end();
+}
+
+fn usage(input_tokens: i64, output_tokens: i64, cost_usd: f64) -> Vec<KeyValue> {
+    vec![
+        KeyValue::new("gen_ai.usage.input_tokens", input_tokens),
+        KeyValue::new("gen_ai.usage.output_tokens", output_tokens),
+        KeyValue::new("gen_ai.usage.cost_usd", cost_usd),
+    ]
+}
+
+fn chat(input_tokens: i64, output_tokens: i64, cost_usd: f64) -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new("gen_ai.operation.name", "chat"),
+        KeyValue::new("gen_ai.request.model", "gemini-2.5-flash"),
+    ];
+    attributes.extend(usage(input_tokens, output_tokens, cost_usd));
+    attributes
+}
+
+/// The spans of one `InstrumentedAgent` prompt under `parent`, priced by a
+/// cost deriver: a `create_agent` span, and a wrapper span with the summed
+/// usage over rig's `invoke_agent` span with the same sum, two `chat` turns
+/// (10 + 5 and 20 + 7 tokens, $0.001 and $0.002) and a tool call between
+/// them. Six spans, two model calls, one tool call, 42 tokens, $0.003.
+pub(crate) fn agent_prompt(tracer: &SdkTracer, parent: &Context) {
+    let create = start(
+        tracer,
+        parent,
+        "create_agent",
+        vec![
+            KeyValue::new("gen_ai.operation.name", "create_agent"),
+            KeyValue::new("gen_ai.request.model", "gemini-2.5-flash"),
+        ],
+    );
+    end(&create);
+    let wrapper = start(tracer, parent, "answer", Vec::new());
+    let invoke = start(
+        tracer,
+        &wrapper,
+        "invoke_agent",
+        vec![KeyValue::new("gen_ai.operation.name", "invoke_agent")],
+    );
+    let first = start(tracer, &invoke, "chat", chat(10, 5, 0.001));
+    end(&first);
+    let tool = start(
+        tracer,
+        &invoke,
+        "execute_tool",
+        vec![
+            KeyValue::new("gen_ai.operation.name", "execute_tool"),
+            KeyValue::new("gen_ai.tool.name", "lookup"),
+        ],
+    );
+    end(&tool);
+    let second = start(tracer, &invoke, "chat", chat(20, 7, 0.002));
+    end(&second);
+    invoke.span().set_attributes(usage(30, 12, 0.003));
+    end(&invoke);
+    wrapper.span().set_attributes(usage(30, 12, 0.003));
+    wrapper
+        .span()
+        .set_attribute(KeyValue::new("gen_ai.request.model", "gemini-2.5-flash"));
+    end(&wrapper);
+}
+
+/// A single `chat` span with 10 + 5 tokens and $0.001 under `parent`.
+pub(crate) fn model_call(tracer: &SdkTracer, parent: &Context) {
+    let call = start(tracer, parent, "chat gemini-2.5-flash", chat(10, 5, 0.001));
+    end(&call);
+}
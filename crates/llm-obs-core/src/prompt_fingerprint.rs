@@ -0,0 +1,73 @@
+//! System-prompt fingerprinting and change detection.
+//!
+//! Every span gets a short hash of the agent's preamble, and the first time a
+//! new hash shows up for an agent name a `llm.system_prompt.changed` event is
+//! emitted. Behaviour shifts can then be lined up against prompt edits
+//! without exporting the prompt text itself.
+
+use crate::fingerprint::fingerprint;
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Default)]
+struct AgentPrompts {
+    /// Fingerprints in the order they were first seen.
+    fingerprints: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct PromptFingerprints {
+    agents: Mutex<HashMap<String, AgentPrompts>>,
+}
+
+impl PromptFingerprints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn global() -> &'static PromptFingerprints {
+        static GLOBAL: OnceLock<PromptFingerprints> = OnceLock::new();
+        GLOBAL.get_or_init(PromptFingerprints::new)
+    }
+
+    /// Records the preamble fingerprint on `span` and returns it.
+    pub fn observe(&self, span: &tracing::Span, agent_name: &str, preamble: &str) -> String {
+        let current = fingerprint(preamble);
+        span.set_attribute("gen_ai.agent.name", agent_name.to_owned());
+        span.set_attribute("llm.system_prompt.fingerprint", current.clone());
+
+        let mut agents = self.agents.lock().unwrap_or_else(PoisonError::into_inner);
+        let prompts = agents.entry(agent_name.to_owned()).or_default();
+        if prompts.fingerprints.contains(&current) {
+            return current;
+        }
+
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.agent.name", agent_name.to_owned()),
+            KeyValue::new("llm.system_prompt.fingerprint", current.clone()),
+            KeyValue::new(
+                "llm.system_prompt.version",
+                prompts.fingerprints.len() as i64 + 1,
+            ),
+        ];
+        if let Some(previous) = prompts.fingerprints.last() {
+            attributes.push(KeyValue::new(
+                "llm.system_prompt.previous_fingerprint",
+                previous.clone(),
+            ));
+        }
+        prompts.fingerprints.push(current.clone());
+        drop(agents);
+
+        span.add_event("llm.system_prompt.changed", attributes);
+        tracing::info!(
+            gen_ai.agent.name = agent_name,
+            llm.system_prompt.fingerprint = %current,
+            "New system prompt fingerprint"
+        );
+
+        current
+    }
+}
@@ -0,0 +1,187 @@
+//! Traced prompt template rendering.
+//!
+//! A prompt with an unfilled `{variable}` or mangled text still reaches the
+//! provider and produces a plausible but wrong answer; nothing fails. Rendering
+//! inside a `render_prompt` span turns those cases into typed
+//! [`TemplateError`]s with `error.type` on the span, so malformed prompts are
+//! rejected and show up in error views instead.
+//!
+//! [`PromptTemplate`] renders `{name}` placeholders (`{{` and `}}` for literal
+//! braces); [`render_traced`] wraps any other engine such as handlebars.
+
+use crate::fingerprint::fingerprint;
+use std::collections::HashMap;
+use std::fmt;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A placeholder has no value.
+    MissingVariable { template: String, variable: String },
+    /// `{` without a matching `}` (or a stray `}`) at byte `offset`.
+    MalformedPlaceholder { template: String, offset: usize },
+    /// A value contains U+FFFD or control characters, typically from lossy
+    /// decoding of bytes that were not UTF-8.
+    InvalidEncoding { template: String, variable: String },
+    /// Error reported by an external template engine.
+    Engine { template: String, message: String },
+}
+
+impl TemplateError {
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            TemplateError::MissingVariable { .. } => "missing_variable",
+            TemplateError::MalformedPlaceholder { .. } => "malformed_placeholder",
+            TemplateError::InvalidEncoding { .. } => "invalid_encoding",
+            TemplateError::Engine { .. } => "template_engine",
+        }
+    }
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::MissingVariable { template, variable } => {
+                write!(f, "template {template}: missing variable `{variable}`")
+            }
+            TemplateError::MalformedPlaceholder { template, offset } => {
+                write!(
+                    f,
+                    "template {template}: malformed placeholder at byte {offset}"
+                )
+            }
+            TemplateError::InvalidEncoding { template, variable } => {
+                write!(
+                    f,
+                    "template {template}: variable `{variable}` is not clean text"
+                )
+            }
+            TemplateError::Engine { template, message } => {
+                write!(f, "template {template}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTemplate {
+    name: String,
+    source: String,
+}
+
+impl PromptTemplate {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Renders inside a `render_prompt` span; extra variables are ignored.
+    pub fn render<K, V>(
+        &self,
+        variables: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<String, TemplateError>
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let variables: HashMap<String, String> = variables
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        render_traced(&self.name, &self.source, || self.substitute(&variables))
+    }
+
+    fn substitute(&self, variables: &HashMap<String, String>) -> Result<String, TemplateError> {
+        let malformed = |offset| TemplateError::MalformedPlaceholder {
+            template: self.name.clone(),
+            offset,
+        };
+        let mut rendered = String::with_capacity(self.source.len());
+        let mut chars = self.source.char_indices().peekable();
+        while let Some((offset, ch)) = chars.next() {
+            match ch {
+                '{' if chars.peek().map(|(_, next)| *next) == Some('{') => {
+                    chars.next();
+                    rendered.push('{');
+                }
+                '}' if chars.peek().map(|(_, next)| *next) == Some('}') => {
+                    chars.next();
+                    rendered.push('}');
+                }
+                '}' => return Err(malformed(offset)),
+                '{' => {
+                    let mut variable = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, '{')) | None => return Err(malformed(offset)),
+                            Some((_, ch)) => variable.push(ch),
+                        }
+                    }
+                    let variable = variable.trim();
+                    let value =
+                        variables
+                            .get(variable)
+                            .ok_or_else(|| TemplateError::MissingVariable {
+                                template: self.name.clone(),
+                                variable: variable.to_owned(),
+                            })?;
+                    if !is_clean_text(value) {
+                        return Err(TemplateError::InvalidEncoding {
+                            template: self.name.clone(),
+                            variable: variable.to_owned(),
+                        });
+                    }
+                    rendered.push_str(value);
+                }
+                ch => rendered.push(ch),
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+fn is_clean_text(value: &str) -> bool {
+    !value
+        .chars()
+        .any(|ch| ch == char::REPLACEMENT_CHARACTER || (ch.is_control() && !ch.is_whitespace()))
+}
+
+/// Runs `render` (any template engine) inside a `render_prompt` span for
+/// `template_name`; `source` is only fingerprinted. Engine errors should be
+/// mapped to [`TemplateError::Engine`].
+pub fn render_traced(
+    template_name: &str,
+    source: &str,
+    render: impl FnOnce() -> Result<String, TemplateError>,
+) -> Result<String, TemplateError> {
+    let span = tracing::info_span!(
+        "render_prompt",
+        llm.prompt.template = template_name,
+        llm.prompt.template.fingerprint = fingerprint(source),
+        llm.prompt.rendered_chars = Empty,
+        error.type = Empty,
+    );
+    let _guard = span.enter();
+    match render() {
+        Ok(rendered) => {
+            span.record("llm.prompt.rendered_chars", rendered.chars().count() as u64);
+            Ok(rendered)
+        }
+        Err(error) => {
+            span.record("error.type", error.error_type());
+            span.set_status(opentelemetry::trace::Status::error(error.to_string()));
+            tracing::warn!(error = %error, "Prompt template rendering failed");
+            Err(error)
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! W3C trace context propagation across HTTP calls.
+//!
+//! Tools often call other services: a search API, a retrieval service, an
+//! internal microservice. Without `traceparent` on those requests the
+//! downstream spans start new traces, and the tool span in the LLM trace is
+//! a dead end. These helpers write the span's context into outgoing headers
+//! and continue incoming ones, using the global propagator:
+//! [`TelemetryBuilder::init`](crate::telemetry::TelemetryBuilder::init)
+//! installs W3C trace context and baggage unless given another one. Hosts
+//! that only use `build_layer` install their own with
+//! `opentelemetry::global::set_text_map_propagator`.
+//!
+//! Anything implementing [`Injector`] or [`Extractor`] works as a carrier,
+//! including `HashMap<String, String>`. With the `http-client` or `axum`
+//! feature [`HeaderInjector`] and [`HeaderExtractor`] adapt
+//! `http::HeaderMap`; `axum` also brings `server::ServerSpanLayer` for
+//! incoming requests, and `http-client` brings [`RequestBuilderExt`] for
+//! reqwest:
+//!
+//! ```ignore
+//! let response = client
+//!     .get("https://search.internal/v1/query")
+//!     .query(&[("q", query)])
+//!     .send_traced()
+//!     .await?;
+//! ```
+
+#[cfg(any(feature = "http-client", feature = "axum"))]
+use http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::{Context, KeyValue};
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Writes `span`'s trace context (`traceparent`, `tracestate`, baggage)
+/// into `injector`.
+pub fn inject(span: &tracing::Span, injector: &mut dyn Injector) {
+    let cx = span.context();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&cx, injector));
+}
+
+/// [`inject`] for the current span.
+pub fn inject_current(injector: &mut dyn Injector) {
+    inject(&tracing::Span::current(), injector);
+}
+
+/// The current span's propagation headers, for clients without an
+/// [`Injector`] adapter.
+pub fn current_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    inject_current(&mut headers);
+    headers
+}
+
+/// The remote context carried by `extractor`, or the current context when
+/// it carries none.
+pub fn extract(extractor: &dyn Extractor) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(extractor))
+}
+
+/// Makes `span` a child of the remote span in `extractor`, so the trace a
+/// caller started continues here. Call it before entering `span`.
+pub fn continue_trace(span: &tracing::Span, extractor: &dyn Extractor) {
+    span.set_parent(extract(extractor));
+}
+
+/// `cx` without the baggage entry `key`, for entries callers must not be
+/// able to set, such as the tenant.
+pub fn without_baggage(cx: &Context, key: &str) -> Context {
+    if cx.baggage().get(key).is_none() {
+        return cx.clone();
+    }
+    let baggage: Baggage = cx
+        .baggage()
+        .iter()
+        .filter(|(name, _)| name.as_str() != key)
+        .map(|(name, (value, _))| KeyValue::new(name.clone(), value.to_string()))
+        .collect();
+    cx.with_baggage(baggage)
+}
+
+/// [`Injector`] over an `http::HeaderMap`; invalid names or values are
+/// skipped.
+#[cfg(any(feature = "http-client", feature = "axum"))]
+#[derive(Debug)]
+pub struct HeaderInjector<'a>(pub &'a mut HeaderMap);
+
+#[cfg(any(feature = "http-client", feature = "axum"))]
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+/// [`Extractor`] over an `http::HeaderMap`.
+#[cfg(any(feature = "http-client", feature = "axum"))]
+#[derive(Debug)]
+pub struct HeaderExtractor<'a>(pub &'a HeaderMap);
+
+#[cfg(any(feature = "http-client", feature = "axum"))]
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+#[cfg(feature = "http-client")]
+pub use self::http_client::RequestBuilderExt;
+
+#[cfg(feature = "http-client")]
+mod http_client {
+    use super::{HeaderInjector, inject};
+    use crate::spans::start_http_client_span;
+    use http::HeaderMap;
+    use opentelemetry::trace::Status;
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    /// Trace context for outgoing reqwest requests.
+    pub trait RequestBuilderExt: Sized {
+        /// Adds the current span's propagation headers.
+        fn with_trace_context(self) -> Self;
+
+        /// Sends the request in a CLIENT `{method}` span whose context is
+        /// propagated, recording `server.address`, `server.port`,
+        /// `http.response.status_code` and, for transport failures and
+        /// 4xx/5xx responses, `error.type` and error status. `url.full` is
+        /// recorded without credentials or query string, which often carry
+        /// API keys.
+        fn send_traced(self) -> impl Future<Output = reqwest::Result<reqwest::Response>> + Send;
+    }
+
+    impl RequestBuilderExt for reqwest::RequestBuilder {
+        fn with_trace_context(self) -> Self {
+            let mut headers = HeaderMap::new();
+            inject(&tracing::Span::current(), &mut HeaderInjector(&mut headers));
+            self.headers(headers)
+        }
+
+        async fn send_traced(self) -> reqwest::Result<reqwest::Response> {
+            let (client, request) = self.build_split();
+            let mut request = request?;
+            let full_url = request.url().to_string();
+            let mut url = request.url().clone();
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            let span = start_http_client_span(request.method().as_str(), url.as_str());
+            if let Some(host) = url.host_str() {
+                span.record("server.address", host);
+            }
+            if let Some(port) = url.port_or_known_default() {
+                span.record("server.port", port);
+            }
+            inject(&span, &mut HeaderInjector(request.headers_mut()));
+
+            match client.execute(request).instrument(span.clone()).await {
+                Ok(response) => {
+                    let status = response.status();
+                    span.record("http.response.status_code", status.as_u16());
+                    if status.is_client_error() || status.is_server_error() {
+                        span.record("error.type", status.as_str());
+                        span.set_status(Status::error(status.to_string()));
+                    }
+                    Ok(response)
+                }
+                Err(error) => {
+                    let error_type = if error.is_timeout() {
+                        "timeout"
+                    } else if error.is_connect() {
+                        "connect"
+                    } else {
+                        "_OTHER"
+                    };
+                    span.record("error.type", error_type);
+                    let description = error.to_string().replace(&full_url, url.as_str());
+                    span.set_status(Status::error(description));
+                    Err(error)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Response-quality regression detection against a rolling baseline.
+//!
+//! Each prompt-template/model pair keeps a baseline window of older
+//! generations and a smaller window of recent ones. When the recent
+//! distribution of a signal (length, judge score, latency, refusal rate)
+//! drifts too far from the baseline, an `llm.quality.regression` event is
+//! emitted on the current span and returned to the caller.
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Signals extracted from one generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationSample {
+    pub response_len: usize,
+    pub judge_score: Option<f64>,
+    pub refused: bool,
+    pub latency: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualitySignal {
+    ResponseLength,
+    JudgeScore,
+    Latency,
+    RefusalRate,
+}
+
+impl QualitySignal {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QualitySignal::ResponseLength => "response_length",
+            QualitySignal::JudgeScore => "judge_score",
+            QualitySignal::Latency => "latency",
+            QualitySignal::RefusalRate => "refusal_rate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityRegression {
+    pub prompt_template: String,
+    pub model: String,
+    pub signal: QualitySignal,
+    pub baseline_mean: f64,
+    pub recent_mean: f64,
+    pub baseline_std_dev: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QualityBaselineConfig {
+    /// Number of older samples that form the baseline distribution.
+    pub baseline_window: usize,
+    /// Number of newest samples compared against the baseline.
+    pub recent_window: usize,
+    /// Shift (in baseline standard deviations) that counts as a regression.
+    pub threshold_std_devs: f64,
+    /// Absolute refusal-rate increase that counts as a regression.
+    pub max_refusal_rate_increase: f64,
+}
+
+impl Default for QualityBaselineConfig {
+    fn default() -> Self {
+        Self {
+            baseline_window: 200,
+            recent_window: 20,
+            threshold_std_devs: 3.0,
+            max_refusal_rate_increase: 0.1,
+        }
+    }
+}
+
+#[derive(Default)]
+struct History {
+    baseline: VecDeque<GenerationSample>,
+    recent: VecDeque<GenerationSample>,
+}
+
+#[derive(Default)]
+pub struct QualityBaseline {
+    config: QualityBaselineConfig,
+    histories: Mutex<HashMap<(String, String), History>>,
+}
+
+impl QualityBaseline {
+    pub fn new(config: QualityBaselineConfig) -> Self {
+        Self {
+            config,
+            histories: Mutex::default(),
+        }
+    }
+
+    /// Adds a sample and returns any regressions detected for the pair.
+    pub fn observe(
+        &self,
+        prompt_template: &str,
+        model: &str,
+        sample: GenerationSample,
+    ) -> Vec<QualityRegression> {
+        let mut histories = self
+            .histories
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let history = histories
+            .entry((prompt_template.to_owned(), model.to_owned()))
+            .or_default();
+
+        history.recent.push_back(sample);
+        if history.recent.len() > self.config.recent_window {
+            if let Some(oldest) = history.recent.pop_front() {
+                history.baseline.push_back(oldest);
+            }
+            if history.baseline.len() > self.config.baseline_window {
+                history.baseline.pop_front();
+            }
+        }
+
+        if history.recent.len() < self.config.recent_window
+            || history.baseline.len() < self.config.recent_window
+        {
+            return Vec::new();
+        }
+
+        let regressions: Vec<QualityRegression> = [
+            QualitySignal::ResponseLength,
+            QualitySignal::JudgeScore,
+            QualitySignal::Latency,
+            QualitySignal::RefusalRate,
+        ]
+        .into_iter()
+        .filter_map(|signal| {
+            let baseline = Stats::of(
+                history
+                    .baseline
+                    .iter()
+                    .filter_map(|s| signal_value(signal, s)),
+            )?;
+            let recent = Stats::of(
+                history
+                    .recent
+                    .iter()
+                    .filter_map(|s| signal_value(signal, s)),
+            )?;
+            let shifted = match signal {
+                QualitySignal::RefusalRate => {
+                    recent.mean - baseline.mean > self.config.max_refusal_rate_increase
+                }
+                _ => {
+                    let tolerance = self.config.threshold_std_devs
+                        * baseline.std_dev.max(baseline.mean.abs() * 0.01);
+                    (recent.mean - baseline.mean).abs() > tolerance
+                }
+            };
+            shifted.then(|| QualityRegression {
+                prompt_template: prompt_template.to_owned(),
+                model: model.to_owned(),
+                signal,
+                baseline_mean: baseline.mean,
+                recent_mean: recent.mean,
+                baseline_std_dev: baseline.std_dev,
+            })
+        })
+        .collect();
+
+        for regression in &regressions {
+            tracing::warn!(
+                prompt_template = %regression.prompt_template,
+                gen_ai.request.model = %regression.model,
+                signal = regression.signal.as_str(),
+                baseline_mean = regression.baseline_mean,
+                recent_mean = regression.recent_mean,
+                baseline_std_dev = regression.baseline_std_dev,
+                "llm.quality.regression"
+            );
+        }
+
+        regressions
+    }
+}
+
+fn signal_value(signal: QualitySignal, sample: &GenerationSample) -> Option<f64> {
+    match signal {
+        QualitySignal::ResponseLength => Some(sample.response_len as f64),
+        QualitySignal::JudgeScore => sample.judge_score,
+        QualitySignal::Latency => Some(sample.latency.as_secs_f64()),
+        QualitySignal::RefusalRate => Some(if sample.refused { 1.0 } else { 0.0 }),
+    }
+}
+
+struct Stats {
+    mean: f64,
+    std_dev: f64,
+}
+
+impl Stats {
+    fn of(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let values: Vec<f64> = values.collect();
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        Some(Self {
+            mean,
+            std_dev: variance.sqrt(),
+        })
+    }
+}
@@ -0,0 +1,90 @@
+//! Instrumentation for retrieval query rewriting.
+//!
+//! HyDE, multi-query expansion and similar rewrites run before retrieval, so
+//! a bad rewrite looks like a relevance bug in the retriever. Wrapping the
+//! step in a `rewrite_query` span that records the original query, every
+//! rewritten query and what the rewrite model cost keeps that cause visible.
+
+use crate::cost::ModelPricing;
+use crate::scopes::Subsystem;
+use crate::tokens::TokenUsage;
+use tracing::field::Empty;
+
+/// Outcome of one rewrite step.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryRewrite {
+    pub original: String,
+    pub rewritten: Vec<String>,
+    /// Model that produced the rewrites, when one was used.
+    pub model: Option<String>,
+    pub usage: Option<TokenUsage>,
+    pub cost_usd: Option<f64>,
+}
+
+impl QueryRewrite {
+    pub fn new(original: impl Into<String>, rewritten: Vec<String>) -> Self {
+        Self {
+            original: original.into(),
+            rewritten,
+            ..Self::default()
+        }
+    }
+
+    /// Records the rewrite model call; the cost is derived from `pricing`
+    /// when given.
+    pub fn with_model_usage(
+        mut self,
+        model: impl Into<String>,
+        usage: TokenUsage,
+        pricing: Option<&ModelPricing>,
+    ) -> Self {
+        self.model = Some(model.into());
+        self.cost_usd = pricing.map(|pricing| pricing.cost(&usage).total_usd());
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Whether the rewrite left the query as it was.
+    pub fn is_identity(&self) -> bool {
+        self.rewritten.len() == 1 && self.rewritten[0].trim() == self.original.trim()
+    }
+}
+
+/// Opens the `rewrite_query` span; enter it around the rewrite call.
+pub fn rewrite_query_span(strategy: &str) -> tracing::Span {
+    tracing::info_span!(
+        "rewrite_query",
+        otel.scope = Subsystem::Rag.scope_name(),
+        llm.rewrite.strategy = strategy,
+        llm.rewrite.original_query = Empty,
+        llm.rewrite.rewritten_queries = Empty,
+        llm.rewrite.count = Empty,
+        llm.rewrite.identity = Empty,
+        gen_ai.request.model = Empty,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        llm.rewrite.cost_usd = Empty,
+    )
+}
+
+pub fn record_rewrite(span: &tracing::Span, rewrite: &QueryRewrite) {
+    span.record("llm.rewrite.original_query", rewrite.original.as_str());
+    span.record(
+        "llm.rewrite.rewritten_queries",
+        serde_json::to_string(&rewrite.rewritten)
+            .unwrap_or_default()
+            .as_str(),
+    );
+    span.record("llm.rewrite.count", rewrite.rewritten.len() as u64);
+    span.record("llm.rewrite.identity", rewrite.is_identity());
+    if let Some(model) = &rewrite.model {
+        span.record("gen_ai.request.model", model.as_str());
+    }
+    if let Some(usage) = &rewrite.usage {
+        span.record("gen_ai.usage.input_tokens", usage.input_tokens);
+        span.record("gen_ai.usage.output_tokens", usage.output_tokens);
+    }
+    if let Some(cost) = rewrite.cost_usd {
+        span.record("llm.rewrite.cost_usd", cost);
+    }
+}
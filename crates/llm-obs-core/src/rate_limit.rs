@@ -0,0 +1,399 @@
+//! Client-side token-bucket rate limiting per provider.
+//!
+//! Hitting a provider's RPM/TPM limit costs a round trip, a 429 and a retry
+//! backoff. Limiting on the client to the published limits delays (or
+//! rejects) calls before that happens. Time spent waiting is recorded as a
+//! `rate_limit.wait` child span, and every throttled call is counted in
+//! `llm.provider.throttled_requests`, so self-inflicted queueing shows up in
+//! the trace instead of as unexplained latency.
+
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// Published limits for one provider (or one model of a provider).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProviderLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// What to do when a call would exceed the limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// Wait for capacity, but give up when the wait would exceed `max_wait`.
+    Delay { max_wait: Duration },
+    /// Fail immediately.
+    Reject,
+}
+
+/// Which limit a throttled call ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKind {
+    Requests,
+    Tokens,
+}
+
+impl LimitKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKind::Requests => "rpm",
+            LimitKind::Tokens => "tpm",
+        }
+    }
+}
+
+/// Returned when a call is rejected instead of delayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throttled {
+    pub provider: String,
+    pub limit: LimitKind,
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} limit reached; retry after {} ms",
+            self.provider,
+            self.limit.as_str(),
+            self.retry_after.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn per_minute(capacity: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until `amount` is available. Requests larger than the bucket
+    /// are clamped to its capacity so they can still proceed eventually.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount.min(self.capacity) - self.available;
+        if missing <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing / self.refill_per_sec)
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+
+    /// Gives back over-estimated tokens or charges for under-estimated ones.
+    fn adjust(&mut self, delta: f64) {
+        self.available = (self.available + delta).min(self.capacity);
+    }
+}
+
+#[derive(Debug)]
+struct ProviderBuckets {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl ProviderBuckets {
+    fn new(limits: &ProviderLimits, now: Instant) -> Self {
+        Self {
+            requests: limits
+                .requests_per_minute
+                .map(|rpm| Bucket::per_minute(f64::from(rpm), now)),
+            tokens: limits
+                .tokens_per_minute
+                .map(|tpm| Bucket::per_minute(tpm as f64, now)),
+        }
+    }
+
+    fn wait_for(&mut self, tokens: u64, now: Instant) -> Option<(LimitKind, Duration)> {
+        let requests_wait = self.requests.as_mut().map(|bucket| {
+            bucket.refill(now);
+            (LimitKind::Requests, bucket.wait_for(1.0))
+        });
+        let tokens_wait = self.tokens.as_mut().map(|bucket| {
+            bucket.refill(now);
+            (LimitKind::Tokens, bucket.wait_for(tokens as f64))
+        });
+        [requests_wait, tokens_wait]
+            .into_iter()
+            .flatten()
+            .filter(|(_, wait)| !wait.is_zero())
+            .max_by_key(|(_, wait)| *wait)
+    }
+
+    fn take(&mut self, tokens: u64) {
+        if let Some(bucket) = self.requests.as_mut() {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = self.tokens.as_mut() {
+            bucket.take(tokens as f64);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    mode: ThrottleMode,
+    limits: HashMap<String, ProviderLimits>,
+    buckets: Mutex<HashMap<String, ProviderBuckets>>,
+}
+
+impl RateLimiter {
+    pub fn new(mode: ThrottleMode) -> Self {
+        Self {
+            mode,
+            limits: HashMap::new(),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Sets the limits for `provider` (matching `gen_ai.provider.name`).
+    /// Providers without limits are never throttled.
+    pub fn with_limits(mut self, provider: impl Into<String>, limits: ProviderLimits) -> Self {
+        self.limits.insert(provider.into(), limits);
+        self
+    }
+
+    /// Reserves one request and `estimated_tokens` for `provider`, waiting
+    /// for capacity in `Delay` mode.
+    pub async fn acquire(&self, provider: &str, estimated_tokens: u64) -> Result<(), Throttled> {
+        let Some(limits) = self.limits.get(provider) else {
+            return Ok(());
+        };
+
+        let mut waited = Duration::ZERO;
+        loop {
+            let Some((limit, wait)) = self.try_take(provider, limits, estimated_tokens) else {
+                return Ok(());
+            };
+
+            let rejected = match self.mode {
+                ThrottleMode::Reject => true,
+                ThrottleMode::Delay { max_wait } => waited + wait > max_wait,
+            };
+            // Counted when first delayed, and again if finally rejected.
+            if waited.is_zero() || rejected {
+                record_throttle(provider, limit, rejected);
+            }
+            if rejected {
+                return Err(Throttled {
+                    provider: provider.to_owned(),
+                    limit,
+                    retry_after: wait,
+                });
+            }
+
+            let wait_span = tracing::info_span!(
+                "rate_limit.wait",
+                gen_ai.provider.name = provider,
+                rate_limit.limit = limit.as_str(),
+                rate_limit.wait_ms = wait.as_millis() as u64,
+            );
+            tokio::time::sleep(wait).instrument(wait_span).await;
+            waited += wait;
+        }
+    }
+
+    /// Takes capacity when available, otherwise returns the binding limit
+    /// and how long until it frees up.
+    fn try_take(
+        &self,
+        provider: &str,
+        limits: &ProviderLimits,
+        tokens: u64,
+    ) -> Option<(LimitKind, Duration)> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let provider_buckets = buckets
+            .entry(provider.to_owned())
+            .or_insert_with(|| ProviderBuckets::new(limits, now));
+        let throttle = provider_buckets.wait_for(tokens, now);
+        if throttle.is_none() {
+            provider_buckets.take(tokens);
+        }
+        throttle
+    }
+
+    /// Corrects the token bucket once the provider reports actual usage.
+    pub fn record_usage(&self, provider: &str, estimated_tokens: u64, actual_tokens: u64) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(bucket) = buckets
+            .get_mut(provider)
+            .and_then(|provider_buckets| provider_buckets.tokens.as_mut())
+        {
+            bucket.adjust(estimated_tokens as f64 - actual_tokens as f64);
+        }
+    }
+}
+
+fn record_throttle(provider: &str, limit: LimitKind, rejected: bool) {
+    let action = if rejected { "rejected" } else { "delayed" };
+    tracing::debug!(
+        gen_ai.provider.name = provider,
+        rate_limit.limit = limit.as_str(),
+        rate_limit.action = action,
+        "Call throttled by client-side rate limiter"
+    );
+    throttle_counter().add(
+        1,
+        &[
+            KeyValue::new("gen_ai.provider.name", provider.to_owned()),
+            KeyValue::new("rate_limit.limit", limit.as_str()),
+            KeyValue::new("rate_limit.action", action),
+        ],
+    );
+}
+
+fn throttle_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.provider.throttled_requests")
+            .with_description("Calls delayed or rejected by the client-side rate limiter")
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(requests_per_minute: Option<u32>, tokens_per_minute: Option<u64>) -> ProviderLimits {
+        ProviderLimits {
+            requests_per_minute,
+            tokens_per_minute,
+        }
+    }
+
+    #[tokio::test]
+    async fn providers_without_limits_are_never_throttled() {
+        let limiter =
+            RateLimiter::new(ThrottleMode::Reject).with_limits("openai", limits(Some(1), None));
+
+        for _ in 0..10 {
+            assert_eq!(limiter.acquire("gcp.gemini", 1_000_000).await, Ok(()));
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_beyond_the_rpm_limit() {
+        let limiter = RateLimiter::new(ThrottleMode::Reject)
+            .with_limits("openai", limits(Some(2), Some(1_000_000)));
+
+        assert_eq!(limiter.acquire("openai", 10).await, Ok(()));
+        assert_eq!(limiter.acquire("openai", 10).await, Ok(()));
+        let throttled = limiter.acquire("openai", 10).await.unwrap_err();
+
+        assert_eq!(throttled.provider, "openai");
+        assert_eq!(throttled.limit, LimitKind::Requests);
+        // One request refills every 30 seconds.
+        assert!(
+            throttled.retry_after > Duration::from_secs(29)
+                && throttled.retry_after <= Duration::from_secs(30),
+            "{throttled}"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_calls_beyond_the_tpm_limit() {
+        let limiter = RateLimiter::new(ThrottleMode::Reject)
+            .with_limits("anthropic", limits(Some(100), Some(600)));
+
+        assert_eq!(limiter.acquire("anthropic", 500).await, Ok(()));
+        let throttled = limiter.acquire("anthropic", 200).await.unwrap_err();
+
+        assert_eq!(throttled.limit, LimitKind::Tokens);
+        // 100 tokens short at 10 tokens per second.
+        assert!(
+            throttled.retry_after > Duration::from_secs(9)
+                && throttled.retry_after <= Duration::from_secs(10),
+            "{throttled}"
+        );
+        assert_eq!(
+            throttled.to_string(),
+            format!(
+                "anthropic tpm limit reached; retry after {} ms",
+                throttled.retry_after.as_millis()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_estimates_are_clamped_to_the_bucket() {
+        let limiter =
+            RateLimiter::new(ThrottleMode::Reject).with_limits("ollama", limits(None, Some(100)));
+
+        assert_eq!(limiter.acquire("ollama", 5_000).await, Ok(()));
+        assert!(limiter.acquire("ollama", 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn delays_until_capacity_frees_up() {
+        let limiter = RateLimiter::new(ThrottleMode::Delay {
+            max_wait: Duration::from_secs(1),
+        })
+        .with_limits("openai", limits(None, Some(60_000)));
+        assert_eq!(limiter.acquire("openai", 60_000).await, Ok(()));
+
+        // 1000 tokens per second, so 50 tokens take 50 ms.
+        let started = Instant::now();
+        assert_eq!(limiter.acquire("openai", 50).await, Ok(()));
+        assert!(started.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[tokio::test]
+    async fn delays_longer_than_max_wait_are_rejected() {
+        let limiter = RateLimiter::new(ThrottleMode::Delay {
+            max_wait: Duration::from_millis(10),
+        })
+        .with_limits("openai", limits(Some(1), None));
+        assert_eq!(limiter.acquire("openai", 0).await, Ok(()));
+
+        let started = Instant::now();
+        let throttled = limiter.acquire("openai", 0).await.unwrap_err();
+
+        assert_eq!(throttled.limit, LimitKind::Requests);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn reported_usage_corrects_the_token_estimate() {
+        let limiter =
+            RateLimiter::new(ThrottleMode::Reject).with_limits("openai", limits(None, Some(1_000)));
+
+        assert_eq!(limiter.acquire("openai", 1_000).await, Ok(()));
+        limiter.record_usage("openai", 1_000, 200);
+        assert_eq!(limiter.acquire("openai", 700).await, Ok(()));
+
+        limiter.record_usage("openai", 700, 900);
+        assert!(limiter.acquire("openai", 50).await.is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! Reasoning-token accounting for thinking models.
+//!
+//! Gemini 2.5 thinking models and OpenAI o-series models spend tokens (and
+//! wall-clock time) on hidden reasoning. Lumping those into completion tokens
+//! makes a short answer look slow and expensive for no visible reason, so
+//! they are recorded as their own attributes next to the thinking budget.
+//! `gen_ai.usage.output_tokens` stays as the provider reported it: OpenAI
+//! counts reasoning in it, Gemini does not.
+
+use crate::cost::ModelPricing;
+use crate::tokens::TokenUsage;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Thinking configuration the request was sent with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThinkingBudget {
+    /// Gemini `thinkingBudget` / OpenAI `max_reasoning_tokens`-style cap.
+    pub budget_tokens: Option<u64>,
+    /// OpenAI `reasoning.effort` or an equivalent label.
+    pub effort: Option<&'static str>,
+}
+
+/// Records reasoning usage, budget utilisation and (optionally) cost split.
+pub fn record_reasoning(
+    span: &tracing::Span,
+    usage: &TokenUsage,
+    budget: &ThinkingBudget,
+    thinking_time: Option<Duration>,
+    pricing: Option<&ModelPricing>,
+) {
+    span.set_attribute(
+        "gen_ai.usage.reasoning_tokens",
+        usage.reasoning_tokens as i64,
+    );
+
+    if let Some(budget_tokens) = budget.budget_tokens {
+        span.set_attribute("gen_ai.request.thinking_budget", budget_tokens as i64);
+        if budget_tokens > 0 {
+            span.set_attribute(
+                "gen_ai.reasoning.budget_utilization",
+                usage.reasoning_tokens as f64 / budget_tokens as f64,
+            );
+        }
+    }
+    if let Some(effort) = budget.effort {
+        span.set_attribute("gen_ai.request.reasoning_effort", effort);
+    }
+    if let Some(thinking_time) = thinking_time {
+        span.set_attribute(
+            "gen_ai.reasoning.duration_ms",
+            thinking_time.as_millis() as i64,
+        );
+    }
+    if let Some(pricing) = pricing {
+        let cost = pricing.cost(usage);
+        span.set_attribute("gen_ai.usage.reasoning_cost_usd", cost.reasoning_usd);
+        span.set_attribute("gen_ai.usage.cost_usd", cost.total_usd());
+    }
+}
@@ -0,0 +1,114 @@
+//! Explicit timestamps for spans replayed from history.
+//!
+//! Backfilling past interactions (e.g. from an existing chat database)
+//! through the usual instrumentation stamps every span with the import time,
+//! so months of history land in one minute of the backend timeline.
+//! [`set_timestamps`] records when the interaction really happened on the
+//! span, and [`ReplayTimestampProcessor`] moves the span there when it ends:
+//! start and end times are replaced, events keep their offset from the
+//! start, and the span is marked `llm.replayed`. `TelemetryBuilder` installs
+//! the processor, so only the call is needed:
+//!
+//! ```ignore
+//! for turn in history {
+//!     let span = tracing::info_span!("chat", gen_ai.request.model = %turn.model);
+//!     replay::set_timestamps(&span, turn.sent_at, Some(turn.answered_at));
+//!     // record usage, content, ... as for a live call
+//! }
+//! ```
+//!
+//! Every span needs its own timestamps; children of a replayed span are not
+//! moved with it. Backends usually reject data older than their retention
+//! window.
+
+use crate::processors::attribute;
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Attribute carrying the replayed start time, in nanoseconds since the
+/// Unix epoch; removed by the processor.
+pub const START_TIME_KEY: &str = "llm.replay.start_time_unix_nano";
+/// Attribute carrying the replayed end time; removed by the processor.
+pub const END_TIME_KEY: &str = "llm.replay.end_time_unix_nano";
+
+/// Marks `span` to be exported as starting at `start` and ending at `end`.
+/// Without an end time the span keeps the duration it had during the import.
+pub fn set_timestamps(span: &tracing::Span, start: SystemTime, end: Option<SystemTime>) {
+    span.set_attribute(START_TIME_KEY, unix_nanos(start));
+    if let Some(end) = end {
+        span.set_attribute(END_TIME_KEY, unix_nanos(end));
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_nanos() as i64)
+}
+
+fn timestamp(span: &SpanData, key: &str) -> Option<SystemTime> {
+    match attribute(span, key)? {
+        Value::I64(nanos) if *nanos >= 0 => Some(UNIX_EPOCH + Duration::from_nanos(*nanos as u64)),
+        _ => None,
+    }
+}
+
+/// Moves spans carrying [`set_timestamps`] attributes to their replayed time.
+#[derive(Debug)]
+pub struct ReplayTimestampProcessor<P> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> ReplayTimestampProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for ReplayTimestampProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let Some(start_time) = timestamp(&span, START_TIME_KEY) else {
+            self.inner.on_end(span);
+            return;
+        };
+        let duration = span
+            .end_time
+            .duration_since(span.start_time)
+            .unwrap_or_default();
+        let end_time = timestamp(&span, END_TIME_KEY)
+            .filter(|end_time| *end_time >= start_time)
+            .unwrap_or(start_time + duration);
+        for event in span.events.events.iter_mut() {
+            let offset = event
+                .timestamp
+                .duration_since(span.start_time)
+                .unwrap_or_default();
+            event.timestamp = (start_time + offset).min(end_time);
+        }
+        span.start_time = start_time;
+        span.end_time = end_time;
+        span.attributes
+            .retain(|kv| kv.key.as_str() != START_TIME_KEY && kv.key.as_str() != END_TIME_KEY);
+        span.attributes.push(KeyValue::new("llm.replayed", true));
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
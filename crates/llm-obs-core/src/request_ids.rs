@@ -0,0 +1,122 @@
+//! Correlation of provider request IDs across retries and fallbacks.
+//!
+//! One logical request can hit the provider several times. Collecting every
+//! provider-reported request/response ID and recording them as an array on
+//! the parent span lets provider support find all attempts at once.
+
+use opentelemetry::{Array, StringValue, Value};
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+tokio::task_local! {
+    static CURRENT: ProviderRequestIds;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProviderRequestIds {
+    ids: Arc<Mutex<Vec<String>>>,
+}
+
+impl ProviderRequestIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `future` with `self` as the collector for [`record_current`].
+    ///
+    /// [`record_current`]: ProviderRequestIds::record_current
+    pub async fn scope<F: Future>(&self, future: F) -> F::Output {
+        CURRENT.scope(self.clone(), future).await
+    }
+
+    /// Runs `future` in a fresh scope, then writes the IDs recorded in it on
+    /// `span`, if there are any, and passes them on to the enclosing scope,
+    /// so both one call's span and the span of the logical request list
+    /// them.
+    pub async fn collect<F: Future>(span: &tracing::Span, future: F) -> F::Output {
+        let ids = Self::new();
+        let output = ids.scope(future).await;
+        let recorded = ids.ids();
+        if !recorded.is_empty() {
+            ids.finish(span);
+            for id in recorded {
+                Self::record_current(id);
+            }
+        }
+        output
+    }
+
+    /// Records an ID into the collector of the enclosing [`scope`], if any,
+    /// so retry loops deep inside adapters need no extra plumbing.
+    ///
+    /// [`scope`]: ProviderRequestIds::scope
+    pub fn record_current(id: impl Into<String>) {
+        let id = id.into();
+        let _ = CURRENT.try_with(|ids| ids.record(id));
+    }
+
+    pub fn record(&self, id: impl Into<String>) {
+        let id = id.into();
+        if id.is_empty() {
+            return;
+        }
+        let mut ids = self.ids.lock().unwrap_or_else(PoisonError::into_inner);
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Writes `gen_ai.provider.request_ids` and their count on `span`.
+    pub fn finish(&self, span: &tracing::Span) {
+        let ids = self.ids();
+        span.set_attribute("gen_ai.provider.request_id_count", ids.len() as i64);
+        span.set_attribute(
+            "gen_ai.provider.request_ids",
+            Value::Array(Array::String(
+                ids.into_iter().map(StringValue::from).collect(),
+            )),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn collected_ids_reach_the_enclosing_scope() {
+        let outer = ProviderRequestIds::new();
+        outer
+            .scope(async {
+                ProviderRequestIds::collect(&tracing::Span::none(), async {
+                    ProviderRequestIds::record_current("attempt-1");
+                    ProviderRequestIds::record_current("attempt-2");
+                })
+                .await;
+                ProviderRequestIds::collect(&tracing::Span::none(), async {
+                    ProviderRequestIds::record_current("fallback-1");
+                })
+                .await;
+            })
+            .await;
+
+        assert_eq!(outer.ids(), ["attempt-1", "attempt-2", "fallback-1"]);
+    }
+
+    #[tokio::test]
+    async fn recording_outside_a_scope_is_ignored() {
+        ProviderRequestIds::record_current("orphan");
+        let ids = ProviderRequestIds::new();
+        ids.scope(async {}).await;
+
+        assert!(ids.ids().is_empty());
+    }
+}
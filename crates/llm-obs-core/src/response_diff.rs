@@ -0,0 +1,121 @@
+//! Diffing answers to repeated prompts.
+//!
+//! The same prompt rarely gets the same answer twice, and how far answers
+//! drift is a production property worth measuring: after a model or
+//! temperature change, repeats that used to be near-identical can start to
+//! diverge. [`ResponseDiffer`] remembers the last answer per normalized
+//! prompt (or takes one from the caller's response cache) and records a
+//! word-level similarity score and diff size on the span.
+
+use crate::duplicates::normalize;
+use crate::fingerprint::fingerprint;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Words compared per answer; bounds the quadratic diff on long outputs.
+const MAX_DIFF_WORDS: usize = 2_000;
+
+/// How a new answer differs from the previous one for the same prompt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResponseDiff {
+    /// `1.0` for identical word sequences, `0.0` for nothing in common.
+    pub similarity: f64,
+    /// Words inserted plus words deleted.
+    pub diff_words: u64,
+    pub identical: bool,
+}
+
+impl ResponseDiff {
+    pub fn between(previous: &str, current: &str) -> Self {
+        let previous: Vec<&str> = previous.split_whitespace().take(MAX_DIFF_WORDS).collect();
+        let current: Vec<&str> = current.split_whitespace().take(MAX_DIFF_WORDS).collect();
+        let common = longest_common_subsequence(&previous, &current);
+        let total = previous.len() + current.len();
+        Self {
+            similarity: if total == 0 {
+                1.0
+            } else {
+                2.0 * common as f64 / total as f64
+            },
+            diff_words: (total - 2 * common) as u64,
+            identical: previous == current,
+        }
+    }
+}
+
+/// Length of the longest common subsequence, in two rows of memory.
+fn longest_common_subsequence(left: &[&str], right: &[&str]) -> usize {
+    let mut previous_row = vec![0_usize; right.len() + 1];
+    let mut row = vec![0_usize; right.len() + 1];
+    for left_word in left {
+        for (index, right_word) in right.iter().enumerate() {
+            row[index + 1] = if left_word == right_word {
+                previous_row[index] + 1
+            } else {
+                row[index].max(previous_row[index + 1])
+            };
+        }
+        std::mem::swap(&mut previous_row, &mut row);
+    }
+    previous_row[right.len()]
+}
+
+/// Records `diff` on `span`.
+pub fn record_response_diff(span: &tracing::Span, diff: &ResponseDiff) {
+    span.set_attribute("llm.response.previous_similarity", diff.similarity);
+    span.set_attribute("llm.response.previous_diff_words", diff.diff_words as i64);
+    span.set_attribute("llm.response.previous_identical", diff.identical);
+}
+
+#[derive(Debug, Default)]
+struct PreviousAnswers {
+    answers: HashMap<String, String>,
+    /// Prompt hashes in insertion order, for eviction.
+    order: VecDeque<String>,
+}
+
+/// Remembers the latest answer for up to `capacity` prompts.
+#[derive(Debug)]
+pub struct ResponseDiffer {
+    capacity: usize,
+    previous: Mutex<PreviousAnswers>,
+}
+
+impl ResponseDiffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            previous: Mutex::default(),
+        }
+    }
+
+    /// Compares `response` with the previous answer to the same normalized
+    /// prompt, records the result on `span` and remembers `response`.
+    /// Returns `None` the first time a prompt is seen.
+    pub fn observe(
+        &self,
+        span: &tracing::Span,
+        prompt: &str,
+        response: &str,
+    ) -> Option<ResponseDiff> {
+        let prompt_hash = fingerprint(normalize(prompt));
+        let mut state = self.previous.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = state
+            .answers
+            .insert(prompt_hash.clone(), response.to_owned());
+        if previous.is_none() {
+            state.order.push_back(prompt_hash);
+            while state.order.len() > self.capacity {
+                if let Some(evicted) = state.order.pop_front() {
+                    state.answers.remove(&evicted);
+                }
+            }
+        }
+        drop(state);
+
+        let diff = ResponseDiff::between(&previous?, response);
+        record_response_diff(span, &diff);
+        Some(diff)
+    }
+}
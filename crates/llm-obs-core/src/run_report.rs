@@ -0,0 +1,281 @@
+//! Summary of a run's telemetry, for the end of batch jobs and examples.
+//!
+//! A batch job that exits cleanly may still have lost half its spans to a
+//! full export queue or an unreachable collector, and finding out means
+//! searching the backend for traces that are not there. [`RunReport`]
+//! counts what the process produced and what reached the exporter:
+//!
+//! ```text
+//! run report
+//!   spans      42 ended, 40 exported, 0 failed, 2 dropped
+//!   calls      6 LLM, 3 tool
+//!   tokens     1234 input, 567 output
+//!   cost       $0.012300 (estimated)
+//!   errors     3 (429: 2, timeout: 1)
+//! ```
+//!
+//! [`RunReportProcessor`] counts sampled spans as they end, with the rules of
+//! `processors::calls` shared with `processors::rollup` and `budget`, and [`CountingExporter`] counts the spans
+//! in export batches that succeeded or failed; spans neither exported nor
+//! failed were dropped by a full queue (or are still queued, before
+//! shutdown). `TelemetryBuilder` installs both; `Telemetry::shutdown`
+//! returns the report and `Telemetry::guard` prints or logs it on drop (see
+//! `TelemetryBuilder::with_shutdown_report`). Counts are process-wide.
+
+use crate::processors::attribute;
+use crate::processors::calls::{CallCounter, Counted};
+use opentelemetry::Context;
+use opentelemetry::trace::Span as _;
+use opentelemetry::trace::Status;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanExporter, SpanProcessor};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+/// Where a guard puts the report when telemetry shuts down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportOutput {
+    /// Printed to stderr, as in the module docs.
+    Stderr,
+    /// One `tracing` event at INFO with target `llm.run_report`, for log
+    /// pipelines. It goes through the console layer; spans are already
+    /// shut down.
+    Log,
+}
+
+/// Counts since the process started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    /// Sampled spans that ended.
+    pub spans_ended: u64,
+    pub spans_exported: u64,
+    /// Spans in batches the exporter failed to send.
+    pub spans_failed: u64,
+    pub llm_calls: u64,
+    pub tool_calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Sum of `gen_ai.usage.cost_usd`, for calls priced with `cost`.
+    pub cost_usd: f64,
+    /// Failed spans by `error.type` (`_OTHER` when unset).
+    pub errors: BTreeMap<String, u64>,
+}
+
+static REPORT: Mutex<RunReport> = Mutex::new(RunReport {
+    spans_ended: 0,
+    spans_exported: 0,
+    spans_failed: 0,
+    llm_calls: 0,
+    tool_calls: 0,
+    input_tokens: 0,
+    output_tokens: 0,
+    cost_usd: 0.0,
+    errors: BTreeMap::new(),
+});
+
+fn report() -> std::sync::MutexGuard<'static, RunReport> {
+    REPORT.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn calls() -> std::sync::MutexGuard<'static, CallCounter> {
+    static CALLS: OnceLock<Mutex<CallCounter>> = OnceLock::new();
+    CALLS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+impl RunReport {
+    /// The counts so far.
+    pub fn snapshot() -> Self {
+        report().clone()
+    }
+
+    /// Ended spans that were neither exported nor failed.
+    pub fn spans_dropped(&self) -> u64 {
+        self.spans_ended
+            .saturating_sub(self.spans_exported + self.spans_failed)
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    fn add(&mut self, span: &SpanData, counted: Counted) {
+        self.spans_ended += 1;
+        if let Status::Error { .. } = span.status {
+            let error_type = attribute(span, "error.type")
+                .map_or_else(|| "_OTHER".to_owned(), |value| value.as_str().into_owned());
+            *self.errors.entry(error_type).or_default() += 1;
+        }
+        self.llm_calls += u64::from(counted.llm_call);
+        self.tool_calls += u64::from(counted.tool_call);
+        self.input_tokens += counted.input_tokens;
+        self.output_tokens += counted.output_tokens;
+        self.cost_usd += counted.cost_usd;
+    }
+
+    /// Emits the report to `output`.
+    pub fn emit(&self, output: ReportOutput) {
+        match output {
+            ReportOutput::Stderr => eprintln!("{self}"),
+            ReportOutput::Log => tracing::info!(
+                target: "llm.run_report",
+                llm_calls = self.llm_calls,
+                tool_calls = self.tool_calls,
+                spans.ended = self.spans_ended,
+                spans.exported = self.spans_exported,
+                spans.failed = self.spans_failed,
+                spans.dropped = self.spans_dropped(),
+                gen_ai.usage.input_tokens = self.input_tokens,
+                gen_ai.usage.output_tokens = self.output_tokens,
+                gen_ai.usage.cost_usd = self.cost_usd,
+                errors = self.error_count(),
+                "Run report"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "run report")?;
+        writeln!(
+            f,
+            "  spans      {} ended, {} exported, {} failed, {} dropped",
+            self.spans_ended,
+            self.spans_exported,
+            self.spans_failed,
+            self.spans_dropped()
+        )?;
+        writeln!(
+            f,
+            "  calls      {} LLM, {} tool",
+            self.llm_calls, self.tool_calls
+        )?;
+        writeln!(
+            f,
+            "  tokens     {} input, {} output",
+            self.input_tokens, self.output_tokens
+        )?;
+        writeln!(f, "  cost       ${:.6} (estimated)", self.cost_usd)?;
+        write!(f, "  errors     {}", self.error_count())?;
+        if !self.errors.is_empty() {
+            let by_type: Vec<String> = self
+                .errors
+                .iter()
+                .map(|(error_type, count)| format!("{error_type}: {count}"))
+                .collect();
+            write!(f, " ({})", by_type.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts every span that ends into the [`RunReport`].
+#[derive(Debug)]
+pub struct RunReportProcessor<P> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> RunReportProcessor<P> {
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for RunReportProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        calls().on_start(span.span_context());
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let counted = calls().on_end(&span);
+        if span.span_context.is_sampled() {
+            report().add(&span, counted);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Counts exported and failed spans into the [`RunReport`].
+#[derive(Debug)]
+pub struct CountingExporter<E> {
+    inner: E,
+}
+
+impl<E: SpanExporter> CountingExporter<E> {
+    pub fn new(inner: E) -> Self {
+        Self { inner }
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for CountingExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let size = batch.len() as u64;
+        let result = self.inner.export(batch).await;
+        let mut report = report();
+        match &result {
+            Ok(()) => report.spans_exported += size,
+            Err(_) => report.spans_failed += size,
+        }
+        result
+    }
+
+    fn shutdown_with_timeout(&mut self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&mut self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, agent_prompt, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    #[test]
+    fn counts_an_agent_prompt_as_its_model_calls() {
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(RunReportProcessor::new(Collect::default()))
+            .build();
+        let tracer = provider.tracer("test");
+        // The report is process-wide; this is the only test adding to it.
+        let before = RunReport::snapshot();
+        let request = start(&tracer, &Context::new(), "request", Vec::new());
+        agent_prompt(&tracer, &request);
+        end(&request);
+        let after = RunReport::snapshot();
+
+        assert_eq!(after.spans_ended - before.spans_ended, 7);
+        assert_eq!(after.llm_calls - before.llm_calls, 2);
+        assert_eq!(after.tool_calls - before.tool_calls, 1);
+        assert_eq!(after.input_tokens - before.input_tokens, 30);
+        assert_eq!(after.output_tokens - before.output_tokens, 12);
+        assert!((after.cost_usd - before.cost_usd - 0.003).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,127 @@
+//! User-defined head sampling policies for LLM spans.
+//!
+//! The SDK samplers only see ratios and parent flags. Policies such as
+//! "always trace tenant X" or "drop health-check chats" need the span name
+//! and the GenAI attributes known when the span starts. [`LlmSampler`]
+//! exposes exactly that, and [`LlmSamplerAdapter`] plugs it into
+//! `SdkTracerProvider::builder().with_sampler(..)` so the init code does not
+//! have to be forked per policy.
+
+use opentelemetry::trace::{
+    SamplingDecision, SamplingResult, SpanContext, SpanKind, TraceContextExt, TraceId,
+};
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::trace::ShouldSample;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a sampler can see about a span that is about to start.
+#[derive(Debug)]
+pub struct SamplingRequest<'a> {
+    pub span_name: &'a str,
+    pub span_kind: &'a SpanKind,
+    pub trace_id: TraceId,
+    /// Attributes recorded at span creation. Fields declared `Empty` and
+    /// filled in later are not available yet.
+    pub attributes: &'a [KeyValue],
+    parent_context: Option<&'a Context>,
+}
+
+impl<'a> SamplingRequest<'a> {
+    pub fn attribute(&self, key: &str) -> Option<&'a Value> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| &attribute.value)
+    }
+
+    /// `gen_ai.*` and `llm.*` attributes, the ones policies usually key on.
+    pub fn gen_ai_attributes(&self) -> impl Iterator<Item = &'a KeyValue> {
+        self.attributes.iter().filter(|attribute| {
+            let key = attribute.key.as_str();
+            key.starts_with("gen_ai.") || key.starts_with("llm.")
+        })
+    }
+
+    pub fn parent_context(&self) -> Option<&'a Context> {
+        self.parent_context
+    }
+
+    /// The parent span, when there is a valid one (local or remote).
+    pub fn parent_span_context(&self) -> Option<SpanContext> {
+        self.parent_context
+            .filter(|context| context.has_active_span())
+            .map(|context| context.span().span_context().clone())
+            .filter(|span_context| span_context.is_valid())
+    }
+
+    /// Whether the parent was sampled; `None` for root spans.
+    pub fn parent_sampled(&self) -> Option<bool> {
+        self.parent_span_context()
+            .map(|span_context| span_context.is_sampled())
+    }
+}
+
+/// A head sampling policy with access to GenAI attributes.
+pub trait LlmSampler: Send + Sync + 'static {
+    fn sample(&self, request: &SamplingRequest<'_>) -> SamplingDecision;
+}
+
+/// Adapts an [`LlmSampler`] to the SDK's `ShouldSample`.
+pub struct LlmSamplerAdapter<S> {
+    sampler: Arc<S>,
+}
+
+impl<S: LlmSampler> LlmSamplerAdapter<S> {
+    pub fn new(sampler: S) -> Self {
+        Self {
+            sampler: Arc::new(sampler),
+        }
+    }
+}
+
+impl<S> Clone for LlmSamplerAdapter<S> {
+    fn clone(&self) -> Self {
+        Self {
+            sampler: Arc::clone(&self.sampler),
+        }
+    }
+}
+
+impl<S> fmt::Debug for LlmSamplerAdapter<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LlmSamplerAdapter")
+            .field("sampler", &std::any::type_name::<S>())
+            .finish()
+    }
+}
+
+impl<S: LlmSampler> ShouldSample for LlmSamplerAdapter<S> {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        _links: &[opentelemetry::trace::Link],
+    ) -> SamplingResult {
+        let request = SamplingRequest {
+            span_name: name,
+            span_kind,
+            trace_id,
+            attributes,
+            parent_context,
+        };
+        let trace_state = request
+            .parent_span_context()
+            .map(|span_context| span_context.trace_state().clone())
+            .unwrap_or_default();
+
+        SamplingResult {
+            decision: self.sampler.sample(&request),
+            attributes: Vec::new(),
+            trace_state,
+        }
+    }
+}
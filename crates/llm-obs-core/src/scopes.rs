@@ -0,0 +1,63 @@
+//! Instrumentation scopes per subsystem.
+//!
+//! Telemetry from this crate is split into `llm_obs::agent`,
+//! `llm_obs::tool` and `llm_obs::rag` scopes carrying the crate version, so
+//! collectors and backends can filter or route by scope. Meters are created
+//! per subsystem. `tracing` spans all go through the `llm_obs::agent` tracer
+//! and are moved to their subsystem's scope at export by
+//! `processors::scope::ScopeProcessor`, which `TelemetryBuilder` installs;
+//! a span names its subsystem with the [`SCOPE_FIELD`] field.
+
+use opentelemetry::InstrumentationScope;
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::metrics::Meter;
+
+/// `tracing` span field naming the scope a span is exported under, e.g.
+/// `otel.scope = Subsystem::Rag.scope_name()`.
+pub const SCOPE_FIELD: &str = "otel.scope";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    /// Model calls, agents and orchestration.
+    Agent,
+    /// Tool execution.
+    Tool,
+    /// Retrieval and query rewriting.
+    Rag,
+}
+
+impl Subsystem {
+    pub fn scope_name(&self) -> &'static str {
+        match self {
+            Subsystem::Agent => "llm_obs::agent",
+            Subsystem::Tool => "llm_obs::tool",
+            Subsystem::Rag => "llm_obs::rag",
+        }
+    }
+
+    /// The subsystem whose [`Subsystem::scope_name`] is `name`.
+    pub fn from_scope_name(name: &str) -> Option<Self> {
+        [Subsystem::Agent, Subsystem::Tool, Subsystem::Rag]
+            .into_iter()
+            .find(|subsystem| subsystem.scope_name() == name)
+    }
+
+    pub fn scope(&self) -> InstrumentationScope {
+        InstrumentationScope::builder(self.scope_name())
+            .with_version(env!("CARGO_PKG_VERSION"))
+            .build()
+    }
+
+    /// Tracer from the global provider for spans created with the OTel API.
+    pub fn tracer(&self) -> BoxedTracer {
+        global::tracer_with_scope(self.scope())
+    }
+
+    /// Meter from the global provider. The crate builds each instrument once
+    /// and caches it for the life of the process, so the meter provider must
+    /// be installed (e.g. with `metrics::init`) before the first instrumented
+    /// call; instruments created earlier stay no-ops.
+    pub fn meter(&self) -> Meter {
+        global::meter_with_scope(self.scope())
+    }
+}
@@ -0,0 +1,9 @@
+//! Attribute keys shared by the instrumentation in this crate.
+//!
+//! Keys follow the OpenTelemetry GenAI semantic conventions where one exists;
+//! `llm.*` keys are crate-specific extensions.
+
+pub const GEN_AI_CONVERSATION_ID: &str = "gen_ai.conversation.id";
+pub const SESSION_ID: &str = "session.id";
+pub const USER_ID: &str = "user.id";
+pub const TENANT_ID: &str = "tenant.id";
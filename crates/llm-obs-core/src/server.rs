@@ -0,0 +1,206 @@
+//! Request-scoped trace roots for axum (or any tower) services.
+//!
+//! Agent orchestration in a web service should hang off the HTTP request
+//! that caused it, and that request off the caller's trace when there is
+//! one. [`ServerSpanLayer`] wraps a service so every request runs inside a
+//! SERVER span (see [`start_server_span`]) that:
+//!
+//! - continues the incoming `traceparent` / baggage (see
+//!   [`crate::propagation`]), minus a caller-supplied `tenant.id`: tenants
+//!   are routed to their own backends (see `tenancy`), so only server code
+//!   may set one;
+//! - is named `{method} {route}` after axum's matched route template, or
+//!   `unknown` when no route matched, so the name stays low-cardinality;
+//! - carries `url.path`, `server.address`, `user_agent.original`,
+//!   `network.protocol.version` and `http.response.status_code`;
+//! - has error status and `error.type` set to the status code for 5xx
+//!   responses. 4xx responses are the client's error and are left unset.
+//!
+//! Handlers reach the span as `tracing::Span::current()`, so anything they
+//! record or start (agent calls, tools, retrieval) nests under the request:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/chat", post(chat))
+//!     .layer(ServerSpanLayer::new().with_trace_id_header(true));
+//! ```
+//!
+//! The request's headers stay readable through [`request_headers`] while
+//! the request is handled, e.g. by span enrichers (see
+//! [`crate::enrichment`]).
+//!
+//! Added with `Router::layer` the layer runs after routing and sees the
+//! matched route; with `Router::route_layer` unmatched requests bypass it.
+
+use crate::propagation::{HeaderExtractor, extract, without_baggage};
+use crate::semconv::TENANT_ID;
+use crate::spans::start_server_span;
+use crate::telemetry::Telemetry;
+use axum::extract::MatchedPath;
+use http::header::{HOST, USER_AGENT};
+use http::{HeaderMap, HeaderValue, Request, Response, Version};
+use opentelemetry::trace::Status;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Route recorded when the request matched no route.
+pub const UNKNOWN_ROUTE: &str = "unknown";
+
+tokio::task_local! {
+    static REQUEST_HEADERS: HeaderMap;
+}
+
+/// Runs `f` on the headers of the request being handled, or returns `None`
+/// outside a [`ServerSpanService`] request or in a task it spawned.
+pub fn request_headers<R>(f: impl FnOnce(&HeaderMap) -> R) -> Option<R> {
+    REQUEST_HEADERS.try_with(f).ok()
+}
+
+/// Layer producing [`ServerSpanService`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServerSpanLayer {
+    trace_id_header: bool,
+    inbound_tenant: bool,
+}
+
+impl ServerSpanLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether responses carry the trace id header (see
+    /// [`Telemetry::trace_id_header`]), so clients can quote it in bug
+    /// reports. Off by default.
+    pub fn with_trace_id_header(mut self, enabled: bool) -> Self {
+        self.trace_id_header = enabled;
+        self
+    }
+
+    /// Whether a `tenant.id` baggage entry sent by the caller is kept. Only
+    /// for services reached solely through other services of the platform,
+    /// which set the tenant themselves. Off by default.
+    pub fn with_inbound_tenant(mut self, enabled: bool) -> Self {
+        self.inbound_tenant = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for ServerSpanLayer {
+    type Service = ServerSpanService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ServerSpanService {
+            inner,
+            trace_id_header: self.trace_id_header,
+            inbound_tenant: self.inbound_tenant,
+        }
+    }
+}
+
+/// Runs each request of `inner` in its own server span.
+#[derive(Debug, Clone)]
+pub struct ServerSpanService<S> {
+    inner: S,
+    trace_id_header: bool,
+    inbound_tenant: bool,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ServerSpanService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let span = request_span(&request, self.inbound_tenant);
+        let headers = request.headers().clone();
+        let future = REQUEST_HEADERS.sync_scope(headers.clone(), || {
+            span.in_scope(|| self.inner.call(request))
+        });
+        let trace_id_header = self.trace_id_header;
+        Box::pin(async move {
+            let result = REQUEST_HEADERS
+                .scope(headers, future.instrument(span.clone()))
+                .await;
+            if let Ok(response) = &result {
+                let status = response.status();
+                span.record("http.response.status_code", status.as_u16());
+                if status.is_server_error() {
+                    span.record("error.type", status.as_str());
+                    span.set_status(Status::error(status.to_string()));
+                }
+            }
+            let mut response = result?;
+            if trace_id_header {
+                if let Some((name, value)) = span.in_scope(Telemetry::trace_id_header) {
+                    if let Ok(value) = HeaderValue::from_str(&value) {
+                        response.headers_mut().insert(name, value);
+                    }
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// `host` without a trailing `:port`; bracketed IPv6 addresses keep their
+/// colons.
+fn without_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((address, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => address,
+        _ => host,
+    }
+}
+
+fn request_span<B>(request: &Request<B>, inbound_tenant: bool) -> tracing::Span {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNKNOWN_ROUTE, MatchedPath::as_str);
+    let span = start_server_span(request.method().as_str(), route);
+    let remote = extract(&HeaderExtractor(request.headers()));
+    span.set_parent(if inbound_tenant {
+        remote
+    } else {
+        without_baggage(&remote, TENANT_ID)
+    });
+
+    span.set_attribute("url.path", request.uri().path().to_owned());
+    let host = request
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().host());
+    if let Some(host) = host {
+        span.set_attribute("server.address", without_port(host).to_owned());
+    }
+    if let Some(user_agent) = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+    {
+        span.set_attribute("user_agent.original", user_agent.to_owned());
+    }
+    let version = match request.version() {
+        Version::HTTP_09 => "0.9",
+        Version::HTTP_10 => "1.0",
+        Version::HTTP_11 => "1.1",
+        Version::HTTP_2 => "2",
+        Version::HTTP_3 => "3",
+        _ => "_OTHER",
+    };
+    span.set_attribute("network.protocol.version", version);
+    span
+}
@@ -0,0 +1,120 @@
+//! Flushing for serverless and other short-lived processes.
+//!
+//! On AWS Lambda or Cloud Run the process is frozen between invocations and
+//! may be killed without warning, so the batch span processor's and the
+//! periodic metric reader's timers often never fire: telemetry sits in
+//! memory and is lost. `ServerlessTelemetry` flushes spans and metrics on an
+//! aggressive cadence while the process runs and synchronously when an
+//! invocation guard drops, before the runtime can freeze the process.
+
+use anyhow::Context;
+#[cfg(feature = "metrics")]
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Default background flush cadence, well below typical invocation times.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ServerlessTelemetry {
+    tracer_provider: SdkTracerProvider,
+    #[cfg(feature = "metrics")]
+    meter_provider: Option<SdkMeterProvider>,
+    flush_interval: Duration,
+}
+
+impl ServerlessTelemetry {
+    pub fn new(tracer_provider: SdkTracerProvider) -> Self {
+        Self {
+            tracer_provider,
+            #[cfg(feature = "metrics")]
+            meter_provider: None,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+        }
+    }
+
+    /// Also flushes `meter_provider`, so metrics aggregated in memory are
+    /// exported with each invocation rather than on the reader's interval.
+    #[cfg(feature = "metrics")]
+    pub fn with_meter_provider(mut self, meter_provider: SdkMeterProvider) -> Self {
+        self.meter_provider = Some(meter_provider);
+        self
+    }
+
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Synchronously exports every finished span and the current metric
+    /// aggregates.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        self.tracer_provider
+            .force_flush()
+            .context("Failed to flush spans")?;
+        #[cfg(feature = "metrics")]
+        if let Some(meter_provider) = &self.meter_provider {
+            meter_provider
+                .force_flush()
+                .context("Failed to flush metrics")?;
+        }
+        Ok(())
+    }
+
+    /// Starts one invocation; the returned guard flushes when dropped, so
+    /// keep it alive until the handler has produced its response.
+    pub fn invocation(&self) -> InvocationGuard {
+        InvocationGuard {
+            telemetry: self.clone(),
+        }
+    }
+
+    /// Runs `handler` as one invocation and flushes before returning.
+    pub async fn run_invocation<F: Future>(&self, handler: F) -> F::Output {
+        let output = handler.await;
+        let telemetry = self.clone();
+        let flushed = tokio::task::spawn_blocking(move || telemetry.flush()).await;
+        match flushed {
+            Ok(Err(error)) => {
+                tracing::warn!("Failed to flush telemetry after invocation: {error:#}");
+            }
+            Err(error) => tracing::warn!("Telemetry flush task failed: {error}"),
+            Ok(Ok(())) => {}
+        }
+        output
+    }
+
+    /// Flushes every `flush_interval` until the task is aborted, covering
+    /// long invocations that could be killed before their guard drops.
+    pub fn spawn_periodic_flush(&self) -> JoinHandle<()> {
+        let telemetry = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(telemetry.flush_interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                // The SDK flush blocks until the exporter answers.
+                let flushing = telemetry.clone();
+                if let Ok(Err(error)) = tokio::task::spawn_blocking(move || flushing.flush()).await
+                {
+                    tracing::debug!(error = %format!("{error:#}"), "Periodic telemetry flush failed");
+                }
+            }
+        })
+    }
+}
+
+pub struct InvocationGuard {
+    telemetry: ServerlessTelemetry,
+}
+
+impl Drop for InvocationGuard {
+    fn drop(&mut self) {
+        if let Err(error) = self.telemetry.flush() {
+            tracing::warn!("Failed to flush telemetry after invocation: {error:#}");
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Semconv-compliant GenAI spans for code the wrappers do not cover.
+//!
+//! Hand-written `info_span!` calls tend to drift: a static name instead of
+//! `{operation} {model}`, a missing `gen_ai.operation.name`, or the default
+//! INTERNAL kind on what is really an outbound provider call. These helpers
+//! create spans with the GenAI semantic-convention name, kind and the usual
+//! attributes pre-declared, so they can be filled in with `span.record(..)`
+//! once the response arrives.
+//!
+//! Several backends build their service maps and latency views from span
+//! kind, so inbound requests use SERVER and queue hops PRODUCER/CONSUMER.
+
+use crate::scopes::Subsystem;
+use tracing::field::Empty;
+
+/// Constructors for GenAI spans on `tracing::Span`, e.g.
+/// `tracing::Span::start_llm_span("chat", "gemini-2.5-flash")`.
+pub trait SpanBuilderExt: Sized {
+    /// CLIENT span for a model call, named `{operation} {model}`.
+    fn start_llm_span(operation: &str, model: &str) -> Self;
+
+    /// INTERNAL span for a tool invocation, named `execute_tool {name}`.
+    fn start_tool_span(name: &str) -> Self;
+}
+
+impl SpanBuilderExt for tracing::Span {
+    fn start_llm_span(operation: &str, model: &str) -> Self {
+        start_llm_span(operation, model)
+    }
+
+    fn start_tool_span(name: &str) -> Self {
+        start_tool_span(name)
+    }
+}
+
+/// See [`SpanBuilderExt::start_llm_span`].
+pub fn start_llm_span(operation: &str, model: &str) -> tracing::Span {
+    tracing::info_span!(
+        "gen_ai.client",
+        otel.name = format!("{operation} {model}"),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        gen_ai.operation.name = operation,
+        gen_ai.request.model = model,
+        gen_ai.provider.name = Empty,
+        gen_ai.conversation.id = Empty,
+        gen_ai.request.max_tokens = Empty,
+        gen_ai.request.temperature = Empty,
+        gen_ai.response.id = Empty,
+        gen_ai.response.model = Empty,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        server.address = Empty,
+        server.port = Empty,
+        error.type = Empty,
+    )
+}
+
+/// See [`SpanBuilderExt::start_tool_span`].
+pub fn start_tool_span(name: &str) -> tracing::Span {
+    tracing::info_span!(
+        "gen_ai.tool",
+        otel.name = format!("execute_tool {name}"),
+        otel.kind = "internal",
+        otel.status_code = Empty,
+        gen_ai.operation.name = "execute_tool",
+        gen_ai.tool.name = name,
+        gen_ai.tool.call.id = Empty,
+        gen_ai.tool.type = Empty,
+        error.type = Empty,
+    )
+}
+
+/// INTERNAL span grouping the tool calls of one model turn, named
+/// `execute_tools`; see [`crate::tool_batch`].
+pub fn start_tool_batch_span(size: usize) -> tracing::Span {
+    tracing::info_span!(
+        "gen_ai.tool_batch",
+        otel.name = "execute_tools",
+        otel.kind = "internal",
+        otel.scope = Subsystem::Tool.scope_name(),
+        otel.status_code = Empty,
+        llm.tool.batch.size = size as u64,
+        llm.tool.batch.mode = Empty,
+        llm.tool.batch.max_in_flight = Empty,
+        llm.tool.batch.failed = Empty,
+        llm.tool.batch.skipped = Empty,
+    )
+}
+
+/// INTERNAL span around concurrent agent branches, named `fanout`; see
+/// [`crate::fanout`].
+pub fn start_fanout_span(size: usize) -> tracing::Span {
+    tracing::info_span!(
+        "agent.fanout",
+        otel.name = "fanout",
+        otel.kind = "internal",
+        otel.status_code = Empty,
+        llm.fanout.size = size as u64,
+        llm.fanout.failed = Empty,
+        llm.fanout.slowest_branch = Empty,
+        error.type = Empty,
+    )
+}
+
+/// INTERNAL span for one branch of a fan-out, named `fanout_branch {name}`.
+pub fn start_fanout_branch_span(name: &str, index: usize) -> tracing::Span {
+    tracing::info_span!(
+        "agent.fanout.branch",
+        otel.name = format!("fanout_branch {name}"),
+        otel.kind = "internal",
+        otel.status_code = Empty,
+        llm.fanout.branch.name = name,
+        llm.fanout.branch.index = index as u64,
+        error.type = Empty,
+    )
+}
+
+/// Root INTERNAL span for a sub-agent run in its own trace, named
+/// `invoke_agent {name}`; see [`crate::sub_agent`].
+pub fn start_sub_agent_span(name: &str) -> tracing::Span {
+    tracing::info_span!(
+        parent: None,
+        "gen_ai.agent",
+        otel.name = format!("invoke_agent {name}"),
+        otel.kind = "internal",
+        otel.status_code = Empty,
+        gen_ai.operation.name = "invoke_agent",
+        gen_ai.agent.name = name,
+        error.type = Empty,
+    )
+}
+
+/// Root INTERNAL span marking the start of a conversation, named
+/// `chat_session`; see [`crate::chat_session`].
+pub fn start_chat_session_span(conversation_id: &str) -> tracing::Span {
+    tracing::info_span!(
+        parent: None,
+        "gen_ai.session",
+        otel.name = "chat_session",
+        otel.kind = "internal",
+        gen_ai.conversation.id = conversation_id,
+        llm.session.resumed = Empty,
+    )
+}
+
+/// INTERNAL span for one conversation turn, named `chat_turn`.
+pub fn start_chat_turn_span(conversation_id: &str, index: u64) -> tracing::Span {
+    tracing::info_span!(
+        "gen_ai.turn",
+        otel.name = "chat_turn",
+        otel.kind = "internal",
+        otel.status_code = Empty,
+        gen_ai.conversation.id = conversation_id,
+        llm.session.turn_index = index,
+        gen_ai.usage.input_tokens = Empty,
+        gen_ai.usage.output_tokens = Empty,
+        llm.session.cumulative.input_tokens = Empty,
+        llm.session.cumulative.output_tokens = Empty,
+        llm.session.cumulative.total_tokens = Empty,
+        error.type = Empty,
+    )
+}
+
+/// SERVER span for an inbound HTTP request, named `{method} {route}`.
+pub fn start_server_span(method: &str, route: &str) -> tracing::Span {
+    tracing::info_span!(
+        "http.server",
+        otel.name = format!("{method} {route}"),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = method,
+        http.route = route,
+        http.response.status_code = Empty,
+        error.type = Empty,
+    )
+}
+
+/// CLIENT span for an outbound HTTP request, named `{method}`.
+pub fn start_http_client_span(method: &str, url: &str) -> tracing::Span {
+    tracing::info_span!(
+        "http.client",
+        otel.name = method,
+        otel.kind = "client",
+        otel.status_code = Empty,
+        http.request.method = method,
+        url.full = url,
+        server.address = Empty,
+        server.port = Empty,
+        http.response.status_code = Empty,
+        error.type = Empty,
+    )
+}
+
+/// PRODUCER span for enqueueing work, named `send {destination}`.
+pub fn start_producer_span(system: &str, destination: &str) -> tracing::Span {
+    tracing::info_span!(
+        "messaging.producer",
+        otel.name = format!("send {destination}"),
+        otel.kind = "producer",
+        otel.status_code = Empty,
+        messaging.system = system,
+        messaging.operation.type = "send",
+        messaging.destination.name = destination,
+        messaging.message.id = Empty,
+        error.type = Empty,
+    )
+}
+
+/// CONSUMER span for processing dequeued work, named
+/// `process {destination}`.
+pub fn start_consumer_span(system: &str, destination: &str) -> tracing::Span {
+    tracing::info_span!(
+        "messaging.consumer",
+        otel.name = format!("process {destination}"),
+        otel.kind = "consumer",
+        otel.status_code = Empty,
+        messaging.system = system,
+        messaging.operation.type = "process",
+        messaging.destination.name = destination,
+        messaging.message.id = Empty,
+        error.type = Empty,
+    )
+}
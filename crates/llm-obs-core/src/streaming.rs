@@ -0,0 +1,131 @@
+//! Streaming completion timing: time to first token, throughput and gaps.
+//!
+//! For chat UIs the total duration matters less than how long the user stared
+//! at an empty bubble and whether the text then flowed or stuttered.
+//! [`StreamRecorder`] timestamps each chunk and records
+//! `llm.stream.time_to_first_token_ms`, chunk count, inter-chunk latency and
+//! tokens per second on the span when the stream ends, plus an
+//! `llm.stream.progress` event every N chunks so long streams show where they
+//! stalled. Adapter crates drive the recorder from their framework's stream,
+//! e.g. `llm-obs-rig`'s `InstrumentedStream`.
+
+use opentelemetry::KeyValue;
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Chunks between `llm.stream.progress` events.
+pub const DEFAULT_EVENT_EVERY: u64 = 20;
+
+#[derive(Debug)]
+pub struct StreamRecorder {
+    span: tracing::Span,
+    event_every: u64,
+    started: Instant,
+    first_chunk: Option<Duration>,
+    last_chunk: Option<Instant>,
+    chunk_count: u64,
+    total_gap: Duration,
+    max_gap: Duration,
+}
+
+impl StreamRecorder {
+    /// Starts timing now; call it right before sending the request.
+    pub fn new(span: tracing::Span) -> Self {
+        Self {
+            span,
+            event_every: DEFAULT_EVENT_EVERY,
+            started: Instant::now(),
+            first_chunk: None,
+            last_chunk: None,
+            chunk_count: 0,
+            total_gap: Duration::ZERO,
+            max_gap: Duration::ZERO,
+        }
+    }
+
+    /// `0` disables progress events.
+    pub fn with_event_every(mut self, event_every: u64) -> Self {
+        self.event_every = event_every;
+        self
+    }
+
+    pub fn chunk_count(&self) -> u64 {
+        self.chunk_count
+    }
+
+    pub fn time_to_first_token(&self) -> Option<Duration> {
+        self.first_chunk
+    }
+
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Records the arrival of one content chunk.
+    pub fn on_chunk(&mut self) {
+        let now = Instant::now();
+        match self.last_chunk {
+            Some(last) => {
+                let gap = now - last;
+                self.total_gap += gap;
+                self.max_gap = self.max_gap.max(gap);
+            }
+            None => self.first_chunk = Some(now - self.started),
+        }
+        self.last_chunk = Some(now);
+        self.chunk_count += 1;
+
+        if self.event_every > 0 && self.chunk_count % self.event_every == 0 {
+            self.span.add_event(
+                "llm.stream.progress",
+                vec![
+                    KeyValue::new("llm.stream.chunk_count", self.chunk_count as i64),
+                    KeyValue::new("llm.stream.elapsed_ms", duration_ms(now - self.started)),
+                ],
+            );
+        }
+    }
+
+    /// Records the summary attributes. `output_tokens` comes from the final
+    /// usage when the provider reports it; without it only chunk throughput
+    /// is recorded.
+    pub fn finish(self, output_tokens: Option<u64>) {
+        let span = &self.span;
+        span.set_attribute("llm.stream.chunk_count", self.chunk_count as i64);
+        let Some(first_chunk) = self.first_chunk else {
+            return;
+        };
+        span.set_attribute(
+            "llm.stream.time_to_first_token_ms",
+            duration_ms(first_chunk),
+        );
+        if self.chunk_count > 1 {
+            let gaps = (self.chunk_count - 1) as f64;
+            span.set_attribute(
+                "llm.stream.mean_inter_chunk_ms",
+                duration_ms(self.total_gap) / gaps,
+            );
+            span.set_attribute("llm.stream.max_inter_chunk_ms", duration_ms(self.max_gap));
+        }
+
+        // Throughput after the first token, so queueing and prompt processing
+        // do not count against generation speed.
+        let generating = self.total_gap.as_secs_f64();
+        if generating > 0.0 {
+            span.set_attribute(
+                "llm.stream.chunks_per_second",
+                (self.chunk_count - 1) as f64 / generating,
+            );
+            if let Some(output_tokens) = output_tokens {
+                span.set_attribute(
+                    "llm.stream.tokens_per_second",
+                    output_tokens as f64 / generating,
+                );
+            }
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,103 @@
+//! Sub-agents in traces of their own, linked to the orchestrator.
+//!
+//! Nesting every sub-agent under the orchestrator's span works while they
+//! run inside the orchestrator's request. Once sub-agents are picked up by
+//! other tasks or workers from a queue, possibly long after the
+//! orchestrator's span ended, one trace stretches over minutes, its
+//! critical path stops meaning anything and tail samplers hold it open.
+//! [`AgentHandoff`] captures the orchestrator's context instead; each
+//! sub-agent then runs in a root `invoke_agent {name}` span starting a new
+//! trace, with a span link (`llm.link.type = orchestrator`) back to the
+//! orchestrator and the orchestrator's baggage. Backends that follow links
+//! navigate from either side.
+//!
+//! ```ignore
+//! let handoff = AgentHandoff::current();
+//! tokio::spawn(handoff.instrument("writer", async move {
+//!     writer.prompt(plan).await
+//! }));
+//! ```
+//!
+//! Across a queue, write the handoff into the message headers with
+//! [`AgentHandoff::inject`] and read it back with [`AgentHandoff::extract`].
+
+use crate::propagation;
+use crate::spans::start_sub_agent_span;
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use opentelemetry::{Context, KeyValue, global};
+use std::future::Future;
+use tracing::Instrument;
+use tracing::instrument::Instrumented;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Link attribute marking the link from a sub-agent to its orchestrator.
+pub const LINK_TYPE_ORCHESTRATOR: &str = "orchestrator";
+
+/// The orchestrator context sub-agents link back to. Cheap to clone and
+/// `Send`, so it can move into spawned tasks.
+#[derive(Debug, Clone)]
+pub struct AgentHandoff {
+    orchestrator: Context,
+}
+
+impl AgentHandoff {
+    /// Hands off from the current span.
+    pub fn current() -> Self {
+        Self::from_span(&tracing::Span::current())
+    }
+
+    pub fn from_span(span: &tracing::Span) -> Self {
+        Self::from_context(span.context())
+    }
+
+    pub fn from_context(orchestrator: Context) -> Self {
+        Self { orchestrator }
+    }
+
+    /// Writes the orchestrator's context into `injector`, e.g. the headers
+    /// of a queued message, using the global propagator.
+    pub fn inject(&self, injector: &mut dyn Injector) {
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&self.orchestrator, injector)
+        });
+    }
+
+    /// The handoff carried by `extractor`; without one, sub-agent spans
+    /// start unlinked traces.
+    pub fn extract(extractor: &dyn Extractor) -> Self {
+        Self::from_context(propagation::extract(extractor))
+    }
+
+    pub fn orchestrator(&self) -> SpanContext {
+        self.orchestrator.span().span_context().clone()
+    }
+
+    /// Starts the root span of sub-agent `agent_name` in a new trace,
+    /// linked to the orchestrator and carrying its baggage.
+    pub fn start_span(&self, agent_name: &str) -> tracing::Span {
+        let span = start_sub_agent_span(agent_name);
+        // No span in the parent context starts a new trace.
+        let baggage: Baggage = self
+            .orchestrator
+            .baggage()
+            .iter()
+            .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+            .collect();
+        span.set_parent(Context::new().with_baggage(baggage));
+        let orchestrator = self.orchestrator();
+        if orchestrator.is_valid() {
+            span.add_link_with_attributes(
+                orchestrator,
+                vec![KeyValue::new("llm.link.type", LINK_TYPE_ORCHESTRATOR)],
+            );
+        }
+        span
+    }
+
+    /// Runs `future` as sub-agent `agent_name`, in [`start_span`](Self::start_span).
+    pub fn instrument<F: Future>(&self, agent_name: &str, future: F) -> Instrumented<F> {
+        future.instrument(self.start_span(agent_name))
+    }
+}
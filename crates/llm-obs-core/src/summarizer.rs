@@ -0,0 +1,189 @@
+//! Periodic, trace-linked summaries of long-running sessions.
+//!
+//! Reading a 200-turn conversation span by span is slow during an incident.
+//! [`SessionSummarizer`] keeps a rolling transcript of each active session
+//! and, on an interval, asks a cheap model for a summary of sessions that
+//! changed. Each summary is emitted as a `llm.session.summary` log event in a
+//! `session.summarize` span that links back to the session's latest span, so
+//! the backend can jump from the summary to the conversation and back.
+
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SummarizerConfig {
+    /// How often changed sessions are summarized.
+    pub interval: Duration,
+    /// Sessions idle for longer than this are summarized one last time and
+    /// then forgotten.
+    pub idle_timeout: Duration,
+    /// Transcript messages kept per session; older ones are dropped first.
+    pub max_messages: usize,
+    /// New messages required before a session is summarized again.
+    pub min_new_messages: usize,
+}
+
+impl Default for SummarizerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            idle_timeout: Duration::from_secs(30 * 60),
+            max_messages: 200,
+            min_new_messages: 4,
+        }
+    }
+}
+
+struct SessionState {
+    transcript: Vec<String>,
+    unsummarized: usize,
+    last_span: Option<SpanContext>,
+    last_activity: Instant,
+}
+
+/// Work item handed to the summarization model.
+struct PendingSummary {
+    session_id: String,
+    transcript: String,
+    last_span: Option<SpanContext>,
+    expired: bool,
+}
+
+#[derive(Clone)]
+pub struct SessionSummarizer {
+    config: SummarizerConfig,
+    sessions: Arc<Mutex<HashMap<String, SessionState>>>,
+}
+
+impl SessionSummarizer {
+    pub fn new(config: SummarizerConfig) -> Self {
+        Self {
+            config,
+            sessions: Arc::default(),
+        }
+    }
+
+    /// Appends a message to the session transcript and remembers the
+    /// current span as the session's latest span.
+    pub fn observe(&self, session_id: &str, role: &str, text: &str) {
+        let span_context = tracing::Span::current()
+            .context()
+            .span()
+            .span_context()
+            .clone();
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let state = sessions
+            .entry(session_id.to_owned())
+            .or_insert_with(|| SessionState {
+                transcript: Vec::new(),
+                unsummarized: 0,
+                last_span: None,
+                last_activity: Instant::now(),
+            });
+        state.transcript.push(format!("{role}: {text}"));
+        if state.transcript.len() > self.config.max_messages {
+            let excess = state.transcript.len() - self.config.max_messages;
+            state.transcript.drain(..excess);
+        }
+        state.unsummarized += 1;
+        state.last_activity = Instant::now();
+        if span_context.is_valid() {
+            state.last_span = Some(span_context);
+        }
+    }
+
+    pub fn active_sessions(&self) -> usize {
+        self.sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Summarizes changed sessions every `interval` until the task is
+    /// dropped. Spawn it with `tokio::spawn` and abort it on shutdown.
+    ///
+    /// `summarize` receives the session ID and the transcript and calls the
+    /// summarization model; its spans nest under `session.summarize`.
+    pub async fn run<F, Fut>(self, summarize: F)
+    where
+        F: Fn(String, String) -> Fut,
+        Fut: Future<Output = anyhow::Result<String>>,
+    {
+        let mut interval = tokio::time::interval(self.config.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            for pending in self.take_pending() {
+                summarize_session(pending, &summarize).await;
+            }
+        }
+    }
+
+    /// Collects sessions due for a summary and evicts idle ones.
+    fn take_pending(&self) -> Vec<PendingSummary> {
+        let now = Instant::now();
+        let mut sessions = self.sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut pending = Vec::new();
+        sessions.retain(|session_id, state| {
+            let expired = now.duration_since(state.last_activity) > self.config.idle_timeout;
+            let due = state.unsummarized >= self.config.min_new_messages
+                || (expired && state.unsummarized > 0);
+            if due {
+                state.unsummarized = 0;
+                pending.push(PendingSummary {
+                    session_id: session_id.clone(),
+                    transcript: state.transcript.join("\n"),
+                    last_span: state.last_span.clone(),
+                    expired,
+                });
+            }
+            !expired
+        });
+        pending
+    }
+}
+
+async fn summarize_session<F, Fut>(pending: PendingSummary, summarize: &F)
+where
+    F: Fn(String, String) -> Fut,
+    Fut: Future<Output = anyhow::Result<String>>,
+{
+    // A root span: the summary is not part of any request's latency.
+    let span = tracing::info_span!(
+        parent: None,
+        "session.summarize",
+        session.id = %pending.session_id,
+        llm.session.transcript_chars = pending.transcript.chars().count() as u64,
+        llm.session.final_summary = pending.expired,
+    );
+    if let Some(last_span) = pending.last_span {
+        span.add_link(last_span);
+    }
+
+    let session_id = pending.session_id;
+    let result = summarize(session_id.clone(), pending.transcript)
+        .instrument(span.clone())
+        .await;
+    let _entered = span.enter();
+    match result {
+        Ok(summary) => tracing::info!(
+            target: "llm.session.summary",
+            summary = %summary,
+            session.id = %session_id,
+            "Session summary"
+        ),
+        Err(error) => {
+            span.set_attribute("error.type", "summarization_failed");
+            tracing::warn!(
+                session.id = %session_id,
+                error = %format!("{error:#}"),
+                "Failed to summarize session"
+            );
+        }
+    }
+}
@@ -0,0 +1,959 @@
+//! Handle to the installed telemetry pipeline, and (with `otlp-grpc` or
+//! `otlp-http`) the bootstrap that installs it: [`init`] for the defaults,
+//! [`TelemetryBuilder`] to configure the service name, endpoint, sampler and
+//! subscriber layers.
+
+#[cfg(feature = "otlp")]
+use crate::budget::BudgetProcessor;
+#[cfg(feature = "otlp")]
+use crate::clock_skew::{self, ClockSkew};
+#[cfg(feature = "otlp")]
+use crate::console_exporter::ConsoleSpanExporter;
+#[cfg(feature = "otlp")]
+use crate::console_format::TraceIdFormat;
+#[cfg(feature = "otlp")]
+use crate::deterministic::Clock;
+#[cfg(feature = "otlp")]
+use crate::enrichment::{EnrichmentLayer, SpanEnricher};
+#[cfg(feature = "otlp")]
+use crate::error::TelemetryError;
+#[cfg(feature = "otlp")]
+use crate::kill_switch::{self, KillSwitchSampler};
+#[cfg(feature = "otlp")]
+use crate::otlp_config::{OtlpEndpoints, OtlpProtocol, Signal, SignalEndpoint, span_exporter};
+#[cfg(feature = "otlp")]
+use crate::processors::baggage::BaggageProcessor;
+#[cfg(feature = "otlp")]
+use crate::processors::capture::{ContentCapturePolicy, ContentCaptureProcessor};
+#[cfg(feature = "otlp")]
+use crate::processors::clock::{ClockLayer, ClockProcessor};
+#[cfg(feature = "otlp")]
+use crate::processors::compat::{SemconvCompat, SemconvCompatProcessor};
+#[cfg(feature = "otlp")]
+use crate::processors::dedup::{ClientSpanDedupProcessor, ClientSpanWinner};
+#[cfg(all(feature = "otlp", feature = "logs"))]
+use crate::processors::event_logs::otlp_logger_provider_with_attributes;
+#[cfg(feature = "otlp")]
+use crate::processors::scope::ScopeProcessor;
+#[cfg(all(feature = "otlp", feature = "redaction"))]
+use crate::processors::scrub::RedactingSpanProcessor;
+#[cfg(feature = "otlp")]
+use crate::processors::tail::{TailSamplingConfig, TailSamplingProcessor};
+#[cfg(feature = "otlp")]
+use crate::replay::ReplayTimestampProcessor;
+#[cfg(feature = "otlp")]
+use crate::run_report::{CountingExporter, RunReportProcessor};
+use crate::run_report::{ReportOutput, RunReport};
+#[cfg(feature = "otlp")]
+use crate::scopes::Subsystem;
+#[cfg(feature = "otlp")]
+use crate::tenancy::TenantRouter;
+#[cfg(feature = "otlp")]
+use opentelemetry::propagation::{TextMapCompositePropagator, TextMapPropagator};
+#[cfg(feature = "otlp")]
+use opentelemetry::trace::{Link, SamplingResult, SpanId, SpanKind, TracerProvider};
+use opentelemetry::trace::{TraceContextExt, TraceId};
+#[cfg(feature = "otlp")]
+use opentelemetry::{KeyValue, global};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::Resource;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::error::OTelSdkResult;
+#[cfg(feature = "logs")]
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+#[cfg(feature = "otlp")]
+use opentelemetry_sdk::trace::{
+    BatchSpanProcessor, IdGenerator, SdkTracer, ShouldSample, SpanData, SpanProcessor,
+};
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+#[cfg(feature = "otlp")]
+use std::sync::{Arc, Mutex, PoisonError};
+#[cfg(feature = "otlp")]
+use std::time::Duration;
+#[cfg(feature = "otlp")]
+use tracing::level_filters::LevelFilter;
+#[cfg(feature = "otlp")]
+use tracing_log::{AsLog, LogTracer};
+#[cfg(feature = "otlp")]
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+#[cfg(feature = "otlp")]
+use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, registry::LookupSpan};
+
+/// Response header carrying the trace id, so users and support staff can
+/// quote the exact trace of a request.
+pub const TRACE_ID_HEADER: &str = "x-trace-id";
+
+/// Sampling state of the current span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingDecision {
+    /// Recorded and exported.
+    RecordAndSample,
+    /// Recorded in process but not exported.
+    RecordOnly,
+    /// Not sampled; attributes recorded on it are discarded.
+    Drop,
+    /// No OpenTelemetry span is active (or the `tracing` span is disabled).
+    NoActiveSpan,
+}
+
+/// Trace UI that deep links are generated for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceBackend {
+    /// SigNoz UI, for example `http://localhost:8080`.
+    SigNoz { base_url: String },
+    /// Jaeger UI, for example `http://localhost:16686`.
+    Jaeger { base_url: String },
+    /// Grafana Explore with a Tempo data source, addressed by its UID.
+    Grafana {
+        base_url: String,
+        datasource_uid: String,
+    },
+    /// Any other UI; `{trace_id}` in the template is replaced with the
+    /// hex trace id.
+    Template(String),
+}
+
+impl TraceBackend {
+    pub fn trace_url(&self, trace_id: TraceId) -> String {
+        let trace_id = trace_id.to_string();
+        match self {
+            TraceBackend::SigNoz { base_url } => {
+                format!("{}/trace/{trace_id}", base_url.trim_end_matches('/'))
+            }
+            TraceBackend::Jaeger { base_url } => {
+                format!("{}/trace/{trace_id}", base_url.trim_end_matches('/'))
+            }
+            TraceBackend::Grafana {
+                base_url,
+                datasource_uid,
+            } => {
+                let pane = serde_json::json!({
+                    "datasource": datasource_uid,
+                    "queries": [{
+                        "refId": "A",
+                        "datasource": { "type": "tempo", "uid": datasource_uid },
+                        "queryType": "traceql",
+                        "query": trace_id,
+                    }],
+                });
+                format!(
+                    "{}/explore?left={}",
+                    base_url.trim_end_matches('/'),
+                    percent_encode(&pane.to_string())
+                )
+            }
+            TraceBackend::Template(template) => template.replace("{trace_id}", &trace_id),
+        }
+    }
+}
+
+/// Encodes everything outside RFC 3986 unreserved characters.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Clone)]
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    trace_backend: Option<TraceBackend>,
+    shutdown_report: Option<ReportOutput>,
+    #[cfg(feature = "logs")]
+    logger_provider: Option<SdkLoggerProvider>,
+}
+
+impl Telemetry {
+    pub fn new(tracer_provider: SdkTracerProvider) -> Self {
+        Self {
+            tracer_provider,
+            trace_backend: None,
+            shutdown_report: None,
+            #[cfg(feature = "logs")]
+            logger_provider: None,
+        }
+    }
+
+    pub fn with_trace_backend(mut self, trace_backend: TraceBackend) -> Self {
+        self.trace_backend = Some(trace_backend);
+        self
+    }
+
+    /// Where [`TelemetryGuard`] puts the [`RunReport`] on drop.
+    pub fn with_shutdown_report(mut self, output: ReportOutput) -> Self {
+        self.shutdown_report = Some(output);
+        self
+    }
+
+    /// Logger provider flushed and shut down with the tracer provider, e.g.
+    /// the one behind a `logs::layer`.
+    #[cfg(feature = "logs")]
+    pub fn with_logger_provider(mut self, logger_provider: SdkLoggerProvider) -> Self {
+        self.logger_provider = Some(logger_provider);
+        self
+    }
+
+    pub fn tracer_provider(&self) -> &SdkTracerProvider {
+        &self.tracer_provider
+    }
+
+    #[cfg(feature = "logs")]
+    pub fn logger_provider(&self) -> Option<&SdkLoggerProvider> {
+        self.logger_provider.as_ref()
+    }
+
+    /// Flushes and shuts down the tracer (and logger) provider, then
+    /// returns the run's counts. Spans still queued when the export fails
+    /// count as failed.
+    pub fn shutdown(&self) -> anyhow::Result<RunReport> {
+        let traces = self.tracer_provider.shutdown();
+        #[cfg(feature = "logs")]
+        if let Some(logger_provider) = &self.logger_provider {
+            logger_provider
+                .shutdown()
+                .map_err(|error| anyhow::anyhow!("Failed to shut down logger provider: {error}"))?;
+        }
+        traces.map_err(|error| anyhow::anyhow!("Failed to shut down tracer provider: {error}"))?;
+        Ok(RunReport::snapshot())
+    }
+
+    /// Shuts telemetry down when the returned guard drops, e.g. at the end
+    /// of `main`, emitting the [`RunReport`] if configured.
+    pub fn guard(self) -> TelemetryGuard {
+        TelemetryGuard { telemetry: self }
+    }
+
+    /// Deep link to `trace_id` in the configured backend, if one is set.
+    pub fn trace_url(&self, trace_id: TraceId) -> Option<String> {
+        self.trace_backend
+            .as_ref()
+            .map(|backend| backend.trace_url(trace_id))
+    }
+
+    /// Deep link to the trace of the current span, for API responses and
+    /// log lines.
+    pub fn current_trace_url(&self) -> Option<String> {
+        self.trace_url(Self::current_trace_id()?)
+    }
+
+    /// Trace id of the current span, if it belongs to a valid trace.
+    pub fn current_trace_id() -> Option<TraceId> {
+        let context = tracing::Span::current().context();
+        let span_context = context.span().span_context().clone();
+        span_context.is_valid().then(|| span_context.trace_id())
+    }
+
+    /// `(TRACE_ID_HEADER, hex trace id)` for the current span, ready to be
+    /// inserted into any HTTP framework's response headers.
+    pub fn trace_id_header() -> Option<(&'static str, String)> {
+        Self::current_trace_id().map(|trace_id| (TRACE_ID_HEADER, trace_id.to_string()))
+    }
+
+    /// Adds the current trace link as context on `error`, so a failed request can be
+    /// followed straight to its trace. Errors pass through unchanged when no
+    /// backend or span is available.
+    pub fn with_trace_link(&self, error: anyhow::Error) -> anyhow::Error {
+        match self.current_trace_url() {
+            Some(url) => error.context(format!("trace: {url}")),
+            None => error,
+        }
+    }
+
+    /// Whether data recorded on the current span will be kept. Use it to
+    /// skip expensive capture work (serialization, judge calls) when not.
+    pub fn is_recording() -> bool {
+        matches!(
+            Self::current_sampling_decision(),
+            SamplingDecision::RecordAndSample | SamplingDecision::RecordOnly
+        )
+    }
+
+    pub fn current_sampling_decision() -> SamplingDecision {
+        Self::sampling_decision(&tracing::Span::current())
+    }
+
+    pub fn sampling_decision(span: &tracing::Span) -> SamplingDecision {
+        if span.is_disabled() {
+            return SamplingDecision::NoActiveSpan;
+        }
+
+        let context = span.context();
+        let otel_span = context.span();
+        let span_context = otel_span.span_context();
+        if !span_context.is_valid() {
+            return SamplingDecision::NoActiveSpan;
+        }
+        if span_context.is_sampled() {
+            SamplingDecision::RecordAndSample
+        } else if otel_span.is_recording() {
+            SamplingDecision::RecordOnly
+        } else {
+            SamplingDecision::Drop
+        }
+    }
+}
+
+/// Shuts the tracer provider down on drop; see [`Telemetry::guard`].
+#[derive(Debug)]
+#[must_use = "telemetry shuts down when the guard is dropped"]
+pub struct TelemetryGuard {
+    telemetry: Telemetry,
+}
+
+impl TelemetryGuard {
+    pub fn telemetry(&self) -> &Telemetry {
+        &self.telemetry
+    }
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        // A later `init` must not hand out the provider shut down here.
+        #[cfg(feature = "otlp")]
+        INSTALLED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let report = match self.telemetry.shutdown() {
+            Ok(report) => report,
+            Err(error) => {
+                tracing::error!("{error:#}");
+                RunReport::snapshot()
+            }
+        };
+        if let Some(output) = self.telemetry.shutdown_report {
+            report.emit(output);
+        }
+    }
+}
+
+#[cfg(feature = "otlp")]
+static INSTALLED: Mutex<Option<Telemetry>> = Mutex::new(None);
+
+/// Installs OTLP tracing with the default configuration; see
+/// [`TelemetryBuilder`] for the options.
+#[cfg(feature = "otlp")]
+pub fn init(service_name: &str) -> anyhow::Result<Telemetry> {
+    TelemetryBuilder::new(service_name).init()
+}
+
+/// Configuration of the tracing pipeline installed by [`TelemetryBuilder::init`].
+///
+/// Unset options fall back to the environment: the endpoint to
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` / `OTEL_EXPORTER_OTLP_ENDPOINT`, the
+/// transport to `OTEL_EXPORTER_OTLP_TRACES_PROTOCOL` /
+/// `OTEL_EXPORTER_OTLP_PROTOCOL` (each transport needs its feature), the
+/// log filter to `RUST_LOG`, and the clock skew check to
+/// `OTEL_CLOCK_SKEW_NTP_SERVER`. The exporter itself reads headers from
+/// `OTEL_EXPORTER_OTLP_*HEADERS`; on a name clash those win over
+/// [`TelemetryBuilder::with_header`].
+#[cfg(feature = "otlp")]
+pub struct TelemetryBuilder {
+    service_name: String,
+    endpoint: Option<String>,
+    protocol: Option<OtlpProtocol>,
+    headers: Vec<(String, String)>,
+    export_timeout: Option<Duration>,
+    sampler: BoxedSampler,
+    resource_attributes: Vec<KeyValue>,
+    env_filter: Option<String>,
+    fmt_layer: bool,
+    console_trace_ids: bool,
+    console_exporter: bool,
+    clock_skew_server: Option<String>,
+    trace_backend: Option<TraceBackend>,
+    tenant_router: Option<TenantRouter>,
+    log_bridge: bool,
+    clock: Option<Arc<dyn Clock>>,
+    id_generator: Option<BoxedIdGenerator>,
+    propagator: Option<TextMapCompositePropagator>,
+    enrichment: EnrichmentLayer,
+    shutdown_report: Option<ReportOutput>,
+    semconv_compat: Option<SemconvCompat>,
+    capture_policy: Option<ContentCapturePolicy>,
+    baggage_keys: Vec<String>,
+    export_wrappers: Vec<ExportWrapper>,
+    #[cfg(feature = "logs")]
+    otlp_logs: bool,
+}
+
+#[cfg(feature = "otlp")]
+type ExportWrapper = Box<dyn FnOnce(BoxedProcessor) -> BoxedProcessor>;
+
+#[cfg(feature = "otlp")]
+impl TelemetryBuilder {
+    pub fn new(service_name: impl Into<String>) -> Self {
+        Self {
+            service_name: service_name.into(),
+            endpoint: None,
+            protocol: None,
+            headers: Vec::new(),
+            export_timeout: None,
+            sampler: BoxedSampler(Box::new(default_sampler())),
+            resource_attributes: Vec::new(),
+            env_filter: None,
+            fmt_layer: true,
+            console_trace_ids: false,
+            console_exporter: std::env::var("OTEL_TRACES_EXPORTER").is_ok_and(|exporters| {
+                exporters
+                    .split(',')
+                    .any(|exporter| exporter.trim() == "console")
+            }),
+            clock_skew_server: std::env::var("OTEL_CLOCK_SKEW_NTP_SERVER").ok(),
+            trace_backend: None,
+            tenant_router: None,
+            log_bridge: true,
+            clock: None,
+            id_generator: None,
+            propagator: None,
+            enrichment: EnrichmentLayer::new(),
+            shutdown_report: None,
+            semconv_compat: None,
+            capture_policy: None,
+            baggage_keys: Vec::new(),
+            export_wrappers: Vec::new(),
+            #[cfg(feature = "logs")]
+            otlp_logs: std::env::var("OTEL_LOGS_EXPORTER").is_ok_and(|exporters| {
+                exporters
+                    .split(',')
+                    .any(|exporter| exporter.trim() == "otlp")
+            }),
+        }
+    }
+
+    /// OTLP endpoint, overriding the environment. Over HTTP this is the
+    /// full URL, including `/v1/traces`.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// OTLP transport, overriding the environment.
+    pub fn with_protocol(mut self, protocol: OtlpProtocol) -> Self {
+        self.protocol = Some(protocol);
+        self
+    }
+
+    /// Exporter header, e.g. an ingestion key loaded from a secret store
+    /// rather than the environment.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_export_timeout(mut self, timeout: Duration) -> Self {
+        self.export_timeout = Some(timeout);
+        self
+    }
+
+    /// Head sampler, such as an `LlmSamplerAdapter` wrapping a custom
+    /// policy. Defaults to [`default_sampler`].
+    pub fn with_sampler(mut self, sampler: impl ShouldSample + 'static) -> Self {
+        self.sampler = BoxedSampler(Box::new(sampler));
+        self
+    }
+
+    /// Extra resource attribute, e.g. `deployment.environment.name`.
+    pub fn with_resource_attribute(mut self, attribute: KeyValue) -> Self {
+        self.resource_attributes.push(attribute);
+        self
+    }
+
+    /// Log filter used when `RUST_LOG` is not set; defaults to `info`.
+    pub fn with_env_filter(mut self, directives: impl Into<String>) -> Self {
+        self.env_filter = Some(directives.into());
+        self
+    }
+
+    /// Whether [`TelemetryBuilder::init`] also installs a console `fmt` layer.
+    pub fn with_fmt_layer(mut self, enabled: bool) -> Self {
+        self.fmt_layer = enabled;
+        self
+    }
+
+    /// Whether console log lines emitted inside a span end with its
+    /// `trace_id` and `span_id`, for finding the trace of a log line in the
+    /// backend; see [`TraceIdFormat`]. Off by default.
+    pub fn with_console_trace_ids(mut self, enabled: bool) -> Self {
+        self.console_trace_ids = enabled;
+        self
+    }
+
+    /// Also prints finished spans to stderr while exporting over OTLP, for
+    /// local debugging. Defaults to on when `OTEL_TRACES_EXPORTER` lists
+    /// `console`.
+    pub fn with_console_exporter(mut self, enabled: bool) -> Self {
+        self.console_exporter = enabled;
+        self
+    }
+
+    /// SNTP server for the startup clock skew check; the check costs one UDP
+    /// round trip.
+    pub fn with_clock_skew_check(mut self, server: impl Into<String>) -> Self {
+        self.clock_skew_server = Some(server.into());
+        self
+    }
+
+    pub fn with_trace_backend(mut self, trace_backend: TraceBackend) -> Self {
+        self.trace_backend = Some(trace_backend);
+        self
+    }
+
+    /// Sends spans of each registered tenant to that tenant's own pipeline;
+    /// spans without a known tenant go to the pipeline configured here.
+    pub fn with_tenant_router(mut self, tenant_router: TenantRouter) -> Self {
+        self.tenant_router = Some(tenant_router);
+        self
+    }
+
+    /// Whether [`TelemetryBuilder::init`] forwards records of the `log`
+    /// crate to `tracing`, so libraries still logging through `log` show up
+    /// in the console output and as events on the current span. On by
+    /// default; skipped with a note when the process already installed a
+    /// `log` logger.
+    pub fn with_log_bridge(mut self, enabled: bool) -> Self {
+        self.log_bridge = enabled;
+        self
+    }
+
+    /// Clock for span and span event timestamps, e.g. a `SimulatedClock` in
+    /// tests. Applied by a `ClockLayer` and a `ClockProcessor`; with
+    /// [`TelemetryBuilder::build_layer`], add the layer yourself. The console
+    /// exporter prints the system time.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    /// Trace and span id source, e.g. a `SequentialIdGenerator` in tests.
+    /// Defaults to the SDK's random ids.
+    pub fn with_id_generator(mut self, id_generator: impl IdGenerator + 'static) -> Self {
+        self.id_generator = Some(BoxedIdGenerator(Box::new(id_generator)));
+        self
+    }
+
+    /// Global propagator for [`crate::propagation`], in place of the default
+    /// W3C trace context plus baggage, e.g.
+    /// [`HintsPropagator`](crate::tracestate::HintsPropagator).
+    pub fn with_propagator(
+        mut self,
+        propagator: impl TextMapPropagator + Send + Sync + 'static,
+    ) -> Self {
+        self.propagator = Some(TextMapCompositePropagator::new(vec![Box::new(propagator)]));
+        self
+    }
+
+    /// Callback adding application attributes to every LLM and tool span as
+    /// it starts; see [`crate::enrichment`].
+    pub fn with_span_enricher(mut self, enricher: impl SpanEnricher) -> Self {
+        self.enrichment = self.enrichment.with_enricher(enricher);
+        self
+    }
+
+    /// Emits a [`RunReport`] (spans exported and dropped, LLM calls, tokens,
+    /// cost, errors) when the [`TelemetryGuard`] from [`Telemetry::guard`]
+    /// drops. Off by default.
+    pub fn with_shutdown_report(mut self, output: ReportOutput) -> Self {
+        self.shutdown_report = Some(output);
+        self
+    }
+
+    /// Whether renamed GenAI attributes are also (or only) exported under
+    /// their legacy names, e.g. `gen_ai.prompt` next to
+    /// `gen_ai.input.messages`; see [`crate::processors::compat`]. Defaults
+    /// to `LLM_SEMCONV_COMPAT`, else off; an invalid value fails the build.
+    pub fn with_semconv_compat(mut self, mode: SemconvCompat) -> Self {
+        self.semconv_compat = Some(mode);
+        self
+    }
+
+    /// Policy for prompt and response content in exported spans; see
+    /// [`crate::processors::capture`]. Defaults to `LLM_CONTENT_CAPTURE`,
+    /// else full; an invalid value fails the build.
+    pub fn with_capture_policy(mut self, policy: ContentCapturePolicy) -> Self {
+        self.capture_policy = Some(policy);
+        self
+    }
+
+    /// Baggage key copied onto LLM spans besides
+    /// `processors::baggage::DEFAULT_BAGGAGE_KEYS`, e.g. `experiment.*`.
+    pub fn with_baggage_key(mut self, key: impl Into<String>) -> Self {
+        self.baggage_keys.push(key.into());
+        self
+    }
+
+    /// Wraps the export pipeline in a processor built around it, e.g.
+    /// `.with_span_processor(TailSamplingProcessor::new)` or a closure
+    /// configuring a `RedactionProcessor`. Each call wraps the previous
+    /// ones, so the last processor added sees spans first. They run after
+    /// the budget, run report and replay processors and before content
+    /// capture and export.
+    pub fn with_span_processor<F, P>(mut self, wrap: F) -> Self
+    where
+        F: FnOnce(BoxedProcessor) -> P + 'static,
+        P: SpanProcessor + 'static,
+    {
+        self.export_wrappers
+            .push(Box::new(move |inner| BoxedProcessor::new(wrap(inner))));
+        self
+    }
+
+    /// Scrubs emails, API keys and card numbers from every exported span
+    /// with a [`RedactingSpanProcessor`]; for other patterns, add one
+    /// through [`TelemetryBuilder::with_span_processor`] instead.
+    #[cfg(feature = "redaction")]
+    pub fn with_redaction(self) -> Self {
+        self.with_span_processor(RedactingSpanProcessor::new)
+    }
+
+    /// Exports whole traces selected by a [`TailSamplingProcessor`] with
+    /// `config`, through [`TelemetryBuilder::with_span_processor`]. Keep the
+    /// default sampler so it sees every span.
+    pub fn with_tail_sampling(self, config: TailSamplingConfig) -> Self {
+        self.with_span_processor(move |inner| TailSamplingProcessor::new(inner).with_config(config))
+    }
+
+    /// Keeps one CLIENT span per provider call when the host app also
+    /// instruments its HTTP client, through a [`ClientSpanDedupProcessor`]
+    /// preferring `winner`; see [`crate::processors::dedup`].
+    pub fn with_client_span_dedup(self, winner: ClientSpanWinner) -> Self {
+        self.with_span_processor(move |inner| {
+            ClientSpanDedupProcessor::new(inner).with_winner(winner)
+        })
+    }
+
+    /// Also exports `tracing` events as OTLP log records carrying the trace
+    /// and span ids, for log/trace correlation; see [`crate::logs`]. The
+    /// logs endpoint comes from `OTEL_EXPORTER_OTLP_LOGS_ENDPOINT` /
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT`. Defaults to on when
+    /// `OTEL_LOGS_EXPORTER` lists `otlp`.
+    #[cfg(feature = "logs")]
+    pub fn with_otlp_logs(mut self, enabled: bool) -> Self {
+        self.otlp_logs = enabled;
+        self
+    }
+
+    /// Installs the global subscriber and tracer provider once per process;
+    /// later calls return the telemetry installed by the first until its
+    /// [`TelemetryGuard`] drops, and fail after that, since the subscriber
+    /// cannot be replaced.
+    ///
+    /// Fails with `TelemetryError::SubscriberAlreadySet` when the host app
+    /// already installed a subscriber; use [`TelemetryBuilder::build_layer`]
+    /// and add the layer to that subscriber instead.
+    pub fn init(mut self) -> anyhow::Result<Telemetry> {
+        let mut installed = INSTALLED.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(telemetry) = installed.as_ref() {
+            return Ok(telemetry.clone());
+        }
+
+        let fallback_filter = self.env_filter.clone().unwrap_or_else(|| "info".to_owned());
+        let fmt_layer = self.fmt_layer;
+        let console_trace_ids = self.console_trace_ids;
+        let log_bridge = self.log_bridge;
+        let trace_backend = self.trace_backend.clone();
+        let shutdown_report = self.shutdown_report;
+        let enrichment = std::mem::take(&mut self.enrichment);
+        let propagator = self.propagator.take().unwrap_or_else(|| {
+            TextMapCompositePropagator::new(vec![
+                Box::new(TraceContextPropagator::new()),
+                Box::new(BaggagePropagator::new()),
+            ])
+        });
+        #[cfg(feature = "logs")]
+        let logger_provider = self
+            .otlp_logs
+            .then(|| {
+                otlp_logger_provider_with_attributes(
+                    &self.service_name,
+                    self.resource_attributes.clone(),
+                )
+            })
+            .transpose()?;
+        #[cfg(feature = "logs")]
+        let log_layer = logger_provider.as_ref().map(crate::logs::layer);
+        #[cfg(not(feature = "logs"))]
+        let log_layer: Option<tracing_subscriber::layer::Identity> = None;
+        let clock_layer = self.clock.clone().map(ClockLayer::new);
+        let (tracer_provider, otel_layer, clock_skew) = self.build_pipeline()?;
+        let filter_layer =
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(fallback_filter));
+
+        let subscriber = tracing_subscriber::registry()
+            .with(filter_layer)
+            .with(fmt_layer.then(|| {
+                fmt::layer().event_format(
+                    TraceIdFormat::new(fmt::format().with_target(false))
+                        .with_ids(console_trace_ids),
+                )
+            }))
+            .with(otel_layer.with_filter(kill_switch::layer_filter()))
+            .with(clock_layer)
+            .with((!enrichment.is_empty()).then_some(enrichment))
+            .with(log_layer);
+        if tracing::subscriber::set_global_default(subscriber).is_err() {
+            let _ = tracer_provider.shutdown();
+            #[cfg(feature = "logs")]
+            if let Some(logger_provider) = &logger_provider {
+                let _ = logger_provider.shutdown();
+            }
+            return Err(TelemetryError::SubscriberAlreadySet.into());
+        }
+        // After the subscriber, so `log` gets its max level and skips
+        // records the filter would drop.
+        if log_bridge {
+            if let Err(error) = LogTracer::builder()
+                .with_max_level(LevelFilter::current().as_log())
+                .init()
+            {
+                tracing::warn!("Log bridge skipped: {error}");
+            }
+        }
+
+        report_clock_skew(clock_skew);
+
+        global::set_tracer_provider(tracer_provider.clone());
+        global::set_text_map_propagator(propagator);
+        let mut telemetry = Telemetry::new(tracer_provider);
+        telemetry.trace_backend = trace_backend;
+        telemetry.shutdown_report = shutdown_report;
+        #[cfg(feature = "logs")]
+        {
+            telemetry.logger_provider = logger_provider;
+        }
+        *installed = Some(telemetry.clone());
+
+        Ok(telemetry)
+    }
+
+    /// Builds the provider and the OpenTelemetry layer without installing
+    /// anything, for host apps that compose their own `tracing` stack. Add
+    /// `kill_switch::layer_filter()` to the layer to make spans no-ops while
+    /// the kill switch is off; the sampler drops them either way. Span
+    /// enrichers are not part of the layer: add an [`EnrichmentLayer`]
+    /// after it, and likewise a `logs::layer` for OTLP logs and a
+    /// `ClockLayer` sharing the clock given to [`TelemetryBuilder::with_clock`].
+    pub fn build_layer<S>(
+        self,
+    ) -> anyhow::Result<(SdkTracerProvider, OpenTelemetryLayer<S, SdkTracer>)>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let (tracer_provider, layer, clock_skew) = self.build_pipeline()?;
+        report_clock_skew(clock_skew);
+        Ok((tracer_provider, layer))
+    }
+
+    /// [`TelemetryBuilder::build_layer`], returning the clock skew check
+    /// for the caller to report once a subscriber can record it.
+    fn build_pipeline<S>(
+        self,
+    ) -> anyhow::Result<(
+        SdkTracerProvider,
+        OpenTelemetryLayer<S, SdkTracer>,
+        ClockSkewCheck,
+    )>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        kill_switch::init_from_env();
+        let traces = OtlpEndpoints::signal_from_env(Signal::Traces, self.protocol);
+        let endpoint = self.endpoint.unwrap_or(traces.endpoint);
+        // The exporter merges in the header variables itself.
+        let export = otlp_span_processor(
+            traces.protocol,
+            endpoint,
+            &self.headers,
+            self.export_timeout,
+        )?;
+        // Replayed timestamps are applied inside the clock, so they win.
+        // The capture policy runs after the processors added with
+        // `with_span_processor`, and attribute names are rewritten last,
+        // after everything else has read the current ones.
+        let export = BoxedProcessor(match self.tenant_router {
+            Some(router) => Box::new(router.with_fallback(export)),
+            None => Box::new(export),
+        });
+        let semconv_compat = match self.semconv_compat {
+            Some(mode) => mode,
+            None => SemconvCompat::from_env()?,
+        };
+        let capture_policy = match self.capture_policy {
+            Some(policy) => policy,
+            None => ContentCapturePolicy::from_env()?,
+        };
+        let mut export = BoxedProcessor::new(
+            ContentCaptureProcessor::new(
+                SemconvCompatProcessor::new(export).with_mode(semconv_compat),
+            )
+            .with_policy(capture_policy),
+        );
+        for wrap in self.export_wrappers {
+            export = wrap(export);
+        }
+        // Spans are moved to their subsystem's scope before the processors
+        // added with `with_span_processor` see them.
+        let export = BudgetProcessor::new(RunReportProcessor::new(ReplayTimestampProcessor::new(
+            ScopeProcessor::new(export),
+        )));
+        let export = self
+            .baggage_keys
+            .into_iter()
+            .fold(BaggageProcessor::new(export), BaggageProcessor::with_key);
+        let mut tracer_provider = SdkTracerProvider::builder();
+        tracer_provider = match &self.clock {
+            Some(clock) => {
+                tracer_provider.with_span_processor(ClockProcessor::new(export, Arc::clone(clock)))
+            }
+            None => tracer_provider.with_span_processor(export),
+        };
+        if let Some(id_generator) = self.id_generator {
+            tracer_provider = tracer_provider.with_id_generator(id_generator);
+        }
+
+        let mut resource = Resource::builder()
+            .with_service_name(self.service_name)
+            .with_attribute(KeyValue::new("telemetry.sdk.language", "rust"))
+            .with_attributes(self.resource_attributes);
+        let clock_skew = self
+            .clock_skew_server
+            .map(|server| clock_skew::measure_sntp(&server, Duration::from_secs(2)));
+        if let Some(Ok(skew)) = &clock_skew {
+            resource = resource.with_attributes(skew.resource_attributes());
+        }
+
+        if self.console_exporter {
+            tracer_provider = tracer_provider.with_simple_exporter(ConsoleSpanExporter::new());
+        }
+        let tracer_provider = tracer_provider
+            .with_sampler(KillSwitchSampler::new(self.sampler))
+            .with_resource(resource.build())
+            .build();
+
+        let tracer = tracer_provider.tracer_with_scope(Subsystem::Agent.scope());
+        Ok((
+            tracer_provider,
+            tracing_opentelemetry::layer().with_tracer(tracer),
+            clock_skew,
+        ))
+    }
+}
+
+/// Outcome of the startup clock skew check, `None` when it is disabled.
+#[cfg(feature = "otlp")]
+type ClockSkewCheck = Option<anyhow::Result<ClockSkew>>;
+
+#[cfg(feature = "otlp")]
+fn report_clock_skew(clock_skew: ClockSkewCheck) {
+    match clock_skew {
+        Some(Ok(skew)) => skew.warn_if_exceeds(clock_skew::DEFAULT_WARN_THRESHOLD),
+        Some(Err(error)) => tracing::warn!("Clock skew check skipped: {error:#}"),
+        None => {}
+    }
+}
+
+/// Batch processor exporting spans over OTLP to `endpoint`, shared by the
+/// main pipeline and per-tenant pipelines.
+#[cfg(feature = "otlp")]
+pub(crate) fn otlp_span_processor(
+    protocol: OtlpProtocol,
+    endpoint: String,
+    headers: &[(String, String)],
+    timeout: Option<Duration>,
+) -> anyhow::Result<BatchSpanProcessor> {
+    let target = SignalEndpoint {
+        protocol,
+        endpoint,
+        headers: headers.to_vec(),
+    };
+    let exporter = span_exporter(&target, timeout)?;
+    Ok(BatchSpanProcessor::builder(CountingExporter::new(exporter)).build())
+}
+
+/// Type-erased sampler, since the provider builder takes a concrete type.
+#[cfg(feature = "otlp")]
+#[derive(Debug, Clone)]
+struct BoxedSampler(Box<dyn ShouldSample>);
+
+#[cfg(feature = "otlp")]
+impl ShouldSample for BoxedSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&opentelemetry::Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        self.0
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Type-erased id generator, for the same reason.
+#[cfg(feature = "otlp")]
+#[derive(Debug)]
+struct BoxedIdGenerator(Box<dyn IdGenerator>);
+
+#[cfg(feature = "otlp")]
+impl IdGenerator for BoxedIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        self.0.new_trace_id()
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        self.0.new_span_id()
+    }
+}
+
+/// Type-erased span processor, so the export pipeline can be wrapped
+/// whether or not it routes by tenant; see
+/// [`TelemetryBuilder::with_span_processor`].
+#[cfg(feature = "otlp")]
+#[derive(Debug)]
+pub struct BoxedProcessor(Box<dyn SpanProcessor>);
+
+#[cfg(feature = "otlp")]
+impl BoxedProcessor {
+    pub fn new(processor: impl SpanProcessor + 'static) -> Self {
+        Self(Box::new(processor))
+    }
+}
+
+#[cfg(feature = "otlp")]
+impl SpanProcessor for BoxedProcessor {
+    fn on_start(&self, span: &mut opentelemetry_sdk::trace::Span, cx: &opentelemetry::Context) {
+        self.0.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        self.0.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.0.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.0.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.0.set_resource(resource);
+    }
+}
+
+/// The SDK default: follow the parent's decision, sample root spans.
+pub fn default_sampler() -> Sampler {
+    Sampler::ParentBased(Box::new(Sampler::AlwaysOn))
+}
@@ -0,0 +1,444 @@
+//! Isolated per-tenant telemetry pipelines in one process.
+//!
+//! A platform serving many customers from one process must not send one
+//! customer's prompts to another customer's backend, and each customer may
+//! need a different resource identity, endpoint, ingestion key and content
+//! capture policy. Everything up to span end is shared (one subscriber, one
+//! sampler); [`TenantRouter`] then hands each finished span to the pipeline
+//! of its tenant, which has its own exporter, resource and
+//! [`ContentCapturePolicy`].
+//!
+//! The tenant of a request travels in baggage under [`TENANT_KEY`], so every
+//! span of the request (and of downstream services) inherits it. Set it from
+//! authenticated request data, never from what the caller sent:
+//! `server::ServerSpanLayer` drops an inbound `tenant.id` entry, since a
+//! forged one would route the request's spans, prompts included, to another
+//! customer's backend.
+//!
+//! ```ignore
+//! let cx = tenancy::with_tenant(&opentelemetry::Context::current(), "acme");
+//! let span = tracing::info_span!("handle_request");
+//! span.set_parent(cx);
+//! ```
+//!
+//! The router stamps the tenant on each span as the `tenant.id` attribute
+//! when it starts; spans without a registered tenant go to the fallback
+//! pipeline, which `TelemetryBuilder::with_tenant_router` sets to the
+//! builder's own exporter.
+//!
+//! The OTLP exporters add `OTEL_EXPORTER_OTLP_HEADERS` /
+//! `OTEL_EXPORTER_OTLP_TRACES_HEADERS` to every pipeline, so
+//! [`TenantRouter::with_tenant`] fails while either is set; in a
+//! multi-tenant process, pass the platform's own credentials with
+//! `TelemetryBuilder::with_header` instead.
+
+use crate::error::TelemetryError;
+use crate::otlp_config::OtlpProtocol;
+use crate::processors::attribute;
+use crate::processors::capture::{ContentCapturePolicy, ContentCaptureProcessor};
+use crate::telemetry::otlp_span_processor;
+use opentelemetry::baggage::{Baggage, BaggageExt};
+use opentelemetry::{Context, KeyValue, Value};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Baggage key and span attribute naming the tenant of a request.
+pub const TENANT_KEY: &str = crate::semconv::TENANT_ID;
+
+/// Header variables the OTLP span exporters read on their own.
+const TRACE_HEADER_VARIABLES: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_HEADERS",
+    "OTEL_EXPORTER_OTLP_TRACES_HEADERS",
+];
+
+/// Returns `cx` with `tenant` in its baggage.
+pub fn with_tenant(cx: &Context, tenant: &str) -> Context {
+    let baggage: Baggage = cx
+        .baggage()
+        .iter()
+        .filter(|(key, _)| key.as_str() != TENANT_KEY)
+        .map(|(key, (value, _))| KeyValue::new(key.clone(), value.to_string()))
+        .chain([KeyValue::new(TENANT_KEY, tenant.to_owned())])
+        .collect();
+    cx.with_baggage(baggage)
+}
+
+/// The tenant found in the baggage of `cx`.
+pub fn tenant_of(cx: &Context) -> Option<String> {
+    cx.baggage()
+        .get(TENANT_KEY)
+        .map(|tenant| tenant.to_string())
+}
+
+/// Where and how one tenant's spans are exported.
+#[derive(Clone)]
+pub struct TenantPipeline {
+    tenant: String,
+    endpoint: String,
+    protocol: OtlpProtocol,
+    headers: Vec<(String, String)>,
+    export_timeout: Option<Duration>,
+    resource_attributes: Vec<KeyValue>,
+    capture_policy: ContentCapturePolicy,
+}
+
+/// Header values usually carry ingestion keys, so only names are printed.
+impl fmt::Debug for TenantPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header_names: Vec<&str> = self.headers.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("TenantPipeline")
+            .field("tenant", &self.tenant)
+            .field("endpoint", &self.endpoint)
+            .field("protocol", &self.protocol)
+            .field("headers", &header_names)
+            .field("export_timeout", &self.export_timeout)
+            .field("resource_attributes", &self.resource_attributes)
+            .field("capture_policy", &self.capture_policy)
+            .finish()
+    }
+}
+
+impl TenantPipeline {
+    /// Exports over OTLP/gRPC to `endpoint`, keeping full content until a
+    /// capture policy is set. Endpoint, protocol and headers come only from
+    /// the pipeline; see [`TenantRouter::with_tenant`] for the header
+    /// variables.
+    pub fn new(tenant: impl Into<String>, endpoint: impl Into<String>) -> Self {
+        Self {
+            tenant: tenant.into(),
+            endpoint: endpoint.into(),
+            protocol: OtlpProtocol::Grpc,
+            headers: Vec::new(),
+            export_timeout: None,
+            resource_attributes: Vec::new(),
+            capture_policy: ContentCapturePolicy::Full,
+        }
+    }
+
+    /// Over HTTP the endpoint is the full URL, including `/v1/traces`.
+    pub fn with_protocol(mut self, protocol: OtlpProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_export_timeout(mut self, timeout: Duration) -> Self {
+        self.export_timeout = Some(timeout);
+        self
+    }
+
+    /// Resource attribute for this tenant only, e.g. its own `service.name`;
+    /// it overrides the platform resource attribute of the same key.
+    pub fn with_resource_attribute(mut self, attribute: KeyValue) -> Self {
+        self.resource_attributes.push(attribute);
+        self
+    }
+
+    pub fn with_capture_policy(mut self, policy: ContentCapturePolicy) -> Self {
+        self.capture_policy = policy;
+        self
+    }
+
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+}
+
+struct TenantProcessor {
+    processor: Box<dyn SpanProcessor>,
+    resource_attributes: Vec<KeyValue>,
+}
+
+/// Span processor routing finished spans to per-tenant pipelines.
+#[derive(Default)]
+pub struct TenantRouter {
+    tenants: HashMap<String, TenantProcessor>,
+    fallback: Option<Box<dyn SpanProcessor>>,
+}
+
+impl TenantRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the tenant's exporter and registers it. Fails with
+    /// `TelemetryError::TenantEnvHeaders` while a header variable the
+    /// exporter would add is set.
+    pub fn with_tenant(self, pipeline: TenantPipeline) -> anyhow::Result<Self> {
+        if let Some(variable) = TRACE_HEADER_VARIABLES
+            .into_iter()
+            .find(|variable| std::env::var_os(variable).is_some_and(|value| !value.is_empty()))
+        {
+            return Err(TelemetryError::TenantEnvHeaders {
+                tenant: pipeline.tenant,
+                variable,
+            }
+            .into());
+        }
+        let export = otlp_span_processor(
+            pipeline.protocol,
+            pipeline.endpoint,
+            &pipeline.headers,
+            pipeline.export_timeout,
+        )?;
+        let processor = ContentCaptureProcessor::new(export).with_policy(pipeline.capture_policy);
+        Ok(self.with_tenant_processor(pipeline.tenant, processor, pipeline.resource_attributes))
+    }
+
+    /// Registers an already built processor chain for `tenant`, replacing any
+    /// earlier one.
+    pub fn with_tenant_processor(
+        mut self,
+        tenant: impl Into<String>,
+        processor: impl SpanProcessor + 'static,
+        resource_attributes: Vec<KeyValue>,
+    ) -> Self {
+        self.tenants.insert(
+            tenant.into(),
+            TenantProcessor {
+                processor: Box::new(processor),
+                resource_attributes,
+            },
+        );
+        self
+    }
+
+    /// Receives spans without a registered tenant; without one they are
+    /// dropped.
+    pub fn with_fallback(mut self, processor: impl SpanProcessor + 'static) -> Self {
+        self.fallback = Some(Box::new(processor));
+        self
+    }
+
+    /// Registered tenant ids, in no particular order.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(String::as_str)
+    }
+
+    fn route(&self, tenant: Option<&str>) -> Option<&dyn SpanProcessor> {
+        tenant
+            .and_then(|tenant| self.tenants.get(tenant))
+            .map(|tenant| tenant.processor.as_ref())
+            .or(self.fallback.as_deref())
+    }
+
+    fn processors(&self) -> impl Iterator<Item = &dyn SpanProcessor> {
+        self.tenants
+            .values()
+            .map(|tenant| tenant.processor.as_ref())
+            .chain(self.fallback.as_deref())
+    }
+
+    /// Runs `operation` on every pipeline, so one failing tenant does not
+    /// stop the others from flushing; the first error is returned.
+    fn for_each(&self, operation: impl Fn(&dyn SpanProcessor) -> OTelSdkResult) -> OTelSdkResult {
+        let results: Vec<OTelSdkResult> = self.processors().map(operation).collect();
+        results.into_iter().collect()
+    }
+}
+
+impl fmt::Debug for TenantRouter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TenantRouter")
+            .field("tenants", &self.tenants.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl SpanProcessor for TenantRouter {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let tenant = tenant_of(cx);
+        if let Some(processor) = self.route(tenant.as_deref()) {
+            processor.on_start(span, cx);
+        }
+        if let Some(tenant) = tenant {
+            opentelemetry::trace::Span::set_attribute(span, KeyValue::new(TENANT_KEY, tenant));
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let tenant = match attribute(&span, TENANT_KEY) {
+            Some(Value::String(tenant)) => Some(tenant.as_str()),
+            _ => None,
+        };
+        if let Some(processor) = self.route(tenant) {
+            processor.on_end(span);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.for_each(|processor| processor.force_flush())
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.for_each(|processor| processor.shutdown_with_timeout(timeout))
+    }
+
+    /// Each tenant gets the platform resource overlaid with its own
+    /// attributes and `tenant.id`.
+    fn set_resource(&mut self, resource: &Resource) {
+        let base: Vec<KeyValue> = resource
+            .iter()
+            .map(|(key, value)| KeyValue::new(key.clone(), value.clone()))
+            .collect();
+        for (tenant, pipeline) in self.tenants.iter_mut() {
+            let tenant_resource = Resource::builder_empty()
+                .with_attributes(base.iter().cloned())
+                .with_attributes(pipeline.resource_attributes.iter().cloned())
+                .with_attribute(KeyValue::new(TENANT_KEY, tenant.clone()))
+                .build();
+            pipeline.processor.set_resource(&tenant_resource);
+        }
+        if let Some(fallback) = self.fallback.as_mut() {
+            fallback.set_resource(resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::testing::{Collect, end, start};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    /// Records the resource it is given.
+    #[derive(Debug, Clone, Default)]
+    struct ResourceProbe(Arc<Mutex<Option<Resource>>>);
+
+    impl ResourceProbe {
+        fn get(&self, key: &'static str) -> Option<Value> {
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .and_then(|resource| resource.get(&opentelemetry::Key::from_static_str(key)))
+        }
+    }
+
+    impl SpanProcessor for ResourceProbe {
+        fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+        fn on_end(&self, _span: SpanData) {}
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn set_resource(&mut self, resource: &Resource) {
+            *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(resource.clone());
+        }
+    }
+
+    fn emit(router: TenantRouter, tenants: &[Option<&'static str>]) {
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(router)
+            .build();
+        let tracer = provider.tracer("test");
+        for tenant in tenants {
+            let parent = match tenant {
+                Some(tenant) => with_tenant(&Context::new(), tenant),
+                None => Context::new(),
+            };
+            let cx = start(&tracer, &parent, tenant.unwrap_or("untenanted"), Vec::new());
+            end(&cx);
+        }
+    }
+
+    fn names(collect: &Collect) -> Vec<String> {
+        collect
+            .spans()
+            .into_iter()
+            .map(|span| span.name.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn routes_spans_to_their_tenant_and_the_rest_to_the_fallback() {
+        let (acme, globex, fallback) = (Collect::default(), Collect::default(), Collect::default());
+        let router = TenantRouter::new()
+            .with_tenant_processor("acme", acme.clone(), Vec::new())
+            .with_tenant_processor("globex", globex.clone(), Vec::new())
+            .with_fallback(fallback.clone());
+
+        emit(
+            router,
+            &[
+                Some("acme"),
+                Some("globex"),
+                Some("initech"),
+                None,
+                Some("acme"),
+            ],
+        );
+
+        assert_eq!(names(&acme), ["acme", "acme"]);
+        assert_eq!(names(&globex), ["globex"]);
+        assert_eq!(names(&fallback), ["initech", "untenanted"]);
+        assert_eq!(
+            attribute(&globex.span("globex"), TENANT_KEY),
+            Some(&Value::from("globex"))
+        );
+    }
+
+    #[test]
+    fn drops_spans_without_a_tenant_pipeline_or_fallback() {
+        let acme = Collect::default();
+        let router = TenantRouter::new().with_tenant_processor("acme", acme.clone(), Vec::new());
+
+        emit(router, &[Some("initech"), None, Some("acme")]);
+
+        assert_eq!(names(&acme), ["acme"]);
+    }
+
+    #[test]
+    fn gives_each_tenant_its_own_resource() {
+        let (acme, globex, fallback) = (
+            ResourceProbe::default(),
+            ResourceProbe::default(),
+            ResourceProbe::default(),
+        );
+        let mut router = TenantRouter::new()
+            .with_tenant_processor(
+                "acme",
+                acme.clone(),
+                vec![KeyValue::new("service.name", "acme-assistant")],
+            )
+            .with_tenant_processor("globex", globex.clone(), Vec::new())
+            .with_fallback(fallback.clone());
+
+        router.set_resource(
+            &Resource::builder_empty()
+                .with_attributes([
+                    KeyValue::new("service.name", "platform"),
+                    KeyValue::new("deployment.environment.name", "prod"),
+                ])
+                .build(),
+        );
+
+        assert_eq!(acme.get("service.name"), Some("acme-assistant".into()));
+        assert_eq!(acme.get(TENANT_KEY), Some("acme".into()));
+        assert_eq!(globex.get("service.name"), Some("platform".into()));
+        assert_eq!(globex.get(TENANT_KEY), Some("globex".into()));
+        assert_eq!(
+            globex.get("deployment.environment.name"),
+            Some("prod".into())
+        );
+        assert_eq!(fallback.get("service.name"), Some("platform".into()));
+        assert_eq!(fallback.get(TENANT_KEY), None);
+    }
+}
@@ -0,0 +1,293 @@
+//! Uniform provider timeouts with explicit timeout spans.
+//!
+//! Each provider SDK ships its own opaque defaults, so "the call hung for
+//! 600 s" and "the call failed after 30 s" look alike in the backend. Connect,
+//! request (per attempt) and total (across retries) budgets are configured
+//! per provider here, and when one fires the span gets `timeout.phase`,
+//! `timeout.limit_ms`, `error.type = "timeout"` and an error status, plus a
+//! count in `llm.provider.timeouts`.
+
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use opentelemetry::trace::Status;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutPhase {
+    /// Establishing the TCP/TLS connection.
+    Connect,
+    /// One HTTP attempt, from send to the end of the response body.
+    Request,
+    /// The whole operation, including retries and backoff.
+    Total,
+}
+
+impl TimeoutPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeoutPhase::Connect => "connect",
+            TimeoutPhase::Request => "request",
+            TimeoutPhase::Total => "total",
+        }
+    }
+}
+
+/// Returned when a budget enforced by this module runs out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderTimeout {
+    pub phase: TimeoutPhase,
+    pub limit: Duration,
+}
+
+impl fmt::Display for ProviderTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "provider {} timeout after {} ms",
+            self.phase.as_str(),
+            self.limit.as_millis()
+        )
+    }
+}
+
+impl std::error::Error for ProviderTimeout {}
+
+/// Timeout budgets for one provider. `None` leaves that phase unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderTimeouts {
+    pub connect: Option<Duration>,
+    pub request: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl Default for ProviderTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Some(Duration::from_secs(10)),
+            request: Some(Duration::from_secs(120)),
+            total: Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+impl ProviderTimeouts {
+    /// Runs one provider attempt under the request budget.
+    pub async fn request<F: Future>(
+        &self,
+        span: &tracing::Span,
+        provider: &str,
+        future: F,
+    ) -> Result<F::Output, ProviderTimeout> {
+        run_with_limit(span, provider, TimeoutPhase::Request, self.request, future).await
+    }
+
+    /// Runs a whole operation (retries included) under the total budget.
+    pub async fn total<F: Future>(
+        &self,
+        span: &tracing::Span,
+        provider: &str,
+        future: F,
+    ) -> Result<F::Output, ProviderTimeout> {
+        run_with_limit(span, provider, TimeoutPhase::Total, self.total, future).await
+    }
+
+    /// HTTP client with the connect and request budgets applied, for
+    /// `rig`'s `ClientBuilder::http_client`.
+    #[cfg(feature = "http-client")]
+    pub fn http_client(&self) -> reqwest::Result<reqwest::Client> {
+        self.client_builder().build()
+    }
+
+    /// [`http_client`](Self::http_client) before building, to combine with
+    /// other settings such as `cold_start::instrument`.
+    #[cfg(feature = "http-client")]
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::Client::builder();
+        if let Some(connect) = self.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        if let Some(request) = self.request {
+            builder = builder.timeout(request);
+        }
+        builder
+    }
+
+    /// Records a timeout reported by the HTTP client built with
+    /// [`http_client`], returning the phase when `error` was one.
+    ///
+    /// [`http_client`]: ProviderTimeouts::http_client
+    #[cfg(feature = "http-client")]
+    pub fn record_http_error(
+        &self,
+        span: &tracing::Span,
+        provider: &str,
+        error: &reqwest::Error,
+    ) -> Option<TimeoutPhase> {
+        if !error.is_timeout() {
+            return None;
+        }
+        let (phase, limit) = if error.is_connect() {
+            (TimeoutPhase::Connect, self.connect)
+        } else {
+            (TimeoutPhase::Request, self.request)
+        };
+        record_timeout(span, provider, phase, limit);
+        Some(phase)
+    }
+}
+
+/// Per-provider budgets with a fallback for providers not listed.
+#[derive(Debug, Clone, Default)]
+pub struct TimeoutConfig {
+    default: ProviderTimeouts,
+    providers: HashMap<String, ProviderTimeouts>,
+}
+
+impl TimeoutConfig {
+    pub fn new(default: ProviderTimeouts) -> Self {
+        Self {
+            default,
+            providers: HashMap::new(),
+        }
+    }
+
+    pub fn with_provider(
+        mut self,
+        provider: impl Into<String>,
+        timeouts: ProviderTimeouts,
+    ) -> Self {
+        self.providers.insert(provider.into(), timeouts);
+        self
+    }
+
+    /// Budgets for `provider` (matching `gen_ai.provider.name`).
+    pub fn for_provider(&self, provider: &str) -> ProviderTimeouts {
+        self.providers
+            .get(provider)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+async fn run_with_limit<F: Future>(
+    span: &tracing::Span,
+    provider: &str,
+    phase: TimeoutPhase,
+    limit: Option<Duration>,
+    future: F,
+) -> Result<F::Output, ProviderTimeout> {
+    let Some(limit) = limit else {
+        return Ok(future.await);
+    };
+    match tokio::time::timeout(limit, future).await {
+        Ok(output) => Ok(output),
+        Err(_) => {
+            record_timeout(span, provider, phase, Some(limit));
+            Err(ProviderTimeout { phase, limit })
+        }
+    }
+}
+
+/// Marks `span` as timed out in `phase` and sets an error status; usable for
+/// timeouts detected outside this module as well.
+pub fn record_timeout(
+    span: &tracing::Span,
+    provider: &str,
+    phase: TimeoutPhase,
+    limit: Option<Duration>,
+) {
+    let mut attributes = vec![
+        KeyValue::new("gen_ai.provider.name", provider.to_owned()),
+        KeyValue::new("timeout.phase", phase.as_str()),
+    ];
+    if let Some(limit) = limit {
+        attributes.push(KeyValue::new("timeout.limit_ms", limit.as_millis() as i64));
+    }
+    span.set_attribute("error.type", "timeout");
+    span.set_status(Status::error(match limit {
+        Some(limit) => format!(
+            "provider {} timeout after {} ms",
+            phase.as_str(),
+            limit.as_millis()
+        ),
+        None => format!("provider {} timeout", phase.as_str()),
+    }));
+    for attribute in &attributes[1..] {
+        span.set_attribute(attribute.key.clone(), attribute.value.clone());
+    }
+    span.add_event("timeout", attributes);
+    tracing::warn!(
+        gen_ai.provider.name = provider,
+        timeout.phase = phase.as_str(),
+        "Provider call timed out"
+    );
+    timeout_counter().add(
+        1,
+        &[
+            KeyValue::new("gen_ai.provider.name", provider.to_owned()),
+            KeyValue::new("timeout.phase", phase.as_str()),
+        ],
+    );
+}
+
+fn timeout_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.provider.timeouts")
+            .with_description("Provider calls that hit a configured timeout, by phase")
+            .build()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::attribute;
+    use crate::processors::testing::Collect;
+    use opentelemetry::Value;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn timed_out_spans_get_an_error_status() {
+        let collect = Collect::default();
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(collect.clone())
+            .build();
+        let _subscriber = tracing::subscriber::set_default(
+            tracing_subscriber::registry()
+                .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test"))),
+        );
+
+        let span = tracing::info_span!("chat");
+        let timeouts = ProviderTimeouts {
+            request: Some(Duration::from_millis(1)),
+            ..ProviderTimeouts::default()
+        };
+        let result = timeouts
+            .request(&span, "openai", std::future::pending::<()>())
+            .await;
+        drop(span);
+
+        let timeout = result.unwrap_err();
+        let chat = collect.span("chat");
+        assert_eq!(chat.status, Status::error(timeout.to_string()));
+        assert_eq!(
+            attribute(&chat, "error.type"),
+            Some(&Value::from("timeout"))
+        );
+        assert_eq!(
+            attribute(&chat, "timeout.phase"),
+            Some(&Value::from("request"))
+        );
+    }
+}
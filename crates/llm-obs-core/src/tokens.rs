@@ -0,0 +1,26 @@
+//! Token accounting shared by the cost, reasoning and session modules.
+
+use serde::Serialize;
+
+/// Provider usage split into billable classes.
+///
+/// `output_tokens` never includes reasoning tokens: providers that fold
+/// thinking into their completion count are split apart on conversion, so
+/// latency and cost analysis can treat the two separately.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub reasoning_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.reasoning_tokens
+    }
+}
+
+/// Rough token count using the common ~4 characters per token heuristic.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64).div_ceil(4)
+}
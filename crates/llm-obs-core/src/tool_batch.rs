@@ -0,0 +1,111 @@
+//! Parent spans for the tool calls of one model turn.
+//!
+//! A model can ask for several tools in one response. Each call gets its own
+//! `execute_tool` span, but nothing groups them: a trace does not show which
+//! calls belonged to the same turn, whether they ran one after another or
+//! overlapped, or how many of them failed. [`ToolBatch`] opens an
+//! `execute_tools` span (see [`start_tool_batch_span`]) and adopts the call
+//! spans as its children, numbered in the order they started with
+//! `llm.tool.batch.index`. When the batch is dropped it records:
+//!
+//! - `llm.tool.batch.mode`: `concurrent` if any calls overlapped, `serial`
+//!   otherwise;
+//! - `llm.tool.batch.max_in_flight`: the most calls running at once;
+//! - `llm.tool.batch.failed` and `llm.tool.batch.skipped`.
+//!
+//! ```ignore
+//! let batch = ToolBatch::start(calls.len());
+//! for call in calls {
+//!     let span = start_tool_span(&call.name);
+//!     batch.call_started(&span);
+//!     let result = run(call).instrument(span).await;
+//!     batch.call_finished(result.is_err());
+//! }
+//! ```
+//!
+//! Adopting works on spans that are still open, so it also applies to call
+//! spans created by a framework, such as rig's (see `llm-obs-rig`'s
+//! `tool_batch`).
+
+use crate::spans::start_tool_batch_span;
+use std::sync::{Mutex, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Default)]
+struct Counts {
+    started: usize,
+    in_flight: usize,
+    max_in_flight: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+/// The `execute_tools` span of one turn and its call counts.
+#[derive(Debug)]
+pub struct ToolBatch {
+    span: tracing::Span,
+    counts: Mutex<Counts>,
+}
+
+impl ToolBatch {
+    /// Opens the batch span under the current span for `size` tool calls.
+    pub fn start(size: usize) -> Self {
+        Self {
+            span: start_tool_batch_span(size),
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Makes `call_span` a child of the batch and counts the call as
+    /// running. Returns its index in the batch.
+    pub fn call_started(&self, call_span: &tracing::Span) -> usize {
+        call_span.set_parent(self.span.context());
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = counts.started;
+        counts.started += 1;
+        counts.in_flight += 1;
+        counts.max_in_flight = counts.max_in_flight.max(counts.in_flight);
+        call_span.set_attribute("llm.tool.batch.index", index as i64);
+        index
+    }
+
+    pub fn call_finished(&self, failed: bool) {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts.in_flight = counts.in_flight.saturating_sub(1);
+        if failed {
+            counts.failed += 1;
+        }
+    }
+
+    /// A started call that did not run, e.g. one rejected by a hook.
+    pub fn call_skipped(&self) {
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        counts.in_flight = counts.in_flight.saturating_sub(1);
+        counts.skipped += 1;
+    }
+}
+
+impl Drop for ToolBatch {
+    fn drop(&mut self) {
+        let counts = self
+            .counts
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mode = if counts.max_in_flight > 1 {
+            "concurrent"
+        } else {
+            "serial"
+        };
+        self.span.record("llm.tool.batch.mode", mode);
+        self.span
+            .record("llm.tool.batch.max_in_flight", counts.max_in_flight as u64);
+        self.span
+            .record("llm.tool.batch.failed", counts.failed as u64);
+        self.span
+            .record("llm.tool.batch.skipped", counts.skipped as u64);
+    }
+}
@@ -0,0 +1,229 @@
+//! Compact W3C `tracestate` vendor entry with session and cost hints.
+//!
+//! Baggage can carry the same data, but downstream services (and
+//! collectors) frequently read `tracestate` without parsing baggage. The
+//! `llmobs` entry carries a session ID and a coarse cumulative-cost bucket,
+//! e.g. `llmobs=s:abc123;c:2`, so sampling or priority decisions can be made
+//! from the trace context alone.
+
+use opentelemetry::Context;
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+
+pub const VENDOR_KEY: &str = "llmobs";
+const MAX_SESSION_ID_LEN: usize = 64;
+
+/// Hints carried in the vendor entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceHints {
+    pub session_id: Option<String>,
+    /// See [`cost_bucket`].
+    pub cost_bucket: Option<u8>,
+}
+
+impl TraceHints {
+    pub fn from_context(cx: &Context) -> Option<&TraceHints> {
+        cx.get::<TraceHints>()
+    }
+
+    pub fn encode(&self) -> String {
+        let mut fields = Vec::new();
+        if let Some(session_id) = &self.session_id {
+            let session_id: String = session_id
+                .chars()
+                .filter(|c| c.is_ascii_graphic() && !matches!(c, ',' | '=' | ';' | ':'))
+                .take(MAX_SESSION_ID_LEN)
+                .collect();
+            fields.push(format!("s:{session_id}"));
+        }
+        if let Some(bucket) = self.cost_bucket {
+            fields.push(format!("c:{bucket}"));
+        }
+        fields.join(";")
+    }
+
+    pub fn decode(value: &str) -> Self {
+        let mut hints = Self::default();
+        for field in value.split(';') {
+            match field.split_once(':') {
+                Some(("s", session_id)) if !session_id.is_empty() => {
+                    hints.session_id = Some(session_id.to_owned());
+                }
+                Some(("c", bucket)) => hints.cost_bucket = bucket.parse().ok(),
+                _ => {}
+            }
+        }
+        hints
+    }
+}
+
+/// Coarse bucket for cumulative cost: 0 (< $0.01), 1 (< $0.10),
+/// 2 (< $1), 3 (>= $1).
+pub fn cost_bucket(cost_usd: f64) -> u8 {
+    match cost_usd {
+        cost if cost < 0.01 => 0,
+        cost if cost < 0.10 => 1,
+        cost if cost < 1.0 => 2,
+        _ => 3,
+    }
+}
+
+/// Returns `cx` carrying `hints` for the next injection.
+pub fn with_trace_hints(cx: &Context, hints: TraceHints) -> Context {
+    cx.with_value(hints)
+}
+
+/// W3C trace-context propagator that also writes and reads the `llmobs`
+/// vendor entry. Install it in place of `TraceContextPropagator`.
+#[derive(Debug, Default)]
+pub struct HintsPropagator {
+    inner: TraceContextPropagator,
+}
+
+impl HintsPropagator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TextMapPropagator for HintsPropagator {
+    fn inject_context(&self, cx: &Context, injector: &mut dyn Injector) {
+        let span_context = cx.span().span_context().clone();
+        let Some(hints) = TraceHints::from_context(cx).filter(|_| span_context.is_valid()) else {
+            self.inner.inject_context(cx, injector);
+            return;
+        };
+
+        let trace_state = match span_context
+            .trace_state()
+            .insert(VENDOR_KEY, hints.encode())
+        {
+            Ok(trace_state) => trace_state,
+            Err(error) => {
+                tracing::debug!(%error, "Failed to add tracestate vendor entry");
+                span_context.trace_state().clone()
+            }
+        };
+        let hinted = SpanContext::new(
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags(),
+            span_context.is_remote(),
+            trace_state,
+        );
+        self.inner
+            .inject_context(&cx.with_remote_span_context(hinted), injector);
+    }
+
+    fn extract_with_context(&self, cx: &Context, extractor: &dyn Extractor) -> Context {
+        let extracted = self.inner.extract_with_context(cx, extractor);
+        let hints = extracted
+            .span()
+            .span_context()
+            .trace_state()
+            .get(VENDOR_KEY)
+            .map(TraceHints::decode);
+        match hints {
+            Some(hints) => extracted.with_value(hints),
+            None => extracted,
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+    use std::collections::HashMap;
+
+    fn hints(session_id: &str, cost_bucket: u8) -> TraceHints {
+        TraceHints {
+            session_id: Some(session_id.to_owned()),
+            cost_bucket: Some(cost_bucket),
+        }
+    }
+
+    fn sampled_context() -> Context {
+        Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from(1),
+            SpanId::from(2),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ))
+    }
+
+    #[test]
+    fn encodes_and_decodes_hints() {
+        let hints = hints("abc123", 2);
+
+        assert_eq!(hints.encode(), "s:abc123;c:2");
+        assert_eq!(TraceHints::decode(&hints.encode()), hints);
+        assert_eq!(TraceHints::decode(""), TraceHints::default());
+    }
+
+    #[test]
+    fn ignores_malformed_fields() {
+        assert_eq!(
+            TraceHints::decode("s:;c:many;garbage;x:1;c:300"),
+            TraceHints::default()
+        );
+        assert_eq!(TraceHints::decode("c:1;s:abc;"), hints("abc", 1));
+    }
+
+    #[test]
+    fn truncates_and_sanitizes_session_ids() {
+        let long = "a".repeat(300);
+        assert_eq!(
+            hints(&long, 0).encode(),
+            format!("s:{};c:0", "a".repeat(MAX_SESSION_ID_LEN))
+        );
+        assert_eq!(hints("a b,c=d;e:f", 0).encode(), "s:abcdef;c:0");
+    }
+
+    #[test]
+    fn buckets_costs() {
+        assert_eq!(cost_bucket(0.0), 0);
+        assert_eq!(cost_bucket(0.01), 1);
+        assert_eq!(cost_bucket(0.5), 2);
+        assert_eq!(cost_bucket(12.0), 3);
+    }
+
+    #[test]
+    fn propagates_hints_through_tracestate() {
+        let propagator = HintsPropagator::new();
+        let cx = with_trace_hints(&sampled_context(), hints(&"s".repeat(300), 2));
+        let mut headers = HashMap::new();
+        propagator.inject_context(&cx, &mut headers);
+
+        assert_eq!(
+            headers.get("tracestate").map(String::as_str),
+            Some(format!("llmobs=s:{};c:2", "s".repeat(MAX_SESSION_ID_LEN)).as_str())
+        );
+        let extracted = propagator.extract(&headers);
+        assert_eq!(
+            TraceHints::from_context(&extracted),
+            Some(&hints(&"s".repeat(MAX_SESSION_ID_LEN), 2))
+        );
+        assert_eq!(extracted.span().span_context().trace_id(), TraceId::from(1));
+    }
+
+    #[test]
+    fn extracts_no_hints_without_the_vendor_entry() {
+        let propagator = HintsPropagator::new();
+        let mut headers = HashMap::new();
+        propagator.inject_context(&sampled_context(), &mut headers);
+
+        assert!(headers.get("tracestate").is_none_or(String::is_empty));
+        assert_eq!(
+            TraceHints::from_context(&propagator.extract(&headers)),
+            None
+        );
+    }
+}
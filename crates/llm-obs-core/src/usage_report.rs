@@ -0,0 +1,292 @@
+//! Periodic usage reports posted to a webhook.
+//!
+//! Finance and on-call channels want "what did we spend and how often did it
+//! fail" without opening a dashboard. [`UsageAggregator`] is a metric
+//! exporter that folds the GenAI semconv metrics (`gen_ai.client.*`) into
+//! per-model totals in process; [`UsageWebhook`] periodically POSTs those
+//! totals as JSON (or as a Slack message) and resets them.
+//!
+//! Register the aggregator like any other exporter:
+//! `SdkMeterProvider::builder().with_reader(PeriodicReader::builder(aggregator.clone()).build())`.
+
+use crate::cost::ModelPricing;
+use crate::tokens::TokenUsage;
+use anyhow::Context;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Totals for one request model since the previous report.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ModelUsage {
+    pub calls: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Set when a price is known for the model.
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageReport {
+    pub period_start_unix_ms: u128,
+    pub period_end_unix_ms: u128,
+    pub models: BTreeMap<String, ModelUsage>,
+}
+
+impl UsageReport {
+    pub fn is_empty(&self) -> bool {
+        self.models.values().all(|usage| usage.calls == 0)
+    }
+
+    pub fn total_cost_usd(&self) -> f64 {
+        self.models
+            .values()
+            .filter_map(|usage| usage.cost_usd)
+            .sum()
+    }
+
+    /// One-line-per-model text for chat webhooks.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!(
+            "LLM usage: {} calls, ${:.4}",
+            self.models.values().map(|usage| usage.calls).sum::<u64>(),
+            self.total_cost_usd()
+        )];
+        for (model, usage) in &self.models {
+            lines.push(format!(
+                "• {model}: {} calls, {} errors, {} in / {} out tokens{}",
+                usage.calls,
+                usage.errors,
+                usage.input_tokens,
+                usage.output_tokens,
+                usage
+                    .cost_usd
+                    .map(|cost| format!(", ${cost:.4}"))
+                    .unwrap_or_default()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Metric exporter that accumulates GenAI usage per model in memory.
+#[derive(Debug, Clone)]
+pub struct UsageAggregator {
+    state: Arc<Mutex<(u128, BTreeMap<String, ModelUsage>)>>,
+}
+
+impl Default for UsageAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsageAggregator {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new((unix_ms_now(), BTreeMap::new()))),
+        }
+    }
+
+    /// Returns the totals since the previous call and starts a new period.
+    pub fn take_report(&self) -> UsageReport {
+        let now = unix_ms_now();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let (period_start, models) = std::mem::replace(&mut *state, (now, BTreeMap::new()));
+        UsageReport {
+            period_start_unix_ms: period_start,
+            period_end_unix_ms: now,
+            models,
+        }
+    }
+
+    fn add(&self, metrics: &ResourceMetrics) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let models = &mut state.1;
+        for metric in metrics
+            .scope_metrics()
+            .flat_map(|scope_metrics| scope_metrics.metrics())
+        {
+            match (metric.name(), metric.data()) {
+                (
+                    "gen_ai.client.operation.duration",
+                    AggregatedMetrics::F64(MetricData::Histogram(histogram)),
+                ) => {
+                    for point in histogram.data_points() {
+                        let attributes: Vec<&KeyValue> = point.attributes().collect();
+                        let usage = models.entry(model_of(&attributes)).or_default();
+                        usage.calls += point.count();
+                        if has_key(&attributes, "error.type") {
+                            usage.errors += point.count();
+                        }
+                    }
+                }
+                (
+                    "gen_ai.client.token.usage",
+                    AggregatedMetrics::U64(MetricData::Histogram(histogram)),
+                ) => {
+                    for point in histogram.data_points() {
+                        let attributes: Vec<&KeyValue> = point.attributes().collect();
+                        let usage = models.entry(model_of(&attributes)).or_default();
+                        match value_of(&attributes, "gen_ai.token.type").as_deref() {
+                            Some("input") => usage.input_tokens += point.sum(),
+                            Some("output") => usage.output_tokens += point.sum(),
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn value_of(attributes: &[&KeyValue], key: &str) -> Option<String> {
+    attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.as_str().into_owned())
+}
+
+fn has_key(attributes: &[&KeyValue], key: &str) -> bool {
+    attributes.iter().any(|kv| kv.key.as_str() == key)
+}
+
+fn model_of(attributes: &[&KeyValue]) -> String {
+    value_of(attributes, "gen_ai.request.model").unwrap_or_else(|| "unknown".to_owned())
+}
+
+fn unix_ms_now() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis())
+        .unwrap_or_default()
+}
+
+impl PushMetricExporter for UsageAggregator {
+    async fn export(&self, metrics: &ResourceMetrics) -> OTelSdkResult {
+        self.add(metrics);
+        Ok(())
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+
+    /// Delta, so every export adds only what happened since the last one.
+    fn temporality(&self) -> Temporality {
+        Temporality::Delta
+    }
+}
+
+/// Body shape expected by the receiving endpoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The [`UsageReport`] serialized as JSON.
+    #[default]
+    Json,
+    /// `{"text": ...}` as accepted by Slack incoming webhooks.
+    Slack,
+}
+
+type PricingFn = Box<dyn Fn(&str) -> Option<ModelPricing> + Send + Sync>;
+
+pub struct UsageWebhook {
+    url: String,
+    aggregator: UsageAggregator,
+    interval: Duration,
+    format: WebhookFormat,
+    pricing: Option<PricingFn>,
+    client: reqwest::Client,
+}
+
+impl UsageWebhook {
+    pub fn new(url: impl Into<String>, aggregator: UsageAggregator) -> Self {
+        Self {
+            url: url.into(),
+            aggregator,
+            interval: Duration::from_secs(3600),
+            format: WebhookFormat::default(),
+            pricing: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_format(mut self, format: WebhookFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Prices used to fill in `cost_usd` per model.
+    pub fn with_pricing<F>(mut self, pricing: F) -> Self
+    where
+        F: Fn(&str) -> Option<ModelPricing> + Send + Sync + 'static,
+    {
+        self.pricing = Some(Box::new(pricing));
+        self
+    }
+
+    /// Takes the current totals and POSTs them; empty periods are skipped.
+    pub async fn send_report(&self) -> anyhow::Result<()> {
+        let mut report = self.aggregator.take_report();
+        if report.is_empty() {
+            return Ok(());
+        }
+        if let Some(pricing) = &self.pricing {
+            for (model, usage) in report.models.iter_mut() {
+                usage.cost_usd = pricing(model).map(|pricing| {
+                    pricing
+                        .cost(&TokenUsage {
+                            input_tokens: usage.input_tokens,
+                            output_tokens: usage.output_tokens,
+                            reasoning_tokens: 0,
+                        })
+                        .total_usd()
+                });
+            }
+        }
+
+        let body = match self.format {
+            WebhookFormat::Json => serde_json::to_value(&report)?,
+            WebhookFormat::Slack => serde_json::json!({ "text": report.to_text() }),
+        };
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await
+            .context("Failed to send usage report")?
+            .error_for_status()
+            .context("Usage webhook rejected the report")?;
+        Ok(())
+    }
+
+    /// Sends a report every `interval` until the task is dropped. Spawn it
+    /// with `tokio::spawn` and abort it on shutdown.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(error) = self.send_report().await {
+                tracing::warn!(error = %format!("{error:#}"), "Failed to post usage report");
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Watchdog for orchestration steps that run far longer than expected.
+//!
+//! A stuck agent loop only becomes visible once its span ends, which may be
+//! never. Steps registered with [`Watchdog::watch`] declare how long they
+//! usually take; a background check emits a warning event on the step's span
+//! and increments `llm.orchestration.overdue_steps` as soon as a step has been
+//! running for `threshold` times its expectation, while it is still running.
+//! Alert on the counter to catch stuck agents before the timeouts do.
+
+use crate::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Default multiple of the expected duration at which a step is flagged.
+pub const DEFAULT_THRESHOLD: f64 = 3.0;
+
+/// Default cadence of the background check.
+pub const DEFAULT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+struct WatchedStep {
+    name: String,
+    expected: Duration,
+    started: Instant,
+    span: tracing::Span,
+    flagged: bool,
+}
+
+/// A step that ran past its threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverdueStep {
+    pub id: u64,
+    pub name: String,
+    pub expected: Duration,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone)]
+pub struct Watchdog {
+    steps: Arc<Mutex<HashMap<u64, WatchedStep>>>,
+    next_id: Arc<AtomicU64>,
+    threshold: f64,
+    check_interval: Duration,
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            steps: Arc::default(),
+            next_id: Arc::default(),
+            threshold: DEFAULT_THRESHOLD,
+            check_interval: DEFAULT_CHECK_INTERVAL,
+        }
+    }
+
+    /// Flags steps once they run for `threshold` × their expected duration.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    /// Watches a step of the current span until the returned guard drops.
+    /// Create it inside the step's span so warnings land on that span.
+    pub fn watch(&self, name: &str, expected: Duration) -> WatchGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock().insert(
+            id,
+            WatchedStep {
+                name: name.to_owned(),
+                expected,
+                started: Instant::now(),
+                span: tracing::Span::current(),
+                flagged: false,
+            },
+        );
+        WatchGuard {
+            watchdog: self.clone(),
+            id,
+        }
+    }
+
+    /// Flags every step that newly crossed its threshold and returns them.
+    /// [`Watchdog::run`] calls this periodically; each step is flagged once.
+    pub fn check(&self) -> Vec<OverdueStep> {
+        let mut overdue = Vec::new();
+        for (id, step) in self.lock().iter_mut() {
+            let elapsed = step.started.elapsed();
+            if step.flagged || elapsed.as_secs_f64() < step.expected.as_secs_f64() * self.threshold
+            {
+                continue;
+            }
+            step.flagged = true;
+
+            tracing::warn!(
+                parent: &step.span,
+                orchestration.step = %step.name,
+                orchestration.expected_ms = step.expected.as_millis() as u64,
+                orchestration.elapsed_ms = elapsed.as_millis() as u64,
+                "Orchestration step exceeded its expected duration"
+            );
+            overdue_counter().add(1, &[KeyValue::new("orchestration.step", step.name.clone())]);
+            overdue.push(OverdueStep {
+                id: *id,
+                name: step.name.clone(),
+                expected: step.expected,
+                elapsed,
+            });
+        }
+        overdue
+    }
+
+    /// Checks every `check_interval` until the task is dropped. Spawn it with
+    /// `tokio::spawn` and abort it on shutdown.
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(self.check_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, WatchedStep>> {
+        self.steps.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keeps a step watched while alive.
+pub struct WatchGuard {
+    watchdog: Watchdog,
+    id: u64,
+}
+
+impl WatchGuard {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        self.watchdog.lock().remove(&self.id);
+    }
+}
+
+fn overdue_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Agent
+            .meter()
+            .u64_counter("llm.orchestration.overdue_steps")
+            .with_description("Orchestration steps still running past their expected duration")
+            .build()
+    })
+}
@@ -0,0 +1,243 @@
+//! Runtime behavior of `#[llm_span]`: spans, captured content and errors for
+//! sync and async functions.
+
+use llm_obs_core::llm_span;
+use opentelemetry::trace::{Status, TracerProvider as _};
+use opentelemetry::{Context, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{SdkTracerProvider, Span, SpanData, SpanProcessor};
+use std::fmt;
+use std::num::ParseIntError;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+
+const MODEL: &str = "gemini-2.5-flash";
+
+#[derive(Debug, Clone, Default)]
+struct Collect(Arc<Mutex<Vec<SpanData>>>);
+
+impl SpanProcessor for Collect {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+/// Collects the spans ended while the returned guard is alive, on this
+/// thread.
+fn collect() -> (Collect, tracing::subscriber::DefaultGuard) {
+    let collect = Collect::default();
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(collect.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    (collect, tracing::subscriber::set_default(subscriber))
+}
+
+impl Collect {
+    fn only(&self) -> SpanData {
+        let spans = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(spans.len(), 1, "{spans:?}");
+        spans[0].clone()
+    }
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
+fn string(span: &SpanData, key: &str) -> Option<String> {
+    attribute(span, key).map(|value| value.as_str().into_owned())
+}
+
+#[llm_span(model = MODEL, provider = "gcp.gemini")]
+fn answer(question: &str, attempt: u32) -> String {
+    format!("{question} -> 4 (attempt {attempt})")
+}
+
+#[test]
+fn sync_functions_run_in_a_model_span() {
+    let (collect, _guard) = collect();
+    assert_eq!(answer("2 + 2", 1), "2 + 2 -> 4 (attempt 1)");
+
+    let span = collect.only();
+    assert_eq!(span.name, "chat gemini-2.5-flash");
+    assert_eq!(span.span_kind, opentelemetry::trace::SpanKind::Client);
+    assert_eq!(
+        string(&span, "gen_ai.provider.name").as_deref(),
+        Some("gcp.gemini")
+    );
+    assert_eq!(
+        string(&span, "gen_ai.input.messages").as_deref(),
+        Some(r#"{"attempt":"1","question":"2 + 2"}"#)
+    );
+    assert_eq!(
+        string(&span, "gen_ai.output.messages").as_deref(),
+        Some("2 + 2 -> 4 (attempt 1)")
+    );
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[llm_span(operation = "generate_content", model = MODEL)]
+async fn parse(text: &str) -> Result<u32, ParseIntError> {
+    let value: u32 = text.trim().parse()?;
+    Ok(value * 2)
+}
+
+#[tokio::test]
+async fn async_results_record_ok_values() {
+    let (collect, _guard) = collect();
+    assert_eq!(parse(" 21 ").await, Ok(42));
+
+    let span = collect.only();
+    assert_eq!(span.name, "generate_content gemini-2.5-flash");
+    assert_eq!(
+        string(&span, "gen_ai.output.messages").as_deref(),
+        Some("42")
+    );
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[tokio::test]
+async fn an_early_question_mark_sets_the_error_status() {
+    let (collect, _guard) = collect();
+    assert!(parse("many").await.is_err());
+
+    let span = collect.only();
+    assert_eq!(
+        string(&span, "error.type").as_deref(),
+        Some("ParseIntError")
+    );
+    assert!(matches!(span.status, Status::Error { .. }));
+    assert_eq!(attribute(&span, "gen_ai.output.messages"), None);
+}
+
+#[derive(Debug)]
+struct ToolError;
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("divide by zero")
+    }
+}
+
+#[llm_span(tool = "divide")]
+fn divide(x: i32, y: i32) -> Result<i32, ToolError> {
+    if y == 0 {
+        return Err(ToolError);
+    }
+    Ok(x / y)
+}
+
+#[test]
+fn an_early_return_from_a_sync_tool_is_recorded() {
+    let (collect, _guard) = collect();
+    assert!(divide(1, 0).is_err());
+
+    let span = collect.only();
+    assert_eq!(span.name, "execute_tool divide");
+    assert_eq!(
+        string(&span, "gen_ai.tool.call.arguments").as_deref(),
+        Some(r#"{"x":"1","y":"0"}"#)
+    );
+    assert_eq!(string(&span, "error.type").as_deref(), Some("ToolError"));
+    assert_eq!(
+        span.status,
+        Status::Error {
+            description: "divide by zero".into()
+        }
+    );
+}
+
+#[llm_span(tool = "lookup")]
+async fn lookup(city: &str) -> Option<u32> {
+    if city.is_empty() {
+        return None;
+    }
+    Some(18)
+}
+
+#[tokio::test]
+async fn an_early_return_from_an_async_function_is_recorded() {
+    let (collect, _guard) = collect();
+    assert_eq!(lookup("").await, None);
+
+    let span = collect.only();
+    assert_eq!(
+        string(&span, "gen_ai.tool.call.result").as_deref(),
+        Some("None")
+    );
+}
+
+#[llm_span(tool = "greeting")]
+fn greeting(name: &str) -> impl fmt::Display {
+    format!("hello {name}")
+}
+
+#[llm_span(tool = "countdown", skip_output)]
+async fn countdown(from: u32) -> impl Iterator<Item = u32> {
+    (0..=from).rev()
+}
+
+#[tokio::test]
+async fn impl_trait_returns_are_supported() {
+    let (collect, _guard) = collect();
+    assert_eq!(greeting("ada").to_string(), "hello ada");
+    assert_eq!(countdown(2).await.collect::<Vec<_>>(), [2, 1, 0]);
+
+    let spans = collect.0.lock().unwrap_or_else(PoisonError::into_inner);
+    let greeting = spans
+        .iter()
+        .find(|span| span.name == "execute_tool greeting")
+        .unwrap();
+    assert_eq!(
+        string(greeting, "gen_ai.tool.call.result").as_deref(),
+        Some("hello ada")
+    );
+    let countdown = spans
+        .iter()
+        .find(|span| span.name == "execute_tool countdown")
+        .unwrap();
+    assert_eq!(attribute(countdown, "gen_ai.tool.call.result"), None);
+}
+
+struct Agent {
+    api_key: &'static str,
+}
+
+impl Agent {
+    #[llm_span(model = MODEL, skip(self, secret))]
+    fn ask(&self, question: &str, secret: &str) -> usize {
+        self.api_key.len() + question.len() + secret.len()
+    }
+}
+
+#[test]
+fn skipped_arguments_are_not_captured() {
+    let (collect, _guard) = collect();
+    let agent = Agent { api_key: "key" };
+    assert_eq!(agent.ask("why?", "hunter2"), 14);
+
+    let span = collect.only();
+    assert_eq!(
+        string(&span, "gen_ai.input.messages").as_deref(),
+        Some(r#"{"question":"why?"}"#)
+    );
+}
@@ -0,0 +1,371 @@
+//! Attribute macros for `llm-obs-core`. Use them through the re-export,
+//! `llm_obs_core::llm_span`; the generated code refers to runtime support in
+//! that crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Expr, FnArg, Ident, ItemFn, LitStr, Pat, Path, ReturnType, Type, parse_macro_input};
+
+/// Like `#[tracing::instrument]`, but the span is a GenAI span with the
+/// semantic-convention name, kind and attributes, the arguments and return
+/// value are recorded as sanitized content, and errors set the span status.
+///
+/// ```ignore
+/// #[llm_span(operation = "chat", model = MODEL, provider = "gcp.gemini", skip(agent))]
+/// async fn ask(agent: &Agent<Model>, question: &str) -> anyhow::Result<String> {
+///     Ok(agent.prompt(question).await?)
+/// }
+///
+/// #[llm_span(tool = "add")]
+/// fn add(x: i32, y: i32) -> i32 {
+///     x + y
+/// }
+/// ```
+///
+/// With `model = ..` the function runs in a `{operation} {model}` CLIENT span
+/// (see `spans::start_llm_span`, `operation` defaults to `"chat"`) and the
+/// arguments and return value go to `gen_ai.input.messages` and
+/// `gen_ai.output.messages`. With `tool = ..` it is an `execute_tool {name}`
+/// span with `gen_ai.tool.call.arguments` and `gen_ai.tool.call.result`.
+/// `model`, `provider` and `tool` take any expression that derefs to `str`.
+///
+/// Arguments are captured by `Display` where implemented, `Debug`
+/// otherwise, as a JSON object keyed by parameter name. Captured text has
+/// emails, API keys and card numbers scrubbed and then goes through
+/// `LLM_CONTENT_CAPTURE` (see `processors::capture`), so `off` records
+/// nothing.
+///
+/// When the return type is a `Result` (any path ending in `Result`), an
+/// `Err` sets the span status to error with the error's `Display` text and
+/// `error.type` to the short error type name; only `Ok` values are captured.
+///
+/// Options:
+/// - `skip(a, b)`: do not capture these arguments (`self` is accepted and
+///   never captured anyway);
+/// - `skip_all`: capture no arguments;
+/// - `skip_output`: do not capture the return value;
+/// - `crate = path`: where `llm-obs-core` is reachable, for code that
+///   depends on it only through a re-exporting crate, e.g.
+///   `crate = rust_llm_observability_guide`.
+#[proc_macro_attribute]
+pub fn llm_span(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| args.parse(meta));
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Args {
+    operation: Option<LitStr>,
+    model: Option<Expr>,
+    provider: Option<Expr>,
+    tool: Option<Expr>,
+    skip: Vec<Ident>,
+    skip_all: bool,
+    skip_output: bool,
+    krate: Option<Path>,
+}
+
+impl Args {
+    fn parse(&mut self, meta: syn::meta::ParseNestedMeta<'_>) -> syn::Result<()> {
+        if meta.path.is_ident("operation") {
+            self.operation = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("model") {
+            self.model = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("provider") {
+            self.provider = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("tool") {
+            self.tool = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("skip") {
+            meta.parse_nested_meta(|inner| {
+                self.skip.push(inner.path.require_ident()?.clone());
+                Ok(())
+            })?;
+        } else if meta.path.is_ident("skip_all") {
+            self.skip_all = true;
+        } else if meta.path.is_ident("skip_output") {
+            self.skip_output = true;
+        } else if meta.path.is_ident("crate") {
+            self.krate = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error(
+                "expected `operation`, `model`, `provider`, `tool`, `skip`, `skip_all`, \
+                 `skip_output` or `crate`",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn expand(args: Args, function: ItemFn) -> syn::Result<TokenStream2> {
+    let krate = args
+        .krate
+        .clone()
+        .unwrap_or_else(|| syn::parse_quote!(::llm_obs_core));
+    let support = quote!(#krate::__private);
+
+    let (start_span, input_key, output_key) = match (&args.model, &args.tool) {
+        (Some(model), None) => {
+            let operation = args
+                .operation
+                .clone()
+                .unwrap_or_else(|| LitStr::new("chat", proc_macro2::Span::call_site()));
+            let provider = match &args.provider {
+                Some(provider) => quote!(::core::option::Option::Some(
+                    ::core::convert::AsRef::<str>::as_ref(&(#provider))
+                )),
+                None => quote!(::core::option::Option::None),
+            };
+            (
+                quote!(#support::llm_span(
+                    #operation,
+                    ::core::convert::AsRef::<str>::as_ref(&(#model)),
+                    #provider,
+                )),
+                "gen_ai.input.messages",
+                "gen_ai.output.messages",
+            )
+        }
+        (None, Some(tool)) => {
+            if args.operation.is_some() || args.provider.is_some() {
+                return Err(syn::Error::new(
+                    proc_macro2::Span::call_site(),
+                    "`operation` and `provider` apply to model spans, not `tool`",
+                ));
+            }
+            (
+                quote!(#support::tool_span(::core::convert::AsRef::<str>::as_ref(&(#tool)))),
+                "gen_ai.tool.call.arguments",
+                "gen_ai.tool.call.result",
+            )
+        }
+        (Some(_), Some(_)) => {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "expected either `model = ..` or `tool = ..`, not both",
+            ));
+        }
+        (None, None) => {
+            return Err(syn::Error::new(
+                proc_macro2::Span::call_site(),
+                "expected `model = ..` or `tool = ..`",
+            ));
+        }
+    };
+
+    let captured = captured_arguments(&args, &function)?;
+    let record_input = if captured.is_empty() {
+        quote!()
+    } else {
+        let names = captured.iter().map(|ident| ident.to_string());
+        quote! {
+            #support::record_input(&__llm_span, #input_key, &[
+                #((#names, (&#support::Captured(&#captured)).capture()),)*
+            ]);
+        }
+    };
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    let return_type = match &sig.output {
+        ReturnType::Default => Some(quote!(())),
+        ReturnType::Type(_, ty) if contains_impl_trait(ty) => None,
+        ReturnType::Type(_, ty) => Some(quote!(#ty)),
+    };
+    let is_result = match &sig.output {
+        ReturnType::Type(_, ty) => is_result(ty),
+        ReturnType::Default => false,
+    };
+
+    let body = match (&sig.asyncness, &return_type) {
+        (Some(_), Some(ty)) => quote! {
+            #support::Instrument::instrument(
+                async move {
+                    let __llm_return: #ty = #block;
+                    __llm_return
+                },
+                ::core::clone::Clone::clone(&__llm_span),
+            )
+            .await
+        },
+        (Some(_), None) => quote! {
+            #support::Instrument::instrument(
+                async move #block,
+                ::core::clone::Clone::clone(&__llm_span),
+            )
+            .await
+        },
+        (None, Some(ty)) => quote!(__llm_span.in_scope(|| -> #ty #block)),
+        (None, None) => quote!(__llm_span.in_scope(|| #block)),
+    };
+
+    let skip_output = args.skip_output || matches!(sig.output, ReturnType::Default);
+    let record_output = match (is_result, skip_output) {
+        (true, false) => quote! {
+            match &__llm_result {
+                ::core::result::Result::Ok(output) => #support::record_output(
+                    &__llm_span,
+                    #output_key,
+                    (&#support::Captured(output)).capture(),
+                ),
+                ::core::result::Result::Err(error) => #support::record_error(&__llm_span, error),
+            }
+        },
+        (true, true) => quote! {
+            if let ::core::result::Result::Err(error) = &__llm_result {
+                #support::record_error(&__llm_span, error);
+            }
+        },
+        (false, false) => quote! {
+            #support::record_output(
+                &__llm_span,
+                #output_key,
+                (&#support::Captured(&__llm_result)).capture(),
+            );
+        },
+        (false, true) => quote!(),
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #[allow(unused_imports)]
+            use #support::{CaptureDebug as _, CaptureDisplay as _};
+            let __llm_span = #start_span;
+            #record_input
+            let __llm_result = #body;
+            #record_output
+            __llm_result
+        }
+    })
+}
+
+/// Parameters to capture, in declaration order. `self` is never captured,
+/// but may be named in `skip` like in `#[tracing::instrument]`.
+fn captured_arguments(args: &Args, function: &ItemFn) -> syn::Result<Vec<Ident>> {
+    let mut parameters = Vec::new();
+    let mut has_receiver = false;
+    for input in &function.sig.inputs {
+        match input {
+            FnArg::Receiver(_) => has_receiver = true,
+            FnArg::Typed(typed) => {
+                if let Pat::Ident(pat) = typed.pat.as_ref() {
+                    parameters.push(pat.ident.clone());
+                }
+            }
+        }
+    }
+    for skipped in &args.skip {
+        let is_receiver = has_receiver && skipped == "self";
+        if !is_receiver && !parameters.contains(skipped) {
+            return Err(syn::Error::new(
+                skipped.span(),
+                format!("`{skipped}` is not a parameter of this function"),
+            ));
+        }
+    }
+    if args.skip_all {
+        return Ok(Vec::new());
+    }
+    parameters.retain(|parameter| !args.skip.contains(parameter));
+    Ok(parameters)
+}
+
+fn is_result(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Result"),
+        _ => false,
+    }
+}
+
+fn contains_impl_trait(ty: &Type) -> bool {
+    match ty {
+        Type::ImplTrait(_) => true,
+        Type::Path(path) => path.path.segments.iter().any(|segment| {
+            let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+                return false;
+            };
+            arguments.args.iter().any(|argument| match argument {
+                syn::GenericArgument::Type(ty) => contains_impl_trait(ty),
+                _ => false,
+            })
+        }),
+        Type::Reference(reference) => contains_impl_trait(&reference.elem),
+        Type::Tuple(tuple) => tuple.elems.iter().any(contains_impl_trait),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn detects_result_types_by_their_last_segment() {
+        assert!(is_result(&parse_quote!(Result<u32, Error>)));
+        assert!(is_result(&parse_quote!(anyhow::Result<String>)));
+        assert!(is_result(&parse_quote!(std::io::Result<()>)));
+        assert!(!is_result(&parse_quote!(Option<u32>)));
+        assert!(!is_result(&parse_quote!(&Result<u32, Error>)));
+    }
+
+    #[test]
+    fn finds_nested_impl_trait() {
+        assert!(contains_impl_trait(&parse_quote!(impl Display)));
+        assert!(contains_impl_trait(&parse_quote!(
+            Result<impl Display, Error>
+        )));
+        assert!(contains_impl_trait(&parse_quote!((
+            u32,
+            impl Iterator<Item = u32>
+        ))));
+        assert!(!contains_impl_trait(&parse_quote!(Box<dyn Display>)));
+    }
+
+    #[test]
+    fn expands_sync_and_async_bodies_differently() {
+        let args = Args {
+            tool: Some(parse_quote!("add")),
+            ..Args::default()
+        };
+        let sync = expand(
+            args,
+            parse_quote!(
+                fn add(x: i32) -> i32 {
+                    x
+                }
+            ),
+        )
+        .unwrap();
+        assert!(sync.to_string().contains("in_scope"));
+
+        let args = Args {
+            tool: Some(parse_quote!("add")),
+            ..Args::default()
+        };
+        let asynchronous = expand(
+            args,
+            parse_quote!(
+                async fn add(x: i32) -> i32 {
+                    x
+                }
+            ),
+        )
+        .unwrap();
+        assert!(asynchronous.to_string().contains("instrument"));
+    }
+}
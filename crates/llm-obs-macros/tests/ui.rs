@@ -0,0 +1,6 @@
+//! Compile errors for invalid `#[llm_span]` arguments.
+
+#[test]
+fn ui() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,8 @@
+use llm_obs_macros::llm_span;
+
+#[llm_span(model = "gemini-2.5-flash", tool = "add")]
+fn add(x: i32, y: i32) -> i32 {
+    x + y
+}
+
+fn main() {}
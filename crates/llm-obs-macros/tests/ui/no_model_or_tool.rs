@@ -0,0 +1,8 @@
+use llm_obs_macros::llm_span;
+
+#[llm_span(skip_all)]
+fn add(x: i32, y: i32) -> i32 {
+    x + y
+}
+
+fn main() {}
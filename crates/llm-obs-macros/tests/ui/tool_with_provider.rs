@@ -0,0 +1,8 @@
+use llm_obs_macros::llm_span;
+
+#[llm_span(tool = "add", provider = "openai")]
+fn add(x: i32, y: i32) -> i32 {
+    x + y
+}
+
+fn main() {}
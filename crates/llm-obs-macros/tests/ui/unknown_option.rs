@@ -0,0 +1,8 @@
+use llm_obs_macros::llm_span;
+
+#[llm_span(model = "gemini-2.5-flash", temperature = 0.2)]
+fn ask(question: &str) -> String {
+    question.to_owned()
+}
+
+fn main() {}
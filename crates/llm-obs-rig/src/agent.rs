@@ -0,0 +1,649 @@
+//! Rig agent wrapper that records token usage on the active span.
+//!
+//! When a prompt runs inside an application span, rig records
+//! `gen_ai.usage.*` with `Span::record`, which silently drops fields the
+//! caller's span did not declare up front; token consumption then never
+//! reaches the backend. `InstrumentedAgent` requests rig's aggregated usage
+//! (summed over every turn, including tool round trips) and sets it as
+//! OpenTelemetry attributes on the active span, which needs no declaration.
+//!
+//! Wrapping an agent also emits one `create_agent` span with the model and
+//! request parameters. The same `gen_ai.request.*` attributes (temperature,
+//! max tokens, and top_p, top_k, penalties, seed and stop sequences from the
+//! agent's additional params), `gen_ai.provider.name` and the preamble
+//! fingerprint are set on the active span of every call, so callers do not
+//! declare them on their own spans. The provider is looked up by model type
+//! (see [`provider_name`]), so Gemini and OpenAI agents produce the same
+//! span schema. A Gemini `cachedContent` in the additional params
+//! is set as `gen_ai.gemini.cache.name` and links each call's span to the
+//! span that created the cache (see [`crate::gemini::files`]).
+//!
+//! [`InstrumentedAgent::stream_prompt`] does the same for streaming answers
+//! and adds time-to-first-token and chunk timing (see [`llm_obs_core::streaming`]).
+//!
+//! Every call is listed in the in-flight registry while it runs (see
+//! [`llm_obs_core::inflight`]), streams with an estimate of the tokens
+//! received so far, and recorded in the GenAI client histograms (see
+//! [`crate::call_metrics`]) when it ends. Agents on this crate's model
+//! wrappers, which record each completion themselves, are not recorded
+//! again unless streaming.
+//!
+//! The provider's response id of every model call a prompt makes (see
+//! [`response_id`]) is collected in a [`ProviderRequestIds`] scope and set
+//! as `gen_ai.provider.request_ids` on the active span, and passed on to an
+//! enclosing scope, e.g. one spanning fallbacks between agents.
+
+use crate::call_metrics::{self, Call, UNKNOWN_PROVIDER, prompt_error_type, token_usage};
+use crate::gemini::files::link_cached_content;
+use crate::history::record_history;
+use crate::tool_batch_hook::ToolBatchHook;
+use futures_core::Stream;
+use llm_obs_core::chat_session::ChatSession;
+use llm_obs_core::inflight::{InflightGuard, InflightRegistry};
+use llm_obs_core::prompt_fingerprint::PromptFingerprints;
+use llm_obs_core::request_ids::ProviderRequestIds;
+use llm_obs_core::streaming::StreamRecorder;
+use llm_obs_core::tokens::estimate_tokens;
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use rig::agent::{
+    Agent, MultiTurnStreamItem, PromptHook, PromptRequest, PromptResponse, StreamingError,
+    StreamingResult,
+};
+use rig::completion::{CompletionModel, GetTokenUsage, Message, PromptError, Usage};
+use rig::providers;
+use rig::providers::gemini::completion::gemini_api_types::GenerateContentResponse;
+use rig::streaming::{StreamedAssistantContent, StreamingPrompt};
+use rig::telemetry::ProviderResponseExt;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub struct InstrumentedAgent<M: CompletionModel, P: PromptHook<M> = ()> {
+    agent: Agent<M, P>,
+    model: String,
+    max_turns: Option<usize>,
+    tool_concurrency: Option<usize>,
+    request_attributes: Vec<KeyValue>,
+    inflight: InflightRegistry,
+}
+
+impl<M, P> InstrumentedAgent<M, P>
+where
+    M: CompletionModel + 'static,
+    P: PromptHook<M> + 'static,
+{
+    /// Wraps `agent`, recording its configuration in a `create_agent` span
+    /// under the current span. rig models do not expose their name, so the
+    /// model the agent was built with is passed alongside it.
+    pub fn new(model: impl Into<String>, agent: Agent<M, P>) -> Self {
+        let model = model.into();
+        let request_attributes = record_creation(&model, &agent);
+        Self {
+            agent,
+            model,
+            max_turns: None,
+            tool_concurrency: None,
+            request_attributes,
+            inflight: InflightRegistry::global().clone(),
+        }
+    }
+
+    /// Turn limit for tool-calling loops; rig's agent default when unset.
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// How many tool calls of one turn may run at once; rig runs them one
+    /// at a time when unset.
+    pub fn with_tool_concurrency(mut self, tool_concurrency: usize) -> Self {
+        self.tool_concurrency = Some(tool_concurrency);
+        self
+    }
+
+    /// Registry listing the agent's running calls; the process-wide one
+    /// when unset.
+    pub fn with_inflight_registry(mut self, inflight: InflightRegistry) -> Self {
+        self.inflight = inflight;
+        self
+    }
+
+    pub fn agent(&self) -> &Agent<M, P> {
+        &self.agent
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// The `gen_ai.request.*`, provider and preamble fingerprint attributes
+    /// set on every call.
+    pub fn request_attributes(&self) -> &[KeyValue] {
+        &self.request_attributes
+    }
+
+    /// Prompts the agent and records the usage on the current span.
+    pub async fn prompt(&self, prompt: impl Into<Message>) -> Result<String, PromptError> {
+        Ok(self.prompt_extended(prompt).await?.output)
+    }
+
+    /// Like [`InstrumentedAgent::prompt`], also returning the usage, e.g.
+    /// for cost accounting or metrics.
+    pub async fn prompt_extended(
+        &self,
+        prompt: impl Into<Message>,
+    ) -> Result<PromptResponse, PromptError> {
+        let mut request = PromptRequest::from_agent(&self.agent, prompt)
+            .with_hook(ToolBatchHook::new(self.agent.hook.clone()));
+        if let Some(max_turns) = self.max_turns {
+            request = request.max_turns(max_turns);
+        }
+        if let Some(tool_concurrency) = self.tool_concurrency {
+            request = request.with_tool_concurrency(tool_concurrency);
+        }
+        self.send_recorded(request.extended_details()).await
+    }
+
+    /// Like [`InstrumentedAgent::prompt`], appending the exchange to
+    /// `history`. The size of the history sent along is recorded on the
+    /// current span (see [`crate::history`]).
+    pub async fn chat(
+        &self,
+        prompt: impl Into<Message>,
+        history: &mut Vec<Message>,
+    ) -> Result<String, PromptError> {
+        Ok(self.chat_extended(prompt, history).await?.output)
+    }
+
+    /// Like [`InstrumentedAgent::chat`], as the next turn of `session`: the
+    /// exchange runs in the turn's `chat_turn` span, which records the
+    /// turn's usage and the conversation's running totals.
+    pub async fn chat_in_session(
+        &self,
+        session: &ChatSession,
+        prompt: impl Into<Message>,
+        history: &mut Vec<Message>,
+    ) -> Result<String, PromptError> {
+        let turn = session.turn();
+        let result = self
+            .chat_extended(prompt, history)
+            .instrument(turn.span().clone())
+            .await;
+        match &result {
+            Ok(response) => turn.record_usage(&token_usage(&response.total_usage)),
+            Err(error) => turn.record_error("prompt_error", error.to_string()),
+        }
+        Ok(result?.output)
+    }
+
+    /// Sends `request` from the current span, listed in the in-flight
+    /// registry until it completes, and records its usage on the span.
+    async fn send_recorded(
+        &self,
+        request: impl IntoFuture<Output = Result<PromptResponse, PromptError>>,
+    ) -> Result<PromptResponse, PromptError> {
+        let span = tracing::Span::current();
+        set_request_attributes(&span, &self.request_attributes);
+        let _call = self.inflight.track(&self.model, "chat");
+        let started = Instant::now();
+        let result = ProviderRequestIds::collect(&span, request.into_future()).await;
+        if !model_records_metrics::<M>() {
+            let usage = result
+                .as_ref()
+                .ok()
+                .map(|response| token_usage(&response.total_usage));
+            call_metrics::record(
+                &self.call("chat"),
+                started.elapsed(),
+                usage.as_ref(),
+                result.as_ref().err().map(prompt_error_type),
+            );
+        }
+        let response = result?;
+        record_usage(&span, &response.total_usage);
+        Ok(response)
+    }
+
+    fn call<'a>(&'a self, operation: &'a str) -> Call<'a> {
+        Call {
+            operation,
+            provider: provider_name::<M>().unwrap_or(UNKNOWN_PROVIDER),
+            request_model: &self.model,
+            response_model: None,
+        }
+    }
+
+    async fn chat_extended(
+        &self,
+        prompt: impl Into<Message>,
+        history: &mut Vec<Message>,
+    ) -> Result<PromptResponse, PromptError> {
+        record_history(&tracing::Span::current(), history);
+        let mut request = PromptRequest::from_agent(&self.agent, prompt)
+            .with_history(history)
+            .with_hook(ToolBatchHook::new(self.agent.hook.clone()));
+        if let Some(max_turns) = self.max_turns {
+            request = request.max_turns(max_turns);
+        }
+        if let Some(tool_concurrency) = self.tool_concurrency {
+            request = request.with_tool_concurrency(tool_concurrency);
+        }
+        self.send_recorded(request.extended_details()).await
+    }
+}
+
+impl<M, P> InstrumentedAgent<M, P>
+where
+    M: CompletionModel + 'static,
+    M::StreamingResponse: GetTokenUsage,
+    P: PromptHook<M> + 'static,
+{
+    /// Streams the agent's answer, recording time to first token, chunk
+    /// timing and usage on the current span as the stream is consumed.
+    pub async fn stream_prompt(
+        &self,
+        prompt: impl Into<Message> + Send,
+    ) -> InstrumentedStream<StreamingResult<M::StreamingResponse>> {
+        let span = tracing::Span::current();
+        set_request_attributes(&span, &self.request_attributes);
+        let call = self.inflight.track(&self.model, "chat_streaming");
+        let recorder = StreamRecorder::new(span);
+        let mut request = self.agent.stream_prompt(prompt);
+        if let Some(max_turns) = self.max_turns {
+            request = request.multi_turn(max_turns);
+        }
+        InstrumentedStream::new(request.await, recorder)
+            .with_inflight(call)
+            .with_call_metrics(StreamMetrics {
+                provider: provider_name::<M>().unwrap_or(UNKNOWN_PROVIDER),
+                model: self.model.clone(),
+                started: Instant::now(),
+            })
+    }
+}
+
+/// Emits the `create_agent` span and returns the attributes describing the
+/// agent's requests.
+fn record_creation<M: CompletionModel + 'static, P: PromptHook<M>>(
+    model: &str,
+    agent: &Agent<M, P>,
+) -> Vec<KeyValue> {
+    let agent_name = agent.name.as_deref().unwrap_or("unnamed");
+    let span = tracing::info_span!(
+        "create_agent",
+        gen_ai.operation.name = "create_agent",
+        gen_ai.agent.name = agent_name,
+    );
+    let mut attributes = vec![KeyValue::new("gen_ai.request.model", model.to_owned())];
+    if let Some(provider) = provider_name::<M>() {
+        attributes.push(KeyValue::new("gen_ai.provider.name", provider));
+    }
+    if let Some(temperature) = agent.temperature {
+        attributes.push(KeyValue::new("gen_ai.request.temperature", temperature));
+    }
+    if let Some(max_tokens) = agent.max_tokens {
+        attributes.push(KeyValue::new(
+            "gen_ai.request.max_tokens",
+            max_tokens as i64,
+        ));
+    }
+    if let Some(params) = &agent.additional_params {
+        attributes.extend(additional_param_attributes(params));
+    }
+    set_attributes(&span, &attributes);
+    // Sets the fingerprint on the span itself.
+    if let Some(preamble) = &agent.preamble {
+        let fingerprint = PromptFingerprints::global().observe(&span, agent_name, preamble);
+        attributes.push(KeyValue::new("llm.system_prompt.fingerprint", fingerprint));
+    }
+    attributes
+}
+
+fn set_attributes(span: &tracing::Span, attributes: &[KeyValue]) {
+    for attribute in attributes {
+        span.set_attribute(attribute.key.clone(), attribute.value.clone());
+    }
+}
+
+/// [`set_attributes`] on the span of a call, which is also linked to the
+/// cached content it reads.
+fn set_request_attributes(span: &tracing::Span, attributes: &[KeyValue]) {
+    set_attributes(span, attributes);
+    if let Some(cache) = attributes
+        .iter()
+        .find(|attribute| attribute.key.as_str() == "gen_ai.gemini.cache.name")
+    {
+        link_cached_content(span, &cache.value.as_str());
+    }
+}
+
+/// Sampling parameters passed through rig's additional params, under the
+/// OpenAI, Anthropic or Gemini (`generationConfig`) names.
+fn additional_param_attributes(params: &serde_json::Value) -> Vec<KeyValue> {
+    let lookup = |names: &[&str]| {
+        let nested = ["generationConfig", "generation_config"]
+            .into_iter()
+            .filter_map(|config| params.get(config));
+        std::iter::once(params)
+            .chain(nested)
+            .find_map(|object| names.iter().find_map(|name| object.get(name)))
+    };
+
+    let mut attributes = Vec::new();
+    for (key, names) in [
+        ("gen_ai.request.top_p", &["top_p", "topP"][..]),
+        (
+            "gen_ai.request.frequency_penalty",
+            &["frequency_penalty", "frequencyPenalty"],
+        ),
+        (
+            "gen_ai.request.presence_penalty",
+            &["presence_penalty", "presencePenalty"],
+        ),
+    ] {
+        if let Some(value) = lookup(names).and_then(serde_json::Value::as_f64) {
+            attributes.push(KeyValue::new(key, value));
+        }
+    }
+    for (key, names) in [
+        ("gen_ai.request.top_k", &["top_k", "topK"][..]),
+        ("gen_ai.request.seed", &["seed"]),
+    ] {
+        if let Some(value) = lookup(names).and_then(serde_json::Value::as_i64) {
+            attributes.push(KeyValue::new(key, value));
+        }
+    }
+    if let Some(cache) = ["cachedContent", "cached_content"]
+        .into_iter()
+        .find_map(|name| params.get(name).and_then(serde_json::Value::as_str))
+    {
+        attributes.push(KeyValue::new("gen_ai.gemini.cache.name", cache.to_owned()));
+    }
+    let stop_sequences: Vec<StringValue> =
+        match lookup(&["stop", "stop_sequences", "stopSequences"]) {
+            Some(serde_json::Value::String(stop)) => vec![stop.clone().into()],
+            Some(serde_json::Value::Array(stops)) => stops
+                .iter()
+                .filter_map(|stop| stop.as_str().map(|stop| stop.to_owned().into()))
+                .collect(),
+            _ => Vec::new(),
+        };
+    if !stop_sequences.is_empty() {
+        attributes.push(KeyValue::new(
+            "gen_ai.request.stop_sequences",
+            Value::Array(Array::String(stop_sequences)),
+        ));
+    }
+    attributes
+}
+
+/// `gen_ai.provider.name` of rig's completion model types (with the default
+/// HTTP client), this crate's model wrappers and types added with
+/// [`register_provider`].
+fn provider_models() -> &'static Mutex<HashMap<TypeId, &'static str>> {
+    static MODELS: OnceLock<Mutex<HashMap<TypeId, &'static str>>> = OnceLock::new();
+    MODELS.get_or_init(|| {
+        #[cfg_attr(not(any(feature = "anthropic", feature = "ollama")), allow(unused_mut))]
+        let mut models = HashMap::from([
+            (
+                TypeId::of::<providers::gemini::CompletionModel>(),
+                "gcp.gemini",
+            ),
+            (TypeId::of::<providers::openai::CompletionModel>(), "openai"),
+            (
+                TypeId::of::<providers::openai::responses_api::ResponsesCompletionModel>(),
+                "openai",
+            ),
+            (
+                TypeId::of::<providers::azure::CompletionModel>(),
+                "azure.ai.openai",
+            ),
+            (
+                TypeId::of::<providers::anthropic::completion::CompletionModel>(),
+                "anthropic",
+            ),
+            (
+                TypeId::of::<providers::mistral::CompletionModel>(),
+                "mistral_ai",
+            ),
+            (
+                TypeId::of::<providers::deepseek::CompletionModel>(),
+                "deepseek",
+            ),
+            (TypeId::of::<providers::groq::CompletionModel>(), "groq"),
+            (
+                TypeId::of::<providers::xai::completion::CompletionModel>(),
+                "x_ai",
+            ),
+            (TypeId::of::<providers::ollama::CompletionModel>(), "ollama"),
+        ]);
+        #[cfg(feature = "anthropic")]
+        models.insert(
+            TypeId::of::<crate::anthropic::InstrumentedModel>(),
+            crate::anthropic::PROVIDER_NAME,
+        );
+        #[cfg(feature = "ollama")]
+        models.insert(
+            TypeId::of::<crate::ollama::InstrumentedModel>(),
+            crate::ollama::PROVIDER_NAME,
+        );
+        Mutex::new(models)
+    })
+}
+
+/// `gen_ai.provider.name` for the model type `M`, if it is a known one.
+pub fn provider_name<M: 'static>() -> Option<&'static str> {
+    provider_models()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&TypeId::of::<M>())
+        .copied()
+}
+
+/// Whether `M` is one of this crate's model wrappers, which record the
+/// GenAI metrics of every completion themselves.
+fn model_records_metrics<M: 'static>() -> bool {
+    #[cfg_attr(
+        not(any(feature = "anthropic", feature = "ollama")),
+        allow(unused_variables)
+    )]
+    let model = TypeId::of::<M>();
+    #[cfg(feature = "anthropic")]
+    if model == TypeId::of::<crate::anthropic::InstrumentedModel>() {
+        return true;
+    }
+    #[cfg(feature = "ollama")]
+    if model == TypeId::of::<crate::ollama::InstrumentedModel>() {
+        return true;
+    }
+    false
+}
+
+/// Maps the model type `M` (a custom model, a wrapper, or a rig model with
+/// another HTTP client) to `provider`, for agents created afterwards.
+pub fn register_provider<M: 'static>(provider: &'static str) {
+    provider_models()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(TypeId::of::<M>(), provider);
+}
+
+/// Sets rig's usage as `gen_ai.usage.*` attributes on `span`.
+pub fn record_usage(span: &tracing::Span, usage: &Usage) {
+    span.set_attribute("gen_ai.usage.input_tokens", usage.input_tokens as i64);
+    span.set_attribute("gen_ai.usage.output_tokens", usage.output_tokens as i64);
+    span.set_attribute(
+        "gen_ai.usage.total_tokens",
+        usage
+            .total_tokens
+            .max(usage.input_tokens + usage.output_tokens) as i64,
+    );
+    if usage.cached_input_tokens > 0 {
+        span.set_attribute(
+            "gen_ai.usage.cache_read.input_tokens",
+            usage.cached_input_tokens as i64,
+        );
+    }
+}
+
+/// Adds the provider's response id to `ids`, for correlating retries and
+/// fallbacks of one logical request.
+pub fn record_response_id<R: ProviderResponseExt>(ids: &ProviderRequestIds, response: &R) {
+    if let Some(id) = response.get_response_id() {
+        ids.record(id);
+    }
+}
+
+/// The provider's id of a raw completion response, for the response types
+/// of rig's Gemini, OpenAI and Anthropic models.
+pub fn response_id<R: 'static>(response: &R) -> Option<String> {
+    let response = response as &dyn Any;
+    if let Some(response) = response.downcast_ref::<GenerateContentResponse>() {
+        return response.get_response_id();
+    }
+    if let Some(response) = response.downcast_ref::<providers::openai::CompletionResponse>() {
+        return response.get_response_id();
+    }
+    if let Some(response) =
+        response.downcast_ref::<providers::openai::responses_api::CompletionResponse>()
+    {
+        return Some(response.id.clone());
+    }
+    if let Some(response) =
+        response.downcast_ref::<providers::anthropic::completion::CompletionResponse>()
+    {
+        return response.get_response_id();
+    }
+    None
+}
+
+/// Passes a rig multi-turn stream through unchanged while timing text
+/// chunks; the final response's usage is recorded on the span as well.
+pub struct InstrumentedStream<S> {
+    inner: S,
+    recorder: Option<StreamRecorder>,
+    inflight: Option<InflightGuard>,
+    metrics: Option<StreamMetrics>,
+}
+
+/// What a stream records in the GenAI client histograms when it ends.
+struct StreamMetrics {
+    provider: &'static str,
+    model: String,
+    started: Instant,
+}
+
+impl StreamMetrics {
+    fn record(self, usage: Option<&Usage>, error_type: Option<&str>) {
+        let usage = usage.map(token_usage);
+        call_metrics::record(
+            &Call {
+                operation: "chat_streaming",
+                provider: self.provider,
+                request_model: &self.model,
+                response_model: None,
+            },
+            self.started.elapsed(),
+            usage.as_ref(),
+            error_type,
+        );
+    }
+}
+
+impl<S> InstrumentedStream<S> {
+    pub fn new(inner: S, recorder: StreamRecorder) -> Self {
+        Self {
+            inner,
+            recorder: Some(recorder),
+            inflight: None,
+            metrics: None,
+        }
+    }
+
+    /// Adds estimated tokens of text chunks to `call` and ends it with the
+    /// stream.
+    pub fn with_inflight(mut self, call: InflightGuard) -> Self {
+        self.inflight = Some(call);
+        self
+    }
+
+    fn with_call_metrics(mut self, metrics: StreamMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+impl<S, R> Stream for InstrumentedStream<S>
+where
+    S: Stream<Item = Result<MultiTurnStreamItem<R>, StreamingError>> + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(item) => item,
+            Poll::Pending => return Poll::Pending,
+        };
+        match &item {
+            Some(Ok(MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(
+                text,
+            )))) => {
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.on_chunk();
+                }
+                if let Some(call) = &self.inflight {
+                    call.add_tokens(estimate_tokens(&text.text));
+                }
+            }
+            Some(Ok(MultiTurnStreamItem::FinalResponse(response))) => {
+                if let Some(recorder) = self.recorder.take() {
+                    let usage = response.usage();
+                    record_usage(recorder.span(), &usage);
+                    let output_tokens = (usage.output_tokens > 0).then_some(usage.output_tokens);
+                    recorder.finish(output_tokens);
+                }
+                if let Some(metrics) = self.metrics.take() {
+                    metrics.record(Some(&response.usage()), None);
+                }
+                self.inflight = None;
+            }
+            Some(Err(error)) => {
+                if let Some(recorder) = self.recorder.take() {
+                    recorder.span().set_attribute("error.type", "stream_error");
+                    recorder.span().set_status(Status::error(error.to_string()));
+                    recorder.finish(None);
+                }
+                if let Some(metrics) = self.metrics.take() {
+                    metrics.record(None, Some("stream_error"));
+                }
+                self.inflight = None;
+            }
+            None => {
+                if let Some(recorder) = self.recorder.take() {
+                    recorder.finish(None);
+                }
+                if let Some(metrics) = self.metrics.take() {
+                    metrics.record(None, None);
+                }
+                self.inflight = None;
+            }
+            Some(Ok(_)) => {}
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// A stream dropped before it ended still reports what it saw.
+impl<S> Drop for InstrumentedStream<S> {
+    fn drop(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            recorder.finish(None);
+        }
+    }
+}
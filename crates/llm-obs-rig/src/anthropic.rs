@@ -0,0 +1,157 @@
+//! Anthropic-specific instrumentation that rig's provider types do not surface.
+//!
+//! rig folds Anthropic's cache reads and writes into its aggregated usage,
+//! keeping only the read count, so the cost of writing a prompt cache never
+//! reaches the trace. [`InstrumentedModel`] wraps rig's Anthropic completion
+//! model and calls [`record_response`] on every response, inside rig's
+//! per-call `chat` span: response id and model, input and output tokens,
+//! cache read and write tokens, and the finish reason. The response id is
+//! also added to the enclosing `ProviderRequestIds` scope. Each call, failed
+//! ones included, is recorded in the GenAI client histograms.
+//!
+//! ```ignore
+//! let model = CompletionModel::new(client, CLAUDE_4_SONNET).with_prompt_caching();
+//! let agent = InstrumentedAgent::new(
+//!     CLAUDE_4_SONNET,
+//!     AgentBuilder::new(InstrumentedModel::new(model)).preamble(preamble).build(),
+//! );
+//! ```
+//!
+//! Streaming calls are passed through; `InstrumentedAgent::stream_prompt`
+//! records their usage.
+
+use crate::call_metrics::{self, Call, completion_error_type};
+use llm_obs_core::finish_reason::{FinishReason, record_finish_reason};
+use llm_obs_core::request_ids::ProviderRequestIds;
+use llm_obs_core::tokens::TokenUsage;
+use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::http_client::HttpClientExt;
+use rig::providers::anthropic::Client;
+use rig::providers::anthropic::completion::{CompletionModel, CompletionResponse, Usage};
+use rig::providers::anthropic::streaming::StreamingCompletionResponse;
+use rig::streaming;
+use rig::wasm_compat::{WasmCompatSend, WasmCompatSync};
+use std::time::Instant;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// `gen_ai.provider.name` for Anthropic.
+pub const PROVIDER_NAME: &str = "anthropic";
+
+/// Anthropic reports cache reads and writes apart from `input_tokens`;
+/// they are counted back in, as rig's `GetTokenUsage` does.
+pub fn token_usage(usage: &Usage) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage.input_tokens
+            + usage.cache_read_input_tokens.unwrap_or_default()
+            + usage.cache_creation_input_tokens.unwrap_or_default(),
+        output_tokens: usage.output_tokens,
+        reasoning_tokens: 0,
+    }
+}
+
+/// Records `response` on `span` and in the current [`ProviderRequestIds`]
+/// scope, and returns its usage. `max_tokens` is the limit the request was
+/// sent with, for the truncation event.
+pub fn record_response(
+    span: &tracing::Span,
+    response: &CompletionResponse,
+    max_tokens: Option<u64>,
+) -> TokenUsage {
+    span.set_attribute("gen_ai.provider.name", PROVIDER_NAME);
+    span.set_attribute("gen_ai.response.id", response.id.clone());
+    ProviderRequestIds::record_current(response.id.clone());
+    span.set_attribute("gen_ai.response.model", response.model.clone());
+    if let Some(stop_reason) = &response.stop_reason {
+        record_finish_reason(
+            span,
+            &response.model,
+            &FinishReason::parse(stop_reason),
+            max_tokens,
+        );
+    }
+
+    let usage = &response.usage;
+    let tokens = token_usage(usage);
+    span.set_attribute("gen_ai.usage.input_tokens", tokens.input_tokens as i64);
+    span.set_attribute("gen_ai.usage.output_tokens", tokens.output_tokens as i64);
+    span.set_attribute("gen_ai.usage.total_tokens", tokens.total_tokens() as i64);
+    if let Some(cache_read) = usage.cache_read_input_tokens {
+        span.set_attribute("gen_ai.usage.cache_read.input_tokens", cache_read as i64);
+    }
+    if let Some(cache_creation) = usage.cache_creation_input_tokens {
+        span.set_attribute(
+            "gen_ai.usage.cache_creation.input_tokens",
+            cache_creation as i64,
+        );
+    }
+    tokens
+}
+
+/// rig's Anthropic completion model, recording each response on the span of
+/// the call.
+#[derive(Clone)]
+pub struct InstrumentedModel<T = reqwest::Client> {
+    inner: CompletionModel<T>,
+}
+
+impl<T> InstrumentedModel<T> {
+    pub fn new(inner: CompletionModel<T>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &CompletionModel<T> {
+        &self.inner
+    }
+}
+
+impl<T> completion::CompletionModel for InstrumentedModel<T>
+where
+    T: HttpClientExt + Clone + Default + WasmCompatSend + WasmCompatSync + 'static,
+{
+    type Response = CompletionResponse;
+    type StreamingResponse = StreamingCompletionResponse;
+    type Client = Client<T>;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self::new(CompletionModel::make(client, model))
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let max_tokens = request.max_tokens.or(self.inner.default_max_tokens);
+        let started = Instant::now();
+        let result = self.inner.completion(request).await;
+        let mut call = Call {
+            operation: "chat",
+            provider: PROVIDER_NAME,
+            request_model: &self.inner.model,
+            response_model: None,
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                let error_type = completion_error_type(&error);
+                call_metrics::record(&call, started.elapsed(), None, Some(error_type));
+                return Err(error);
+            }
+        };
+        let usage = record_response(
+            &tracing::Span::current(),
+            &response.raw_response,
+            max_tokens,
+        );
+        call.response_model = Some(&response.raw_response.model);
+        call_metrics::record(&call, started.elapsed(), Some(&usage), None);
+        Ok(response)
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<streaming::StreamingCompletionResponse<StreamingCompletionResponse>, CompletionError>
+    {
+        self.inner.stream(request).await
+    }
+}
@@ -0,0 +1,125 @@
+//! Blocking API for CLI tools and synchronous services.
+//!
+//! The OTLP/gRPC exporter and rig's providers need a Tokio runtime. This
+//! module owns one internally, so sync code can initialize telemetry, prompt
+//! agents and flush without restructuring around `async`.
+//!
+//! The `prompt_instrumented`, `prompt_extended` and `chat` methods run an
+//! [`InstrumentedAgent`] in the current span, recording its usage like the
+//! async calls do; [`BlockingTelemetry::prompt`] takes any rig agent but
+//! records only what rig itself records.
+
+use crate::agent::InstrumentedAgent;
+use anyhow::Context;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use rig::agent::{PromptHook, PromptResponse};
+use rig::completion::{CompletionModel, Message, Prompt, PromptError};
+use std::future::{Future, IntoFuture};
+use tokio::runtime::{Builder, Runtime};
+
+pub struct BlockingTelemetry {
+    tracer_provider: SdkTracerProvider,
+    runtime: Runtime,
+}
+
+impl BlockingTelemetry {
+    /// Runs `init` inside the internal runtime so the exporter can bind to
+    /// it, for example
+    /// `|| Ok(llm_obs_core::telemetry::init("my-cli")?.tracer_provider().clone())`
+    /// or the same with a configured `TelemetryBuilder`.
+    pub fn init<F>(init: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce() -> anyhow::Result<SdkTracerProvider>,
+    {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("llm-obs-blocking")
+            .enable_all()
+            .build()
+            .context("Failed to build blocking telemetry runtime")?;
+        let tracer_provider = {
+            let _runtime_guard = runtime.enter();
+            init()?
+        };
+
+        Ok(Self {
+            tracer_provider,
+            runtime,
+        })
+    }
+
+    pub fn tracer_provider(&self) -> &SdkTracerProvider {
+        &self.tracer_provider
+    }
+
+    /// Drives any future to completion on the internal runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocking counterpart of `agent.prompt(..).await`.
+    pub fn prompt<A: Prompt>(
+        &self,
+        agent: &A,
+        prompt: impl Into<Message> + Send,
+    ) -> Result<String, PromptError> {
+        self.runtime.block_on(agent.prompt(prompt).into_future())
+    }
+
+    /// Blocking counterpart of [`InstrumentedAgent::prompt`].
+    pub fn prompt_instrumented<M, P>(
+        &self,
+        agent: &InstrumentedAgent<M, P>,
+        prompt: impl Into<Message>,
+    ) -> Result<String, PromptError>
+    where
+        M: CompletionModel + 'static,
+        P: PromptHook<M> + 'static,
+    {
+        self.runtime.block_on(agent.prompt(prompt))
+    }
+
+    /// Blocking counterpart of [`InstrumentedAgent::prompt_extended`].
+    pub fn prompt_extended<M, P>(
+        &self,
+        agent: &InstrumentedAgent<M, P>,
+        prompt: impl Into<Message>,
+    ) -> Result<PromptResponse, PromptError>
+    where
+        M: CompletionModel + 'static,
+        P: PromptHook<M> + 'static,
+    {
+        self.runtime.block_on(agent.prompt_extended(prompt))
+    }
+
+    /// Blocking counterpart of [`InstrumentedAgent::chat`].
+    pub fn chat<M, P>(
+        &self,
+        agent: &InstrumentedAgent<M, P>,
+        prompt: impl Into<Message>,
+        history: &mut Vec<Message>,
+    ) -> Result<String, PromptError>
+    where
+        M: CompletionModel + 'static,
+        P: PromptHook<M> + 'static,
+    {
+        self.runtime.block_on(agent.chat(prompt, history))
+    }
+
+    /// Exports every finished span before returning.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        let _runtime_guard = self.runtime.enter();
+        self.tracer_provider
+            .force_flush()
+            .context("Failed to flush spans")
+    }
+}
+
+impl Drop for BlockingTelemetry {
+    fn drop(&mut self) {
+        let _runtime_guard = self.runtime.enter();
+        if let Err(error) = self.tracer_provider.shutdown() {
+            tracing::warn!("Failed to shut down tracer provider: {error}");
+        }
+    }
+}
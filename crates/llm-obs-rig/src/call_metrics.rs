@@ -0,0 +1,76 @@
+//! GenAI client metrics for the calls made through this crate's wrappers.
+//!
+//! Every adapter records `gen_ai.client.operation.duration` and
+//! `gen_ai.client.token.usage` (see [`llm_obs_core::genai_metrics`]) once per
+//! call, with `error.type` set on failures, so the semconv dashboards work
+//! without recording anything by hand.
+
+use llm_obs_core::genai_metrics::{GenAiCall, GenAiMetrics};
+use llm_obs_core::tokens::TokenUsage;
+use rig::completion::{CompletionError, PromptError, Usage};
+use std::time::Duration;
+
+/// `gen_ai.provider.name` recorded when the model type is not a known one.
+pub(crate) const UNKNOWN_PROVIDER: &str = "_OTHER";
+
+/// One model call for [`record`].
+pub(crate) struct Call<'a> {
+    pub(crate) operation: &'a str,
+    pub(crate) provider: &'a str,
+    pub(crate) request_model: &'a str,
+    pub(crate) response_model: Option<&'a str>,
+}
+
+/// Records `call` in the global GenAI histograms.
+pub(crate) fn record(
+    call: &Call<'_>,
+    duration: Duration,
+    usage: Option<&TokenUsage>,
+    error_type: Option<&str>,
+) {
+    GenAiMetrics::global().record(
+        &GenAiCall {
+            operation: call.operation,
+            provider: call.provider,
+            request_model: call.request_model,
+            response_model: call.response_model,
+            server_address: None,
+            server_port: None,
+        },
+        duration,
+        usage,
+        error_type,
+    );
+}
+
+/// rig's aggregated usage; rig does not report reasoning tokens apart.
+pub(crate) fn token_usage(usage: &Usage) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        reasoning_tokens: 0,
+    }
+}
+
+/// `error.type` of a failed completion call.
+pub fn completion_error_type(error: &CompletionError) -> &'static str {
+    match error {
+        CompletionError::HttpError(_) => "http_error",
+        CompletionError::JsonError(_) => "json_error",
+        CompletionError::UrlError(_) => "url_error",
+        CompletionError::RequestError(_) => "request_error",
+        CompletionError::ResponseError(_) => "response_error",
+        CompletionError::ProviderError(_) => "provider_error",
+    }
+}
+
+/// `error.type` of a failed prompt, the completion's when a model call
+/// failed.
+pub fn prompt_error_type(error: &PromptError) -> &'static str {
+    match error {
+        PromptError::CompletionError(error) => completion_error_type(error),
+        PromptError::ToolError(_) | PromptError::ToolServerError(_) => "tool_error",
+        PromptError::MaxTurnsError { .. } => "max_turns",
+        PromptError::PromptCancelled { .. } => "cancelled",
+    }
+}
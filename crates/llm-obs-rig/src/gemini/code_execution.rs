@@ -0,0 +1,153 @@
+//! Built-in code-execution tool instrumentation.
+//!
+//! With the `code_execution` tool enabled, Gemini returns `executableCode`
+//! and `codeExecutionResult` parts that otherwise end up as opaque text. Each
+//! code/result pair is recorded as a nested `gemini.code_execution` span with
+//! the language, a code fingerprint, the outcome and the output size. The
+//! code itself is recorded as `code.source` under a `ContentCapturePolicy`.
+
+use llm_obs_core::fingerprint::fingerprint;
+use llm_obs_core::processors::capture::ContentCapturePolicy;
+use rig::providers::gemini::completion::gemini_api_types::{GenerateContentResponse, PartKind};
+use rig::providers::gemini::gemini_api_types::{
+    CodeExecutionOutcome, CodeExecutionResult, ExecutableCode, ExecutionLanguage,
+};
+use tracing::field::Empty;
+
+/// Records one child span of `parent` per executed code block; returns how
+/// many were found. The generated code itself is attached as `code.source`
+/// under `capture_policy`.
+pub fn record_code_execution(
+    parent: &tracing::Span,
+    response: &GenerateContentResponse,
+    capture_policy: ContentCapturePolicy,
+) -> usize {
+    let blocks = code_blocks(response);
+    for (index, (code, result)) in blocks.iter().enumerate() {
+        record_block(parent, index, code, *result, capture_policy);
+    }
+    blocks.len()
+}
+
+/// Each `executableCode` part with the `codeExecutionResult` that follows it
+/// before the next code part, if any.
+fn code_blocks(
+    response: &GenerateContentResponse,
+) -> Vec<(&ExecutableCode, Option<&CodeExecutionResult>)> {
+    let parts: Vec<&PartKind> = response
+        .candidates
+        .iter()
+        .filter_map(|candidate| candidate.content.as_ref())
+        .flat_map(|content| content.parts.iter().map(|part| &part.part))
+        .collect();
+
+    let mut blocks = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        let PartKind::ExecutableCode(code) = part else {
+            continue;
+        };
+        // A block without a result must not take the next block's.
+        let result = parts[index + 1..]
+            .iter()
+            .take_while(|next| !matches!(next, PartKind::ExecutableCode(_)))
+            .find_map(|next| match next {
+                PartKind::CodeExecutionResult(result) => Some(result),
+                _ => None,
+            });
+        blocks.push((code, result));
+    }
+    blocks
+}
+
+fn record_block(
+    parent: &tracing::Span,
+    index: usize,
+    code: &ExecutableCode,
+    result: Option<&CodeExecutionResult>,
+    capture_policy: ContentCapturePolicy,
+) {
+    let span = tracing::info_span!(
+        parent: parent,
+        "gemini.code_execution",
+        code.index = index as u64,
+        code.language = language_name(&code.language),
+        code.length = code.code.len() as u64,
+        code.fingerprint = %fingerprint(&code.code),
+        code.source = Empty,
+        code.outcome = Empty,
+        code.output_bytes = Empty,
+        otel.status_code = Empty,
+    );
+
+    if let Some(source) = capture_policy.apply(&code.code) {
+        span.record("code.source", source);
+    }
+
+    match result {
+        Some(result) => {
+            span.record("code.outcome", outcome_name(&result.outcome));
+            span.record(
+                "code.output_bytes",
+                result.output.as_ref().map_or(0, String::len) as u64,
+            );
+            if !matches!(result.outcome, CodeExecutionOutcome::Ok) {
+                span.record("otel.status_code", "ERROR");
+            }
+        }
+        None => {
+            span.record("code.outcome", "missing_result");
+        }
+    }
+}
+
+fn language_name(language: &ExecutionLanguage) -> &'static str {
+    match language {
+        ExecutionLanguage::Python => "python",
+        _ => "unspecified",
+    }
+}
+
+fn outcome_name(outcome: &CodeExecutionOutcome) -> &'static str {
+    match outcome {
+        CodeExecutionOutcome::Ok => "ok",
+        CodeExecutionOutcome::Failed => "failed",
+        CodeExecutionOutcome::DeadlineExceeded => "deadline_exceeded",
+        CodeExecutionOutcome::Unspecified => "unspecified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn a_block_without_a_result_does_not_take_the_next_one() {
+        let response: GenerateContentResponse = serde_json::from_value(json!({
+            "responseId": "response-1",
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "executableCode": { "language": "PYTHON", "code": "print(1 / 0)" } },
+                        { "text": "That was cut off; retrying." },
+                        { "executableCode": { "language": "PYTHON", "code": "print(2)" } },
+                        { "codeExecutionResult": { "outcome": "OUTCOME_OK", "output": "2\n" } },
+                    ],
+                },
+            }],
+        }))
+        .unwrap();
+
+        let blocks = code_blocks(&response);
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].0.code, "print(1 / 0)");
+        assert!(blocks[0].1.is_none());
+        assert_eq!(blocks[1].0.code, "print(2)");
+        assert_eq!(
+            blocks[1].1.and_then(|result| result.output.as_deref()),
+            Some("2\n")
+        );
+    }
+}
@@ -0,0 +1,712 @@
+//! File uploads and context caches, with retries.
+//!
+//! Long documents go to Gemini once, through the Files API, and are then
+//! referenced from a cached content that later `generateContent` calls name
+//! in `cachedContent`. rig has no client for either API, so the steps that
+//! take longest and fail most (multi-megabyte uploads, cache creation that
+//! tokenizes the whole document) would be invisible, and a generation span
+//! reading a cache would not say which document it was reading.
+//!
+//! [`GeminiFiles`] performs both calls in their own spans:
+//!
+//! ```text
+//! gemini.file.upload   gen_ai.gemini.file.size_bytes, .upload_duration_ms, .uri
+//! gemini.cache.create  gen_ai.gemini.cache.ttl_s, .token_count, .expire_time
+//! ```
+//!
+//! Throttled, timed-out and 5xx uploads are retried with exponential backoff
+//! (see [`RetryPolicy`]). Cache creation is not idempotent, so only attempts
+//! that were throttled or never reached the server are retried; after a
+//! timeout or a 5xx the cache may exist. Each retry is a
+//! `gen_ai.gemini.retry` event and the span's `gen_ai.gemini.retry.attempts`
+//! counts all attempts. The request ids the responses of every attempt carry
+//! (see [`REQUEST_ID_HEADERS`]) are set as `gen_ai.provider.request_ids` on
+//! the span and passed on to an enclosing `ProviderRequestIds` scope.
+//!
+//! Pass [`CachedContent::additional_params`] to the agent builder:
+//! `InstrumentedAgent` then sets `gen_ai.gemini.cache.name` on every call
+//! and links its span to the `gemini.cache.create` span, so a trace of a
+//! generation leads to the upload of the document it was grounded on.
+//! Tokens served from the cache are already reported by rig as
+//! `gen_ai.usage.cache_read.input_tokens`.
+
+use bytes::Bytes;
+use llm_obs_core::request_ids::ProviderRequestIds;
+use opentelemetry::KeyValue;
+use opentelemetry::trace::{SpanContext, Status, TraceContextExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+const PROVIDER: &str = "gcp.gemini";
+/// TTL the API gives caches created without one.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// `llm.link.type` of links from generation spans to the span that created
+/// the cached content they read.
+pub const LINK_TYPE_CACHED_CONTENT: &str = "cached_content";
+
+/// Response headers carrying an id for the request: the upload id of the
+/// resumable upload endpoints, and the `x-request-id` a proxy in front of
+/// `with_base_url` may add.
+pub const REQUEST_ID_HEADERS: [&str; 2] = ["x-guploader-uploadid", "x-request-id"];
+
+/// Retries for throttled (408, 429), failed (5xx) and timed-out attempts;
+/// cache creation retries only 429s and connection failures.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Attempts including the first; 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug)]
+pub enum FileApiError {
+    /// `GEMINI_API_KEY` is not set.
+    MissingApiKey,
+    Http(reqwest::Error),
+    /// The API answered with a non-success status.
+    Status {
+        status: u16,
+        body: String,
+    },
+    /// The response lacked a field the protocol requires.
+    InvalidResponse(String),
+}
+
+impl FileApiError {
+    /// `error.type` recorded for this error.
+    pub fn error_type(&self) -> String {
+        match self {
+            FileApiError::MissingApiKey => "missing_api_key".to_owned(),
+            FileApiError::Http(error) if error.is_timeout() => "timeout".to_owned(),
+            FileApiError::Http(_) => "http".to_owned(),
+            FileApiError::Status { status, .. } => status.to_string(),
+            FileApiError::InvalidResponse(_) => "invalid_response".to_owned(),
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            FileApiError::Http(error) => error.is_timeout() || error.is_connect(),
+            FileApiError::Status { status, .. } => matches!(status, 408 | 429 | 500..=599),
+            FileApiError::MissingApiKey | FileApiError::InvalidResponse(_) => false,
+        }
+    }
+
+    /// Whether a request that must not run twice can be retried: it was
+    /// throttled, or it failed before it was sent.
+    fn is_retryable_once_only(&self) -> bool {
+        match self {
+            FileApiError::Http(error) => error.is_connect(),
+            FileApiError::Status { status, .. } => *status == 429,
+            FileApiError::MissingApiKey | FileApiError::InvalidResponse(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for FileApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileApiError::MissingApiKey => write!(f, "GEMINI_API_KEY is not set"),
+            FileApiError::Http(error) => write!(f, "Gemini request failed: {error}"),
+            FileApiError::Status { status, body } => {
+                write!(f, "Gemini returned {status}: {body}")
+            }
+            FileApiError::InvalidResponse(reason) => {
+                write!(f, "unexpected Gemini response: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileApiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FileApiError::Http(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FileApiError {
+    fn from(error: reqwest::Error) -> Self {
+        FileApiError::Http(error)
+    }
+}
+
+/// A file stored by the Files API.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadedFile {
+    /// `files/{id}`.
+    pub name: String,
+    pub uri: String,
+    pub mime_type: String,
+    /// Sent as a decimal string by the API.
+    #[serde(default)]
+    pub size_bytes: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub expiration_time: Option<String>,
+}
+
+/// Contents to cache for `model`.
+#[derive(Debug, Clone)]
+pub struct CacheRequest {
+    model: String,
+    parts: Vec<Value>,
+    file_count: usize,
+    system_instruction: Option<String>,
+    ttl: Option<Duration>,
+    display_name: Option<String>,
+}
+
+impl CacheRequest {
+    /// `model` without the `models/` prefix, e.g. `gemini-2.5-flash`.
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            parts: Vec::new(),
+            file_count: 0,
+            system_instruction: None,
+            ttl: None,
+            display_name: None,
+        }
+    }
+
+    pub fn with_file(mut self, file: &UploadedFile) -> Self {
+        self.parts.push(json!({
+            "fileData": { "mimeType": file.mime_type, "fileUri": file.uri }
+        }));
+        self.file_count += 1;
+        self
+    }
+
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.parts.push(json!({ "text": text.into() }));
+        self
+    }
+
+    pub fn with_system_instruction(mut self, instruction: impl Into<String>) -> Self {
+        self.system_instruction = Some(instruction.into());
+        self
+    }
+
+    /// Time to live; the API defaults to one hour.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_display_name(mut self, name: impl Into<String>) -> Self {
+        self.display_name = Some(name.into());
+        self
+    }
+
+    fn body(&self) -> Value {
+        let mut body = json!({
+            "model": format!("models/{}", self.model),
+            "contents": [{ "role": "user", "parts": self.parts }],
+        });
+        if let Some(instruction) = &self.system_instruction {
+            body["systemInstruction"] = json!({ "parts": [{ "text": instruction }] });
+        }
+        if let Some(ttl) = self.ttl {
+            body["ttl"] = json!(format!("{}s", ttl.as_secs()));
+        }
+        if let Some(name) = &self.display_name {
+            body["displayName"] = json!(name);
+        }
+        body
+    }
+}
+
+/// A context cache, and the span that created it.
+#[derive(Debug, Clone)]
+pub struct CachedContent {
+    /// `cachedContents/{id}`.
+    pub name: String,
+    pub model: String,
+    pub expire_time: Option<String>,
+    pub token_count: Option<u64>,
+    pub span_context: SpanContext,
+}
+
+impl CachedContent {
+    /// `{"cachedContent": name}`, for rig's `additional_params`.
+    pub fn additional_params(&self) -> Value {
+        json!({ "cachedContent": self.name })
+    }
+
+    /// Marks `span` as reading this cache and links it to the creation span.
+    pub fn link(&self, span: &tracing::Span) {
+        span.set_attribute("gen_ai.gemini.cache.name", self.name.clone());
+        add_cache_link(span, self.span_context.clone());
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheResponse {
+    name: String,
+    #[serde(default)]
+    expire_time: Option<String>,
+    #[serde(default)]
+    usage_metadata: Option<CacheUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheUsage {
+    #[serde(default)]
+    total_token_count: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    file: UploadedFile,
+}
+
+/// Instrumented client for the Files and cachedContents APIs.
+#[derive(Debug, Clone)]
+pub struct GeminiFiles {
+    api_key: String,
+    base_url: String,
+    http: reqwest::Client,
+    retry: RetryPolicy,
+}
+
+impl GeminiFiles {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            base_url: DEFAULT_BASE_URL.to_owned(),
+            http: reqwest::Client::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Reads the key from `GEMINI_API_KEY`, like rig's Gemini client.
+    pub fn from_env() -> Result<Self, FileApiError> {
+        std::env::var("GEMINI_API_KEY")
+            .map(Self::new)
+            .map_err(|_| FileApiError::MissingApiKey)
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// E.g. `ProviderTimeouts::http_client` for per-attempt timeouts.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Uploads `bytes` with the resumable protocol, in a
+    /// `gemini.file.upload` span. A failed attempt restarts the session.
+    pub async fn upload(
+        &self,
+        display_name: &str,
+        mime_type: &str,
+        bytes: impl Into<Bytes>,
+    ) -> Result<UploadedFile, FileApiError> {
+        let bytes: Bytes = bytes.into();
+        let span = tracing::info_span!(
+            "gemini.file.upload",
+            gen_ai.provider.name = PROVIDER,
+            gen_ai.gemini.file.display_name = display_name,
+            gen_ai.gemini.file.mime_type = mime_type,
+            gen_ai.gemini.file.size_bytes = bytes.len() as u64,
+            gen_ai.gemini.file.upload_duration_ms = Empty,
+            gen_ai.gemini.file.name = Empty,
+            gen_ai.gemini.file.uri = Empty,
+            gen_ai.gemini.retry.attempts = Empty,
+            error.type = Empty,
+        );
+        let result = self
+            .with_retries(&span, FileApiError::is_retryable, || async {
+                let started = Instant::now();
+                let file = self
+                    .upload_once(display_name, mime_type, bytes.clone())
+                    .await?;
+                Ok((file, started.elapsed()))
+            })
+            .instrument(span.clone())
+            .await;
+        let (file, duration) = result?;
+        span.record(
+            "gen_ai.gemini.file.upload_duration_ms",
+            duration.as_millis() as u64,
+        );
+        span.record("gen_ai.gemini.file.name", file.name.as_str());
+        span.record("gen_ai.gemini.file.uri", file.uri.as_str());
+        Ok(file)
+    }
+
+    async fn upload_once(
+        &self,
+        display_name: &str,
+        mime_type: &str,
+        bytes: Bytes,
+    ) -> Result<UploadedFile, FileApiError> {
+        let start = self
+            .http
+            .post(format!("{}/upload/v1beta/files", self.base_url))
+            .header("x-goog-api-key", &self.api_key)
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", bytes.len())
+            .header("X-Goog-Upload-Header-Content-Type", mime_type)
+            .header("Content-Type", "application/json")
+            .body(json!({ "file": { "display_name": display_name } }).to_string())
+            .send()
+            .await?;
+        let start = success(start).await?;
+        let upload_url = start
+            .headers()
+            .get("x-goog-upload-url")
+            .and_then(|url| url.to_str().ok())
+            .ok_or_else(|| FileApiError::InvalidResponse("no x-goog-upload-url header".into()))?
+            .to_owned();
+
+        let response = self
+            .http
+            .post(upload_url)
+            .header("X-Goog-Upload-Offset", 0)
+            .header("X-Goog-Upload-Command", "upload, finalize")
+            .body(bytes)
+            .send()
+            .await?;
+        let body = success(response).await?.bytes().await?;
+        serde_json::from_slice::<UploadResponse>(&body)
+            .map(|response| response.file)
+            .map_err(|error| FileApiError::InvalidResponse(error.to_string()))
+    }
+
+    /// Creates a cache in a `gemini.cache.create` span and remembers the
+    /// span, so generation spans naming the cache link to it.
+    pub async fn create_cache(
+        &self,
+        request: &CacheRequest,
+    ) -> Result<CachedContent, FileApiError> {
+        let span = tracing::info_span!(
+            "gemini.cache.create",
+            gen_ai.provider.name = PROVIDER,
+            gen_ai.request.model = request.model.as_str(),
+            gen_ai.gemini.cache.file_count = request.file_count as u64,
+            gen_ai.gemini.cache.ttl_s = request.ttl.map(|ttl| ttl.as_secs()),
+            gen_ai.gemini.cache.name = Empty,
+            gen_ai.gemini.cache.token_count = Empty,
+            gen_ai.gemini.cache.expire_time = Empty,
+            gen_ai.gemini.retry.attempts = Empty,
+            error.type = Empty,
+        );
+        let body = request.body().to_string();
+        let response = self
+            .with_retries(&span, FileApiError::is_retryable_once_only, || async {
+                let response = self
+                    .http
+                    .post(format!("{}/v1beta/cachedContents", self.base_url))
+                    .header("x-goog-api-key", &self.api_key)
+                    .header("Content-Type", "application/json")
+                    .body(body.clone())
+                    .send()
+                    .await?;
+                let body = success(response).await?.bytes().await?;
+                serde_json::from_slice::<CacheResponse>(&body)
+                    .map_err(|error| FileApiError::InvalidResponse(error.to_string()))
+            })
+            .instrument(span.clone())
+            .await?;
+
+        span.record("gen_ai.gemini.cache.name", response.name.as_str());
+        if let Some(expire_time) = &response.expire_time {
+            span.record("gen_ai.gemini.cache.expire_time", expire_time.as_str());
+        }
+        let token_count = response
+            .usage_metadata
+            .and_then(|usage| usage.total_token_count);
+        if let Some(token_count) = token_count {
+            span.record("gen_ai.gemini.cache.token_count", token_count);
+        }
+        let span_context = span.context().span().span_context().clone();
+        let ttl = request.ttl.unwrap_or(DEFAULT_CACHE_TTL);
+        remember_cache(&response.name, span_context.clone(), Instant::now() + ttl);
+        Ok(CachedContent {
+            name: response.name,
+            model: request.model.clone(),
+            expire_time: response.expire_time,
+            token_count,
+            span_context,
+        })
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error `retryable`
+    /// rejects or runs out of attempts, recording retries and the outcome
+    /// on `span`.
+    async fn with_retries<T, F, Fut>(
+        &self,
+        span: &tracing::Span,
+        retryable: fn(&FileApiError) -> bool,
+        mut attempt: F,
+    ) -> Result<T, FileApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, FileApiError>>,
+    {
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut attempts = 0;
+        let result = ProviderRequestIds::collect(span, async {
+            loop {
+                attempts += 1;
+                match attempt().await {
+                    Err(error) if retryable(&error) && attempts < max_attempts => {
+                        let backoff = self.retry.backoff(attempts - 1);
+                        span.add_event(
+                            "gen_ai.gemini.retry",
+                            vec![
+                                KeyValue::new("attempt", attempts as i64),
+                                KeyValue::new("error.type", error.error_type()),
+                                KeyValue::new("backoff_ms", backoff.as_millis() as i64),
+                            ],
+                        );
+                        tokio::time::sleep(backoff).await;
+                    }
+                    result => break result,
+                }
+            }
+        })
+        .await;
+        span.record("gen_ai.gemini.retry.attempts", attempts as u64);
+        if let Err(error) = &result {
+            span.record("error.type", error.error_type());
+            span.set_status(Status::error(error.to_string()));
+        }
+        result
+    }
+}
+
+/// `response` if it succeeded, its status and body otherwise. The request
+/// ids in its headers go to the current `ProviderRequestIds` scope either way.
+async fn success(response: reqwest::Response) -> Result<reqwest::Response, FileApiError> {
+    for header in REQUEST_ID_HEADERS {
+        if let Some(id) = response
+            .headers()
+            .get(header)
+            .and_then(|id| id.to_str().ok())
+        {
+            ProviderRequestIds::record_current(id);
+        }
+    }
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(FileApiError::Status {
+        status: status.as_u16(),
+        body,
+    })
+}
+
+/// Creation spans of the caches this process created, with when each
+/// cache expires.
+fn caches() -> &'static Mutex<HashMap<String, (SpanContext, Instant)>> {
+    static CACHES: OnceLock<Mutex<HashMap<String, (SpanContext, Instant)>>> = OnceLock::new();
+    CACHES.get_or_init(Mutex::default)
+}
+
+/// Remembers the creation span of cache `name` until `expires_at`,
+/// dropping the caches that have expired since.
+fn remember_cache(name: &str, created: SpanContext, expires_at: Instant) {
+    let now = Instant::now();
+    let mut caches = caches().lock().unwrap_or_else(PoisonError::into_inner);
+    caches.retain(|_, (_, expires_at)| *expires_at > now);
+    caches.insert(name.to_owned(), (created, expires_at));
+}
+
+/// The creation span of cache `name`, unless it has expired.
+fn created_span(name: &str) -> Option<SpanContext> {
+    let mut caches = caches().lock().unwrap_or_else(PoisonError::into_inner);
+    let (created, expires_at) = caches.get(name)?;
+    if *expires_at > Instant::now() {
+        return Some(created.clone());
+    }
+    caches.remove(name);
+    None
+}
+
+/// Marks `span` as reading cached content `name`, linking it to the
+/// creation span when the cache was created by this process.
+pub fn link_cached_content(span: &tracing::Span, name: &str) {
+    span.set_attribute("gen_ai.gemini.cache.name", name.to_owned());
+    if let Some(created) = created_span(name) {
+        add_cache_link(span, created);
+    }
+}
+
+fn add_cache_link(span: &tracing::Span, created: SpanContext) {
+    if created.is_valid() {
+        span.add_link_with_attributes(
+            created,
+            vec![KeyValue::new("llm.link.type", LINK_TYPE_CACHED_CONTENT)],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+
+    fn span_context(span_id: u64) -> SpanContext {
+        SpanContext::new(
+            TraceId::from(1),
+            SpanId::from(span_id),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        )
+    }
+
+    fn status(status: u16) -> FileApiError {
+        FileApiError::Status {
+            status,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn expired_caches_are_forgotten() {
+        let now = Instant::now();
+        remember_cache(
+            "cachedContents/live",
+            span_context(1),
+            now + DEFAULT_CACHE_TTL,
+        );
+        remember_cache("cachedContents/expired", span_context(2), now);
+
+        assert_eq!(created_span("cachedContents/live"), Some(span_context(1)));
+        assert_eq!(created_span("cachedContents/expired"), None);
+        assert!(
+            !caches()
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .contains_key("cachedContents/expired")
+        );
+    }
+
+    #[test]
+    fn cache_creation_retries_only_throttling() {
+        assert!(status(429).is_retryable_once_only());
+        for error in [status(408), status(500), status(503)] {
+            assert!(error.is_retryable());
+            assert!(!error.is_retryable_once_only());
+        }
+        assert!(!status(400).is_retryable());
+    }
+
+    /// Serves `responses` to one connection each, reading every request in
+    /// full first.
+    async fn serve(responses: Vec<String>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                loop {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    let Some(headers_end) = text.find("\r\n\r\n") else {
+                        continue;
+                    };
+                    let content_length = text[..headers_end]
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case("content-length")
+                                .then(|| value.trim().parse::<usize>().ok())?
+                        })
+                        .unwrap_or(0);
+                    if request.len() >= headers_end + 4 + content_length {
+                        break;
+                    }
+                }
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        base_url
+    }
+
+    fn response(status: &str, request_id: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nx-request-id: {request_id}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn request_ids_of_every_attempt_reach_the_enclosing_scope() {
+        let base_url = serve(vec![
+            response("429 Too Many Requests", "attempt-1", "{}"),
+            response("200 OK", "attempt-2", r#"{"name": "cachedContents/ids"}"#),
+        ])
+        .await;
+        let files = GeminiFiles::new("key")
+            .with_base_url(base_url)
+            .with_retry(RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(1),
+            });
+
+        let ids = ProviderRequestIds::new();
+        let cache = ids
+            .scope(files.create_cache(&CacheRequest::new("gemini-2.5-flash").with_text("doc")))
+            .await
+            .unwrap();
+
+        assert_eq!(cache.name, "cachedContents/ids");
+        assert_eq!(ids.ids(), ["attempt-1", "attempt-2"]);
+    }
+}
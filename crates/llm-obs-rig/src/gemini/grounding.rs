@@ -0,0 +1,173 @@
+//! Grounding (Google Search) metadata capture.
+//!
+//! When the `google_search` tool is enabled, Gemini attaches
+//! `groundingMetadata` to each candidate: the search queries it issued and the
+//! web sources it cited. rig's response types drop this block, so it is read
+//! from the raw response JSON and recorded as span events, which makes
+//! search-backed answers distinguishable and their sources auditable.
+
+use opentelemetry::KeyValue;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingMetadata {
+    #[serde(default)]
+    pub web_search_queries: Vec<String>,
+    #[serde(default)]
+    pub grounding_chunks: Vec<GroundingChunk>,
+    #[serde(default)]
+    pub grounding_supports: Vec<GroundingSupport>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GroundingChunk {
+    pub web: Option<WebSource>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WebSource {
+    pub uri: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingSupport {
+    #[serde(default)]
+    pub grounding_chunk_indices: Vec<usize>,
+    #[serde(default)]
+    pub confidence_scores: Vec<f64>,
+}
+
+impl GroundingMetadata {
+    /// Collects grounding metadata from every candidate of a raw
+    /// `generateContent` response body.
+    pub fn from_response_json(response: &Value) -> Option<Self> {
+        let candidates = response.get("candidates")?.as_array()?;
+        let mut merged = GroundingMetadata::default();
+        let mut found = false;
+
+        for metadata in candidates
+            .iter()
+            .filter_map(|candidate| candidate.get("groundingMetadata"))
+        {
+            let Ok(metadata) = serde_json::from_value::<GroundingMetadata>(metadata.clone()) else {
+                continue;
+            };
+            found = true;
+            // Each candidate indexes its own chunks; shift them past the
+            // chunks merged so far.
+            let offset = merged.grounding_chunks.len();
+            merged
+                .web_search_queries
+                .extend(metadata.web_search_queries);
+            merged.grounding_chunks.extend(metadata.grounding_chunks);
+            merged
+                .grounding_supports
+                .extend(metadata.grounding_supports.into_iter().map(|mut support| {
+                    for index in &mut support.grounding_chunk_indices {
+                        *index += offset;
+                    }
+                    support
+                }));
+        }
+
+        found.then_some(merged)
+    }
+
+    /// How many supports cite each chunk, indexed like `grounding_chunks`.
+    fn support_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.grounding_chunks.len()];
+        for index in self
+            .grounding_supports
+            .iter()
+            .flat_map(|support| support.grounding_chunk_indices.iter())
+        {
+            if let Some(count) = counts.get_mut(*index) {
+                *count += 1;
+            }
+        }
+        counts
+    }
+}
+
+/// Records grounding attributes and one event per query and cited source.
+pub fn record_grounding(span: &tracing::Span, metadata: &GroundingMetadata) {
+    span.set_attribute("gen_ai.gemini.grounding.used", true);
+    span.set_attribute(
+        "gen_ai.gemini.grounding.search_query_count",
+        metadata.web_search_queries.len() as i64,
+    );
+    span.set_attribute(
+        "gen_ai.gemini.grounding.citation_count",
+        metadata.grounding_chunks.len() as i64,
+    );
+
+    for query in &metadata.web_search_queries {
+        span.add_event(
+            "gen_ai.gemini.grounding.search_query",
+            vec![KeyValue::new("query", query.clone())],
+        );
+    }
+
+    for (index, (chunk, supports)) in metadata
+        .grounding_chunks
+        .iter()
+        .zip(metadata.support_counts())
+        .enumerate()
+    {
+        let Some(web) = &chunk.web else {
+            continue;
+        };
+        let mut attributes = vec![
+            KeyValue::new("index", index as i64),
+            KeyValue::new("support_count", supports as i64),
+        ];
+        if let Some(uri) = &web.uri {
+            attributes.push(KeyValue::new("url", uri.clone()));
+        }
+        if let Some(title) = &web.title {
+            attributes.push(KeyValue::new("title", title.clone()));
+        }
+        span.add_event("gen_ai.gemini.grounding.citation", attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn candidate(urls: &[&str], cited: &[usize]) -> Value {
+        json!({
+            "groundingMetadata": {
+                "webSearchQueries": ["rust otel"],
+                "groundingChunks": urls
+                    .iter()
+                    .map(|url| json!({ "web": { "uri": url } }))
+                    .collect::<Vec<_>>(),
+                "groundingSupports": [{ "groundingChunkIndices": cited }],
+            }
+        })
+    }
+
+    #[test]
+    fn later_candidates_cite_their_own_chunks() {
+        let response = json!({
+            "candidates": [
+                candidate(&["https://a.example", "https://b.example"], &[1]),
+                candidate(&["https://c.example"], &[0]),
+            ]
+        });
+
+        let metadata = GroundingMetadata::from_response_json(&response).unwrap();
+
+        assert_eq!(metadata.grounding_chunks.len(), 3);
+        assert_eq!(metadata.grounding_supports[0].grounding_chunk_indices, [1]);
+        assert_eq!(metadata.grounding_supports[1].grounding_chunk_indices, [2]);
+        assert_eq!(metadata.support_counts(), [0, 1, 1]);
+    }
+}
@@ -0,0 +1,80 @@
+//! Gemini-specific instrumentation that rig's provider types do not surface,
+//! and conversions from Gemini responses into the core's provider-neutral
+//! types.
+
+pub mod code_execution;
+pub mod files;
+pub mod grounding;
+
+use llm_obs_core::finish_reason::FinishReason;
+use llm_obs_core::logprobs::LogprobStats;
+use llm_obs_core::outcome::{ResponseOutcome, classify_response};
+use llm_obs_core::tokens::TokenUsage;
+use rig::providers::gemini::completion::gemini_api_types::{
+    ContentCandidate, FinishReason as GeminiFinishReason, GenerateContentResponse, UsageMetadata,
+};
+use rig::telemetry::ProviderResponseExt;
+
+pub fn token_usage(usage: &UsageMetadata) -> TokenUsage {
+    TokenUsage {
+        input_tokens: usage.prompt_token_count.max(0) as u64,
+        output_tokens: usage.candidates_token_count.unwrap_or_default().max(0) as u64,
+        reasoning_tokens: usage.thoughts_token_count.unwrap_or_default().max(0) as u64,
+    }
+}
+
+pub fn finish_reason(reason: &GeminiFinishReason) -> FinishReason {
+    serde_json::to_value(reason)
+        .ok()
+        .and_then(|value| value.as_str().map(FinishReason::parse))
+        .unwrap_or_else(|| FinishReason::Other(format!("{reason:?}")))
+}
+
+/// Uses the chosen tokens' logprobs when Gemini returned them, falling back
+/// to `avgLogprobs`.
+pub fn logprob_stats(
+    candidate: &ContentCandidate,
+    low_confidence_threshold: f64,
+) -> Option<LogprobStats> {
+    candidate
+        .logprobs_result
+        .as_ref()
+        .and_then(|result| {
+            LogprobStats::from_logprobs(
+                result
+                    .chosen_candidate
+                    .iter()
+                    .map(|token| token.log_probability),
+                low_confidence_threshold,
+            )
+        })
+        .or_else(|| {
+            candidate.avg_logprobs.map(|mean| LogprobStats {
+                mean,
+                min: None,
+                token_count: None,
+                low_confidence_tokens: None,
+            })
+        })
+}
+
+/// Outcome classification that also honours prompt-level blocks.
+pub fn classify_outcome(response: &GenerateContentResponse) -> ResponseOutcome {
+    let prompt_blocked = response
+        .prompt_feedback
+        .as_ref()
+        .is_some_and(|feedback| feedback.block_reason.is_some());
+    if prompt_blocked {
+        return ResponseOutcome::SafetyBlocked;
+    }
+
+    let finish_reason = response
+        .candidates
+        .first()
+        .and_then(|candidate| candidate.finish_reason.as_ref())
+        .and_then(|reason| serde_json::to_value(reason).ok())
+        .and_then(|reason| reason.as_str().map(str::to_owned));
+    let text = response.get_text_response().unwrap_or_default();
+
+    classify_response(&text, finish_reason.as_deref())
+}
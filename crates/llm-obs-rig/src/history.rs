@@ -0,0 +1,155 @@
+//! Chat history size capture.
+//!
+//! Long sessions quietly grow the context sent with every turn. Recording the
+//! number of messages per role and an estimate of the history tokens on the
+//! span makes that growth visible long before it hits a context limit.
+//! `InstrumentedAgent`'s chat methods record the history they send on the
+//! span of each call.
+
+use llm_obs_core::tokens::estimate_tokens;
+use rig::completion::message::{AssistantContent, Message, ToolResultContent, UserContent};
+use serde::Serialize;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct HistoryStats {
+    pub user_messages: u64,
+    pub assistant_messages: u64,
+    /// User messages that only carry tool results.
+    pub tool_messages: u64,
+    pub estimated_tokens: u64,
+}
+
+impl HistoryStats {
+    pub fn from_messages(messages: &[Message]) -> Self {
+        let mut stats = Self::default();
+        for message in messages {
+            match message {
+                Message::User { content } => {
+                    let only_tool_results = content
+                        .iter()
+                        .all(|part| matches!(part, UserContent::ToolResult(_)));
+                    if only_tool_results {
+                        stats.tool_messages += 1;
+                    } else {
+                        stats.user_messages += 1;
+                    }
+                    stats.estimated_tokens += content.iter().map(user_content_tokens).sum::<u64>();
+                }
+                Message::Assistant { content, .. } => {
+                    stats.assistant_messages += 1;
+                    stats.estimated_tokens +=
+                        content.iter().map(assistant_content_tokens).sum::<u64>();
+                }
+            }
+        }
+        stats
+    }
+
+    pub fn total_messages(&self) -> u64 {
+        self.user_messages + self.assistant_messages + self.tool_messages
+    }
+}
+
+/// Records per-role message counts and estimated history tokens on `span`.
+pub fn record_history(span: &tracing::Span, history: &[Message]) -> HistoryStats {
+    let stats = HistoryStats::from_messages(history);
+    span.set_attribute("llm.history.messages", stats.total_messages() as i64);
+    span.set_attribute("llm.history.user_messages", stats.user_messages as i64);
+    span.set_attribute(
+        "llm.history.assistant_messages",
+        stats.assistant_messages as i64,
+    );
+    span.set_attribute("llm.history.tool_messages", stats.tool_messages as i64);
+    span.set_attribute(
+        "llm.history.estimated_tokens",
+        stats.estimated_tokens as i64,
+    );
+    stats
+}
+
+fn user_content_tokens(content: &UserContent) -> u64 {
+    match content {
+        UserContent::Text(text) => estimate_tokens(&text.text),
+        UserContent::ToolResult(result) => result
+            .content
+            .iter()
+            .map(|part| match part {
+                ToolResultContent::Text(text) => estimate_tokens(&text.text),
+                ToolResultContent::Image(_) => 0,
+            })
+            .sum(),
+        _ => 0,
+    }
+}
+
+fn assistant_content_tokens(content: &AssistantContent) -> u64 {
+    match content {
+        AssistantContent::Text(text) => estimate_tokens(&text.text),
+        AssistantContent::ToolCall(call) => {
+            estimate_tokens(&call.function.name)
+                + estimate_tokens(&call.function.arguments.to_string())
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::OneOrMany;
+    use serde_json::json;
+
+    #[test]
+    fn counts_messages_by_role() {
+        let history = [
+            Message::user("What is the weather in Paris?"),
+            Message::Assistant {
+                id: None,
+                content: OneOrMany::one(AssistantContent::tool_call(
+                    "call-1",
+                    "weather",
+                    json!({"city": "Paris"}),
+                )),
+            },
+            Message::tool_result("call-1", "18C and sunny"),
+            Message::assistant("It is 18C and sunny."),
+        ];
+
+        let stats = HistoryStats::from_messages(&history);
+        assert_eq!(stats.user_messages, 1);
+        assert_eq!(stats.assistant_messages, 2);
+        assert_eq!(stats.tool_messages, 1);
+        assert_eq!(stats.total_messages(), 4);
+        assert_eq!(
+            stats.estimated_tokens,
+            estimate_tokens("What is the weather in Paris?")
+                + estimate_tokens("weather")
+                + estimate_tokens(r#"{"city":"Paris"}"#)
+                + estimate_tokens("18C and sunny")
+                + estimate_tokens("It is 18C and sunny.")
+        );
+    }
+
+    #[test]
+    fn a_user_message_with_text_and_tool_results_is_a_user_message() {
+        let content = OneOrMany::many([
+            UserContent::tool_result(
+                "call-1",
+                OneOrMany::one(ToolResultContent::text("18C and sunny")),
+            ),
+            UserContent::text("And tomorrow?"),
+        ])
+        .unwrap();
+        let history = [Message::User { content }];
+
+        let stats = HistoryStats::from_messages(&history);
+        assert_eq!(stats.user_messages, 1);
+        assert_eq!(stats.tool_messages, 0);
+    }
+
+    #[test]
+    fn an_empty_history_has_no_messages() {
+        assert_eq!(HistoryStats::from_messages(&[]), HistoryStats::default());
+    }
+}
@@ -0,0 +1,20 @@
+//! Rig adapter for `llm-obs-core`: instrumented agents, tools and provider
+//! models, plus conversions from rig's provider response types into the
+//! core's usage, finish reason and outcome types.
+
+pub mod agent;
+#[cfg(feature = "anthropic")]
+pub mod anthropic;
+pub mod blocking;
+pub mod call_metrics;
+pub mod gemini;
+pub mod history;
+pub mod multimodal;
+#[cfg(feature = "ollama")]
+pub mod ollama;
+#[cfg(feature = "openai")]
+pub mod openai;
+pub mod tool;
+pub mod tool_batch_hook;
+pub mod tool_cache;
+pub mod tool_schema;
@@ -0,0 +1,308 @@
+//! Reference-only capture of image, audio, video and document parts.
+//!
+//! Raw media never goes into span attributes. Each part is reduced to a
+//! `MediaPart` (mime type, byte size, dimensions or duration when they can be
+//! read from the header, and a SHA-256 content hash) so multimodal calls are
+//! still represented in `gen_ai.input.messages` / `gen_ai.output.messages`.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD;
+use llm_obs_core::fingerprint::sha256_hex;
+use llm_obs_core::telemetry::{SamplingDecision, Telemetry};
+use rig::completion::message::{
+    AssistantContent, DocumentSourceKind, Image, Message, MimeType, ToolResult, ToolResultContent,
+    UserContent,
+};
+use rig::telemetry::SpanCombinator;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Image,
+    Audio,
+    Video,
+    Document,
+}
+
+/// Metadata describing one media part without its content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MediaPart {
+    pub kind: MediaKind,
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub byte_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Set when the part is a URL reference rather than inline data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl MediaPart {
+    /// Describes inline bytes, reading dimensions/duration from common headers.
+    pub fn from_bytes(kind: MediaKind, mime_type: Option<&str>, bytes: &[u8]) -> Self {
+        let (width, height) = image_dimensions(bytes).unzip();
+        Self {
+            kind,
+            mime_type: mime_type.map(str::to_owned),
+            byte_size: Some(bytes.len()),
+            width,
+            height,
+            duration_ms: wav_duration_ms(bytes),
+            sha256: Some(sha256_hex(bytes)),
+            url: None,
+        }
+    }
+
+    pub fn from_source(
+        kind: MediaKind,
+        mime_type: Option<&str>,
+        source: &DocumentSourceKind,
+    ) -> Self {
+        match source {
+            DocumentSourceKind::Raw(bytes) => Self::from_bytes(kind, mime_type, bytes),
+            DocumentSourceKind::Base64(encoded) => match STANDARD.decode(encoded) {
+                Ok(bytes) => Self::from_bytes(kind, mime_type, &bytes),
+                Err(_) => Self::from_bytes(kind, mime_type, encoded.as_bytes()),
+            },
+            DocumentSourceKind::String(text) => Self::from_bytes(kind, mime_type, text.as_bytes()),
+            DocumentSourceKind::Url(url) => Self {
+                kind,
+                mime_type: mime_type.map(str::to_owned),
+                byte_size: None,
+                width: None,
+                height: None,
+                duration_ms: None,
+                sha256: None,
+                url: Some(url.clone()),
+            },
+            _ => Self {
+                kind,
+                mime_type: mime_type.map(str::to_owned),
+                byte_size: None,
+                width: None,
+                height: None,
+                duration_ms: None,
+                sha256: None,
+                url: None,
+            },
+        }
+    }
+}
+
+/// Serializes rig messages with every media part replaced by its `MediaPart`.
+pub fn reference_only_messages(messages: &[Message]) -> Value {
+    Value::Array(messages.iter().map(reference_only_message).collect())
+}
+
+fn reference_only_message(message: &Message) -> Value {
+    match message {
+        Message::User { content } => json!({
+            "role": "user",
+            "content": content.iter().map(reference_only_user_content).collect::<Vec<_>>(),
+        }),
+        Message::Assistant { content, .. } => json!({
+            "role": "assistant",
+            "content": content.iter().map(reference_only_assistant_content).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+fn reference_only_user_content(content: &UserContent) -> Value {
+    let part = match content {
+        UserContent::Image(image) => image_part(image),
+        UserContent::ToolResult(result) => return reference_only_tool_result(result),
+        UserContent::Audio(audio) => MediaPart::from_source(
+            MediaKind::Audio,
+            audio.media_type.as_ref().map(MimeType::to_mime_type),
+            &audio.data,
+        ),
+        UserContent::Video(video) => MediaPart::from_source(
+            MediaKind::Video,
+            video.media_type.as_ref().map(MimeType::to_mime_type),
+            &video.data,
+        ),
+        UserContent::Document(document) => MediaPart::from_source(
+            MediaKind::Document,
+            document.media_type.as_ref().map(MimeType::to_mime_type),
+            &document.data,
+        ),
+        other => return serde_json::to_value(other).unwrap_or(Value::Null),
+    };
+    json!({ "type": "media", "media": part })
+}
+
+/// Serializes a tool result like rig does, with image results replaced by
+/// their `MediaPart`.
+fn reference_only_tool_result(result: &ToolResult) -> Value {
+    let content: Vec<Value> = result
+        .content
+        .iter()
+        .map(|content| match content {
+            ToolResultContent::Image(image) => {
+                json!({ "type": "media", "media": image_part(image) })
+            }
+            text => serde_json::to_value(text).unwrap_or(Value::Null),
+        })
+        .collect();
+    let mut value = json!({ "type": "toolresult", "id": result.id, "content": content });
+    if let Some(call_id) = &result.call_id {
+        value["call_id"] = json!(call_id);
+    }
+    value
+}
+
+fn reference_only_assistant_content(content: &AssistantContent) -> Value {
+    match content {
+        AssistantContent::Image(image) => json!({ "type": "media", "media": image_part(image) }),
+        other => serde_json::to_value(other).unwrap_or(Value::Null),
+    }
+}
+
+fn image_part(image: &Image) -> MediaPart {
+    MediaPart::from_source(
+        MediaKind::Image,
+        image.media_type.as_ref().map(MimeType::to_mime_type),
+        &image.data,
+    )
+}
+
+/// `SpanCombinator` counterpart that never records raw media bytes.
+pub trait MultimodalSpanExt {
+    fn record_multimodal_input(&self, messages: &[Message]);
+    fn record_multimodal_output(&self, messages: &[Message]);
+}
+
+impl MultimodalSpanExt for tracing::Span {
+    fn record_multimodal_input(&self, messages: &[Message]) {
+        if matches!(
+            Telemetry::sampling_decision(self),
+            SamplingDecision::Drop | SamplingDecision::NoActiveSpan
+        ) {
+            return;
+        }
+        self.record_model_input(&reference_only_messages(messages));
+    }
+
+    fn record_multimodal_output(&self, messages: &[Message]) {
+        if matches!(
+            Telemetry::sampling_decision(self),
+            SamplingDecision::Drop | SamplingDecision::NoActiveSpan
+        ) {
+            return;
+        }
+        self.record_model_output(&reference_only_messages(messages));
+    }
+}
+
+fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") && bytes.len() >= 24 {
+        let width = u32::from_be_bytes(bytes[16..20].try_into().ok()?);
+        let height = u32::from_be_bytes(bytes[20..24].try_into().ok()?);
+        return Some((width, height));
+    }
+    if (bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")) && bytes.len() >= 10 {
+        let width = u16::from_le_bytes([bytes[6], bytes[7]]);
+        let height = u16::from_le_bytes([bytes[8], bytes[9]]);
+        return Some((width.into(), height.into()));
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        return jpeg_dimensions(bytes);
+    }
+    None
+}
+
+fn jpeg_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+    while offset + 9 < bytes.len() {
+        if bytes[offset] != 0xFF {
+            return None;
+        }
+        let marker = bytes[offset + 1];
+        let segment_len = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let is_start_of_frame =
+            (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+        if is_start_of_frame {
+            let height = u16::from_be_bytes([bytes[offset + 5], bytes[offset + 6]]);
+            let width = u16::from_be_bytes([bytes[offset + 7], bytes[offset + 8]]);
+            return Some((width.into(), height.into()));
+        }
+        offset += 2 + segment_len;
+    }
+    None
+}
+
+fn wav_duration_ms(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() < 44 || !bytes.starts_with(b"RIFF") || &bytes[8..12] != b"WAVE" {
+        return None;
+    }
+    let byte_rate = u32::from_le_bytes(bytes[28..32].try_into().ok()?) as u64;
+    if byte_rate == 0 {
+        return None;
+    }
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let chunk_len = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?) as u64;
+        if &bytes[offset..offset + 4] == b"data" {
+            return Some(chunk_len * 1000 / byte_rate);
+        }
+        offset += 8 + chunk_len as usize;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::OneOrMany;
+    use rig::completion::message::{ImageMediaType, Text};
+
+    #[test]
+    fn tool_result_images_are_reduced_to_references() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x02\0\0\0\x03";
+        let encoded = STANDARD.encode(png);
+        let result = UserContent::ToolResult(ToolResult {
+            id: "screenshot".to_owned(),
+            call_id: Some("call_1".to_owned()),
+            content: OneOrMany::many(vec![
+                ToolResultContent::Text(Text {
+                    text: "captured".to_owned(),
+                }),
+                ToolResultContent::Image(Image {
+                    data: DocumentSourceKind::Base64(encoded.clone()),
+                    media_type: Some(ImageMediaType::PNG),
+                    detail: None,
+                    additional_params: None,
+                }),
+            ])
+            .unwrap(),
+        });
+
+        let value = reference_only_messages(&[Message::User {
+            content: OneOrMany::one(result),
+        }]);
+
+        assert!(!value.to_string().contains(&encoded));
+        let part = &value[0]["content"][0];
+        assert_eq!(part["type"], "toolresult");
+        assert_eq!(part["id"], "screenshot");
+        assert_eq!(part["call_id"], "call_1");
+        assert_eq!(part["content"][0]["text"], "captured");
+        let media = &part["content"][1]["media"];
+        assert_eq!(part["content"][1]["type"], "media");
+        assert_eq!(media["kind"], "image");
+        assert_eq!(media["mime_type"], "image/png");
+        assert_eq!(media["byte_size"], png.len());
+        assert_eq!(media["width"], 2);
+        assert_eq!(media["height"], 3);
+        assert_eq!(media["sha256"], sha256_hex(png));
+    }
+}
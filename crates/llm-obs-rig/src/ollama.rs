@@ -0,0 +1,156 @@
+//! Instrumentation for local models served by Ollama.
+//!
+//! A local runtime's latency is dominated by things a hosted API hides:
+//! loading the weights into memory on the first call after a restart or
+//! model switch, prompt evaluation, and generation speed on the local
+//! hardware. Ollama's native API reports each of them with every response.
+//! [`InstrumentedModel`] wraps rig's Ollama completion model and calls
+//! [`record_response`] inside rig's per-call `chat` span, recording usage,
+//! the finish reason and the server timings as `llm.server.*` attributes in
+//! seconds, plus the generation rate. Each call, failed ones included, is
+//! recorded in the GenAI client histograms. Ollama assigns responses no id;
+//! their `created_at` timestamp, which the server log also shows, goes into
+//! the enclosing `ProviderRequestIds` scope in its place.
+//!
+//! ```ignore
+//! let client = ollama::Client::builder().api_key(Nothing).build()?;
+//! let agent = InstrumentedAgent::new(
+//!     "llama3.2",
+//!     AgentBuilder::new(InstrumentedModel::new(CompletionModel::new(client, "llama3.2"))).build(),
+//! );
+//! ```
+//!
+//! OpenAI-compatible servers (vLLM, or Ollama's `/v1` endpoint) work with
+//! rig's OpenAI client and `InstrumentedAgent` as they are, but return none
+//! of these timings.
+
+use crate::call_metrics::{self, Call, completion_error_type};
+use llm_obs_core::finish_reason::{FinishReason, record_finish_reason};
+use llm_obs_core::request_ids::ProviderRequestIds;
+use llm_obs_core::tokens::TokenUsage;
+use rig::completion::{self, CompletionError, CompletionRequest};
+use rig::http_client::HttpClientExt;
+use rig::providers::ollama::{
+    Client, CompletionModel, CompletionResponse, StreamingCompletionResponse,
+};
+use rig::streaming;
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// `gen_ai.provider.name` for Ollama.
+pub const PROVIDER_NAME: &str = "ollama";
+
+/// Records `response` on `span` and in the current [`ProviderRequestIds`]
+/// scope, and returns its usage.
+pub fn record_response(span: &tracing::Span, response: &CompletionResponse) -> TokenUsage {
+    span.set_attribute("gen_ai.provider.name", PROVIDER_NAME);
+    ProviderRequestIds::record_current(response.created_at.clone());
+    span.set_attribute("gen_ai.response.model", response.model.clone());
+    if let Some(done_reason) = &response.done_reason {
+        record_finish_reason(
+            span,
+            &response.model,
+            &FinishReason::parse(done_reason),
+            None,
+        );
+    }
+
+    let input_tokens = response.prompt_eval_count.unwrap_or_default();
+    let output_tokens = response.eval_count.unwrap_or_default();
+    span.set_attribute("gen_ai.usage.input_tokens", input_tokens as i64);
+    span.set_attribute("gen_ai.usage.output_tokens", output_tokens as i64);
+    span.set_attribute(
+        "gen_ai.usage.total_tokens",
+        (input_tokens + output_tokens) as i64,
+    );
+
+    // Ollama reports durations in nanoseconds.
+    for (key, nanos) in [
+        ("llm.server.total_duration", response.total_duration),
+        ("llm.server.load_duration", response.load_duration),
+        (
+            "llm.server.prompt_eval_duration",
+            response.prompt_eval_duration,
+        ),
+        ("llm.server.eval_duration", response.eval_duration),
+    ] {
+        if let Some(nanos) = nanos {
+            span.set_attribute(key, Duration::from_nanos(nanos).as_secs_f64());
+        }
+    }
+    if let Some(eval_duration) = response.eval_duration.filter(|nanos| *nanos > 0) {
+        span.set_attribute(
+            "llm.server.output_tokens_per_second",
+            output_tokens as f64 / Duration::from_nanos(eval_duration).as_secs_f64(),
+        );
+    }
+    TokenUsage {
+        input_tokens,
+        output_tokens,
+        reasoning_tokens: 0,
+    }
+}
+
+/// rig's Ollama completion model, recording each response on the span of
+/// the call.
+#[derive(Clone)]
+pub struct InstrumentedModel<T = reqwest::Client> {
+    inner: CompletionModel<T>,
+}
+
+impl<T> InstrumentedModel<T> {
+    pub fn new(inner: CompletionModel<T>) -> Self {
+        Self { inner }
+    }
+
+    pub fn inner(&self) -> &CompletionModel<T> {
+        &self.inner
+    }
+}
+
+impl<T> completion::CompletionModel for InstrumentedModel<T>
+where
+    T: HttpClientExt + Clone + Default + std::fmt::Debug + Send + 'static,
+{
+    type Response = CompletionResponse;
+    type StreamingResponse = StreamingCompletionResponse;
+    type Client = Client<T>;
+
+    fn make(client: &Self::Client, model: impl Into<String>) -> Self {
+        Self::new(CompletionModel::make(client, model))
+    }
+
+    async fn completion(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<completion::CompletionResponse<CompletionResponse>, CompletionError> {
+        let started = Instant::now();
+        let result = self.inner.completion(request).await;
+        let mut call = Call {
+            operation: "chat",
+            provider: PROVIDER_NAME,
+            request_model: &self.inner.model,
+            response_model: None,
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                let error_type = completion_error_type(&error);
+                call_metrics::record(&call, started.elapsed(), None, Some(error_type));
+                return Err(error);
+            }
+        };
+        let usage = record_response(&tracing::Span::current(), &response.raw_response);
+        call.response_model = Some(&response.raw_response.model);
+        call_metrics::record(&call, started.elapsed(), Some(&usage), None);
+        Ok(response)
+    }
+
+    async fn stream(
+        &self,
+        request: CompletionRequest,
+    ) -> Result<streaming::StreamingCompletionResponse<StreamingCompletionResponse>, CompletionError>
+    {
+        self.inner.stream(request).await
+    }
+}
@@ -0,0 +1,100 @@
+//! OpenAI-specific instrumentation that rig's provider types do not surface.
+//!
+//! `InstrumentedAgent` already gives OpenAI agents the same span schema as
+//! Gemini ones. When the Responses API is called directly through a rig
+//! completion model, [`record_response`] fills in what the agent wrapper
+//! cannot see: response id and model, usage split into input, output,
+//! reasoning and cached tokens, and the finish reason, including the
+//! truncation event for `max_output_tokens`, and records the call in the
+//! GenAI client histograms. The response id is also added to the enclosing
+//! `ProviderRequestIds` scope.
+
+use crate::call_metrics::{self, Call};
+use llm_obs_core::finish_reason::{FinishReason, record_finish_reason};
+use llm_obs_core::request_ids::ProviderRequestIds;
+use llm_obs_core::tokens::TokenUsage;
+use rig::providers::openai::responses_api::{CompletionResponse, ResponseStatus, ResponsesUsage};
+use std::time::Duration;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// `gen_ai.provider.name` for OpenAI.
+pub const PROVIDER_NAME: &str = "openai";
+
+/// OpenAI reports reasoning tokens as a subset of `output_tokens`.
+pub fn token_usage(usage: &ResponsesUsage) -> TokenUsage {
+    let reasoning_tokens = usage.output_tokens_details.reasoning_tokens;
+    TokenUsage {
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens.saturating_sub(reasoning_tokens),
+        reasoning_tokens,
+    }
+}
+
+/// Records `response` on `span` and in the current [`ProviderRequestIds`]
+/// scope, and the call that took `duration` in the GenAI metrics, and
+/// returns its usage, if reported.
+pub fn record_response(
+    span: &tracing::Span,
+    response: &CompletionResponse,
+    duration: Duration,
+) -> Option<TokenUsage> {
+    span.set_attribute("gen_ai.provider.name", PROVIDER_NAME);
+    span.set_attribute("gen_ai.response.id", response.id.clone());
+    ProviderRequestIds::record_current(response.id.clone());
+    span.set_attribute("gen_ai.response.model", response.model.clone());
+    record_finish_reason(
+        span,
+        &response.model,
+        &finish_reason(response),
+        response.max_output_tokens,
+    );
+
+    let tokens = response.usage.as_ref().map(token_usage);
+    call_metrics::record(
+        &Call {
+            operation: "chat",
+            provider: PROVIDER_NAME,
+            request_model: &response.model,
+            response_model: Some(&response.model),
+        },
+        duration,
+        tokens.as_ref(),
+        None,
+    );
+
+    let usage = response.usage.as_ref()?;
+    let tokens = tokens?;
+    span.set_attribute("gen_ai.usage.input_tokens", tokens.input_tokens as i64);
+    // As reported, reasoning included; `tokens` splits it out for pricing.
+    span.set_attribute("gen_ai.usage.output_tokens", usage.output_tokens as i64);
+    span.set_attribute(
+        "gen_ai.usage.reasoning_tokens",
+        tokens.reasoning_tokens as i64,
+    );
+    span.set_attribute("gen_ai.usage.total_tokens", usage.total_tokens as i64);
+    if let Some(details) = usage
+        .input_tokens_details
+        .as_ref()
+        .filter(|details| details.cached_tokens > 0)
+    {
+        span.set_attribute(
+            "gen_ai.usage.cache_read.input_tokens",
+            details.cached_tokens as i64,
+        );
+    }
+    Some(tokens)
+}
+
+/// The Responses API reports a status rather than a finish reason; an
+/// incomplete response carries the reason (`max_output_tokens`,
+/// `content_filter`) separately.
+pub fn finish_reason(response: &CompletionResponse) -> FinishReason {
+    match (&response.status, &response.incomplete_details) {
+        (ResponseStatus::Incomplete, Some(details)) => FinishReason::parse(&details.reason),
+        (ResponseStatus::Completed, _) => FinishReason::Stop,
+        (status, _) => serde_json::to_value(status)
+            .ok()
+            .and_then(|value| value.as_str().map(FinishReason::parse))
+            .unwrap_or_else(|| FinishReason::Other(format!("{status:?}"))),
+    }
+}
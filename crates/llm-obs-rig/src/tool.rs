@@ -0,0 +1,227 @@
+//! Span and duration instrumentation for rig tools.
+//!
+//! Without it every `Tool::call` has to open its own span, serialize its
+//! arguments and result and remember to mark failures, as in the
+//! `gemini_rig_tools` example. [`InstrumentedTool`] does that around any
+//! tool: each call runs in an `execute_tool {name}` span (see
+//! [`start_tool_span`]) carrying `gen_ai.tool.call.arguments` and
+//! `gen_ai.tool.call.result`, failures set the span status and `error.type`,
+//! and the duration is recorded in the `llm.tool.duration` histogram. The
+//! span also carries the hash of the tool's definition, and a schema change
+//! since the last call is flagged (see [`crate::tool_schema`]).
+//!
+//! ```ignore
+//! let agent = client
+//!     .agent("gemini-2.5-flash")
+//!     .tool(InstrumentedTool::new(AddTool))
+//!     .build();
+//! ```
+//!
+//! Inside an agent the span is a child of rig's own `execute_tool` span.
+
+use crate::tool_schema::ToolSchemaRegistry;
+use llm_obs_core::processors::capture::{CAPTURE_POLICY_KEY, ContentCapturePolicy};
+use llm_obs_core::scopes::Subsystem;
+use llm_obs_core::spans::start_tool_span;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::trace::Status;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Instant;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+fn duration_histogram() -> &'static Histogram<f64> {
+    static HISTOGRAM: OnceLock<Histogram<f64>> = OnceLock::new();
+    HISTOGRAM.get_or_init(|| {
+        Subsystem::Tool
+            .meter()
+            .f64_histogram("llm.tool.duration")
+            .with_unit("s")
+            .with_description("Tool call duration, by tool and error type")
+            .build()
+    })
+}
+
+/// A rig tool whose calls are traced and timed.
+#[derive(Debug, Clone)]
+pub struct InstrumentedTool<T> {
+    inner: T,
+    capture_policy: ContentCapturePolicy,
+    schemas: Arc<ToolSchemaRegistry>,
+    /// The definition last handed to rig, observed on each call.
+    definition: Arc<Mutex<Option<ToolDefinition>>>,
+}
+
+impl<T: Tool> InstrumentedTool<T> {
+    /// Captures arguments and results per `LLM_CONTENT_CAPTURE`. The export
+    /// pipeline's `ContentCaptureProcessor` applies its own policy to them as
+    /// well, so a `TelemetryBuilder` policy also covers tool calls.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            capture_policy: ContentCapturePolicy::from_env_or_off(),
+            schemas: Arc::clone(ToolSchemaRegistry::global()),
+            definition: Arc::default(),
+        }
+    }
+
+    /// How arguments and results are recorded on the span, e.g.
+    /// `ContentCapturePolicy::Off` for tools that handle data which must not
+    /// be exported.
+    pub fn with_capture_policy(mut self, policy: ContentCapturePolicy) -> Self {
+        self.capture_policy = policy;
+        self
+    }
+
+    /// Registry the tool's schema hash is checked against; the process-wide
+    /// in-memory one when unset. Use `ToolSchemaRegistry::with_store` to
+    /// flag changes between deployments.
+    pub fn with_schema_registry(mut self, schemas: Arc<ToolSchemaRegistry>) -> Self {
+        self.schemas = schemas;
+        self
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Last path segment of the error type, e.g. `ToolError`.
+fn error_type<E>() -> &'static str {
+    let name = std::any::type_name::<E>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// `value` as JSON, under `policy`.
+fn capture<V: Serialize>(policy: ContentCapturePolicy, value: &V) -> Option<String> {
+    if policy == ContentCapturePolicy::Off {
+        return None;
+    }
+    policy.apply(&serde_json::to_string(value).ok()?)
+}
+
+impl<T> Tool for InstrumentedTool<T>
+where
+    T: Tool,
+    T::Args: Serialize,
+{
+    const NAME: &'static str = T::NAME;
+    type Error = T::Error;
+    type Args = T::Args;
+    type Output = T::Output;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        let definition = self.inner.definition(prompt).await;
+        *self
+            .definition
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(definition.clone());
+        definition
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let name = self.inner.name();
+        let span = start_tool_span(&name);
+        span.record("gen_ai.tool.type", "function");
+        let definition = self
+            .definition
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let definition = match definition {
+            Some(definition) => definition,
+            // Called directly rather than by an agent.
+            None => self.inner.definition(String::new()).await,
+        };
+        self.schemas.observe(&span, &definition);
+        let policy = self.capture_policy;
+        if policy != ContentCapturePolicy::Full {
+            span.set_attribute(CAPTURE_POLICY_KEY, policy.to_string());
+        }
+        if let Some(arguments) = capture(policy, &args) {
+            span.set_attribute("gen_ai.tool.call.arguments", arguments);
+        }
+
+        let started = Instant::now();
+        let result = self.inner.call(args).instrument(span.clone()).await;
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let mut attributes = vec![KeyValue::new("gen_ai.tool.name", name)];
+        match &result {
+            Ok(output) => {
+                if let Some(output) = capture(policy, output) {
+                    span.set_attribute("gen_ai.tool.call.result", output);
+                }
+            }
+            Err(error) => {
+                let error_type = error_type::<T::Error>();
+                span.record("error.type", error_type);
+                span.set_status(Status::error(error.to_string()));
+                attributes.push(KeyValue::new("error.type", error_type));
+            }
+        }
+        duration_histogram().record(elapsed, &attributes);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_schema::schema_hash;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::convert::Infallible;
+
+    #[derive(Deserialize, Serialize)]
+    struct EchoArgs {
+        text: String,
+    }
+
+    struct Echo;
+
+    impl Tool for Echo {
+        const NAME: &'static str = "echo";
+        type Error = Infallible;
+        type Args = EchoArgs;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_owned(),
+                description: "Echoes the text".to_owned(),
+                parameters: json!({"type": "object", "properties": {"text": {"type": "string"}}}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            Ok(args.text)
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_observe_the_tool_schema() {
+        let schemas = Arc::new(ToolSchemaRegistry::in_memory());
+        let tool = InstrumentedTool::new(Echo).with_schema_registry(Arc::clone(&schemas));
+
+        let output = tool
+            .call(EchoArgs {
+                text: "hi".to_owned(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(output, "hi");
+        let definition = Echo.definition(String::new()).await;
+        assert_eq!(schemas.known_hash("echo"), Some(schema_hash(&definition)));
+    }
+}
@@ -0,0 +1,240 @@
+//! Groups the tool calls of each agent turn under an `execute_tools` span.
+//!
+//! rig runs every tool call the model asks for in one response in its own
+//! `execute_tool` span, parented to the agent span like the model calls
+//! around them. [`ToolBatchHook`] is a prompt hook that opens a
+//! [`ToolBatch`] when a response contains two or more tool calls and adopts
+//! rig's call spans into it as they start, so a turn's calls, their order,
+//! whether they overlapped and how many failed show up together.
+//! [`InstrumentedAgent`](crate::agent::InstrumentedAgent) installs it on
+//! every prompt, around the agent's own hook; calls only overlap with
+//! [`InstrumentedAgent::with_tool_concurrency`](crate::agent::InstrumentedAgent::with_tool_concurrency)
+//! above 1.
+//!
+//! Batches can be switched off at runtime with the [`TOOL_BATCH_FLAG`]
+//! feature flag, evaluated once for each turn with several calls.
+//!
+//! The hook also adds the provider's id of every model response (see
+//! [`response_id`]) to the enclosing
+//! [`ProviderRequestIds`](llm_obs_core::request_ids::ProviderRequestIds)
+//! scope.
+//!
+//! rig passes failed calls to hooks as the error's text in place of a
+//! result, so failures are recognized by rig's tool server error messages.
+//! Streaming prompts are not covered.
+
+use crate::agent::response_id;
+use llm_obs_core::flags::{TOOL_BATCH_FLAG, bool_flag};
+use llm_obs_core::request_ids::ProviderRequestIds;
+use llm_obs_core::tool_batch::ToolBatch;
+use rig::agent::{HookAction, PromptHook, ToolCallHookAction};
+use rig::completion::{CompletionModel, CompletionResponse, Message};
+use rig::message::AssistantContent;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Display prefixes of rig's `ToolServerError`, which hooks receive as the
+/// result of a failed call. The tests run them against a real `ToolServer`,
+/// so a rig upgrade that rewords its errors fails there.
+const TOOL_ERROR_PREFIXES: [&str; 4] = [
+    "Toolset error: ",
+    "Error while sending message: ",
+    "Sending message was cancelled",
+    "An invalid message type was returned",
+];
+
+/// A prompt hook recording tool-call batches, delegating to `inner`.
+#[derive(Debug, Clone)]
+pub struct ToolBatchHook<P> {
+    inner: Option<P>,
+    batch: Arc<Mutex<Option<ToolBatch>>>,
+}
+
+impl<P> ToolBatchHook<P> {
+    pub fn new(inner: Option<P>) -> Self {
+        Self {
+            inner,
+            batch: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn with_batch(&self, f: impl FnOnce(&ToolBatch)) {
+        let batch = self.batch.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(batch) = batch.as_ref() {
+            f(batch);
+        }
+    }
+}
+
+fn is_tool_error(result: &str) -> bool {
+    TOOL_ERROR_PREFIXES
+        .iter()
+        .any(|prefix| result.starts_with(prefix))
+}
+
+impl<M, P> PromptHook<M> for ToolBatchHook<P>
+where
+    M: CompletionModel + 'static,
+    P: PromptHook<M>,
+{
+    async fn on_completion_call(&self, prompt: &Message, history: &[Message]) -> HookAction {
+        // The previous turn's tool calls have all returned by now.
+        self.batch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        match &self.inner {
+            Some(inner) => inner.on_completion_call(prompt, history).await,
+            None => HookAction::cont(),
+        }
+    }
+
+    async fn on_completion_response(
+        &self,
+        prompt: &Message,
+        response: &CompletionResponse<M::Response>,
+    ) -> HookAction {
+        if let Some(id) = response_id(&response.raw_response) {
+            ProviderRequestIds::record_current(id);
+        }
+        let tool_calls = response
+            .choice
+            .iter()
+            .filter(|content| matches!(content, AssistantContent::ToolCall(_)))
+            .count();
+        if tool_calls > 1 && bool_flag(TOOL_BATCH_FLAG, true) {
+            *self.batch.lock().unwrap_or_else(PoisonError::into_inner) =
+                Some(ToolBatch::start(tool_calls));
+        }
+        match &self.inner {
+            Some(inner) => inner.on_completion_response(prompt, response).await,
+            None => HookAction::cont(),
+        }
+    }
+
+    async fn on_tool_call(
+        &self,
+        tool_name: &str,
+        tool_call_id: Option<String>,
+        internal_call_id: &str,
+        args: &str,
+    ) -> ToolCallHookAction {
+        // rig calls this inside the call's `execute_tool` span.
+        let call_span = tracing::Span::current();
+        self.with_batch(|batch| {
+            batch.call_started(&call_span);
+        });
+        let action = match &self.inner {
+            Some(inner) => {
+                inner
+                    .on_tool_call(tool_name, tool_call_id, internal_call_id, args)
+                    .await
+            }
+            None => ToolCallHookAction::cont(),
+        };
+        if action != ToolCallHookAction::Continue {
+            self.with_batch(ToolBatch::call_skipped);
+        }
+        action
+    }
+
+    async fn on_tool_result(
+        &self,
+        tool_name: &str,
+        tool_call_id: Option<String>,
+        internal_call_id: &str,
+        args: &str,
+        result: &str,
+    ) -> HookAction {
+        self.with_batch(|batch| batch.call_finished(is_tool_error(result)));
+        match &self.inner {
+            Some(inner) => {
+                inner
+                    .on_tool_result(tool_name, tool_call_id, internal_call_id, args, result)
+                    .await
+            }
+            None => HookAction::cont(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rig::completion::ToolDefinition;
+    use rig::tool::Tool;
+    use rig::tool::server::{ToolServer, ToolServerError, ToolServerResponse};
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct DivisionByZero;
+
+    impl fmt::Display for DivisionByZero {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("division by zero")
+        }
+    }
+
+    impl std::error::Error for DivisionByZero {}
+
+    #[derive(Deserialize)]
+    struct DivideArgs {
+        x: f64,
+        y: f64,
+    }
+
+    struct Divide;
+
+    impl Tool for Divide {
+        const NAME: &'static str = "divide";
+        type Error = DivisionByZero;
+        type Args = DivideArgs;
+        type Output = f64;
+
+        async fn definition(&self, _prompt: String) -> ToolDefinition {
+            ToolDefinition {
+                name: Self::NAME.to_owned(),
+                description: "Divides x by y".to_owned(),
+                parameters: json!({"type": "object"}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            if args.y == 0.0 {
+                return Err(DivisionByZero);
+            }
+            Ok(args.x / args.y)
+        }
+    }
+
+    /// What rig hands `on_tool_result` for a call, as in its prompt loop.
+    async fn tool_result(name: &str, args: &str) -> String {
+        let tools = ToolServer::new().tool(Divide).run();
+        match tools.call_tool(name, args).await {
+            Ok(result) => result,
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn recognizes_rig_tool_server_failures() {
+        let failed = tool_result("divide", r#"{"x": 1, "y": 0}"#).await;
+        assert!(is_tool_error(&failed), "{failed}");
+        let missing = tool_result("multiply", r#"{"x": 1, "y": 2}"#).await;
+        assert!(is_tool_error(&missing), "{missing}");
+        let malformed = tool_result("divide", "not json").await;
+        assert!(is_tool_error(&malformed), "{malformed}");
+        let invalid = ToolServerError::InvalidMessage(ToolServerResponse::ToolAdded).to_string();
+        assert!(is_tool_error(&invalid), "{invalid}");
+    }
+
+    #[tokio::test]
+    async fn successful_results_are_not_failures() {
+        let result = tool_result("divide", r#"{"x": 1, "y": 4}"#).await;
+
+        assert_eq!(result, "0.25");
+        assert!(!is_tool_error(&result));
+        assert!(!is_tool_error("The Toolset error: line was in the output"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Opt-in result caching for idempotent tools.
+//!
+//! Within one agent loop a model often calls the same lookup tool (weather,
+//! exchange rates, documentation search) with the same arguments several
+//! times. [`CachedTool`] wraps a rig tool and answers repeats from memory for
+//! a TTL, keyed on a hash of the serialized arguments; failed calls are not
+//! cached. The tool span records `tool.cache.hit` and, on a hit,
+//! `tool.cache.age_ms` and `tool.cache.staleness` (age as a fraction of the
+//! TTL), so answers built on an old result can be spotted. Lookups are
+//! counted in `llm.tool.cache.requests` by tool and outcome.
+//!
+//! Only wrap tools whose result depends on nothing but their arguments for
+//! the length of the TTL.
+
+use llm_obs_core::fingerprint::sha256_hex;
+use llm_obs_core::scopes::Subsystem;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Entries kept per tool unless set with [`CachedTool::with_max_entries`].
+pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+#[derive(Debug)]
+struct CacheEntry<O> {
+    output: O,
+    stored_at: Instant,
+}
+
+fn requests_counter() -> &'static Counter<u64> {
+    static COUNTER: OnceLock<Counter<u64>> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        Subsystem::Tool
+            .meter()
+            .u64_counter("llm.tool.cache.requests")
+            .with_description("Tool calls looked up in the result cache, by tool and hit")
+            .build()
+    })
+}
+
+/// A rig tool whose results are reused for `ttl`. Clones share the cache.
+pub struct CachedTool<T: Tool> {
+    tool: T,
+    ttl: Duration,
+    max_entries: usize,
+    entries: Arc<Mutex<HashMap<String, CacheEntry<T::Output>>>>,
+}
+
+impl<T: Tool> CachedTool<T>
+where
+    T::Args: Serialize,
+    T::Output: Clone,
+{
+    pub fn new(tool: T, ttl: Duration) -> Self {
+        Self {
+            tool,
+            ttl,
+            max_entries: DEFAULT_MAX_ENTRIES,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn tool(&self) -> &T {
+        &self.tool
+    }
+
+    /// Drops every cached result, e.g. after the underlying data changed.
+    pub fn invalidate(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CacheEntry<T::Output>>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn lookup(&self, key: &str) -> Option<(T::Output, Duration)> {
+        let mut entries = self.lock();
+        let age = entries.get(key)?.stored_at.elapsed();
+        if age >= self.ttl {
+            entries.remove(key);
+            return None;
+        }
+        entries.get(key).map(|entry| (entry.output.clone(), age))
+    }
+
+    fn store(&self, key: String, output: T::Output) {
+        let mut entries = self.lock();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            let ttl = self.ttl;
+            entries.retain(|_, entry| entry.stored_at.elapsed() < ttl);
+            if entries.len() >= self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.stored_at)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                output,
+                stored_at: Instant::now(),
+            },
+        );
+    }
+
+    fn record(&self, span: &tracing::Span, hit: Option<Duration>) {
+        span.set_attribute("tool.cache.hit", hit.is_some());
+        if let Some(age) = hit {
+            span.set_attribute("tool.cache.age_ms", age.as_millis() as i64);
+            span.set_attribute(
+                "tool.cache.staleness",
+                age.as_secs_f64() / self.ttl.as_secs_f64(),
+            );
+        }
+        requests_counter().add(
+            1,
+            &[
+                KeyValue::new("gen_ai.tool.name", self.tool.name()),
+                KeyValue::new("tool.cache.hit", hit.is_some()),
+            ],
+        );
+    }
+}
+
+impl<T: Tool + Clone> Clone for CachedTool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tool: self.tool.clone(),
+            ttl: self.ttl,
+            max_entries: self.max_entries,
+            entries: Arc::clone(&self.entries),
+        }
+    }
+}
+
+impl<T> Tool for CachedTool<T>
+where
+    T: Tool,
+    T::Args: Serialize,
+    T::Output: Clone + Send + Sync,
+{
+    const NAME: &'static str = T::NAME;
+    type Error = T::Error;
+    type Args = T::Args;
+    type Output = T::Output;
+
+    fn name(&self) -> String {
+        self.tool.name()
+    }
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.tool.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let span = tracing::Span::current();
+        // Arguments that cannot be serialized are never cached.
+        let Ok(serialized) = serde_json::to_vec(&args) else {
+            return self.tool.call(args).await;
+        };
+        let key = sha256_hex(serialized);
+        if let Some((output, age)) = self.lookup(&key) {
+            self.record(&span, Some(age));
+            return Ok(output);
+        }
+        self.record(&span, None);
+        let output = self.tool.call(args).await?;
+        self.store(key, output.clone());
+        Ok(output)
+    }
+}
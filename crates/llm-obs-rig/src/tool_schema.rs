@@ -0,0 +1,137 @@
+//! Tool schema drift detection.
+//!
+//! Each tool span carries a hash of the tool's `ToolDefinition`. The last
+//! known hash per tool can be persisted to a small JSON file so that a schema
+//! change between two deployments is flagged with a
+//! `gen_ai.tool.schema_changed` warning event on the first call after it.
+//! `InstrumentedTool` observes every call in [`ToolSchemaRegistry::global`]
+//! unless given a registry with `with_schema_registry`.
+
+use anyhow::Context;
+use llm_obs_core::fingerprint::fingerprint;
+use opentelemetry::KeyValue;
+use rig::completion::ToolDefinition;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+#[derive(Debug, Default)]
+pub struct ToolSchemaRegistry {
+    store: Option<PathBuf>,
+    known: Mutex<BTreeMap<String, String>>,
+}
+
+impl ToolSchemaRegistry {
+    /// Detects drift within this process only.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Process-wide in-memory registry.
+    pub fn global() -> &'static Arc<Self> {
+        static GLOBAL: OnceLock<Arc<ToolSchemaRegistry>> = OnceLock::new();
+        GLOBAL.get_or_init(|| Arc::new(Self::in_memory()))
+    }
+
+    /// Loads and persists known hashes at `path` to detect drift across
+    /// process versions.
+    pub fn with_store(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let known = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse tool schema store {}", path.display()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                return Err(error).with_context(|| {
+                    format!("Failed to read tool schema store {}", path.display())
+                });
+            }
+        };
+
+        Ok(Self {
+            store: Some(path),
+            known: Mutex::new(known),
+        })
+    }
+
+    /// The last hash observed for the tool `name`.
+    pub fn known_hash(&self, name: &str) -> Option<String> {
+        self.known
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned()
+    }
+
+    /// Records the schema hash on `span`, warning when it differs from the
+    /// last known hash for the tool. Returns the current hash.
+    pub fn observe(&self, span: &tracing::Span, definition: &ToolDefinition) -> String {
+        let current = schema_hash(definition);
+        span.set_attribute("gen_ai.tool.name", definition.name.clone());
+        span.set_attribute("gen_ai.tool.schema_hash", current.clone());
+
+        let mut known = self.known.lock().unwrap_or_else(PoisonError::into_inner);
+        let previous = known.insert(definition.name.clone(), current.clone());
+        if previous.as_deref() == Some(current.as_str()) {
+            return current;
+        }
+
+        if let Some(previous) = &previous {
+            span.add_event(
+                "gen_ai.tool.schema_changed",
+                vec![
+                    KeyValue::new("gen_ai.tool.name", definition.name.clone()),
+                    KeyValue::new("previous_hash", previous.clone()),
+                    KeyValue::new("current_hash", current.clone()),
+                ],
+            );
+            tracing::warn!(
+                gen_ai.tool.name = %definition.name,
+                previous_hash = %previous,
+                current_hash = %current,
+                "Tool schema changed"
+            );
+        }
+
+        if let Some(path) = &self.store {
+            let persisted = serde_json::to_string_pretty(&*known)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| std::fs::write(path, contents).map_err(Into::into));
+            if let Err(error) = persisted {
+                tracing::warn!(%error, path = %path.display(), "Failed to persist tool schema store");
+            }
+        }
+
+        current
+    }
+}
+
+/// Hash of the definition with object keys sorted, so key order in the JSON
+/// schema does not count as drift.
+pub fn schema_hash(definition: &ToolDefinition) -> String {
+    let value = serde_json::to_value(definition).unwrap_or(Value::Null);
+    fingerprint(canonical_json(&value))
+}
+
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let sorted: BTreeMap<&String, String> = map
+                .iter()
+                .map(|(key, value)| (key, canonical_json(value)))
+                .collect();
+            let fields: Vec<String> = sorted
+                .into_iter()
+                .map(|(key, value)| format!("{}:{value}", Value::String(key.clone())))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
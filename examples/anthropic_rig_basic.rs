@@ -0,0 +1,88 @@
+use anyhow::Context;
+use rig::agent::AgentBuilder;
+use rig::prelude::*;
+use rig::providers::anthropic::{self, completion::CompletionModel};
+use rig::telemetry::SpanCombinator;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::anthropic::InstrumentedModel;
+use serde_json::json;
+
+mod otel;
+
+const MODEL: &str = anthropic::completion::CLAUDE_3_5_HAIKU;
+
+// Anthropic only caches prompts above a minimum length (1024 tokens for most
+// models), so the preamble is padded with reference material; the second
+// prompt then reads the preamble from the cache the first one wrote.
+fn preamble() -> String {
+    let reference = "OpenTelemetry traces are trees of spans. Each span has a name, \
+        a start and end time, attributes, events and a status. Context propagation \
+        carries the trace id across async tasks and service boundaries. ";
+    format!(
+        "You are a concise technical assistant. Answer clearly and with short bullets.\n\n\
+         Reference material:\n{}",
+        reference.repeat(60)
+    )
+}
+
+#[tracing::instrument(name = "rig_anthropic_cached_prompts")]
+async fn run_prompts() -> anyhow::Result<Vec<String>> {
+    let client = anthropic::Client::from_env();
+    let model = CompletionModel::new(client, MODEL).with_prompt_caching();
+
+    let agent = InstrumentedAgent::new(
+        MODEL,
+        AgentBuilder::new(InstrumentedModel::new(model))
+            .preamble(&preamble())
+            .temperature(0.2)
+            .build(),
+    );
+
+    let mut answers = Vec::new();
+    for prompt_text in [
+        "Explain OpenTelemetry in exactly 3 bullets for a Rust backend engineer.",
+        "Explain context propagation in exactly 2 bullets.",
+    ] {
+        let prompt_span = tracing::info_span!("agent.prompt", model = MODEL, stage = "planner");
+        let _prompt_guard = prompt_span.enter();
+
+        prompt_span.record_model_input(&json!({
+            "prompt": prompt_text,
+        }));
+        tracing::info!(model = MODEL, "Sending prompt to Claude");
+
+        // Each call's `chat` span carries `gen_ai.usage.cache_read.input_tokens`
+        // and `gen_ai.usage.cache_creation.input_tokens`.
+        let answer = agent
+            .prompt(prompt_text)
+            .await
+            .context("Anthropic prompt failed")?;
+
+        prompt_span.record_model_output(&json!({
+            "response_len": answer.len(),
+            "response_preview": answer.chars().take(120).collect::<String>(),
+        }));
+        tracing::info!(response_len = answer.len(), "Received response");
+        answers.push(answer);
+    }
+
+    Ok(answers)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _telemetry = otel::init_with_report("rig-anthropic-basic-example")
+        .context("Failed to initialize telemetry")?;
+
+    if !otel::has_api_key("ANTHROPIC_API_KEY") {
+        println!("Set ANTHROPIC_API_KEY to run this example against the live Anthropic API.");
+        println!("Traces are still initialized with local fallback defaults.");
+        return Ok(());
+    }
+
+    for answer in run_prompts().await? {
+        println!("=== Claude response ===\n{answer}");
+    }
+
+    Ok(())
+}
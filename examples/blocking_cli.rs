@@ -0,0 +1,35 @@
+use anyhow::Context;
+use rig::prelude::*;
+use rig::providers::gemini;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::blocking::BlockingTelemetry;
+
+mod otel;
+
+fn main() -> anyhow::Result<()> {
+    let telemetry = BlockingTelemetry::init(|| otel::init_telemetry("rig-gemini-blocking-example"))
+        .context("Failed to initialize telemetry")?;
+
+    if !otel::has_api_key("GEMINI_API_KEY") {
+        println!("Set GEMINI_API_KEY to run this example against live Gemini.");
+        return Ok(());
+    }
+
+    let span = tracing::info_span!("rig_gemini_blocking_prompt", model = "gemini-2.5-flash");
+    let answer = {
+        let _guard = span.enter();
+        let agent = InstrumentedAgent::new(
+            "gemini-2.5-flash",
+            gemini::Client::from_env()
+                .agent("gemini-2.5-flash")
+                .preamble("You are a concise technical assistant.")
+                .build(),
+        );
+        telemetry
+            .prompt_instrumented(&agent, "Explain span context propagation in one sentence.")
+            .context("Gemini prompt failed")?
+    };
+    println!("=== Gemini response ===\n{answer}");
+
+    telemetry.flush()
+}
@@ -1,9 +1,14 @@
 use anyhow::Context;
+use rig::completion::PromptError;
 use rig::prelude::*;
-use rig::completion::Prompt;
 use rig::providers::gemini;
 use rig::telemetry::SpanCombinator;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::budget::WorkflowBudget;
+use rust_llm_observability_guide::fanout::fanout;
+use rust_llm_observability_guide::sub_agent::AgentHandoff;
 use serde_json::json;
+use std::time::Duration;
 
 mod otel;
 
@@ -13,76 +18,108 @@ async fn run_orchestration(topic: &str) -> anyhow::Result<String> {
     let _orchestrator_guard = orchestrator.enter();
     orchestrator.record_model_input(&json!({
         "topic": topic,
-        "workflow": "planner_then_writer",
+        "workflow": "parallel_planners_then_writer",
     }));
+    // Counts the planner calls; the writer runs in its own trace.
+    let budget = WorkflowBudget::new()
+        .with_max_calls(4)
+        .with_max_wall_time(Duration::from_secs(90))
+        .track(&orchestrator);
 
     let client = gemini::Client::from_env();
 
-    let planner = client
-        .agent("gemini-2.5-pro")
-        .preamble("You are a planning assistant. Produce a structured plan first, then a 1-line summary.")
-        .temperature(0.2)
-        .build();
-
+    // Two planners run side by side; the pro plan wins when it succeeds.
     let planner_prompt = format!("Create a practical rollout plan for this topic: {topic}");
-    let planner_span = tracing::info_span!(
-        "agent.planner",
-        model = "gemini-2.5-pro",
-        agent_role = "planner",
-        task = topic
-    );
-    let _planner_guard = planner_span.enter();
-    planner_span.record_model_input(&json!({
-        "prompt": planner_prompt,
-    }));
+    let plan_with = |model: &'static str| {
+        let planner = InstrumentedAgent::new(
+            model,
+            client
+                .agent(model)
+                .preamble("You are a planning assistant. Produce a structured plan first, then a 1-line summary.")
+                .temperature(0.2)
+                .build(),
+        );
+        let prompt = planner_prompt.clone();
+        async move {
+            let planner_span = tracing::Span::current();
+            planner_span.record_model_input(&json!({
+                "prompt": prompt,
+            }));
 
-    tracing::info!(agent = "planner", "Running planner step");
-    let plan = planner
-        .prompt(planner_prompt)
-        .await
+            tracing::info!(agent = "planner", model, "Running planner step");
+            let plan = planner.prompt(prompt).await?;
+            planner_span.record_model_output(&json!({
+                "plan_len": plan.len(),
+                "plan_preview": plan.chars().take(180).collect::<String>(),
+            }));
+            Ok::<_, PromptError>(plan)
+        }
+    };
+    let plans = fanout([
+        ("planner_pro", plan_with("gemini-2.5-pro")),
+        ("planner_flash", plan_with("gemini-2.5-flash")),
+    ])
+    .await;
+    for (branch, error) in plans.failures() {
+        tracing::warn!(branch, error = %error, "Planner failed");
+    }
+    let plan = plans
+        .into_results()
+        .into_iter()
+        .find_map(Result::ok)
         .context("Planner step failed")?;
-    planner_span.record_model_output(&json!({
-        "plan_len": plan.len(),
-        "plan_preview": plan.chars().take(180).collect::<String>(),
-    }));
 
-    let writer = client
-        .agent("gemini-2.5-flash")
-        .preamble("You are a concise writer. Return a short executive version of the plan.")
-        .max_tokens(700)
-        .build();
+    let writer = InstrumentedAgent::new(
+        "gemini-2.5-flash",
+        client
+            .agent("gemini-2.5-flash")
+            .preamble("You are a concise writer. Return a short executive version of the plan.")
+            .max_tokens(700)
+            .build(),
+    );
 
-    let writer_span = tracing::info_span!("agent_writer");
-    let _writer_guard = writer_span.enter();
+    // The writer runs as its own trace, linked back to the orchestrator, as
+    // it would when a worker picks it up from a queue.
+    let handoff = AgentHandoff::current();
     let writer_prompt = format!("Summarize this plan into 5 short bullet points:\n\n{plan}");
-    writer_span.record_model_input(&json!({
-        "model": "gemini-2.5-flash",
-        "prompt": writer_prompt,
-    }));
+    let summary = tokio::spawn(handoff.instrument("writer", async move {
+        let writer_span = tracing::Span::current();
+        writer_span.record_model_input(&json!({
+            "model": "gemini-2.5-flash",
+            "prompt": writer_prompt,
+        }));
 
-    tracing::info!(agent = "writer", "Running rewrite step");
-    let summary = writer
-        .prompt(writer_prompt)
-        .await
-        .context("Writer step failed")?;
-    writer_span.record_model_output(&json!({
-        "response_len": summary.len(),
-        "response_preview": summary.chars().take(180).collect::<String>(),
-    }));
+        tracing::info!(agent = "writer", "Running rewrite step");
+        let summary = writer
+            .prompt(writer_prompt)
+            .await
+            .context("Writer step failed")?;
+        writer_span.record_model_output(&json!({
+            "response_len": summary.len(),
+            "response_preview": summary.chars().take(180).collect::<String>(),
+        }));
+        anyhow::Ok(summary)
+    }))
+    .await
+    .context("Writer task panicked")??;
 
     orchestrator.record_model_output(&json!({
         "plan_len": plan.len(),
         "summary_len": summary.len(),
     }));
+    let verdict = budget.finish();
+    if !verdict.passed() {
+        tracing::warn!(%verdict, "Orchestration over budget");
+    }
 
     Ok(format!("Plan:\n{plan}\n\nExecutive summary:\n{summary}"))
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let _provider = otel::init_telemetry("rig-gemini-multi-agent-example").context("Failed to initialize telemetry")?;
+    let _telemetry = otel::init_with_report("rig-gemini-multi-agent-example").context("Failed to initialize telemetry")?;
 
-    if !otel::has_gemini_api_key() {
+    if !otel::has_api_key("GEMINI_API_KEY") {
         println!("Set GEMINI_API_KEY to run this example against live Gemini.");
         return Ok(());
     }
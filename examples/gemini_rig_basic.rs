@@ -1,54 +1,52 @@
 use anyhow::Context;
 use rig::prelude::*;
-use rig::{completion::Prompt, providers::gemini};
-use rig::telemetry::SpanCombinator;
-use serde_json::json;
+use rig::providers::gemini;
+use rig::completion::CompletionModel;
+use rig::http_client::ReqwestClient;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::cold_start;
+use rust_llm_observability_guide::llm_span;
 
 mod otel;
 
+const MODEL: &str = "gemini-2.5-flash";
+
+#[llm_span(operation = "invoke_agent", model = MODEL, provider = "gcp.gemini", skip(agent))]
+async fn ask<M: CompletionModel + 'static>(agent: &InstrumentedAgent<M>, prompt: &str) -> anyhow::Result<String> {
+    tracing::info!(model = MODEL, "Sending prompt to Gemini");
+    let answer = agent.prompt(prompt).await.context("Gemini prompt failed")?;
+    tracing::info!(response_len = answer.len(), "Received response");
+    Ok(answer)
+}
+
 #[tracing::instrument(name = "rig_gemini_basic_prompt")]
 async fn run_prompt() -> anyhow::Result<String> {
-    let client = gemini::Client::from_env();
-
-    let agent = client
-        .agent("gemini-2.5-flash")
-        .preamble("You are a concise technical assistant. Answer clearly and with short bullets.")
-        .temperature(0.2)
-        .build();
-
-    let prompt_text =
-        "Explain OpenTelemetry in exactly 3 bullets for a Rust backend engineer.";
-    let prompt_span = tracing::info_span!(
-        "agent.prompt",
-        model = "gemini-2.5-flash",
-        stage = "planner"
+    let api_key = std::env::var("GEMINI_API_KEY").context("GEMINI_API_KEY not set")?;
+    // The first call's DNS lookup and connection get spans of their own
+    // instead of inflating the model span.
+    let client = gemini::Client::<ReqwestClient>::builder()
+        .api_key(api_key)
+        .http_client(cold_start::http_client("gcp.gemini").context("Failed to build HTTP client")?)
+        .build()
+        .context("Failed to create Gemini client")?;
+
+    let agent = InstrumentedAgent::new(
+        MODEL,
+        client
+            .agent(MODEL)
+            .preamble("You are a concise technical assistant. Answer clearly and with short bullets.")
+            .temperature(0.2)
+            .build(),
     );
-    let _prompt_guard = prompt_span.enter();
-
-    prompt_span.record_model_input(&json!({
-        "prompt": prompt_text,
-    }));
-    tracing::info!(model = "gemini-2.5-flash", "Sending prompt to Gemini");
-
-    let answer = agent
-        .prompt(prompt_text)
-        .await
-        .context("Gemini prompt failed")?;
-
-    prompt_span.record_model_output(&json!({
-        "response_len": answer.len(),
-        "response_preview": answer.chars().take(120).collect::<String>(),
-    }));
-    tracing::info!(response_len = answer.len(), "Received response");
 
-    Ok(answer)
+    ask(&agent, "Explain OpenTelemetry in exactly 3 bullets for a Rust backend engineer.").await
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let _provider = otel::init_telemetry("rig-gemini-basic-example").context("Failed to initialize telemetry")?;
+    let _telemetry = otel::init_with_report("rig-gemini-basic-example").context("Failed to initialize telemetry")?;
 
-    if !otel::has_gemini_api_key() {
+    if !otel::has_api_key("GEMINI_API_KEY") {
         println!("Set GEMINI_API_KEY to run this example against the live Gemini API.");
         println!("Traces are still initialized with local fallback defaults.");
         return Ok(());
@@ -1,8 +1,9 @@
 use anyhow::Context;
 use rig::prelude::*;
-use rig::completion::Prompt;
 use rig::{completion::ToolDefinition, providers::gemini, tool::Tool};
 use rig::telemetry::SpanCombinator;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::tool::InstrumentedTool;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -51,22 +52,17 @@ impl Tool for AddTool {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let span = tracing::info_span!("tool.add_numbers", x = args.x, y = args.y);
-        span.record_model_input(&args);
-        let _guard = span.enter();
-
+        // `InstrumentedTool` wraps this call in an `execute_tool add_numbers`
+        // span with the arguments, result and duration.
         tracing::info!("Executing math tool");
-        let result = args.x + args.y;
-
-        span.record_model_output(&result);
-        Ok(result)
+        Ok(args.x + args.y)
     }
 }
 
 #[tracing::instrument(name = "rig_gemini_with_tool")]
 async fn run_tool_agent() -> anyhow::Result<String> {
     let client = gemini::Client::from_env();
-    let prompt = "Use the add_numbers tool to compute 42 + 58";
+    let prompt = "Use the add_numbers tool to compute 42 + 58 and 17 + 25";
     let tool_span = tracing::info_span!(
         "agent.planner",
         model = "gemini-2.5-flash",
@@ -78,13 +74,17 @@ async fn run_tool_agent() -> anyhow::Result<String> {
         "prompt": prompt,
     }));
 
-    let agent = client
-        .agent("gemini-2.5-flash")
-        .preamble(
-            "You are a calculator assistant. Use the `add_numbers` tool whenever the user asks for arithmetic.",
-        )
-        .tool(AddTool)
-        .build();
+    let agent = InstrumentedAgent::new(
+        "gemini-2.5-flash",
+        client
+            .agent("gemini-2.5-flash")
+            .preamble(
+                "You are a calculator assistant. Use the `add_numbers` tool whenever the user asks for arithmetic.",
+            )
+            .tool(InstrumentedTool::new(AddTool))
+            .build(),
+    )
+    .with_tool_concurrency(2);
 
     let answer = agent
         .prompt(prompt)
@@ -101,9 +101,9 @@ async fn run_tool_agent() -> anyhow::Result<String> {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let _provider = otel::init_telemetry("rig-gemini-tools-example").context("Failed to initialize telemetry")?;
+    let _telemetry = otel::init_with_report("rig-gemini-tools-example").context("Failed to initialize telemetry")?;
 
-    if !otel::has_gemini_api_key() {
+    if !otel::has_api_key("GEMINI_API_KEY") {
         println!("Set GEMINI_API_KEY to run this example against live Gemini.");
         return Ok(());
     }
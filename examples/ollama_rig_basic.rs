@@ -0,0 +1,89 @@
+use anyhow::Context;
+use rig::agent::AgentBuilder;
+use rig::client::Nothing;
+use rig::providers::ollama::{self, CompletionModel};
+use rig::telemetry::SpanCombinator;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::ollama::InstrumentedModel;
+use serde_json::json;
+
+mod otel;
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "llama3.2";
+
+// Two prompts against the same model: the first call's `chat` span shows the
+// model being loaded (`llm.server.load_duration`), the second runs warm.
+#[tracing::instrument(name = "rig_ollama_local_prompts", skip(agent))]
+async fn run_prompts(
+    agent: &InstrumentedAgent<InstrumentedModel>,
+    model: &str,
+) -> anyhow::Result<Vec<String>> {
+    let mut answers = Vec::new();
+    for prompt_text in [
+        "Explain OpenTelemetry in exactly 3 bullets for a Rust backend engineer.",
+        "Explain context propagation in exactly 2 bullets.",
+    ] {
+        let prompt_span = tracing::info_span!("agent.prompt", model, stage = "planner");
+        let _prompt_guard = prompt_span.enter();
+
+        prompt_span.record_model_input(&json!({
+            "prompt": prompt_text,
+        }));
+        tracing::info!(model, "Sending prompt to the local model");
+
+        let answer = agent
+            .prompt(prompt_text)
+            .await
+            .context("Ollama prompt failed")?;
+
+        prompt_span.record_model_output(&json!({
+            "response_len": answer.len(),
+            "response_preview": answer.chars().take(120).collect::<String>(),
+        }));
+        tracing::info!(response_len = answer.len(), "Received response");
+        answers.push(answer);
+    }
+
+    Ok(answers)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _telemetry = otel::init_with_report("rig-ollama-basic-example")
+        .context("Failed to initialize telemetry")?;
+
+    let base_url =
+        std::env::var("OLLAMA_API_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_owned());
+    let model = std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_owned());
+    let client = ollama::Client::builder()
+        .api_key(Nothing)
+        .base_url(&base_url)
+        .build()
+        .context("Failed to create Ollama client")?;
+
+    let agent = InstrumentedAgent::new(
+        model.clone(),
+        AgentBuilder::new(InstrumentedModel::new(CompletionModel::new(client, &model)))
+            .preamble(
+                "You are a concise technical assistant. Answer clearly and with short bullets.",
+            )
+            .temperature(0.2)
+            .build(),
+    );
+
+    match run_prompts(&agent, &model).await {
+        Ok(answers) => {
+            for answer in answers {
+                println!("=== {model} response ===\n{answer}");
+            }
+        }
+        Err(error) => {
+            println!("Could not reach {model} at {base_url}: {error:#}");
+            println!("Start Ollama (`ollama serve`) and pull the model (`ollama pull {model}`),");
+            println!("or point OLLAMA_API_BASE_URL / OLLAMA_MODEL at a running server.");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::Context;
+use rig::prelude::*;
+use rig::providers::openai;
+use rig::telemetry::SpanCombinator;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use serde_json::json;
+
+mod otel;
+
+#[tracing::instrument(name = "rig_openai_basic_prompt")]
+async fn run_prompt() -> anyhow::Result<String> {
+    let client = openai::Client::from_env();
+
+    let agent = InstrumentedAgent::new(
+        "gpt-4.1-mini",
+        client
+            .agent("gpt-4.1-mini")
+            .preamble(
+                "You are a concise technical assistant. Answer clearly and with short bullets.",
+            )
+            .temperature(0.2)
+            .build(),
+    );
+
+    let prompt_text = "Explain OpenTelemetry in exactly 3 bullets for a Rust backend engineer.";
+    let prompt_span =
+        tracing::info_span!("agent.prompt", model = "gpt-4.1-mini", stage = "planner");
+    let _prompt_guard = prompt_span.enter();
+
+    prompt_span.record_model_input(&json!({
+        "prompt": prompt_text,
+    }));
+    tracing::info!(model = "gpt-4.1-mini", "Sending prompt to OpenAI");
+
+    let answer = agent
+        .prompt(prompt_text)
+        .await
+        .context("OpenAI prompt failed")?;
+
+    prompt_span.record_model_output(&json!({
+        "response_len": answer.len(),
+        "response_preview": answer.chars().take(120).collect::<String>(),
+    }));
+    tracing::info!(response_len = answer.len(), "Received response");
+
+    Ok(answer)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _telemetry = otel::init_with_report("rig-openai-basic-example")
+        .context("Failed to initialize telemetry")?;
+
+    if !otel::has_api_key("OPENAI_API_KEY") {
+        println!("Set OPENAI_API_KEY to run this example against the live OpenAI API.");
+        println!("Traces are still initialized with local fallback defaults.");
+        return Ok(());
+    }
+
+    let answer = run_prompt().await?;
+    println!("=== OpenAI response ===\n{answer}");
+
+    Ok(())
+}
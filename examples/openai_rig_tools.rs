@@ -0,0 +1,111 @@
+use anyhow::Context;
+use rig::prelude::*;
+use rig::telemetry::SpanCombinator;
+use rig::{completion::ToolDefinition, providers::openai, tool::Tool};
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::tool::InstrumentedTool;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+mod otel;
+
+#[derive(Debug)]
+struct ToolError;
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "math tool execution failed")
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AddArgs {
+    x: i32,
+    y: i32,
+}
+
+#[derive(Clone, Default)]
+struct AddTool;
+
+impl Tool for AddTool {
+    const NAME: &'static str = "add_numbers";
+    type Error = ToolError;
+    type Args = AddArgs;
+    type Output = i32;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        serde_json::from_value(json!({
+            "name": "add_numbers",
+            "description": "Add two numbers together",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "x": {"type": "number", "description": "first operand"},
+                    "y": {"type": "number", "description": "second operand"}
+                },
+                "required": ["x", "y"]
+            }
+        }))
+        .expect("tool definition json")
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // `InstrumentedTool` wraps this call in an `execute_tool add_numbers`
+        // span with the arguments, result and duration.
+        tracing::info!("Executing math tool");
+        Ok(args.x + args.y)
+    }
+}
+
+#[tracing::instrument(name = "rig_openai_with_tool")]
+async fn run_tool_agent() -> anyhow::Result<String> {
+    let client = openai::Client::from_env();
+    let prompt = "Use the add_numbers tool to compute 42 + 58";
+    let tool_span = tracing::info_span!("agent.planner", model = "gpt-4.1-mini", role = "planner");
+    let _tool_guard = tool_span.enter();
+    tool_span.record_model_input(&json!({
+        "task": "Use add_numbers tool for arithmetic",
+        "prompt": prompt,
+    }));
+
+    let agent = InstrumentedAgent::new(
+        "gpt-4.1-mini",
+        client
+            .agent("gpt-4.1-mini")
+            .preamble(
+                "You are a calculator assistant. Use the `add_numbers` tool whenever the user asks for arithmetic.",
+            )
+            .tool(InstrumentedTool::new(AddTool))
+            .build(),
+    );
+
+    let answer = agent
+        .prompt(prompt)
+        .await
+        .context("OpenAI tool-enabled prompt failed")?;
+
+    tool_span.record_model_output(&json!({
+        "response_len": answer.len(),
+        "response_preview": answer.chars().take(120).collect::<String>(),
+    }));
+
+    Ok(answer)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let _telemetry = otel::init_with_report("rig-openai-tools-example")
+        .context("Failed to initialize telemetry")?;
+
+    if !otel::has_api_key("OPENAI_API_KEY") {
+        println!("Set OPENAI_API_KEY to run this example against live OpenAI.");
+        return Ok(());
+    }
+
+    let answer = run_tool_agent().await?;
+    println!("=== OpenAI tool trace result ===\n{answer}");
+
+    Ok(())
+}
@@ -1,47 +1,28 @@
-use anyhow::Context;
-use opentelemetry::global;
-use opentelemetry::KeyValue;
-use opentelemetry_otlp::WithExportConfig;
 use opentelemetry_sdk::trace::SdkTracerProvider;
-use opentelemetry_sdk::Resource;
-use opentelemetry::trace::TracerProvider as TracerProviderTrait;
-use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use rust_llm_observability_guide::run_report::ReportOutput;
+use rust_llm_observability_guide::telemetry::{self, TelemetryBuilder, TelemetryGuard};
 
+/// Initializes tracing once per process; later calls return the same provider.
+#[allow(dead_code)]
 pub fn init_telemetry(service_name: &str) -> anyhow::Result<SdkTracerProvider> {
-    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
-        .unwrap_or_else(|_| "http://localhost:4317".to_string());
-
-    let exporter = opentelemetry_otlp::SpanExporter::builder()
-        .with_tonic()
-        .with_endpoint(endpoint)
-        .build()
-        .context("Failed to create OTLP span exporter")?;
-
-    let tracer_provider = SdkTracerProvider::builder()
-        .with_batch_exporter(exporter)
-        .with_resource(
-            Resource::builder()
-                .with_service_name(service_name.to_owned())
-                .with_attribute(KeyValue::new("telemetry.sdk.language", "rust"))
-                .build(),
-        )
-        .build();
-
-    global::set_tracer_provider(tracer_provider.clone());
-
-    let tracer = TracerProviderTrait::tracer(&tracer_provider, "rig-gemini-tracer");
-    let otel_layer = tracing_opentelemetry::layer().with_tracer(tracer);
-    let filter_layer = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    tracing_subscriber::registry()
-        .with(filter_layer)
-        .with(fmt::layer().with_target(false))
-        .with(otel_layer)
-        .init();
+    Ok(telemetry::init(service_name)?.tracer_provider().clone())
+}
 
-    Ok(tracer_provider)
+/// Like [`init_telemetry`], returning a guard that shuts telemetry down and
+/// prints the run report (spans exported, LLM calls, tokens, cost, errors)
+/// when `main` returns. Console lines carry the trace and span ids.
+#[allow(dead_code)]
+pub fn init_with_report(service_name: &str) -> anyhow::Result<TelemetryGuard> {
+    Ok(TelemetryBuilder::new(service_name)
+        .with_shutdown_report(ReportOutput::Stderr)
+        .with_console_trace_ids(true)
+        .init()?
+        .guard())
 }
 
-pub fn has_gemini_api_key() -> bool {
-    std::env::var("GEMINI_API_KEY").is_ok()
+/// Whether the provider key in `var` (e.g. `GEMINI_API_KEY`) is set. Local
+/// model examples need none.
+#[allow(dead_code)]
+pub fn has_api_key(var: &str) -> bool {
+    std::env::var(var).is_ok()
 }
@@ -0,0 +1,359 @@
+//! Reference RAG chatbot service: the pieces of the other examples wired
+//! into one runnable HTTP service.
+//!
+//! `POST /chat` with `{"question": "...", "session_id": "..."}` produces one
+//! trace per request:
+//!
+//! ```text
+//! POST /chat                  (SERVER, continues the caller's traceparent;
+//!                              session.id, http.response.status_code)
+//! ├─ rewrite_query            (normalized query)
+//! ├─ retrieve                 (top-k document ids and scores)
+//! └─ generate_answer          (model, request params, usage, cost)
+//!    └─ ... rig's chat spans
+//! ```
+//!
+//! Spans pass through a `RedactionProcessor` before export, so prompts and
+//! answers leave the process only as fingerprints, and a `BaggageProcessor`
+//! stamps the `user.id` a caller sends in the `baggage` header on every LLM
+//! span; token, latency and cost metrics go to the same collector. Without
+//! `GEMINI_API_KEY` the service answers extractively from the best document,
+//! so the whole pipeline can be tried offline:
+//!
+//! ```text
+//! docker compose -f examples/rag_chatbot/docker-compose.yml up -d
+//! cargo run --features "rig metrics axum" --example rag_chatbot
+//! curl -s localhost:3000/chat -H 'baggage: user.id=u-42' \
+//!     -d '{"question":"What is a span?","session_id":"demo"}'
+//! ```
+
+use anyhow::Context;
+use axum::Router;
+use axum::body::Bytes;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use opentelemetry::global;
+use opentelemetry::propagation::TextMapCompositePropagator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+use opentelemetry_sdk::trace::{BatchSpanProcessor, SdkTracerProvider};
+use rig::client::ProviderClient;
+use rig::prelude::*;
+use rig::providers::gemini;
+use rust_llm_observability_guide::agent::InstrumentedAgent;
+use rust_llm_observability_guide::cost::{ModelPricing, PricingTable, record_cost};
+use rust_llm_observability_guide::genai_metrics::GenAiCall;
+use rust_llm_observability_guide::metrics::{self as llm_metrics, LlmMetrics};
+use rust_llm_observability_guide::processors::baggage::BaggageProcessor;
+use rust_llm_observability_guide::processors::redact::{RedactionAction, RedactionProcessor};
+use rust_llm_observability_guide::processors::scope::ScopeProcessor;
+use rust_llm_observability_guide::query_rewrite::{
+    QueryRewrite, record_rewrite, rewrite_query_span,
+};
+use rust_llm_observability_guide::scopes::Subsystem;
+use rust_llm_observability_guide::semconv::SESSION_ID;
+use rust_llm_observability_guide::server::ServerSpanLayer;
+use rust_llm_observability_guide::telemetry::Telemetry;
+use rust_llm_observability_guide::tokens::TokenUsage;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::Instrument;
+use tracing::field::Empty;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const SERVICE_NAME: &str = "rag-chatbot";
+const MODEL: &str = "gemini-2.5-flash";
+const TOP_K: usize = 2;
+
+/// The knowledge base; a real service would query a vector store here.
+const DOCUMENTS: [(&str, &str); 5] = [
+    (
+        "span",
+        "A span is one timed operation in a trace, with a name, start and end time, attributes, events and a status.",
+    ),
+    (
+        "trace",
+        "A trace is the tree of spans produced by one request, linked by a shared trace id and parent span ids.",
+    ),
+    (
+        "context-propagation",
+        "Context propagation carries the trace id across async tasks and service boundaries, for example in the traceparent HTTP header.",
+    ),
+    (
+        "sampling",
+        "Sampling decides which traces are kept; parent-based sampling keeps every span of a trace whose root was sampled.",
+    ),
+    (
+        "collector",
+        "The OpenTelemetry Collector receives OTLP data, processes it and exports it to one or more observability backends.",
+    ),
+];
+
+#[derive(Debug, Deserialize)]
+struct ChatRequest {
+    question: String,
+    session_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatResponse {
+    answer: String,
+    sources: Vec<&'static str>,
+    trace_id: Option<String>,
+}
+
+struct AppState {
+    /// `None` without `GEMINI_API_KEY`: answers are extracted from documents.
+    agent: Option<InstrumentedAgent<gemini::completion::CompletionModel>>,
+}
+
+/// Prompts and answers are exported as fingerprints only; everything else
+/// (timings, usage, cost, document ids) stays readable.
+fn init_tracing() -> anyhow::Result<SdkTracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .build()
+        .context("Failed to create OTLP span exporter")?;
+    let export = RedactionProcessor::new(BatchSpanProcessor::builder(exporter).build())
+        .with_rule("gen_ai.input.messages", RedactionAction::Hash)
+        .with_rule("gen_ai.output.messages", RedactionAction::Hash)
+        .with_rule("gen_ai.system_instructions", RedactionAction::Hash)
+        .with_rule("llm.rewrite.*_query", RedactionAction::Hash)
+        .with_rule("llm.rewrite.rewritten_queries", RedactionAction::Hash);
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_span_processor(BaggageProcessor::new(ScopeProcessor::new(export)))
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build();
+    global::set_tracer_provider(tracer_provider.clone());
+    // Lets `ServerSpanLayer` continue callers' traces and pick up the
+    // `user.id` / `session.id` they send as baggage.
+    global::set_text_map_propagator(TextMapCompositePropagator::new(vec![
+        Box::new(TraceContextPropagator::new()),
+        Box::new(BaggagePropagator::new()),
+    ]));
+
+    let tracer = tracer_provider.tracer_with_scope(Subsystem::Agent.scope());
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+    Ok(tracer_provider)
+}
+
+fn normalize(question: &str) -> String {
+    question
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn rewrite(question: &str) -> String {
+    let span = rewrite_query_span("normalize");
+    let _guard = span.enter();
+    let query = normalize(question);
+    record_rewrite(&span, &QueryRewrite::new(question, vec![query.clone()]));
+    query
+}
+
+/// Keyword-overlap retrieval, standing in for an embedding search.
+fn retrieve(query: &str) -> Vec<(&'static str, &'static str, f64)> {
+    let span = tracing::info_span!(
+        "retrieve",
+        otel.scope = Subsystem::Rag.scope_name(),
+        llm.retrieval.top_k = TOP_K as u64,
+        llm.retrieval.candidates = DOCUMENTS.len() as u64,
+        llm.retrieval.returned = Empty,
+        llm.retrieval.top_score = Empty,
+        llm.retrieval.document_ids = Empty,
+    );
+    let _guard = span.enter();
+
+    let terms: Vec<&str> = query
+        .split_whitespace()
+        .filter(|term| term.len() > 2)
+        .collect();
+    let mut scored: Vec<_> = DOCUMENTS
+        .iter()
+        .map(|(id, text)| {
+            let haystack = normalize(&format!("{id} {text}"));
+            let hits = terms.iter().filter(|term| haystack.contains(*term)).count();
+            (*id, *text, hits as f64 / terms.len().max(1) as f64)
+        })
+        .filter(|(_, _, score)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+    scored.truncate(TOP_K);
+
+    span.record("llm.retrieval.returned", scored.len() as u64);
+    if let Some((_, _, score)) = scored.first() {
+        span.record("llm.retrieval.top_score", *score);
+    }
+    let ids: Vec<&str> = scored.iter().map(|(id, _, _)| *id).collect();
+    span.record("llm.retrieval.document_ids", ids.join(",").as_str());
+    scored
+}
+
+async fn generate(
+    state: &AppState,
+    question: &str,
+    documents: &[(&'static str, &'static str, f64)],
+) -> anyhow::Result<String> {
+    let span = tracing::info_span!("generate_answer", llm.answer.mode = Empty);
+    async {
+        let span = tracing::Span::current();
+        let Some(agent) = &state.agent else {
+            span.record("llm.answer.mode", "extractive");
+            return Ok(documents
+                .first()
+                .map(|(_, text, _)| (*text).to_owned())
+                .unwrap_or_else(|| "I could not find anything about that.".to_owned()));
+        };
+        span.record("llm.answer.mode", "generative");
+
+        let context: Vec<String> = documents
+            .iter()
+            .map(|(id, text, _)| format!("[{id}] {text}"))
+            .collect();
+        let prompt = format!(
+            "Answer using only this context and cite the ids in brackets.\n\n{}\n\nQuestion: {question}",
+            context.join("\n")
+        );
+
+        let call = GenAiCall {
+            operation: "chat",
+            provider: "gcp.gemini",
+            request_model: MODEL,
+            response_model: None,
+            server_address: None,
+            server_port: None,
+        };
+        match agent.prompt_extended(prompt).await {
+            Ok(response) => {
+                let usage = TokenUsage {
+                    input_tokens: response.total_usage.input_tokens,
+                    output_tokens: response.total_usage.output_tokens,
+                    reasoning_tokens: 0,
+                };
+                LlmMetrics::global().record_counts(&call, Some(&usage), None);
+                record_cost(&span, PricingTable::global(), MODEL, &usage);
+                Ok(response.output)
+            }
+            Err(error) => {
+                LlmMetrics::global().record_counts(&call, None, Some("prompt_error"));
+                Err(error).context("Gemini prompt failed")
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+async fn answer(state: &AppState, request: ChatRequest) -> anyhow::Result<ChatResponse> {
+    let query = rewrite(&request.question);
+    let documents = retrieve(&query);
+    let answer = generate(state, &request.question, &documents).await?;
+    Ok(ChatResponse {
+        answer,
+        sources: documents.iter().map(|(id, _, _)| *id).collect(),
+        trace_id: Telemetry::current_trace_id().map(|trace_id| trace_id.to_string()),
+    })
+}
+
+async fn chat(state: Arc<AppState>, body: Bytes) -> Response {
+    // The request's server span, opened by `ServerSpanLayer`.
+    let span = tracing::Span::current();
+    let (status, body) = match serde_json::from_slice::<ChatRequest>(&body) {
+        Err(error) => {
+            span.record("error.type", "invalid_request");
+            (
+                StatusCode::BAD_REQUEST,
+                serde_json::json!({ "error": error.to_string() }),
+            )
+        }
+        Ok(request) => {
+            if let Some(session_id) = &request.session_id {
+                span.set_attribute(SESSION_ID, session_id.clone());
+            }
+            match answer(&state, request).await {
+                Ok(response) => (StatusCode::OK, serde_json::json!(response)),
+                Err(error) => {
+                    tracing::error!(error = format!("{error:#}"), "Chat request failed");
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        serde_json::json!({ "error": "internal error" }),
+                    )
+                }
+            }
+        }
+    };
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let tracer_provider = init_tracing()?;
+    let meter_provider = llm_metrics::init(SERVICE_NAME).context("Failed to initialize metrics")?;
+    PricingTable::global().set(
+        MODEL,
+        ModelPricing {
+            input_per_1k: 0.0003,
+            output_per_1k: 0.0025,
+            reasoning_per_1k: None,
+        },
+    );
+
+    let agent = std::env::var("GEMINI_API_KEY").is_ok().then(|| {
+        InstrumentedAgent::new(
+            MODEL,
+            gemini::Client::from_env()
+                .agent(MODEL)
+                .preamble("You are a concise documentation assistant for OpenTelemetry.")
+                .temperature(0.2)
+                .build(),
+        )
+    });
+    if agent.is_none() {
+        tracing::warn!("GEMINI_API_KEY is not set; answering extractively from the documents");
+    }
+    let state = Arc::new(AppState { agent });
+
+    let app = Router::new()
+        .route("/chat", post(move |body: Bytes| chat(state.clone(), body)))
+        .layer(ServerSpanLayer::new().with_trace_id_header(true));
+    let address = std::env::var("RAG_CHATBOT_ADDR").unwrap_or_else(|_| "127.0.0.1:3000".to_owned());
+    let listener = tokio::net::TcpListener::bind(&address)
+        .await
+        .with_context(|| format!("Failed to bind {address}"))?;
+    tracing::info!(%address, "RAG chatbot listening; POST /chat");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+        .context("Server failed")?;
+
+    tracer_provider
+        .shutdown()
+        .context("Failed to shut down tracer provider")?;
+    meter_provider
+        .shutdown()
+        .context("Failed to shut down meter provider")?;
+    Ok(())
+}
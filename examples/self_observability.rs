@@ -0,0 +1,197 @@
+//! Runs the whole crate surface against a mock provider and an in-process
+//! collector, then queries the collected trace the way a backend would.
+//!
+//! No network, no API key: `cargo run --example self_observability`.
+
+use anyhow::{Context, bail};
+use opentelemetry::KeyValue;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use rust_llm_observability_guide::bundle::{ConversationBundle, ConversationCollector};
+use rust_llm_observability_guide::compression::{
+    CompressionStats, compress_context_span, record_compression,
+};
+use rust_llm_observability_guide::cost::ModelPricing;
+use rust_llm_observability_guide::fingerprint::fingerprint;
+use rust_llm_observability_guide::finish_reason::{FinishReason, record_finish_reason};
+use rust_llm_observability_guide::genai_metrics::{GenAiCall, GenAiMetrics};
+use rust_llm_observability_guide::outcome::{classify_response, record_outcome};
+use rust_llm_observability_guide::processors::derived::{
+    DerivedAttributesProcessor, cost_from_usage, tokens_per_second,
+};
+use rust_llm_observability_guide::processors::rollup::RollupProcessor;
+use rust_llm_observability_guide::scopes::Subsystem;
+use rust_llm_observability_guide::spans::SpanBuilderExt;
+use rust_llm_observability_guide::tokens::{TokenUsage, estimate_tokens};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+const MODEL: &str = "mock-1";
+const CONVERSATION_ID: &str = "self-observability-demo";
+
+/// Deterministic stand-in for a provider SDK.
+struct MockProvider;
+
+struct MockResponse {
+    text: String,
+    usage: TokenUsage,
+    finish_reason: &'static str,
+}
+
+impl MockProvider {
+    async fn complete(&self, prompt: &str) -> MockResponse {
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        let text = format!("Echo: {}", prompt.to_uppercase());
+        MockResponse {
+            usage: TokenUsage {
+                input_tokens: estimate_tokens(prompt),
+                output_tokens: estimate_tokens(&text),
+                reasoning_tokens: 0,
+            },
+            text,
+            finish_reason: "stop",
+        }
+    }
+}
+
+fn pricing(model: &str) -> Option<ModelPricing> {
+    (model == MODEL).then_some(ModelPricing {
+        input_per_1k: 0.5,
+        output_per_1k: 1.5,
+        reasoning_per_1k: None,
+    })
+}
+
+async fn run_turn(provider: &MockProvider, history: &[&str], prompt: &str) -> anyhow::Result<()> {
+    let compress_span = compress_context_span("keep_last_2");
+    let kept = &history[history.len().saturating_sub(2)..];
+    record_compression(
+        &compress_span,
+        &CompressionStats::from_messages(history, kept),
+    );
+    drop(compress_span);
+
+    // Content is never captured verbatim: only its size and fingerprint.
+    let span = tracing::Span::start_llm_span("chat", MODEL);
+    span.record("gen_ai.provider.name", "mock");
+    span.set_attribute("gen_ai.input.chars", prompt.chars().count() as i64);
+    span.set_attribute("gen_ai.input.fingerprint", fingerprint(prompt));
+    let started = Instant::now();
+    let response = provider.complete(prompt).instrument(span.clone()).await;
+
+    span.record("gen_ai.usage.input_tokens", response.usage.input_tokens);
+    span.record("gen_ai.usage.output_tokens", response.usage.output_tokens);
+    let reason = FinishReason::parse(response.finish_reason);
+    record_finish_reason(&span, MODEL, &reason, Some(256));
+    record_outcome(
+        &span,
+        MODEL,
+        classify_response(&response.text, Some(response.finish_reason)),
+    );
+    GenAiMetrics::global().record(
+        &GenAiCall {
+            operation: "chat",
+            provider: "mock",
+            request_model: MODEL,
+            response_model: Some(MODEL),
+            server_address: None,
+            server_port: None,
+        },
+        started.elapsed(),
+        Some(&response.usage),
+        None,
+    );
+    Ok(())
+}
+
+/// Backend-style checks over the collected conversation.
+fn verify(bundle: &ConversationBundle) -> anyhow::Result<()> {
+    let find = |name: &str| bundle.spans.iter().find(|span| span.name == name);
+    let root = find("conversation").context("root span missing")?;
+    let chat = find("chat mock-1").context("chat span missing")?;
+    find("compress_context").context("compress_context span missing")?;
+
+    let mut missing = Vec::new();
+    for (span, key) in [
+        (root, "llm.rollup.llm_calls"),
+        (root, "llm.rollup.cost_usd"),
+        (chat, "gen_ai.usage.cost_usd"),
+        (chat, "gen_ai.input.fingerprint"),
+        (chat, "gen_ai.response.finish_reasons"),
+        (chat, "llm.response.outcome"),
+    ] {
+        if !span.attributes.contains_key(key) {
+            missing.push(format!("{}: {key}", span.name));
+        }
+    }
+    if bundle.spans.iter().any(|span| {
+        span.attributes
+            .values()
+            .any(|value| value.as_str().is_some_and(|text| text.contains("ECHO")))
+    }) {
+        missing.push("raw response content leaked into a span".to_owned());
+    }
+    if bundle.trace_ids.len() != 1 {
+        missing.push(format!(
+            "expected one trace, got {}",
+            bundle.trace_ids.len()
+        ));
+    }
+    if !missing.is_empty() {
+        bail!("trace verification failed:\n  {}", missing.join("\n  "));
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let collector = ConversationCollector::new();
+    let processor = DerivedAttributesProcessor::new(RollupProcessor::new(collector.clone()))
+        .with_deriver(tokens_per_second)
+        .with_deriver(cost_from_usage(pricing));
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_span_processor(processor)
+        .build();
+    let tracer = tracer_provider.tracer_with_scope(Subsystem::Agent.scope());
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new("info"))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .context("Failed to install tracing subscriber")?;
+
+    let provider = MockProvider;
+    let history = ["hello", "what is a span?", "and a trace?"];
+    let root = tracing::info_span!("conversation", gen_ai.conversation.id = CONVERSATION_ID);
+    async {
+        run_turn(&provider, &history, "explain context propagation").await?;
+        run_turn(&provider, &history, "now in one sentence").await
+    }
+    .instrument(root)
+    .await?;
+    collector.record_metric(
+        CONVERSATION_ID,
+        "llm.turns",
+        2.0,
+        &[KeyValue::new("gen_ai.request.model", MODEL)],
+    );
+
+    tracer_provider
+        .force_flush()
+        .context("Failed to flush spans")?;
+    let bundle = collector.export(CONVERSATION_ID);
+    verify(&bundle)?;
+    println!("{}", bundle.to_json()?);
+    println!(
+        "self-observability check passed: {} spans in trace {}",
+        bundle.spans.len(),
+        bundle.trace_ids[0]
+    );
+
+    tracer_provider
+        .shutdown()
+        .context("Failed to shutdown tracer provider")?;
+    Ok(())
+}
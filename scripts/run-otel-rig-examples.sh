@@ -41,8 +41,8 @@ for example in "${examples_to_run[@]}"; do
     continue
   fi
 
-  echo "▶ cargo run --example $example"
-  if cargo run --example "$example" > "$run_log" 2>&1; then
+  echo "▶ cargo run --features rig,macros --example $example"
+  if cargo run --features rig,macros --example "$example" > "$run_log" 2>&1; then
     passed=$((passed + 1))
     echo "   status: PASS"
     tail -n 40 "$run_log"
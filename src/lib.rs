@@ -0,0 +1,13 @@
+//! Reusable observability building blocks for Rig-based LLM applications.
+//!
+//! The examples in `examples/` show the tracing patterns step by step; the
+//! modules here package the pieces that are worth sharing between services.
+//!
+//! This crate re-exports `llm-obs-core` and, with the `rig` feature,
+//! `llm-obs-rig`, so one dependency and one set of features cover both.
+//! Services that only need the provider-agnostic core can depend on
+//! `llm-obs-core` directly.
+
+pub use llm_obs_core::*;
+#[cfg(feature = "rig")]
+pub use llm_obs_rig::*;
@@ -0,0 +1,142 @@
+//! Exports one span and one metric data point to pinned OpenTelemetry
+//! Collector images and checks that each version received both signals from
+//! this crate's exporters. Exporter/collector protocol mismatches only show
+//! up against a real collector, so this needs Docker:
+//!
+//! ```bash
+//! cargo test --features collector-tests --test collector_compat
+//! OTEL_MATRIX_VERSIONS="0.110.0 0.146.1" cargo test --features collector-tests --test collector_compat
+//! ```
+
+use anyhow::Context;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::MeterProvider as _;
+use rust_llm_observability_guide::metrics::MetricsBuilder;
+use rust_llm_observability_guide::telemetry::TelemetryBuilder;
+use std::time::Duration;
+use testcontainers::core::{IntoContainerPort, WaitFor};
+use testcontainers::runners::AsyncRunner;
+use testcontainers::{ContainerAsync, GenericImage, ImageExt};
+use tracing_subscriber::layer::SubscriberExt;
+
+const IMAGE: &str = "otel/opentelemetry-collector-contrib";
+const DEFAULT_VERSIONS: &str = "0.100.0 0.120.0 0.146.1";
+const CONFIG_PATH: &str = "/etc/otelcol-contrib/config.yaml";
+const CONFIG: &str = r#"
+receivers:
+  otlp:
+    protocols:
+      grpc:
+        endpoint: 0.0.0.0:4317
+
+exporters:
+  debug:
+    verbosity: detailed
+
+service:
+  pipelines:
+    traces:
+      receivers: [otlp]
+      exporters: [debug]
+    metrics:
+      receivers: [otlp]
+      exporters: [debug]
+"#;
+
+async fn start_collector(version: &str) -> anyhow::Result<ContainerAsync<GenericImage>> {
+    GenericImage::new(IMAGE, version)
+        .with_exposed_port(4317.tcp())
+        .with_wait_for(WaitFor::message_on_stderr("Everything is ready"))
+        .with_copy_to(CONFIG_PATH, CONFIG.as_bytes().to_vec())
+        .with_cmd([format!("--config={CONFIG_PATH}")])
+        .start()
+        .await
+        .with_context(|| format!("Failed to start collector {version}"))
+}
+
+/// Exports the probe span and metric to `endpoint`; shutting the providers
+/// down flushes both, so an export the collector rejects fails here.
+fn export_probe(endpoint: &str, marker: &str) -> anyhow::Result<()> {
+    let (tracer_provider, layer) = TelemetryBuilder::new("collector-compat")
+        .with_endpoint(endpoint)
+        .with_fmt_layer(false)
+        .build_layer()
+        .context("Failed to build the trace pipeline")?;
+    let meter_provider = MetricsBuilder::new("collector-compat")
+        .with_endpoint(endpoint)
+        .with_export_interval(Duration::from_secs(1))
+        .build()
+        .context("Failed to build the metrics pipeline")?;
+
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("collector_compat_probe", marker).in_scope(|| {
+            tracing::info!("Emitting compatibility probe");
+        });
+    });
+    meter_provider
+        .meter("collector-compat")
+        .u64_counter("collector_compat.probe")
+        .build()
+        .add(1, &[KeyValue::new("marker", marker.to_owned())]);
+
+    meter_provider
+        .shutdown()
+        .context("Failed to export metrics")?;
+    tracer_provider
+        .shutdown()
+        .context("Failed to export spans")?;
+    Ok(())
+}
+
+/// Waits until the collector's debug exporter printed `needles`.
+async fn wait_for_output(
+    collector: &ContainerAsync<GenericImage>,
+    needles: &[&str],
+) -> anyhow::Result<()> {
+    let mut output = String::new();
+    for _ in 0..15 {
+        output = String::from_utf8_lossy(&collector.stderr_to_vec().await?).into_owned();
+        if needles.iter().all(|needle| output.contains(needle)) {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    let missing: Vec<_> = needles
+        .iter()
+        .filter(|needle| !output.contains(*needle))
+        .collect();
+    anyhow::bail!("collector output lacks {missing:?}")
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn collectors_receive_spans_and_metrics() -> anyhow::Result<()> {
+    let versions =
+        std::env::var("OTEL_MATRIX_VERSIONS").unwrap_or_else(|_| DEFAULT_VERSIONS.to_owned());
+    let mut failures = Vec::new();
+    for version in versions.split_whitespace() {
+        let collector = start_collector(version).await?;
+        let port = collector.get_host_port_ipv4(4317).await?;
+        let endpoint = format!("http://{}:{port}", collector.get_host().await?);
+        let marker = format!("matrix-{version}");
+
+        let result = match tokio::task::block_in_place(|| export_probe(&endpoint, &marker)) {
+            Ok(()) => {
+                wait_for_output(
+                    &collector,
+                    &["collector_compat_probe", "collector_compat.probe", &marker],
+                )
+                .await
+            }
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            failures.push(format!("{version}: {error:#}"));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "collector versions failed: {failures:#?}"
+    );
+    Ok(())
+}